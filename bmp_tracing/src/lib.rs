@@ -2,13 +2,15 @@ use std::error::Error as _;
 use std::fs::File;
 use std::io;
 use std::path::PathBuf;
-use std::sync::{Mutex, PoisonError};
+use std::mem;
+use std::sync::{Mutex, OnceLock, PoisonError};
 
-use tracing_subscriber::filter::EnvFilter;
+use tracing::Level;
+use tracing_subscriber::filter::{Directive, EnvFilter, LevelFilter, ParseError};
 use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt as _;
-use tracing_subscriber::{Layer, fmt};
+use tracing_subscriber::{Layer, Registry, fmt, reload};
 pub use {tracing, tracing_subscriber};
 
 #[derive(Debug, Clone)]
@@ -51,6 +53,20 @@ pub fn init(default_level: &str) {
 }
 
 static TRACE_INIT: Mutex<()> = Mutex::new(());
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+#[expect(clippy::exhaustive_enums)]
+pub enum SetLogLevelError {
+    #[error("tracing not initialized")]
+    NotInitialized,
+    #[error("invalid module path: {0}")]
+    InvalidModule(String),
+    #[error("could not parse filter directive: {0}")]
+    Directive(#[from] ParseError),
+    #[error("could not reload filter: {0}")]
+    Reload(#[from] reload::Error),
+}
 
 /// Initialize tracing with custom output configuration.
 pub fn init_with_config(default_level: &str, config: LogConfig) {
@@ -69,15 +85,36 @@ pub fn init_with_config(default_level: &str, config: LogConfig) {
 
     // Create the filter layer
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|e| {
-        if matches!(e.source(), Some(s) if s.is::<tracing_subscriber::filter::ParseError>()) {
+        if matches!(e.source(), Some(s) if s.is::<ParseError>()) {
             eprintln!("Could not parse `RUST_LOG` environment variable: {e}");
         }
         EnvFilter::new(default_level)
     });
 
-    // Build and init the subscriber
+    // Build and init the subscriber, keeping a handle to the filter so that it may be changed later
+    let (filter, handle) = reload::Layer::new(filter);
     tracing_subscriber::registry()
         .with(filter)
         .with(config.layer())
         .init();
+    FILTER_HANDLE.set(handle).unwrap_or_else(|_| unreachable!("tracing initialized twice"));
+}
+
+/// Change the log level of the given module (and its submodules) at runtime, or of all modules not
+/// otherwise configured if `module` is empty, overriding any level set by `RUST_LOG`.
+pub fn set_log_level(module: &str, level: Level) -> Result<(), SetLogLevelError> {
+    let directive = if module.is_empty() {
+        Directive::from(LevelFilter::from_level(level))
+    } else {
+        // Only plain module paths are accepted, not the span & field filters of a full directive.
+        let is_valid_module = module.split("::")
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+        if !is_valid_module {
+            return Err(SetLogLevelError::InvalidModule(module.to_owned()));
+        }
+        format!("{module}={level}").parse()?
+    };
+    let handle = FILTER_HANDLE.get().ok_or(SetLogLevelError::NotInitialized)?;
+    handle.modify(|filter| *filter = mem::take(filter).add_directive(directive))?;
+    Ok(())
 }
@@ -1,15 +1,14 @@
-use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use bdk_kyoto::bip157::{Builder, Network};
-use bdk_kyoto::{
-    BuilderExt as _, Info, Receiver, ScanType, TrustedPeer, UnboundedReceiver, Update, Warning,
-};
-use bdk_wallet::Wallet;
 use bdk_wallet::bitcoin::{Address, Amount, Transaction, Txid};
-use bdk_wallet::chain::DescriptorId;
 use bdk_wallet::chain::spk_client::{FullScanRequest, FullScanResponse};
-use tokio::select;
+
+#[cfg(feature = "native")]
+mod cbf_scanner;
+
+#[cfg(feature = "native")]
+pub use cbf_scanner::CBFScanner;
+
 /// Minimal abstraction over blockchain interaction for broadcasting transactions.
 pub trait ChainApi: Send + Sync {
     fn transaction_broadcast(&self, tx: &Transaction) -> anyhow::Result<Txid>;
@@ -38,72 +37,3 @@ pub trait ChainScanner {
         fetch_prev_txouts: bool,
     ) -> anyhow::Result<FullScanResponse<K>>;
 }
-
-pub struct CBFScanner {
-    pub peers: Vec<TrustedPeer>,
-}
-
-impl CBFScanner {
-    pub const fn new(peers: Vec<TrustedPeer>) -> Self {
-        Self { peers }
-    }
-
-    async fn traces(
-        mut info_subscriber: Receiver<Info>,
-        mut warning_subscriber: UnboundedReceiver<Warning>,
-    ) {
-        loop {
-            select! {
-                info = info_subscriber.recv() => {
-                    if let Some(info) = info {
-                        match info {
-                            Info::Progress(p) => {
-                                tracing::info!("chain height: {}, filter download progress: {}%", p.chain_height(), p.percentage_complete());
-                            },
-                            Info::BlockReceived(b) => {
-                                tracing::info!("downloaded block: {b}");
-                            },
-                            _ => (),
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                warn = warning_subscriber.recv() => {
-                    if let Some(warn) = warn {
-                        tracing::warn!("{warn}");
-                    } else {
-                        break;
-                    }
-                }
-            }
-        }
-    }
-
-    pub async fn sync_cbf(
-        &self,
-        network: Network,
-        peers: Vec<TrustedPeer>,
-        wallets: Vec<(&Wallet, ScanType)>,
-    ) -> anyhow::Result<BTreeMap<DescriptorId, Update>> {
-        let client = Builder::new(network)
-            .add_peers(peers)
-            .build_with_wallets(wallets)?;
-
-        let (client, logging, mut update_subscriber) = client.subscribe();
-
-        tokio::task::spawn(async move {
-            Self::traces(logging.info_subscriber, logging.warning_subscriber).await;
-        });
-        let client = client.start();
-        let requester = client.requester();
-        // Updates are grouped with the `DescriptorId` of the public, external descriptor.
-        let updates = update_subscriber
-            .updates()
-            .await?
-            .collect::<BTreeMap<_, _>>();
-
-        requester.shutdown()?;
-        Ok(updates)
-    }
-}
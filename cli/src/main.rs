@@ -0,0 +1,277 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use bdk_wallet::serde_json;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rpc::pb::musigrpc::musig_client::MusigClient;
+use rpc::pb::musigrpc::{
+    self, CloseTradeRequest, DepositTxSignatureRequest, NonceSharesRequest, PubKeySharesRequest,
+    ReceiverAddressAndAmount,
+};
+use rpc::message_log::{MessageLog, ReplayOutcome, replay_entry};
+use rpc::pb::walletrpc::wallet_client::WalletClient;
+use rpc::protocol::MAX_SUPPORTED_VERSION;
+use rpc::pb::walletrpc::{ListUnspentRequest, NewAddressRequest, WalletBalanceRequest};
+use rpc::server::MusigImpl;
+use rpc::wallet::WalletServiceImpl;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+#[command(propagate_version = true)]
+#[expect(clippy::doc_markdown, reason = "doc comments are used verbatim by Clap and not intended to be markdown")]
+struct Cli {
+    /// The host of the MuSig daemon
+    #[arg(long, env = "BISQ_MUSIG_HOST", default_value = "127.0.0.1")]
+    host: String,
+
+    /// The port of the MuSig daemon
+    #[arg(short, long, env = "BISQ_MUSIG_PORT", default_value_t = 50051)]
+    port: u16,
+
+    /// Connect with TLS, verifying the daemon's certificate against this CA certificate (PEM file)
+    #[arg(long, env = "BISQ_MUSIG_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    #[command(subcommand)]
+    commands: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Start a new trade, returning my public key shares
+    InitTrade {
+        #[arg(long)]
+        trade_id: String,
+        #[arg(long, value_enum)]
+        role: Role,
+        /// The first sequence number of the trade's requests
+        #[arg(long, default_value_t = 0)]
+        sequence_number: u64,
+        /// The wallet to broadcast & track the trade's txs with [default: the server's default wallet]
+        #[arg(long, default_value = "")]
+        wallet_id: String,
+    },
+    /// Set the peer's public key shares & the trade parameters, returning my nonce shares
+    GetNonceShares(NonceSharesArgs),
+    /// Sign the deposit tx, given the peer's partial signatures, returning my deposit PSBT
+    SignDepositTx {
+        #[arg(long)]
+        trade_id: String,
+        /// The peer's partial signatures message, as JSON
+        #[arg(long)]
+        peers_partial_signatures: String,
+        #[arg(long)]
+        sequence_number: u64,
+    },
+    /// Close the trade, returning my private key share for the peer's payout output
+    CloseTrade {
+        #[arg(long)]
+        trade_id: String,
+        /// The peer's private key share for my payout output, if the peer closed cooperatively
+        #[arg(long, value_parser = parse_hex)]
+        peers_prv_key_share_hex: Option<Vec<u8>>,
+        /// The signed swap tx, if found on the network (for the buyer only)
+        #[arg(long, value_parser = parse_hex, conflicts_with = "peers_prv_key_share_hex")]
+        swap_tx_hex: Option<Vec<u8>>,
+        #[arg(long)]
+        sequence_number: u64,
+    },
+    /// Wallet operations
+    #[command(subcommand)]
+    Wallet(WalletCommands),
+    /// Print the trade protocol state diagram in Graphviz DOT format (no daemon connection needed)
+    DumpStateDiagram,
+    /// Replay the requests of a daemon's message log against a fresh in-process trade service,
+    /// checking that each has the recorded outcome (no daemon connection needed)
+    ReplayLog {
+        #[arg(long)]
+        file: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+struct NonceSharesArgs {
+    #[arg(long)]
+    trade_id: String,
+    #[arg(long, value_parser = parse_hex)]
+    buyer_output_peers_pub_key_share: Vec<u8>,
+    #[arg(long, value_parser = parse_hex)]
+    seller_output_peers_pub_key_share: Vec<u8>,
+    #[arg(long, value_parser = parse_hex)]
+    peers_multisig_script_key: Vec<u8>,
+    /// Deposit tx fee rate, in sats per kwu
+    #[arg(long)]
+    deposit_tx_fee_rate: u64,
+    /// Fee rate of the prepared (swap, warning, redirect & claim) txs, in sats per kwu
+    #[arg(long)]
+    prepared_tx_fee_rate: u64,
+    /// Trade amount, in sats
+    #[arg(long)]
+    trade_amount: u64,
+    /// Buyer's security deposit, in sats
+    #[arg(long)]
+    buyers_security_deposit: u64,
+    /// Seller's security deposit, in sats
+    #[arg(long)]
+    sellers_security_deposit: u64,
+    /// Address to pay the trade fee to, if any
+    #[arg(long, requires = "trade_fee_amount")]
+    trade_fee_address: Option<String>,
+    /// Trade fee, in sats
+    #[arg(long, requires = "trade_fee_address")]
+    trade_fee_amount: Option<u64>,
+    /// Additional adaptor point to lock the swap tx signature to, for atomic swaps
+    #[arg(long, value_parser = parse_hex)]
+    adaptor_point_hex: Option<Vec<u8>>,
+    /// The secret of the adaptor point (for the buyer only, who locks its side of the swap with it)
+    #[arg(long, value_parser = parse_hex, requires = "adaptor_point_hex")]
+    adaptor_secret_hex: Option<Vec<u8>>,
+    #[arg(long)]
+    sequence_number: u64,
+}
+
+#[derive(Debug, Subcommand)]
+enum WalletCommands {
+    /// Compute and display the wallet's current balance
+    Balance,
+    /// Generate a new address
+    Address,
+    /// List utxos available for spending
+    Utxos,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Role {
+    SellerAsMaker,
+    SellerAsTaker,
+    BuyerAsMaker,
+    BuyerAsTaker,
+}
+
+impl From<Role> for musigrpc::Role {
+    fn from(value: Role) -> Self {
+        match value {
+            Role::SellerAsMaker => Self::SellerAsMaker,
+            Role::SellerAsTaker => Self::SellerAsTaker,
+            Role::BuyerAsMaker => Self::BuyerAsMaker,
+            Role::BuyerAsTaker => Self::BuyerAsTaker,
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, hex::FromHexError> { hex::decode(s) }
+
+async fn connect(cli: &Cli) -> Result<Channel> {
+    let scheme = if cli.tls_cert.is_some() { "https" } else { "http" };
+    let mut endpoint = Channel::from_shared(format!("{scheme}://{}:{}", cli.host, cli.port))?;
+    if let Some(tls_cert) = &cli.tls_cert {
+        let ca_certificate = Certificate::from_pem(std::fs::read(tls_cert)?);
+        endpoint = endpoint.tls_config(ClientTlsConfig::new().ca_certificate(ca_certificate))?;
+    }
+    Ok(endpoint.connect().await?)
+}
+
+async fn replay_log(file: &Path) -> Result<()> {
+    let musig = MusigImpl::new(Arc::new(WalletServiceImpl::new()));
+    let (mut replayed, mut mismatched) = (0, 0);
+    for (i, entry) in MessageLog::read_entries(file)?.enumerate() {
+        match replay_entry(&musig, &entry).await {
+            ReplayOutcome::Matched => replayed += 1,
+            ReplayOutcome::Mismatched { recorded, replayed: actual } => {
+                replayed += 1;
+                mismatched += 1;
+                println!("Entry {}: {} outcome mismatch: recorded {recorded:?}, replayed {actual:?}", i + 1, entry.method);
+            }
+            ReplayOutcome::Skipped => println!("Entry {}: skipped {}", i + 1, entry.method),
+            _ => {}
+        }
+    }
+    println!("Replayed {replayed} requests, of which {mismatched} mismatched.");
+    if mismatched > 0 {
+        bail!("replay did not match the message log");
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli: Cli = Cli::parse();
+    if let Commands::DumpStateDiagram = cli.commands {
+        print!("{}", rpc::docs::state_diagram_dot());
+        return Ok(());
+    }
+    if let Commands::ReplayLog { file } = &cli.commands {
+        return replay_log(file).await;
+    }
+    let channel = connect(&cli).await?;
+
+    match cli.commands {
+        Commands::InitTrade { trade_id, role, sequence_number, wallet_id } => {
+            let request = PubKeySharesRequest {
+                trade_id,
+                my_role: musigrpc::Role::from(role).into(),
+                sequence_number,
+                protocol_version: MAX_SUPPORTED_VERSION,
+                wallet_id,
+            };
+            let response = MusigClient::new(channel).init_trade(request).await?.into_inner();
+            println!("{}", serde_json::to_string_pretty(&response)?);
+        }
+        Commands::GetNonceShares(args) => {
+            let trade_fee_receiver = args.trade_fee_address.zip(args.trade_fee_amount)
+                .map(|(address, amount)| ReceiverAddressAndAmount { address, amount });
+            let request = NonceSharesRequest {
+                trade_id: args.trade_id,
+                buyer_output_peers_pub_key_share: args.buyer_output_peers_pub_key_share,
+                seller_output_peers_pub_key_share: args.seller_output_peers_pub_key_share,
+                peers_multisig_script_key: args.peers_multisig_script_key,
+                deposit_tx_fee_rate: args.deposit_tx_fee_rate,
+                prepared_tx_fee_rate: args.prepared_tx_fee_rate,
+                trade_amount: args.trade_amount,
+                buyers_security_deposit: args.buyers_security_deposit,
+                sellers_security_deposit: args.sellers_security_deposit,
+                trade_fee_receiver,
+                sequence_number: args.sequence_number,
+                adaptor_point: args.adaptor_point_hex,
+                adaptor_secret: args.adaptor_secret_hex,
+            };
+            let response = MusigClient::new(channel).get_nonce_shares(request).await?.into_inner();
+            println!("{}", serde_json::to_string_pretty(&response)?);
+        }
+        Commands::SignDepositTx { trade_id, peers_partial_signatures, sequence_number } => {
+            let request = DepositTxSignatureRequest {
+                trade_id,
+                peers_partial_signatures: Some(serde_json::from_str(&peers_partial_signatures)?),
+                sequence_number,
+            };
+            let response = MusigClient::new(channel).sign_deposit_tx(request).await?.into_inner();
+            println!("{}", serde_json::to_string_pretty(&response)?);
+        }
+        Commands::CloseTrade { trade_id, peers_prv_key_share_hex, swap_tx_hex, sequence_number } => {
+            let request = CloseTradeRequest {
+                trade_id,
+                my_output_peers_prv_key_share: peers_prv_key_share_hex,
+                swap_tx: swap_tx_hex,
+                sequence_number,
+            };
+            let response = MusigClient::new(channel).close_trade(request).await?.into_inner();
+            println!("{}", serde_json::to_string_pretty(&response)?);
+        }
+        Commands::Wallet(WalletCommands::Balance) => {
+            let response = WalletClient::new(channel).wallet_balance(WalletBalanceRequest::default()).await?.into_inner();
+            println!("{}", serde_json::to_string_pretty(&response)?);
+        }
+        Commands::Wallet(WalletCommands::Address) => {
+            let response = WalletClient::new(channel).new_address(NewAddressRequest::default()).await?.into_inner();
+            println!("{}", serde_json::to_string_pretty(&response)?);
+        }
+        Commands::Wallet(WalletCommands::Utxos) => {
+            let response = WalletClient::new(channel).list_unspent(ListUnspentRequest::default()).await?.into_inner();
+            println!("{}", serde_json::to_string_pretty(&response)?);
+        }
+        Commands::DumpStateDiagram | Commands::ReplayLog { .. } => unreachable!("handled before connecting"),
+    }
+    Ok(())
+}
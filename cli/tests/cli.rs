@@ -0,0 +1,143 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use assert_cmd::assert::Assert;
+use assert_cmd::cargo::cargo_bin_cmd;
+use predicates::prelude::PredicateBooleanExt as _;
+use predicates::str;
+use rpc::server::{MusigImpl, MusigServer, WalletImpl, WalletServer};
+use rpc::wallet::WalletServiceImpl;
+use testenv::TestEnv;
+use tokio::net::TcpListener;
+use tokio::task::{self, JoinHandle};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{self, Server};
+
+const CLI_TIMEOUT: Duration = Duration::from_millis(500);
+
+const EXPECTED_WALLET_BALANCE_RESPONSE: &str = r#"{
+  "immature": 0,
+  "trustedPending": 0,
+  "untrustedPending": 0,
+  "confirmed": 0
+}
+"#;
+
+#[test]
+fn test_cli_usage() {
+    assert_cli([])
+        .code(2)
+        .stdout(str::is_empty())
+        .stderr(str::starts_with("Usage:"));
+}
+
+#[test]
+fn test_cli_bad_hex_arg() {
+    assert_cli(["close-trade", "--trade-id", "t1", "--swap-tx-hex", "xyz", "--sequence-number", "1"])
+        .code(2)
+        .stdout(str::is_empty())
+        .stderr(str::contains("invalid value 'xyz'"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_cli_no_connection() {
+    // Release the port again, so that nothing is listening on it.
+    let (port, listener) = TestEnv::get_bound_port().await.expect("listener");
+    drop(listener);
+
+    task::spawn_blocking(move || assert_cli_with_port(port, ["wallet", "balance"]))
+        .await.unwrap()
+        .code(1)
+        .stdout(str::is_empty())
+        .stderr(str::contains("Connection refused"));
+}
+
+#[test]
+fn test_cli_dump_state_diagram() {
+    assert_cli_with_port(50050, ["dump-state-diagram"])
+        .success()
+        .stdout(str::starts_with("digraph TradeState {")
+            .and(str::contains("DepositTxSigned -> DepositTxPublished [label=\"PublishDepositTx\"];")))
+        .stderr(str::is_empty());
+}
+
+#[test]
+fn test_cli_replay_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("message-log.jsonl");
+    let entry = |error_message: &str| format!(
+        r#"{{"timestamp_unix_ms":0,"method":"musig_initTrade","request":{{"tradeId":"bad trade id","myRole":"SELLER_AS_MAKER","sequenceNumber":0,"protocolVersion":1}},"error":{{"code":3,"message":"{error_message}"}}}}"#);
+    let path_arg = path.to_str().unwrap();
+
+    std::fs::write(&path, entry("trade id has invalid chars")).unwrap();
+    assert_cli(["replay-log", "--file", path_arg])
+        .success()
+        .stdout("Replayed 1 requests, of which 0 mismatched.\n");
+
+    std::fs::write(&path, entry("some other error")).unwrap();
+    assert_cli(["replay-log", "--file", path_arg])
+        .code(1)
+        .stdout(str::starts_with("Entry 1: musig_initTrade outcome mismatch:"))
+        .stderr(str::contains("replay did not match the message log"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_cli_wallet_balance() {
+    let (port, listener) = TestEnv::get_bound_port().await.expect("listener");
+    spawn_grpc_services(listener);
+
+    task::spawn_blocking(move || assert_cli_with_port(port, ["wallet", "balance"]))
+        .await.unwrap()
+        .success()
+        .stdout(EXPECTED_WALLET_BALANCE_RESPONSE)
+        .stderr(str::is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_cli_init_trade() {
+    let (port, listener) = TestEnv::get_bound_port().await.expect("listener");
+    spawn_grpc_services(listener);
+
+    task::spawn_blocking(move || assert_cli_with_port(port, ["init-trade",
+        "--trade-id", "cli-init-trade", "--role", "seller-as-maker"]))
+        .await.unwrap()
+        .success()
+        .stdout(str::contains("\"buyerOutputPubKeyShare\"")
+            .and(str::contains("\"sellerOutputPubKeyShare\""))
+            .and(str::contains("\"currentBlockHeight\"")))
+        .stderr(str::is_empty());
+}
+
+fn assert_cli<'a>(args: impl IntoIterator<Item = &'a str>) -> Assert {
+    cargo_bin_cmd!("bisq-musig-cli")
+        .env_remove("BISQ_MUSIG_HOST")
+        .env_remove("BISQ_MUSIG_PORT")
+        .env_remove("BISQ_MUSIG_TLS_CERT")
+        .args(args)
+        .timeout(CLI_TIMEOUT)
+        .assert()
+}
+
+fn assert_cli_with_port<'a>(port: u16, args: impl IntoIterator<Item = &'a str>) -> Assert {
+    let port = port.to_string();
+    #[expect(clippy::map_identity, reason = "change-of-lifetime false positive; see \
+        https://github.com/rust-lang/rust-clippy/issues/9280")]
+    let args = ["--port", &port].into_iter()
+        .chain(args.into_iter().map(|s| s));
+    assert_cli(args)
+}
+
+fn spawn_grpc_services(listener: TcpListener) -> JoinHandle<Result<(), transport::Error>> {
+    let wallet_service = Arc::new(WalletServiceImpl::new());
+    let musig = MusigImpl::new(wallet_service.clone());
+    let wallet = WalletImpl::new(wallet_service);
+    let incoming = TcpIncoming::from(listener);
+
+    task::spawn(async move {
+        Server::builder()
+            .add_service(MusigServer::new(musig))
+            .add_service(WalletServer::new(wallet))
+            .serve_with_incoming(incoming)
+            .await
+    })
+}
@@ -0,0 +1,4 @@
+fn main() {
+    #[cfg(feature = "mobile")]
+    uniffi::generate_scaffolding("src/bisq_musig.udl").unwrap();
+}
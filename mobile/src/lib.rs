@@ -0,0 +1,107 @@
+//! `UniFFI` bindings for the trade protocol, so that the Bisq mobile clients may run it on-device
+//! (with the `mobile` feature).
+//!
+//! The interface is defined in `bisq_musig.udl`. Each trade step calls the very same step function
+//! as the daemon's gRPC handler (see `rpc::server::steps`), taking the JSON of its gRPC request and
+//! returning that of its response. Generate the Kotlin (Android) & Swift (iOS) wrappers from the
+//! built library, with the `uniffi-bindgen` binary, as described in the README.
+#![cfg(feature = "mobile")]
+#![expect(unsafe_attr_outside_unsafe, clippy::empty_line_after_doc_comments, clippy::missing_const_for_fn,
+    reason = "the code generated by UniFFI doesn't follow the workspace lints")]
+
+use std::fmt::Display;
+use std::sync::{Mutex, MutexGuard};
+
+use bdk_wallet::serde::Serialize;
+use bdk_wallet::serde::de::DeserializeOwned;
+use bdk_wallet::serde_json;
+use rpc::protocol;
+use rpc::server::{Config, steps};
+use tonic::{Code, Status};
+
+uniffi::include_scaffolding!("bisq_musig");
+
+type Result<T, E = TradeError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+#[expect(clippy::exhaustive_enums)]
+pub enum TradeError {
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("protocol error: {0}")]
+    Protocol(String),
+}
+
+impl TradeError {
+    fn invalid_argument(e: impl Display) -> Self { Self::InvalidArgument(e.to_string()) }
+
+    fn protocol(e: impl Display) -> Self { Self::Protocol(e.to_string()) }
+}
+
+impl From<Status> for TradeError {
+    fn from(status: Status) -> Self {
+        match status.code() {
+            Code::InvalidArgument | Code::NotFound | Code::OutOfRange => Self::invalid_argument(status.message()),
+            _ => Self::protocol(status.message()),
+        }
+    }
+}
+
+/// One side of a trade, with the trade limits of a daemon of the default config. (The foreign
+/// bindings share it between threads, hence the lock.)
+pub struct TradeModel {
+    config: Config,
+    trade_model: Mutex<protocol::TradeModel>,
+}
+
+impl TradeModel {
+    /// Start a new trade, given the JSON of a `PubKeySharesRequest`.
+    pub fn new(request: &str) -> Result<Self> {
+        let request = serde_json::from_str(request).map_err(TradeError::invalid_argument)?;
+        let trade_model = steps::init_trade(request)?;
+        Ok(Self { config: Config::default(), trade_model: Mutex::new(trade_model) })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, protocol::TradeModel> { self.trade_model.lock().unwrap() }
+
+    pub fn trade_id(&self) -> String { self.lock().trade_id().to_owned() }
+
+    pub fn pub_key_shares(&self) -> Result<String> {
+        to_json(&steps::pub_key_shares_response(&self.config, &self.lock())?)
+    }
+
+    pub fn get_nonce_shares(&self, request: &str) -> Result<String> {
+        run_step(request, |request| steps::get_nonce_shares(&self.config, request, &mut self.lock()))
+    }
+
+    pub fn get_partial_signatures(&self, request: &str) -> Result<String> {
+        run_step(request, |request| steps::get_partial_signatures(&self.config, request, &mut self.lock()))
+    }
+
+    pub fn sign_deposit_tx(&self, request: &str) -> Result<String> {
+        run_step(request, |request| steps::sign_deposit_tx(request, &mut self.lock()))
+    }
+
+    pub fn sign_swap_tx(&self, request: &str) -> Result<String> {
+        run_step(request, |request| steps::sign_swap_tx(request, &mut self.lock()))
+    }
+
+    /// Close the trade, given the peer's private key share for my payout output or (as buyer) the
+    /// signed swap tx. There is no wallet to broadcast the swap tx with, so a seller can't
+    /// force-close the trade here.
+    pub fn close_trade(&self, request: &str) -> Result<String> {
+        run_step(request, |request| steps::close_trade(request, &mut self.lock(), None))
+    }
+}
+
+fn run_step<Req, Res, F>(request: &str, step: F) -> Result<String>
+    where Req: DeserializeOwned,
+          Res: Serialize,
+          F: FnOnce(Req) -> tonic::Result<Res> {
+    let request = serde_json::from_str(request).map_err(TradeError::invalid_argument)?;
+    to_json(&step(request)?)
+}
+
+fn to_json(response: &impl Serialize) -> Result<String> {
+    serde_json::to_string(response).map_err(TradeError::protocol)
+}
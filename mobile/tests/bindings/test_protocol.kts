@@ -0,0 +1,70 @@
+// Runs the full two-party trade protocol on the JVM, through the generated Kotlin bindings, as the
+// Android client would. Run with: cargo test -p bisq_musig_mobile --features bindgen-tests
+
+import uniffi.bisq_musig.*
+
+val redirectionAddress = "bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz"
+val preparedTxFeeRate = 2_500UL
+val p2trOutputWeight = 172UL
+
+// The value of the given top-level string or number field of a JSON message.
+fun field(json: String, name: String): String =
+    Regex("\"$name\":\"?([^\",}]*)").find(json)!!.groupValues[1]
+
+fun initTrade(tradeId: String, role: String) =
+    TradeModel("""{"tradeId":"$tradeId","myRole":"$role","protocolVersion":1}""")
+
+fun nonceSharesRequest(peersPubKeyShares: String) = """{
+    "buyerOutputPeersPubKeyShare":"${field(peersPubKeyShares, "buyerOutputPubKeyShare")}",
+    "sellerOutputPeersPubKeyShare":"${field(peersPubKeyShares, "sellerOutputPubKeyShare")}",
+    "peersMultisigScriptKey":"${field(peersPubKeyShares, "multisigScriptKey")}",
+    "depositTxFeeRate":12500,"preparedTxFeeRate":$preparedTxFeeRate,
+    "tradeAmount":200000,"buyersSecurityDeposit":30000,"sellersSecurityDeposit":30000}"""
+
+// A single redirection receiver taking the whole of the available amount, less its output cost.
+fun partialSignaturesRequest(peersNonceShares: String): String {
+    val availableMsat = field(peersNonceShares, "redirectionAmountMsat").toULong()
+    val amount = (availableMsat - preparedTxFeeRate * p2trOutputWeight) / 1000UL
+    return """{"peersNonceShares":$peersNonceShares,
+        "redirectionReceivers":[{"address":"$redirectionAddress","amount":$amount}]}"""
+}
+
+val seller = initTrade("kotlin-trade-seller", "SELLER_AS_MAKER")
+val buyer = initTrade("kotlin-trade-buyer", "BUYER_AS_TAKER")
+
+// Key share exchange:
+val sellerKeyShares = seller.pubKeyShares()
+val buyerKeyShares = buyer.pubKeyShares()
+
+// Nonce share exchange:
+val sellerNonceShares = seller.getNonceShares(nonceSharesRequest(buyerKeyShares))
+val buyerNonceShares = buyer.getNonceShares(nonceSharesRequest(sellerKeyShares))
+
+// Partial signing:
+val sellerPartialSignatures = seller.getPartialSignatures(partialSignaturesRequest(buyerNonceShares))
+val buyerPartialSignatures = buyer.getPartialSignatures(partialSignaturesRequest(sellerNonceShares))
+assert(field(seller.signDepositTx("""{"peersPartialSignatures":$buyerPartialSignatures}"""), "depositPsbt").isNotEmpty())
+assert(field(buyer.signDepositTx("""{"peersPartialSignatures":$sellerPartialSignatures}"""), "depositPsbt").isNotEmpty())
+
+// Swap tx signing, with the seller ready to release its private key share for the buyer's output:
+val buyersSwapTxSignature = field(buyer.getPartialSignatures("""{"buyerReadyToRelease":true}"""),
+    "swapTxInputPartialSignature")
+val sellersPrvKeyShare = field(seller.signSwapTx(
+    """{"swapTxInputPeersPartialSignature":"$buyersSwapTxSignature","sellerReadyToRelease":true}"""),
+    "peerOutputPrvKeyShare")
+
+// Key recovery, on cooperative close:
+val buyersPrvKeyShare = field(buyer.closeTrade("""{"myOutputPeersPrvKeyShare":"$sellersPrvKeyShare"}"""),
+    "peerOutputPrvKeyShare")
+assert(field(seller.closeTrade("""{"myOutputPeersPrvKeyShare":"$buyersPrvKeyShare"}"""),
+    "peerOutputPrvKeyShare") == sellersPrvKeyShare)
+
+try {
+    initTrade("kotlin-trade-bad-role", "ARBITRATOR")
+    throw RuntimeException("should have thrown")
+} catch (e: TradeException.InvalidArgument) {
+    // Expected.
+}
+
+seller.close()
+buyer.close()
@@ -0,0 +1,117 @@
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+use bdk_wallet::serde_json::{self, Value, json};
+use bisq_musig::{TradeError, TradeModel};
+
+const TRADE_AMOUNT: u64 = 200_000;
+const SECURITY_DEPOSIT: u64 = 30_000;
+const DEPOSIT_TX_FEE_RATE: u64 = 12_500;
+const PREPARED_TX_FEE_RATE: u64 = 2_500;
+
+//noinspection SpellCheckingInspection
+const REDIRECTION_ADDRESS: &str = "bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz";
+const P2TR_OUTPUT_WEIGHT: u64 = 172;
+
+fn parse(json: &str) -> Value { serde_json::from_str(json).unwrap() }
+
+fn init_trade(trade_id: &str, role: &str) -> TradeModel {
+    TradeModel::new(&json!({"tradeId": trade_id, "myRole": role, "protocolVersion": 1}).to_string()).unwrap()
+}
+
+fn nonce_shares_request(peers_pub_key_shares: &str) -> Value {
+    let peers_pub_key_shares = parse(peers_pub_key_shares);
+    json!({
+        "buyerOutputPeersPubKeyShare": peers_pub_key_shares["buyerOutputPubKeyShare"],
+        "sellerOutputPeersPubKeyShare": peers_pub_key_shares["sellerOutputPubKeyShare"],
+        "peersMultisigScriptKey": peers_pub_key_shares["multisigScriptKey"],
+        "depositTxFeeRate": DEPOSIT_TX_FEE_RATE,
+        "preparedTxFeeRate": PREPARED_TX_FEE_RATE,
+        "tradeAmount": TRADE_AMOUNT,
+        "buyersSecurityDeposit": SECURITY_DEPOSIT,
+        "sellersSecurityDeposit": SECURITY_DEPOSIT,
+    })
+}
+
+/// A single redirection receiver taking the whole of the available amount, less its output cost.
+fn partial_signatures_request(peers_nonce_shares: &str) -> String {
+    let peers_nonce_shares = parse(peers_nonce_shares);
+    let available_msat = peers_nonce_shares["redirectionAmountMsat"].as_u64().unwrap();
+    let amount = (available_msat - PREPARED_TX_FEE_RATE * P2TR_OUTPUT_WEIGHT) / 1000;
+    json!({
+        "peersNonceShares": peers_nonce_shares,
+        "redirectionReceivers": [{"address": REDIRECTION_ADDRESS, "amount": amount}],
+    }).to_string()
+}
+
+/// Run the trade protocol between a seller (as maker) and buyer (as taker), up to the point where
+/// both have signed their half of the deposit tx.
+fn sign_deposit_txs(trade_id: &str) -> (TradeModel, TradeModel) {
+    let seller = init_trade(&format!("{trade_id}-seller"), "SELLER_AS_MAKER");
+    let buyer = init_trade(&format!("{trade_id}-buyer"), "BUYER_AS_TAKER");
+
+    let seller_key_shares = seller.pub_key_shares().unwrap();
+    let buyer_key_shares = buyer.pub_key_shares().unwrap();
+
+    let seller_nonce_shares = seller.get_nonce_shares(&nonce_shares_request(&buyer_key_shares).to_string()).unwrap();
+    let buyer_nonce_shares = buyer.get_nonce_shares(&nonce_shares_request(&seller_key_shares).to_string()).unwrap();
+
+    let seller_partial_signatures = seller.get_partial_signatures(&partial_signatures_request(&buyer_nonce_shares)).unwrap();
+    let buyer_partial_signatures = buyer.get_partial_signatures(&partial_signatures_request(&seller_nonce_shares)).unwrap();
+    let deposit_psbt = seller.sign_deposit_tx(&json!({"peersPartialSignatures": parse(&buyer_partial_signatures)})
+        .to_string()).unwrap();
+    assert!(parse(&deposit_psbt)["depositPsbt"].as_str().is_some_and(|psbt| !psbt.is_empty()));
+    buyer.sign_deposit_tx(&json!({"peersPartialSignatures": parse(&seller_partial_signatures)}).to_string()).unwrap();
+    (seller, buyer)
+}
+
+/// Buyer signals readiness to release, sending its partial signature on the swap tx, which the
+/// seller uses to sign the swap tx, returning the seller's signed swap tx request & response.
+fn sign_swap_tx(seller: &TradeModel, buyer: &TradeModel, seller_ready_to_release: bool) -> (String, Value) {
+    let buyer_partial_signatures = parse(&buyer.get_partial_signatures(r#"{"buyerReadyToRelease":true}"#).unwrap());
+    let request = json!({
+        "swapTxInputPeersPartialSignature": buyer_partial_signatures["swapTxInputPartialSignature"],
+        "sellerReadyToRelease": seller_ready_to_release,
+    }).to_string();
+    let response = parse(&seller.sign_swap_tx(&request).unwrap());
+    (request, response)
+}
+
+#[test]
+fn test_cooperative_close() {
+    let (seller, buyer) = sign_deposit_txs("mobile-cooperative-close");
+
+    // The seller's private key share for the buyer's payout output is withheld until it is ready to
+    // release it (on receipt of the payment).
+    let (_, response) = sign_swap_tx(&seller, &buyer, false);
+    assert_eq!(response["peerOutputPrvKeyShare"], "");
+    let (_, response) = sign_swap_tx(&seller, &buyer, true);
+    let sellers_prv_key_share = &response["peerOutputPrvKeyShare"];
+
+    let response = buyer.close_trade(&json!({"myOutputPeersPrvKeyShare": sellers_prv_key_share}).to_string()).unwrap();
+    let buyers_prv_key_share = &parse(&response)["peerOutputPrvKeyShare"];
+    let response = seller.close_trade(&json!({"myOutputPeersPrvKeyShare": buyers_prv_key_share}).to_string()).unwrap();
+    assert_eq!(&parse(&response)["peerOutputPrvKeyShare"], sellers_prv_key_share);
+}
+
+#[test]
+fn test_key_recovery_from_swap_tx() {
+    let (seller, buyer) = sign_deposit_txs("mobile-key-recovery");
+
+    // The seller signs (and publishes) the swap tx, revealing its key share for the buyer's output.
+    let (request, response) = sign_swap_tx(&seller, &buyer, true);
+    let response = buyer.close_trade(&json!({"swapTx": response["swapTx"]}).to_string()).unwrap();
+    assert_eq!(BASE64_STANDARD.decode(parse(&response)["peerOutputPrvKeyShare"].as_str().unwrap()).unwrap().len(), 32);
+
+    let Err(TradeError::Protocol(message)) = buyer.sign_swap_tx(&request) else {
+        panic!("buyer should not be able to sign the swap tx");
+    };
+    assert_eq!(message, "this operation is only valid for the Seller role");
+}
+
+#[test]
+fn test_bad_peer_key_share() {
+    let trade = init_trade("mobile-bad-key", "SELLER_AS_MAKER");
+    let mut request = nonce_shares_request(&trade.pub_key_shares().unwrap());
+    request["buyerOutputPeersPubKeyShare"] = BASE64_STANDARD.encode([2]).into();
+    assert!(matches!(trade.get_nonce_shares(&request.to_string()), Err(TradeError::InvalidArgument(_))));
+}
@@ -0,0 +1,3 @@
+#![cfg(feature = "bindgen-tests")]
+
+uniffi::build_foreign_language_testcases!("tests/bindings/test_protocol.kts");
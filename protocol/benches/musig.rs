@@ -0,0 +1,99 @@
+//! Benchmarks of the `MuSig2` operations performed by each trader for every multisig input of the
+//! trade txs. (The `TradeModel` in the `rpc` crate repeats these for each of the swap, warning,
+//! redirect & claim txs, so its per-request latency is dominated by them.)
+//!
+//! Run with `cargo bench -p protocol`. Criterion reports the median time of each operation, and its
+//! throughput in operations per second.
+
+use std::hint::black_box;
+
+use bdk_wallet::bitcoin::TapSighash;
+use bdk_wallet::bitcoin::hashes::Hash as _;
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use protocol::multisig::{KeyCtx, SigCtx};
+
+/// Both traders' key contexts, with public key shares exchanged but not yet aggregated.
+fn key_ctx_pair() -> [KeyCtx; 2] {
+    let [mut alice, mut bob] = [KeyCtx::default(), KeyCtx::default()];
+    let alice_pub_key = *alice.init_my_key_share().pub_key();
+    let bob_pub_key = *bob.init_my_key_share().pub_key();
+    alice.set_peers_pub_key(bob_pub_key);
+    bob.set_peers_pub_key(alice_pub_key);
+    [alice, bob]
+}
+
+/// Both traders' signing contexts for a keyspend of the aggregated key, with no nonces generated.
+fn sig_ctx_pair() -> [SigCtx; 2] {
+    key_ctx_pair().map(|mut key_ctx| {
+        key_ctx.aggregate_pub_key_shares().unwrap();
+        let mut sig_ctx = SigCtx::default();
+        sig_ctx.set_tweaked_key_ctx(key_ctx.with_taproot_tweak(None).unwrap());
+        sig_ctx
+    })
+}
+
+/// Both traders' signing contexts, with nonce shares exchanged but not yet aggregated.
+fn sig_ctx_pair_with_nonce_shares() -> [SigCtx; 2] {
+    let [mut alice, mut bob] = sig_ctx_pair();
+    alice.init_my_nonce_share().unwrap();
+    bob.init_my_nonce_share().unwrap();
+    alice.set_peers_nonce_share(bob.my_nonce_share().unwrap().clone());
+    bob.set_peers_nonce_share(alice.my_nonce_share().unwrap().clone());
+    [alice, bob]
+}
+
+/// Both traders' signing contexts, ready to sign.
+fn sig_ctx_pair_with_agg_nonce() -> [SigCtx; 2] {
+    sig_ctx_pair_with_nonce_shares().map(|mut sig_ctx| {
+        sig_ctx.aggregate_nonce_shares().unwrap();
+        sig_ctx
+    })
+}
+
+/// Alice's signing context, with both partial signatures on the message but not yet aggregated.
+fn sig_ctx_with_partial_sigs() -> SigCtx {
+    let [mut alice, mut bob] = sig_ctx_pair_with_agg_nonce();
+    alice.sign_partial(message()).unwrap();
+    let bob_partial_sig = *bob.sign_partial(message()).unwrap();
+    alice.set_peers_partial_sig(bob_partial_sig);
+    alice
+}
+
+fn message() -> TapSighash { TapSighash::from_byte_array([0x42; 32]) }
+
+fn alice<T>([alice, _bob]: [T; 2]) -> T { alice }
+
+fn bench_musig(c: &mut Criterion) {
+    let mut group = c.benchmark_group("musig");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("aggregate_key_shares", |b| b.iter_batched_ref(
+        || alice(key_ctx_pair()),
+        |key_ctx| key_ctx.aggregate_pub_key_shares().unwrap(),
+        BatchSize::SmallInput,
+    ));
+    group.bench_function("init_my_nonce_share", |b| b.iter_batched_ref(
+        || alice(sig_ctx_pair()),
+        |sig_ctx| sig_ctx.init_my_nonce_share().unwrap(),
+        BatchSize::SmallInput,
+    ));
+    group.bench_function("aggregate_nonce_shares", |b| b.iter_batched_ref(
+        || alice(sig_ctx_pair_with_nonce_shares()),
+        |sig_ctx| { sig_ctx.aggregate_nonce_shares().unwrap(); },
+        BatchSize::SmallInput,
+    ));
+    group.bench_function("sign_partial", |b| b.iter_batched_ref(
+        || alice(sig_ctx_pair_with_agg_nonce()),
+        |sig_ctx| *sig_ctx.sign_partial(black_box(message())).unwrap(),
+        BatchSize::SmallInput,
+    ));
+    group.bench_function("aggregate_partial_signatures", |b| b.iter_batched_ref(
+        sig_ctx_with_partial_sigs,
+        |sig_ctx| *sig_ctx.aggregate_partial_signatures().unwrap(),
+        BatchSize::SmallInput,
+    ));
+    group.finish();
+}
+
+criterion_group!(benches, bench_musig);
+criterion_main!(benches);
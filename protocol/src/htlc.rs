@@ -0,0 +1,312 @@
+//! Hash time-locked contracts (HTLCs), for cross-chain atomic swaps in which the counterparty can't
+//! take part in a multisig key-path spend. The HTLC is a single-leaf Taproot output, with a provably
+//! unspendable internal key, paying the recipient on revealing the preimage of the payment hash, or
+//! else refunding the sender once the timeout has passed.
+
+use bdk_wallet::bitcoin::hashes::{Hash as _, sha256};
+use bdk_wallet::bitcoin::key::{Keypair, Secp256k1};
+use bdk_wallet::bitcoin::opcodes::all::{
+    OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CLTV, OP_ELSE, OP_ENDIF, OP_EQUAL, OP_EQUALVERIFY, OP_NOTIF,
+    OP_SHA256, OP_SIZE,
+};
+use bdk_wallet::bitcoin::secp256k1::{Message, PublicKey};
+use bdk_wallet::bitcoin::sighash::{Prevouts, SighashCache};
+use bdk_wallet::bitcoin::taproot::{
+    LeafVersion, Signature, TapLeafHash, TaprootBuilder, TaprootSpendInfo,
+};
+use bdk_wallet::bitcoin::transaction::Version;
+use bdk_wallet::bitcoin::{
+    Address, Network, OutPoint, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut,
+    Witness, absolute, script,
+};
+
+use crate::transaction::{Result, TransactionErrorKind};
+
+/// The BIP 341 "nothing up my sleeve" point, with no known discrete log, so that the HTLC output
+/// can only be spent through its script path.
+const UNSPENDABLE_INTERNAL_KEY: &str =
+    "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// The HTLC leaf script, paying `recipient` with the preimage of `payment_hash`, or `refund_key`
+/// from the (block height or time based) `timeout` onwards. Only the x-only parts of the keys go
+/// into the script, as Taproot leaf scripts take BIP 340 keys.
+pub fn build_htlc_script(
+    recipient: &PublicKey,
+    refund_key: &PublicKey,
+    payment_hash: [u8; 32],
+    timeout: absolute::LockTime,
+) -> ScriptBuf {
+    // Comes from miniscript policy:
+    //   format!("or(and(pk({recipient}),sha256({payment_hash})),and(pk({refund_key}),after({timeout})))")
+    // which compiles to miniscript:
+    //   format!("andor(pk({recipient}),sha256({payment_hash}),and_v(v:pk({refund_key}),after({timeout})))")
+    let (recipient, refund_key) = (
+        recipient.x_only_public_key().0,
+        refund_key.x_only_public_key().0,
+    );
+    script::Builder::new()
+        .push_x_only_key(&recipient)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_NOTIF)
+        .push_x_only_key(&refund_key)
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .push_lock_time(timeout)
+        .push_opcode(OP_CLTV)
+        .push_opcode(OP_ELSE)
+        .push_opcode(OP_SIZE)
+        .push_int(32)
+        .push_opcode(OP_EQUALVERIFY)
+        .push_opcode(OP_SHA256)
+        .push_slice(payment_hash)
+        .push_opcode(OP_EQUAL)
+        .push_opcode(OP_ENDIF)
+        .into_script()
+}
+
+pub fn htlc_spend_info(htlc_script: ScriptBuf) -> TaprootSpendInfo {
+    let internal_key = UNSPENDABLE_INTERNAL_KEY
+        .parse()
+        .expect("hardcoded key should be valid");
+    TaprootBuilder::with_capacity(1)
+        .add_leaf(0, htlc_script)
+        .expect("hardcoded TapTree build sequence should be valid")
+        .finalize(&Secp256k1::verification_only(), internal_key)
+        .expect("hardcoded TapTree build sequence should be complete")
+}
+
+pub fn htlc_address(htlc_script: ScriptBuf, network: Network) -> Address {
+    let spend_info = htlc_spend_info(htlc_script);
+    Address::p2tr_tweaked(spend_info.output_key(), network)
+}
+
+/// Sign a tx claiming the HTLC output for the recipient, revealing the preimage of its payment hash.
+pub fn build_htlc_redeem_tx(
+    htlc_outpoint: OutPoint,
+    htlc_prevout: &TxOut,
+    htlc_script: &ScriptBuf,
+    preimage: [u8; 32],
+    recipient_key: &Keypair,
+    payout: TxOut,
+) -> Result<Transaction> {
+    let tx = unsigned_spend_tx(htlc_outpoint, absolute::LockTime::ZERO, payout);
+    let signature = sign_script_spend(&tx, htlc_prevout, htlc_script, recipient_key)?;
+    with_script_spend_witness(tx, htlc_script, [&preimage[..], &signature.serialize()])
+}
+
+/// Sign a tx refunding the HTLC output to the sender, which only becomes final at the timeout.
+pub fn build_htlc_refund_tx(
+    htlc_outpoint: OutPoint,
+    htlc_prevout: &TxOut,
+    htlc_script: &ScriptBuf,
+    timeout: absolute::LockTime,
+    refund_key: &Keypair,
+    payout: TxOut,
+) -> Result<Transaction> {
+    let tx = unsigned_spend_tx(htlc_outpoint, timeout, payout);
+    let signature = sign_script_spend(&tx, htlc_prevout, htlc_script, refund_key)?;
+    // The empty signature fails the recipient's check, selecting the refund branch.
+    with_script_spend_witness(tx, htlc_script, [&signature.serialize()[..], &[]])
+}
+
+pub fn payment_hash(preimage: &[u8; 32]) -> [u8; 32] {
+    sha256::Hash::hash(preimage).to_byte_array()
+}
+
+fn unsigned_spend_tx(
+    htlc_outpoint: OutPoint,
+    lock_time: absolute::LockTime,
+    payout: TxOut,
+) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: htlc_outpoint,
+            // A non-final sequence number is needed for the lock time to be enforced.
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            ..TxIn::default()
+        }],
+        output: vec![payout],
+    }
+}
+
+fn sign_script_spend(
+    tx: &Transaction,
+    htlc_prevout: &TxOut,
+    htlc_script: &ScriptBuf,
+    keypair: &Keypair,
+) -> Result<Signature> {
+    let leaf_hash = TapLeafHash::from_script(htlc_script, LeafVersion::TapScript);
+    let sighash = SighashCache::new(tx).taproot_script_spend_signature_hash(
+        0,
+        &Prevouts::All(&[htlc_prevout]),
+        leaf_hash,
+        TapSighashType::Default,
+    )?;
+    let signature = Secp256k1::signing_only().sign_schnorr(&Message::from(sighash), keypair);
+    Ok(Signature {
+        signature,
+        sighash_type: TapSighashType::Default,
+    })
+}
+
+fn with_script_spend_witness<const N: usize>(
+    mut tx: Transaction,
+    htlc_script: &ScriptBuf,
+    stack: [&[u8]; N],
+) -> Result<Transaction> {
+    let control_block = htlc_spend_info(htlc_script.clone())
+        .control_block(&(htlc_script.clone(), LeafVersion::TapScript))
+        .ok_or(TransactionErrorKind::MissingScriptLeaf)?;
+    let mut witness = Witness::from_slice(&stack);
+    witness.push(htlc_script.as_bytes());
+    witness.push(control_block.serialize());
+    tx.input[0].witness = witness;
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk_wallet::bitcoin::secp256k1::SecretKey;
+    use bdk_wallet::bitcoin::{Amount, XOnlyPublicKey};
+    use bdk_wallet::miniscript::interpreter::Interpreter;
+    use bdk_wallet::miniscript::{Miniscript, Tap};
+
+    use super::*;
+
+    struct Fixture {
+        recipient_key: Keypair,
+        refund_key: Keypair,
+        preimage: [u8; 32],
+        timeout: absolute::LockTime,
+        htlc_script: ScriptBuf,
+        htlc_prevout: TxOut,
+        payout: TxOut,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let secp = Secp256k1::new();
+            let keypair =
+                |b| Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[b; 32]).unwrap());
+            let (recipient_key, refund_key) = (keypair(1), keypair(2));
+            let preimage = [3; 32];
+            let timeout = absolute::LockTime::from_height(900_000).unwrap();
+            let htlc_script = build_htlc_script(
+                &recipient_key.public_key(),
+                &refund_key.public_key(),
+                payment_hash(&preimage),
+                timeout,
+            );
+            let htlc_prevout = TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: htlc_address(htlc_script.clone(), Network::Regtest).script_pubkey(),
+            };
+            let payout = TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: ScriptBuf::new_p2a(),
+            };
+            Self {
+                recipient_key,
+                refund_key,
+                preimage,
+                timeout,
+                htlc_script,
+                htlc_prevout,
+                payout,
+            }
+        }
+
+        /// Run the script interpreter over the given spend of the HTLC output, checking signatures.
+        fn verify(&self, tx: &Transaction) -> bool {
+            let tx_in = &tx.input[0];
+            let interpreter = Interpreter::from_txdata(
+                &self.htlc_prevout.script_pubkey,
+                &tx_in.script_sig,
+                &tx_in.witness,
+                tx_in.sequence,
+                tx.lock_time,
+            )
+            .unwrap();
+            let prevouts = Prevouts::All(&[&self.htlc_prevout]);
+            let secp = Secp256k1::verification_only();
+            let mut satisfied = interpreter.iter(&secp, tx, 0, &prevouts);
+            satisfied.all(|result| result.is_ok())
+        }
+    }
+
+    #[test]
+    fn htlc_script_matches_miniscript() {
+        let f = Fixture::new();
+        let (recipient, refund_key) = (
+            f.recipient_key.x_only_public_key().0,
+            f.refund_key.x_only_public_key().0,
+        );
+        let payment_hash = sha256::Hash::hash(&f.preimage);
+        let htlc_ms = format!(
+            "andor(pk({recipient}),sha256({payment_hash}),and_v(v:pk({refund_key}),after(900000)))"
+        )
+        .parse::<Miniscript<XOnlyPublicKey, Tap>>()
+        .unwrap();
+        assert_eq!(htlc_ms.encode(), f.htlc_script);
+    }
+
+    #[test]
+    fn htlc_redeem_and_refund_txs_satisfy_script() {
+        let f = Fixture::new();
+        let outpoint = OutPoint::null();
+
+        let redeem_tx = build_htlc_redeem_tx(
+            outpoint,
+            &f.htlc_prevout,
+            &f.htlc_script,
+            f.preimage,
+            &f.recipient_key,
+            f.payout.clone(),
+        )
+        .unwrap();
+        assert!(f.verify(&redeem_tx));
+        let wrong_preimage_tx = build_htlc_redeem_tx(
+            outpoint,
+            &f.htlc_prevout,
+            &f.htlc_script,
+            [4; 32],
+            &f.recipient_key,
+            f.payout.clone(),
+        )
+        .unwrap();
+        assert!(!f.verify(&wrong_preimage_tx));
+
+        let refund_tx = build_htlc_refund_tx(
+            outpoint,
+            &f.htlc_prevout,
+            &f.htlc_script,
+            f.timeout,
+            &f.refund_key,
+            f.payout.clone(),
+        )
+        .unwrap();
+        assert_eq!(refund_tx.lock_time, f.timeout);
+        assert!(f.verify(&refund_tx));
+        let early_refund_tx = build_htlc_refund_tx(
+            outpoint,
+            &f.htlc_prevout,
+            &f.htlc_script,
+            absolute::LockTime::from_height(899_999).unwrap(),
+            &f.refund_key,
+            f.payout.clone(),
+        )
+        .unwrap();
+        assert!(!f.verify(&early_refund_tx));
+        let wrong_key_tx = build_htlc_refund_tx(
+            outpoint,
+            &f.htlc_prevout,
+            &f.htlc_script,
+            f.timeout,
+            &f.recipient_key,
+            f.payout.clone(),
+        )
+        .unwrap();
+        assert!(!f.verify(&wrong_key_tx));
+    }
+}
@@ -1,7 +1,9 @@
+pub mod htlc;
 pub mod mocks;
 pub mod multisig;
 pub mod protocol_musig_adaptor;
 mod psbt;
+pub mod psbt_v2;
 pub mod receiver;
 pub mod script_paths;
 mod swap;
@@ -1,25 +1,41 @@
-use bdk_wallet::bitcoin::hashes::Hash as _;
+use std::collections::VecDeque;
+
+use bdk_wallet::bitcoin::hashes::{Hash as _, sha256};
 use bdk_wallet::bitcoin::key::TweakedPublicKey;
 use bdk_wallet::bitcoin::taproot::Signature;
 use bdk_wallet::bitcoin::{Address, Network, PublicKey, TapNodeHash, TapSighash};
 use musig2::adaptor::AdaptorSignature;
 use musig2::secp::{MaybePoint, MaybeScalar, Point, Scalar};
 use musig2::{
-    AggNonce, KeyAggContext, LiftedSignature, NonceSeed, PartialSignature, PubNonce, SecNonce,
-    SecNonceBuilder,
+    AggNonce, BinaryEncoding as _, KeyAggContext, LiftedSignature, NonceSeed, PartialSignature,
+    PubNonce, SEC_NONCE_SIZE, SecNonce, SecNonceBuilder,
 };
 use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Raw private key bytes, wiped from memory when dropped. Secret scalars are only held in this
+/// form, so that no copies linger in a `KeyCtx` or `SigCtx` (and thus the trade model owning
+/// them) once it goes away.
+type SecretBytes = Zeroizing<[u8; 32]>;
+
+fn to_secret_bytes(prv_key: Scalar) -> SecretBytes {
+    Zeroizing::new(prv_key.serialize())
+}
+
+fn from_secret_bytes(bytes: &SecretBytes) -> Scalar {
+    Scalar::from_slice(bytes.as_ref()).expect("secret bytes should encode a valid scalar")
+}
 
 pub struct KeyPair {
     pub_key: Point,
-    prv_key: Option<Scalar>,
+    prv_key: Option<SecretBytes>,
 }
 
 impl KeyPair {
     pub const fn pub_key(&self) -> &Point { &self.pub_key }
 
-    pub fn prv_key(&self) -> Result<&Scalar> {
-        self.prv_key.as_ref().ok_or(MultisigErrorKind::MissingPrvKey)
+    pub fn prv_key(&self) -> Result<Scalar> {
+        self.prv_key.as_ref().map(from_secret_bytes).ok_or(MultisigErrorKind::MissingPrvKey)
     }
 
     const fn from_public(pub_key: Point) -> Self {
@@ -27,24 +43,33 @@ impl KeyPair {
     }
 
     fn from_private(prv_key: Scalar) -> Self {
-        Self { pub_key: prv_key.base_point_mul(), prv_key: Some(prv_key) }
+        Self { pub_key: prv_key.base_point_mul(), prv_key: Some(to_secret_bytes(prv_key)) }
     }
 
     fn random<R: rand::RngCore + rand::CryptoRng>(rng: &mut R) -> Self {
         Self::from_private(Scalar::random(rng))
     }
 
-    fn set_prv_key(&mut self, prv_key: Scalar) -> Result<&Scalar> {
+    fn set_prv_key(&mut self, prv_key: Scalar) -> Result<Scalar> {
         if self.pub_key != prv_key.base_point_mul() {
             return Err(MultisigErrorKind::MismatchedKeyPair);
         }
-        Ok(self.prv_key.insert(prv_key))
+        self.prv_key = Some(to_secret_bytes(prv_key));
+        Ok(prv_key)
+    }
+
+    fn check_integrity(&self) -> Result<()> {
+        match self.prv_key {
+            Some(ref bytes) if from_secret_bytes(bytes).base_point_mul() != self.pub_key =>
+                Err(MultisigErrorKind::MismatchedKeyPair),
+            _ => Ok(())
+        }
     }
 }
 
 struct NoncePair {
     pub_nonce: PubNonce,
-    sec_nonce: Option<SecNonce>,
+    sec_nonce: Option<Zeroizing<[u8; SEC_NONCE_SIZE]>>,
 }
 
 impl NoncePair {
@@ -52,16 +77,66 @@ impl NoncePair {
         let sec_nonce = SecNonceBuilder::from_seckey(nonce_seed, seckey)
             .with_aggregated_pubkey(aggregated_pubkey)
             .build();
-        Self { pub_nonce: sec_nonce.public_nonce(), sec_nonce: Some(sec_nonce) }
+        Self::from_sec_nonce(&sec_nonce)
+    }
+
+    fn from_sec_nonce(sec_nonce: &SecNonce) -> Self {
+        Self { pub_nonce: sec_nonce.public_nonce(), sec_nonce: Some(Zeroizing::new(sec_nonce.to_bytes())) }
+    }
+
+    fn take_sec_nonce(&mut self) -> Option<SecNonce> {
+        let bytes = self.sec_nonce.take()?;
+        Some(SecNonce::from_bytes(bytes.as_ref()).expect("secret nonce bytes should be well formed"))
     }
 }
 
+/// Generate `count` secret nonces at once, each seeded from a cryptographically secure RNG and bound
+/// to the given private key share, so that they may be drawn on later without delay. (Unlike nonces
+/// generated on demand, they cannot commit to the aggregated key, which isn't yet known.)
+pub fn pre_generate_nonces(seckey: Scalar, count: usize) -> Vec<SecNonce> {
+    let mut rng = rand::rng();
+    (0..count).map(|_| SecNonceBuilder::from_seckey(&mut rng, seckey).build()).collect()
+}
+
+/// Secret nonces generated ahead of the signing sessions that use them, keyed by the public key
+/// share each is bound to. Each is removed from the pool as it is drawn, so is only ever used once.
+#[derive(Default)]
+pub struct NoncePool {
+    nonces: VecDeque<(Point, Zeroizing<[u8; SEC_NONCE_SIZE]>)>,
+}
+
+impl NoncePool {
+    /// Add `count` fresh nonces, bound to the private key of the given key pair, to the pool.
+    pub fn fill(&mut self, key_pair: &KeyPair, count: usize) -> Result<()> {
+        let nonces = pre_generate_nonces(key_pair.prv_key()?, count);
+        self.nonces.extend(nonces.iter()
+            .map(|sec_nonce| (key_pair.pub_key, Zeroizing::new(sec_nonce.to_bytes()))));
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize { self.nonces.len() }
+
+    pub fn is_empty(&self) -> bool { self.nonces.is_empty() }
+
+    /// Remove the first nonce in the pool bound to the given public key share, if any.
+    fn take(&mut self, pub_key: &Point) -> Option<SecNonce> {
+        let index = self.nonces.iter().position(|(p, _)| p == pub_key)?;
+        let (_, bytes) = self.nonces.remove(index)?;
+        Some(SecNonce::from_bytes(bytes.as_ref()).expect("secret nonce bytes should be well formed"))
+    }
+}
+
+/// The domain separation tag hashed with the Diffie-Hellman secret of two key shares, to derive
+/// the tweak blinding their aggregated key.
+const KEY_BLINDING_TAG: &[u8] = b"BisqMuSig/key-blinding";
+
 #[derive(Default)]
 pub struct KeyCtx {
     my_key_share: Option<KeyPair>,
     peers_key_share: Option<KeyPair>,
     aggregated_key: Option<KeyPair>,
     key_agg_ctx: Option<KeyAggContext>,
+    blinding_tweak: Option<SecretBytes>,
 }
 
 impl KeyCtx {
@@ -79,6 +154,11 @@ impl KeyCtx {
         self.peers_key_share.get_or_insert(KeyPair::from_public(pub_key))
     }
 
+    /// Forget the peer's key share and everything aggregated with it, keeping only my key share.
+    pub fn clear_peers_key_share(&mut self) {
+        *self = Self { my_key_share: self.my_key_share.take(), ..Self::default() };
+    }
+
     pub fn peers_key_share(&self) -> Result<&KeyPair> {
         self.peers_key_share.as_ref().ok_or(MultisigErrorKind::MissingKeyShare)
     }
@@ -100,12 +180,37 @@ impl KeyCtx {
         Ok(())
     }
 
+    /// Aggregate the key shares as [`Self::aggregate_pub_key_shares`] does, then blind the result with
+    /// a plain tweak that only the two traders can compute, being derived from the Diffie-Hellman
+    /// secret of their key shares. Whoever sees the public key shares in transit, such as a server
+    /// relaying the trade messages, thus can't link them to the trade's keys on chain. The tweak is
+    /// carried by the key aggregation context, so it is accounted for in signing and in
+    /// [`Self::aggregate_prv_key_shares`]. Both traders must aggregate their shares this way, for
+    /// their aggregated keys to match.
+    pub fn aggregate_blinded_key_shares(&mut self) -> Result<()> {
+        let blinding_tweak = self.compute_blinding_tweak()?;
+        let agg_ctx = KeyAggContext::new(self.key_shares()?.map(|p| *p.pub_key()))?
+            .with_plain_tweak(blinding_tweak)?;
+        self.aggregated_key.get_or_insert(KeyPair::from_public(agg_ctx.aggregated_pubkey()));
+        self.key_agg_ctx = Some(agg_ctx);
+        self.blinding_tweak = Some(to_secret_bytes(blinding_tweak));
+        Ok(())
+    }
+
+    /// The hash of the Diffie-Hellman secret `x_me * P_peer` (equal to `x_peer * P_me`) of the key
+    /// shares, as a scalar.
+    fn compute_blinding_tweak(&self) -> Result<Scalar> {
+        let shared_point = *self.peers_key_share()?.pub_key() * self.my_key_share()?.prv_key()?;
+        let hash = sha256::Hash::hash(&[KEY_BLINDING_TAG, &shared_point.serialize()].concat());
+        Ok(Scalar::reduce_from(&hash.to_byte_array()))
+    }
+
     fn prv_key_shares(&self) -> Result<[Scalar; 2]> {
         let shares = self.key_shares()?;
-        Ok([*shares[0].prv_key()?, *shares[1].prv_key()?])
+        Ok([shares[0].prv_key()?, shares[1].prv_key()?])
     }
 
-    pub fn aggregate_prv_key_shares(&mut self) -> Result<&Scalar> {
+    pub fn aggregate_prv_key_shares(&mut self) -> Result<Scalar> {
         let prv_key_shares = self.prv_key_shares()?;
         let agg_ctx = self.key_agg_ctx.as_ref()
             .ok_or(MultisigErrorKind::MissingAggPubKey)?;
@@ -114,13 +219,33 @@ impl KeyCtx {
         agg_key.set_prv_key(agg_ctx.aggregated_seckey(prv_key_shares)?)
     }
 
-    pub fn set_peers_prv_key(&mut self, prv_key: Scalar) -> Result<&Scalar> {
+    pub fn set_peers_prv_key(&mut self, prv_key: Scalar) -> Result<Scalar> {
         self.peers_key_share.as_mut().ok_or(MultisigErrorKind::MissingKeyShare)?.set_prv_key(prv_key)
     }
 
+    /// Check that each key pair's private key, where known, matches its public key, and that the
+    /// aggregated key, if any, is indeed that of the two key shares (with any blinding tweak).
+    pub fn check_integrity(&self) -> Result<()> {
+        for key_pair in [&self.my_key_share, &self.peers_key_share, &self.aggregated_key].into_iter().flatten() {
+            key_pair.check_integrity()?;
+        }
+        if let Some(ref aggregated_key) = self.aggregated_key {
+            let mut agg_ctx = KeyAggContext::new(self.key_shares()?.map(|p| *p.pub_key()))?;
+            if let Some(ref blinding_tweak) = self.blinding_tweak {
+                agg_ctx = agg_ctx.with_plain_tweak(from_secret_bytes(blinding_tweak))?;
+            }
+            let agg_pub_key: Point = agg_ctx.aggregated_pubkey();
+            let key_agg_ctx = self.key_agg_ctx.as_ref().ok_or(MultisigErrorKind::MissingAggPubKey)?;
+            if aggregated_key.pub_key != agg_pub_key || key_agg_ctx.aggregated_pubkey::<Point>() != agg_pub_key {
+                return Err(MultisigErrorKind::MismatchedAggKey);
+            }
+        }
+        Ok(())
+    }
+
     pub fn with_taproot_tweak(&self, merkle_root: Option<&TapNodeHash>) -> Result<TweakedKeyCtx> {
         let key_agg_ctx = self.compute_tweaked_key_agg_ctx(merkle_root)?;
-        let my_prv_key = *self.my_key_share()?.prv_key()?;
+        let my_prv_key = to_secret_bytes(self.my_key_share()?.prv_key()?);
         Ok(TweakedKeyCtx { my_prv_key, key_agg_ctx })
     }
 
@@ -137,11 +262,13 @@ impl KeyCtx {
 
 #[derive(Clone)]
 pub struct TweakedKeyCtx {
-    my_prv_key: Scalar,
+    my_prv_key: SecretBytes,
     key_agg_ctx: KeyAggContext,
 }
 
 impl TweakedKeyCtx {
+    fn my_prv_key(&self) -> Scalar { from_secret_bytes(&self.my_prv_key) }
+
     pub fn tweaked_public_key(&self) -> TweakedPublicKey {
         let pub_key: Point = self.key_agg_ctx.aggregated_pubkey();
         let pub_key = pub_key.to_public_key().into();
@@ -220,7 +347,7 @@ impl SigCtx {
     }
 
     pub fn init_my_nonce_share(&mut self) -> Result<()> {
-        let seckey = self.tweaked_key_ctx()?.my_prv_key;
+        let seckey = self.tweaked_key_ctx()?.my_prv_key();
         let aggregated_pubkey = self.tweaked_key_ctx()?.key_agg_ctx.aggregated_pubkey();
         // TODO: Consider making the RNG configurable, to aid unit testing:
         self.my_nonce_pair_share.get_or_insert_with(||
@@ -228,9 +355,30 @@ impl SigCtx {
         Ok(())
     }
 
+    /// Initialize my nonce share with one drawn from the pool, if it holds one bound to my key
+    /// share, else with one generated on demand.
+    pub fn init_my_nonce_share_from_pool(&mut self, nonce_pool: &mut NoncePool) -> Result<()> {
+        if self.my_nonce_pair_share.is_some() {
+            return Ok(());
+        }
+        let my_pub_key = self.tweaked_key_ctx()?.my_prv_key().base_point_mul();
+        match nonce_pool.take(&my_pub_key) {
+            Some(sec_nonce) => {
+                self.my_nonce_pair_share = Some(NoncePair::from_sec_nonce(&sec_nonce));
+                Ok(())
+            }
+            None => self.init_my_nonce_share(),
+        }
+    }
+
+    /// Sum my & the peer's nonce shares. As neither share may change once set, this is only done
+    /// once, with the result reused by any retry.
     pub fn aggregate_nonce_shares(&mut self) -> Result<&AggNonce> {
-        let agg_nonce = AggNonce::sum([self.my_nonce_share()?, self.peers_nonce_share()?]);
-        Ok(self.aggregated_nonce.insert(agg_nonce))
+        if self.aggregated_nonce.is_none() {
+            let agg_nonce = AggNonce::sum([self.my_nonce_share()?, self.peers_nonce_share()?]);
+            self.aggregated_nonce = Some(agg_nonce);
+        }
+        self.aggregated_nonce.as_ref().ok_or(MultisigErrorKind::MissingAggNonce)
     }
 
     pub fn sign_partial(&mut self, message: TapSighash) -> Result<&PartialSignature> {
@@ -240,11 +388,11 @@ impl SigCtx {
         let tweaked_key_ctx = self.tweaked_key_ctx.as_ref()
             .ok_or(MultisigErrorKind::MissingAggPubKey)?;
         let key_agg_ctx = &tweaked_key_ctx.key_agg_ctx;
-        let seckey = tweaked_key_ctx.my_prv_key;
+        let seckey = tweaked_key_ctx.my_prv_key();
         let aggregated_nonce = self.aggregated_nonce.as_ref()
             .ok_or(MultisigErrorKind::MissingAggNonce)?;
         let secnonce = self.my_nonce_pair_share.as_mut()
-            .ok_or(MultisigErrorKind::MissingNonceShare)?.sec_nonce.take()
+            .ok_or(MultisigErrorKind::MissingNonceShare)?.take_sec_nonce()
             .ok_or(MultisigErrorKind::NonceReuse)?;
 
         let sig = musig2::adaptor::sign_partial(key_agg_ctx, seckey, secnonce, aggregated_nonce,
@@ -266,6 +414,33 @@ impl SigCtx {
         Ok(self.aggregated_sig.insert(sig))
     }
 
+    /// Check that the aggregated nonce, if any, is the sum of the two nonce shares, and that each
+    /// partial signature on the message, where both are known, is valid under them.
+    pub fn check_integrity(&self) -> Result<()> {
+        let Some(ref aggregated_nonce) = self.aggregated_nonce else {
+            return Ok(());
+        };
+        let nonce_shares = [self.my_nonce_share()?, self.peers_nonce_share()?];
+        if *aggregated_nonce != AggNonce::sum(nonce_shares) {
+            return Err(MultisigErrorKind::MismatchedAggNonce);
+        }
+        let Some(message) = self.message else {
+            return Ok(());
+        };
+        let key_agg_ctx = &self.tweaked_key_ctx()?.key_agg_ctx;
+        let my_pub_key = self.tweaked_key_ctx()?.my_prv_key().base_point_mul();
+        let peers_pub_key = *key_agg_ctx.pubkeys().iter().find(|&&p| p != my_pub_key)
+            .ok_or(MultisigErrorKind::MissingKeyShare)?;
+        let partial_sigs = [(self.my_partial_sig, my_pub_key), (self.peers_partial_sig, peers_pub_key)];
+        for ((partial_sig, pub_key), nonce_share) in partial_sigs.into_iter().zip(nonce_shares) {
+            if let Some(partial_sig) = partial_sig {
+                musig2::adaptor::verify_partial(key_agg_ctx, partial_sig, aggregated_nonce,
+                    self.adaptor_point, pub_key, nonce_share, message.as_byte_array())?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn compute_taproot_signature(&self, adaptor_secret: MaybeScalar) -> Result<Signature> {
         let adaptor_sig = self.aggregated_sig
             .ok_or(MultisigErrorKind::MissingAggSig)?;
@@ -319,6 +494,10 @@ pub enum MultisigErrorKind {
     MissingAggSig,
     #[error("missing aggregated nonce")]
     MissingAggNonce,
+    #[error("aggregated pubkey does not match key shares")]
+    MismatchedAggKey,
+    #[error("aggregated nonce does not match nonce shares")]
+    MismatchedAggNonce,
     #[error("nonce has already been used")]
     NonceReuse,
     #[error("nonce is zero")]
@@ -335,3 +514,149 @@ pub enum MultisigErrorKind {
     DecodeLiftedSignature(#[from] musig2::errors::DecodeError<LiftedSignature>),
     ZeroScalar(#[from] musig2::secp::errors::ZeroScalarError),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::mem::ManuallyDrop;
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn test_secret_key_bytes_zeroed_on_drop() {
+        let mut key_ctx = ManuallyDrop::new(KeyCtx::default());
+        key_ctx.init_my_key_share();
+        let secret_bytes = key_ctx.my_key_share.as_ref().unwrap().prv_key.as_ref().unwrap();
+        let secret = &raw const **secret_bytes;
+
+        // SAFETY: The key share is alive and `secret` points into its inline storage.
+        assert_ne!(unsafe { ptr::read_volatile(secret) }, [0; 32]);
+
+        // SAFETY: `key_ctx` is not used again after being dropped in place. As it is wrapped in
+        // `ManuallyDrop`, its storage stays allocated, so the secret bytes may still be inspected.
+        unsafe { ManuallyDrop::drop(&mut key_ctx) };
+        assert_eq!(unsafe { ptr::read_volatile(secret) }, [0; 32]);
+    }
+
+    /// A pair of key contexts, one for each peer, with the key shares exchanged.
+    fn key_ctx_pair() -> [KeyCtx; 2] {
+        let mut key_ctxs: [KeyCtx; 2] = Default::default();
+        let [pub_key_1, pub_key_2] = key_ctxs.each_mut().map(|key_ctx| *key_ctx.init_my_key_share().pub_key());
+        key_ctxs[0].set_peers_pub_key(pub_key_2);
+        key_ctxs[1].set_peers_pub_key(pub_key_1);
+        key_ctxs
+    }
+
+    /// A pair of signing contexts, one for each peer, with their key shares aggregated and their
+    /// own nonce shares initialized.
+    fn sig_ctx_pair() -> [SigCtx; 2] {
+        sig_ctx_pair_with(KeyCtx::aggregate_pub_key_shares)
+    }
+
+    fn sig_ctx_pair_with(aggregate_key_shares: fn(&mut KeyCtx) -> Result<()>) -> [SigCtx; 2] {
+        key_ctx_pair().map(|mut key_ctx| {
+            aggregate_key_shares(&mut key_ctx).unwrap();
+            let mut sig_ctx = SigCtx::default();
+            sig_ctx.set_tweaked_key_ctx(key_ctx.with_taproot_tweak(None).unwrap());
+            sig_ctx.init_my_nonce_share().unwrap();
+            sig_ctx
+        })
+    }
+
+    #[test]
+    fn test_aggregate_nonce_shares_cached() {
+        let [mut sig_ctx, peers_sig_ctx] = sig_ctx_pair();
+        sig_ctx.set_peers_nonce_share(peers_sig_ctx.my_nonce_share().unwrap().clone());
+        let agg_nonce = sig_ctx.aggregate_nonce_shares().unwrap().clone();
+        let nonce_shares = [sig_ctx.my_nonce_share().unwrap(), peers_sig_ctx.my_nonce_share().unwrap()];
+        assert_eq!(agg_nonce, AggNonce::sum(nonce_shares));
+
+        // A different nonce share from the peer (say in a replayed request) is ignored, so the
+        // cached aggregate nonce cannot go stale...
+        let [_, other_peers_sig_ctx] = sig_ctx_pair();
+        sig_ctx.set_peers_nonce_share(other_peers_sig_ctx.my_nonce_share().unwrap().clone());
+        assert_eq!(sig_ctx.aggregate_nonce_shares().unwrap(), &agg_nonce);
+
+        // ...and it is returned without being recomputed, which would need the peer's share.
+        sig_ctx.peers_nonce_share = None;
+        assert_eq!(sig_ctx.aggregate_nonce_shares().unwrap(), &agg_nonce);
+    }
+
+    #[test]
+    fn test_sig_ctx_integrity() {
+        let [mut sig_ctx, mut peers_sig_ctx] = sig_ctx_pair();
+        sig_ctx.set_peers_nonce_share(peers_sig_ctx.my_nonce_share().unwrap().clone());
+        peers_sig_ctx.set_peers_nonce_share(sig_ctx.my_nonce_share().unwrap().clone());
+        let message = TapSighash::from_byte_array([1; 32]);
+        for ctx in [&mut sig_ctx, &mut peers_sig_ctx] {
+            ctx.aggregate_nonce_shares().unwrap();
+            ctx.sign_partial(message).unwrap();
+        }
+        sig_ctx.set_peers_partial_sig(*peers_sig_ctx.my_partial_sig().unwrap());
+        sig_ctx.check_integrity().unwrap();
+
+        let other_nonce_shares = sig_ctx_pair().map(|ctx| ctx.my_nonce_share().unwrap().clone());
+        sig_ctx.aggregated_nonce = Some(AggNonce::sum(other_nonce_shares));
+        assert!(matches!(sig_ctx.check_integrity(), Err(MultisigErrorKind::MismatchedAggNonce)));
+
+        sig_ctx.aggregated_nonce = peers_sig_ctx.aggregated_nonce.clone();
+        sig_ctx.peers_partial_sig = Some(*sig_ctx.my_partial_sig().unwrap());
+        assert!(matches!(sig_ctx.check_integrity(), Err(MultisigErrorKind::Verify(_))));
+    }
+
+    #[test]
+    fn test_blinded_key_shares() {
+        let mut key_ctxs = key_ctx_pair();
+        for key_ctx in &mut key_ctxs {
+            key_ctx.aggregate_blinded_key_shares().unwrap();
+            key_ctx.check_integrity().unwrap();
+        }
+        // Both peers arrive at the same blinded key, which isn't the plain aggregate of the shares.
+        let blinded_key = *key_ctxs[0].aggregated_key().unwrap().pub_key();
+        assert_eq!(key_ctxs[1].aggregated_key().unwrap().pub_key(), &blinded_key);
+        let shares = key_ctxs[0].key_shares().unwrap().map(|p| *p.pub_key());
+        assert_ne!(KeyAggContext::new(shares).unwrap().aggregated_pubkey::<Point>(), blinded_key);
+
+        // The blinding tweak is accounted for on recovery of the aggregated private key...
+        let peers_prv_key = key_ctxs[1].my_key_share().unwrap().prv_key().unwrap();
+        key_ctxs[0].set_peers_prv_key(peers_prv_key).unwrap();
+        assert_eq!(key_ctxs[0].aggregate_prv_key_shares().unwrap().base_point_mul(), blinded_key);
+        key_ctxs[0].check_integrity().unwrap();
+
+        // ...and in signing.
+        let [mut sig_ctx, mut peers_sig_ctx] = sig_ctx_pair_with(KeyCtx::aggregate_blinded_key_shares);
+        sig_ctx.set_peers_nonce_share(peers_sig_ctx.my_nonce_share().unwrap().clone());
+        peers_sig_ctx.set_peers_nonce_share(sig_ctx.my_nonce_share().unwrap().clone());
+        let message = TapSighash::from_byte_array([1; 32]);
+        for ctx in [&mut sig_ctx, &mut peers_sig_ctx] {
+            ctx.aggregate_nonce_shares().unwrap();
+            ctx.sign_partial(message).unwrap();
+        }
+        sig_ctx.set_peers_partial_sig(*peers_sig_ctx.my_partial_sig().unwrap());
+        sig_ctx.check_integrity().unwrap();
+        sig_ctx.aggregate_partial_signatures().unwrap();
+    }
+
+    #[test]
+    fn test_nonce_pool() {
+        let mut key_ctxs: [KeyCtx; 2] = Default::default();
+        let [key_pair_1, key_pair_2] = key_ctxs.each_mut().map(KeyCtx::init_my_key_share);
+        let mut nonce_pool = NoncePool::default();
+        nonce_pool.fill(key_pair_1, 2).unwrap();
+        nonce_pool.fill(key_pair_2, 1).unwrap();
+        assert_eq!(nonce_pool.len(), 3);
+
+        let nonce_1 = nonce_pool.take(key_pair_1.pub_key()).unwrap();
+        let nonce_2 = nonce_pool.take(key_pair_2.pub_key()).unwrap();
+        assert!(nonce_pool.take(key_pair_2.pub_key()).is_none());
+        let nonce_3 = nonce_pool.take(key_pair_1.pub_key()).unwrap();
+        assert!(nonce_pool.is_empty());
+
+        // Each nonce is fresh, and bound to the key share it was drawn for (as the BIP 327 encoding
+        // of a secret nonce ends with the public key).
+        assert_ne!(nonce_1, nonce_3);
+        for (nonce, key_pair) in [(nonce_1, key_pair_1), (nonce_2, key_pair_2), (nonce_3, key_pair_1)] {
+            assert_eq!(nonce.to_bytes()[64..], key_pair.pub_key().serialize());
+        }
+    }
+}
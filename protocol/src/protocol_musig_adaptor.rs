@@ -639,7 +639,7 @@ impl SwapTx {
     pub fn sign(&mut self, p_tik: &KeyCtx) -> anyhow::Result<Transaction> {
         // only seller can do this
         if self.role == ProtocolRole::Seller {
-            let adaptor_secret = (*p_tik.my_key_share()?.prv_key()?).into();
+            let adaptor_secret = p_tik.my_key_share()?.prv_key()?.into();
             let tx = self.builder
                 .set_input_signature(self.fund_sig.compute_taproot_signature(adaptor_secret)?)
                 .compute_signed_tx()?
@@ -0,0 +1,339 @@
+//! Deserialization of version 2 PSBTs, as specified by [BIP370], which the `bitcoin` crate does not
+//! yet support. A v2 PSBT has no global unsigned tx, instead giving the outpoint, sequence and
+//! lock time requirements of each input and the amount and script of each output in their own
+//! maps, so that inputs and outputs may be added by each party in turn (as needed for `MuSig2`
+//! workflows). Once parsed, it is converted to the equivalent v0 [`Psbt`] used everywhere else.
+//!
+//! [BIP370]: https://github.com/bitcoin/bips/blob/master/bip-0370.mediawiki
+
+use std::iter;
+
+use bdk_wallet::bitcoin::consensus::encode::{self, Decodable, Encodable as _, VarInt};
+use bdk_wallet::bitcoin::io;
+use bdk_wallet::bitcoin::psbt::{self, raw};
+use bdk_wallet::bitcoin::{
+    Amount, OutPoint, Psbt, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, absolute,
+};
+
+use crate::transaction::{Result, TransactionErrorKind};
+
+const MAGIC_AND_SEPARATOR: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_TX_VERSION: u8 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const PSBT_GLOBAL_VERSION: u8 = 0xFB;
+
+const PSBT_IN_PREVIOUS_TXID: u8 = 0x0E;
+const PSBT_IN_OUTPUT_INDEX: u8 = 0x0F;
+const PSBT_IN_SEQUENCE: u8 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+
+const PSBT_OUT_AMOUNT: u8 = 0x03;
+const PSBT_OUT_SCRIPT: u8 = 0x04;
+
+/// A parsed version 2 PSBT. Every field it shares with version 0 is held in the equivalent v0
+/// PSBT, whose unsigned tx is assembled from the v2-only fields.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PsbtV2 {
+    psbt: Psbt,
+    tx_modifiable_flags: u8,
+}
+
+impl PsbtV2 {
+    /// # Errors
+    /// Will return `Err` if the bytes are not a valid BIP370 PSBT
+    pub fn deserialize(mut bytes: &[u8]) -> Result<Self> {
+        let bytes = &mut bytes;
+        if read_bytes(bytes, MAGIC_AND_SEPARATOR.len())? != MAGIC_AND_SEPARATOR {
+            return Err(psbt::Error::InvalidMagic.into());
+        }
+        let mut global = read_map(bytes)?;
+        if let Some(pair) = global.iter().find(|pair| pair.key.type_value == PSBT_GLOBAL_UNSIGNED_TX) {
+            return Err(psbt::Error::InvalidKey(pair.key.clone()).into());
+        }
+        let version: u32 = take_field(&mut global, PSBT_GLOBAL_VERSION)?.unwrap_or_default();
+        if version != 2 {
+            return Err(psbt::Error::Version("expected PSBT version 2").into());
+        }
+        let tx_version = take_required_field(&mut global, PSBT_GLOBAL_TX_VERSION, "tx version")?;
+        let fallback_lock_time = take_field(&mut global, PSBT_GLOBAL_FALLBACK_LOCKTIME)?;
+        let VarInt(input_count) = take_required_field(&mut global, PSBT_GLOBAL_INPUT_COUNT, "input count")?;
+        let VarInt(output_count) = take_required_field(&mut global, PSBT_GLOBAL_OUTPUT_COUNT, "output count")?;
+        let tx_modifiable_flags = take_field(&mut global, PSBT_GLOBAL_TX_MODIFIABLE)?.unwrap_or_default();
+
+        let mut inputs = Vec::new();
+        let mut input_maps = Vec::new();
+        for _ in 0..input_count {
+            let mut map = read_map(bytes)?;
+            inputs.push(V2Input::take_from(&mut map)?);
+            input_maps.push(map);
+        }
+        let mut outputs = Vec::new();
+        let mut output_maps = Vec::new();
+        for _ in 0..output_count {
+            let mut map = read_map(bytes)?;
+            let value = Amount::from_sat(take_required_field(&mut map, PSBT_OUT_AMOUNT, "output amount")?);
+            let script_pubkey = take_required_raw_field(&mut map, PSBT_OUT_SCRIPT, "output script")?;
+            outputs.push(TxOut { value, script_pubkey: ScriptBuf::from_bytes(script_pubkey) });
+            output_maps.push(map);
+        }
+        if !bytes.is_empty() {
+            return Err(psbt::Error::PartialDataConsumption.into());
+        }
+
+        let unsigned_tx = Transaction {
+            version: tx_version,
+            lock_time: V2Input::compute_lock_time(&inputs, fallback_lock_time)?,
+            input: inputs.into_iter().map(|input| input.tx_in).collect(),
+            output: outputs,
+        };
+        // Reuse the v0 parser for the remaining (shared) fields, by reassembling the maps around
+        // the unsigned tx. This way, they are validated exactly as those of a v0 PSBT would be.
+        let mut v0_bytes = MAGIC_AND_SEPARATOR.to_vec();
+        write_pair(&mut v0_bytes, PSBT_GLOBAL_UNSIGNED_TX, &[], &serialize_unsigned_tx(&unsigned_tx));
+        for map in iter::once(&global).chain(&input_maps).chain(&output_maps) {
+            for pair in map {
+                write_pair(&mut v0_bytes, pair.key.type_value, &pair.key.key, &pair.value);
+            }
+            v0_bytes.push(0x00);
+        }
+        Ok(Self { psbt: Psbt::deserialize(&v0_bytes)?, tx_modifiable_flags })
+    }
+
+    /// The `PSBT_GLOBAL_TX_MODIFIABLE` flags, saying whether inputs or outputs may still be added
+    /// and whether any signature uses `SIGHASH_SINGLE`.
+    pub const fn tx_modifiable_flags(&self) -> u8 { self.tx_modifiable_flags }
+}
+
+/// Convert a v2 PSBT to the equivalent v0 PSBT, for use with BDK and the rest of the protocol.
+pub fn psbt_v2_to_v0(v2: PsbtV2) -> Psbt { v2.psbt }
+
+struct V2Input {
+    tx_in: TxIn,
+    required_time: Option<absolute::Time>,
+    required_height: Option<absolute::Height>,
+}
+
+impl V2Input {
+    fn take_from(map: &mut Vec<raw::Pair>) -> Result<Self> {
+        let txid: Txid = take_required_field(map, PSBT_IN_PREVIOUS_TXID, "previous txid")?;
+        let vout = take_required_field(map, PSBT_IN_OUTPUT_INDEX, "output index")?;
+        let sequence = take_field(map, PSBT_IN_SEQUENCE)?.unwrap_or(Sequence::MAX);
+        let required_time = take_field(map, PSBT_IN_REQUIRED_TIME_LOCKTIME)?
+            .map(absolute::Time::from_consensus).transpose()
+            .map_err(|_| TransactionErrorKind::InvalidPsbt)?;
+        let required_height = take_field(map, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)?
+            .map(absolute::Height::from_consensus).transpose()
+            .map_err(|_| TransactionErrorKind::InvalidPsbt)?;
+        let tx_in = TxIn { previous_output: OutPoint::new(txid, vout), sequence, ..TxIn::default() };
+        Ok(Self { tx_in, required_time, required_height })
+    }
+
+    /// Determine the tx lock time as BIP370 specifies: the fallback (or zero) if no input has a
+    /// lock time requirement, else the greatest required height if every input with a requirement
+    /// allows a height, else the greatest required time if every such input allows a time.
+    fn compute_lock_time(inputs: &[Self], fallback: Option<absolute::LockTime>) -> Result<absolute::LockTime> {
+        let constrained = || inputs.iter().filter(|i| i.required_time.is_some() || i.required_height.is_some());
+        if constrained().next().is_none() {
+            return Ok(fallback.unwrap_or(absolute::LockTime::ZERO));
+        }
+        if let Some(heights) = constrained().map(|i| i.required_height).collect::<Option<Vec<_>>>() {
+            return Ok(heights.into_iter().max().map_or(absolute::LockTime::ZERO, absolute::LockTime::Blocks));
+        }
+        if let Some(times) = constrained().map(|i| i.required_time).collect::<Option<Vec<_>>>() {
+            return Ok(times.into_iter().max().map_or(absolute::LockTime::ZERO, absolute::LockTime::Seconds));
+        }
+        Err(TransactionErrorKind::IncompatibleLockTimes)
+    }
+}
+
+fn read_bytes<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    let (head, tail) = bytes.split_at_checked(len)
+        .ok_or(TransactionErrorKind::Decode(encode::Error::Io(io::ErrorKind::UnexpectedEof.into())))?;
+    *bytes = tail;
+    Ok(head)
+}
+
+fn read_var_int(bytes: &mut &[u8]) -> Result<usize> {
+    let VarInt(n) = VarInt::consensus_decode(bytes)?;
+    usize::try_from(n).map_err(|_| TransactionErrorKind::InvalidPsbt)
+}
+
+fn read_map(bytes: &mut &[u8]) -> Result<Vec<raw::Pair>> {
+    let mut map: Vec<raw::Pair> = Vec::new();
+    loop {
+        let key_len = read_var_int(bytes)?;
+        let Some((&type_value, key)) = read_bytes(bytes, key_len)?.split_first() else {
+            return Ok(map);
+        };
+        let key = raw::Key { type_value, key: key.to_vec() };
+        let value_len = read_var_int(bytes)?;
+        let value = read_bytes(bytes, value_len)?.to_vec();
+        if map.iter().any(|pair| pair.key == key) {
+            return Err(psbt::Error::DuplicateKey(key).into());
+        }
+        map.push(raw::Pair { key, value });
+    }
+}
+
+/// Remove the v2-only field of the given type from the map, if present, as its raw value. Such
+/// fields have no key data, so may only appear once.
+fn take_raw_field(map: &mut Vec<raw::Pair>, type_value: u8) -> Result<Option<Vec<u8>>> {
+    let (fields, rest) = map.drain(..).partition::<Vec<_>, _>(|pair| pair.key.type_value == type_value);
+    *map = rest;
+    let mut fields = fields.into_iter();
+    match (fields.next(), fields.next()) {
+        (None, _) => Ok(None),
+        (Some(pair), None) if pair.key.key.is_empty() => Ok(Some(pair.value)),
+        (Some(pair), _) => Err(psbt::Error::InvalidKey(pair.key).into()),
+    }
+}
+
+fn take_field<T: Decodable>(map: &mut Vec<raw::Pair>, type_value: u8) -> Result<Option<T>> {
+    take_raw_field(map, type_value)?
+        .map(|value| encode::deserialize(&value))
+        .transpose()
+        .map_err(TransactionErrorKind::from)
+}
+
+fn take_required_raw_field(map: &mut Vec<raw::Pair>, type_value: u8, name: &'static str) -> Result<Vec<u8>> {
+    take_raw_field(map, type_value)?.ok_or(TransactionErrorKind::MissingPsbtField(name))
+}
+
+fn take_required_field<T: Decodable>(map: &mut Vec<raw::Pair>, type_value: u8, name: &'static str) -> Result<T> {
+    take_field(map, type_value)?.ok_or(TransactionErrorKind::MissingPsbtField(name))
+}
+
+fn write_pair(buf: &mut Vec<u8>, type_value: u8, key: &[u8], value: &[u8]) {
+    VarInt::from(key.len() + 1).consensus_encode(buf).expect("in-memory writers don't error");
+    buf.push(type_value);
+    buf.extend_from_slice(key);
+    value.to_vec().consensus_encode(buf).expect("in-memory writers don't error");
+}
+
+/// Serialize a tx in the legacy (non-segwit) format that v0 PSBTs require, even if it has no inputs.
+fn serialize_unsigned_tx(tx: &Transaction) -> Vec<u8> {
+    let mut buf = encode::serialize(&tx.version);
+    buf.extend(encode::serialize(&tx.input));
+    buf.extend(encode::serialize(&tx.output));
+    buf.extend(encode::serialize(&tx.lock_time));
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk_wallet::bitcoin::bip32::{DerivationPath, Fingerprint};
+    use bdk_wallet::bitcoin::hashes::Hash as _;
+    use bdk_wallet::bitcoin::secp256k1::PublicKey;
+    use bdk_wallet::bitcoin::transaction;
+
+    use super::*;
+
+    fn v0_psbt() -> Psbt {
+        let tx_in = |vout| TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), vout),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            ..TxIn::default()
+        };
+        let tx_out = |value| TxOut { value: Amount::from_sat(value), script_pubkey: ScriptBuf::new_op_return([]) };
+        let unsigned_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::from_height(800_000).unwrap(),
+            input: vec![tx_in(0), tx_in(3)],
+            output: vec![tx_out(10_000), tx_out(20_000), tx_out(30_000)],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx).unwrap();
+        psbt.inputs[1].witness_utxo = Some(tx_out(70_000));
+        let key: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        psbt.outputs[2].bip32_derivation.insert(key, (Fingerprint::from([1, 2, 3, 4]), DerivationPath::master()));
+        psbt
+    }
+
+    /// Re-encode a v0 PSBT in the v2 format, with the given per-input lock time requirements
+    /// (required time & height respectively, if any).
+    fn to_v2_bytes(psbt: &Psbt, lock_times: &[(Option<u32>, Option<u32>)]) -> Vec<u8> {
+        let v0_bytes = psbt.serialize();
+        let mut v0_bytes = &v0_bytes[MAGIC_AND_SEPARATOR.len()..];
+        let tx = &psbt.unsigned_tx;
+        let mut buf = MAGIC_AND_SEPARATOR.to_vec();
+
+        let mut global = read_map(&mut v0_bytes).unwrap();
+        take_raw_field(&mut global, PSBT_GLOBAL_UNSIGNED_TX).unwrap().unwrap();
+        write_pair(&mut buf, PSBT_GLOBAL_VERSION, &[], &encode::serialize(&2u32));
+        write_pair(&mut buf, PSBT_GLOBAL_TX_VERSION, &[], &encode::serialize(&tx.version));
+        write_pair(&mut buf, PSBT_GLOBAL_FALLBACK_LOCKTIME, &[], &encode::serialize(&tx.lock_time));
+        write_pair(&mut buf, PSBT_GLOBAL_INPUT_COUNT, &[], &encode::serialize(&VarInt::from(tx.input.len())));
+        write_pair(&mut buf, PSBT_GLOBAL_OUTPUT_COUNT, &[], &encode::serialize(&VarInt::from(tx.output.len())));
+        write_map(&mut buf, &global);
+
+        for (i, tx_in) in tx.input.iter().enumerate() {
+            let txid = encode::serialize(&tx_in.previous_output.txid);
+            write_pair(&mut buf, PSBT_IN_PREVIOUS_TXID, &[], &txid);
+            write_pair(&mut buf, PSBT_IN_OUTPUT_INDEX, &[], &encode::serialize(&tx_in.previous_output.vout));
+            write_pair(&mut buf, PSBT_IN_SEQUENCE, &[], &encode::serialize(&tx_in.sequence));
+            let (time, height) = lock_times.get(i).copied().unwrap_or_default();
+            if let Some(time) = time {
+                write_pair(&mut buf, PSBT_IN_REQUIRED_TIME_LOCKTIME, &[], &encode::serialize(&time));
+            }
+            if let Some(height) = height {
+                write_pair(&mut buf, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME, &[], &encode::serialize(&height));
+            }
+            write_map(&mut buf, &read_map(&mut v0_bytes).unwrap());
+        }
+        for tx_out in &tx.output {
+            write_pair(&mut buf, PSBT_OUT_AMOUNT, &[], &encode::serialize(&tx_out.value));
+            write_pair(&mut buf, PSBT_OUT_SCRIPT, &[], tx_out.script_pubkey.as_bytes());
+            write_map(&mut buf, &read_map(&mut v0_bytes).unwrap());
+        }
+        buf
+    }
+
+    fn write_map(buf: &mut Vec<u8>, map: &[raw::Pair]) {
+        for pair in map {
+            write_pair(buf, pair.key.type_value, &pair.key.key, &pair.value);
+        }
+        buf.push(0x00);
+    }
+
+    #[test]
+    fn test_psbt_v2_to_v0() -> Result<()> {
+        let psbt = v0_psbt();
+        let v2 = PsbtV2::deserialize(&to_v2_bytes(&psbt, &[]))?;
+        assert_eq!(v2.tx_modifiable_flags(), 0);
+        assert_eq!(psbt_v2_to_v0(v2), psbt);
+
+        // A v0 PSBT, or a truncated or padded v2 PSBT, is rejected.
+        assert!(matches!(PsbtV2::deserialize(&psbt.serialize()),
+            Err(TransactionErrorKind::Psbt(psbt::Error::InvalidKey(_)))));
+        let v2_bytes = to_v2_bytes(&psbt, &[]);
+        assert!(PsbtV2::deserialize(&v2_bytes[..v2_bytes.len() - 1]).is_err());
+        assert!(matches!(PsbtV2::deserialize(&[&v2_bytes[..], &[0x00]].concat()),
+            Err(TransactionErrorKind::Psbt(psbt::Error::PartialDataConsumption))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_psbt_v2_lock_time() -> Result<()> {
+        let psbt = v0_psbt();
+        let lock_time = |lock_times: &[(Option<u32>, Option<u32>)]| PsbtV2::deserialize(&to_v2_bytes(&psbt, lock_times))
+            .map(|v2| psbt_v2_to_v0(v2).unsigned_tx.lock_time);
+
+        // Falls back to the global lock time if no input has a requirement...
+        assert_eq!(lock_time(&[])?, psbt.unsigned_tx.lock_time);
+        // ...else takes the greatest required height, preferring heights to times where allowed...
+        assert_eq!(lock_time(&[(None, Some(900_000)), (Some(1_700_000_000), Some(850_000))])?,
+            absolute::LockTime::from_height(900_000).unwrap());
+        // ...else the greatest required time, if every constrained input allows one.
+        assert_eq!(lock_time(&[(Some(1_700_000_000), None), (Some(1_600_000_000), Some(850_000))])?,
+            absolute::LockTime::from_time(1_700_000_000).unwrap());
+        assert!(matches!(lock_time(&[(Some(1_700_000_000), None), (None, Some(850_000))]),
+            Err(TransactionErrorKind::IncompatibleLockTimes)));
+        assert!(matches!(lock_time(&[(Some(100), None)]), Err(TransactionErrorKind::InvalidPsbt)));
+        Ok(())
+    }
+}
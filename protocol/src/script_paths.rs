@@ -1,9 +1,10 @@
-use bdk_wallet::bitcoin::opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CSV};
-use bdk_wallet::bitcoin::taproot::TaprootBuilder;
-use bdk_wallet::bitcoin::{ScriptBuf, TapNodeHash, XOnlyPublicKey, relative, script};
+use bdk_wallet::bitcoin::opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CLTV, OP_CSV};
+use bdk_wallet::bitcoin::secp256k1::Secp256k1;
+use bdk_wallet::bitcoin::taproot::{ControlBlock, LeafVersion, TaprootBuilder, TaprootBuilderError};
+use bdk_wallet::bitcoin::{ScriptBuf, TapNodeHash, XOnlyPublicKey, absolute, relative, script};
 use bdk_wallet::miniscript::{DefiniteDescriptorKey, Descriptor, Miniscript, Tap};
 
-use crate::transaction::{NetworkParams, Result};
+use crate::transaction::{NetworkParams, Result, TransactionErrorKind};
 
 pub fn deposit_payout_merkle_root(
     buyer_pub_key: &XOnlyPublicKey,
@@ -12,6 +13,35 @@ pub fn deposit_payout_merkle_root(
     single_path_merkle_root(multisig_script(buyer_pub_key, seller_pub_key))
 }
 
+/// The merkle root of the deposit payout output script tree, with the given extra leaves (such as
+/// an `arbitration_script`) following the multisig leaf, in the same left-nested tree shape as
+/// `deposit_payout_descriptor_with_extra_leaves`.
+pub fn deposit_payout_merkle_root_with_extra_leaves(
+    buyer_pub_key: &XOnlyPublicKey,
+    seller_pub_key: &XOnlyPublicKey,
+    extra_leaves: &[ScriptBuf],
+) -> Result<TapNodeHash> {
+    Ok(deposit_payout_taproot_builder(buyer_pub_key, seller_pub_key, extra_leaves)?
+        .try_into_taptree()
+        .expect("hardcoded TapTree build sequence should be complete")
+        .root_hash())
+}
+
+/// The control block needed to spend a deposit payout output via the given leaf of its script tree.
+pub fn deposit_payout_control_block(
+    internal_key: &XOnlyPublicKey,
+    buyer_pub_key: &XOnlyPublicKey,
+    seller_pub_key: &XOnlyPublicKey,
+    extra_leaves: &[ScriptBuf],
+    leaf_script: ScriptBuf,
+) -> Result<ControlBlock> {
+    deposit_payout_taproot_builder(buyer_pub_key, seller_pub_key, extra_leaves)?
+        .finalize(&Secp256k1::verification_only(), *internal_key)
+        .expect("hardcoded TapTree build sequence should be complete")
+        .control_block(&(leaf_script, LeafVersion::TapScript))
+        .ok_or(TransactionErrorKind::MissingScriptLeaf)
+}
+
 pub fn warning_escrow_merkle_root(
     claim_pub_key: &XOnlyPublicKey,
     network: impl NetworkParams + Copy,
@@ -27,6 +57,55 @@ pub fn deposit_payout_descriptor(
     Ok(format!("tr({internal_key},and_v(v:pk({buyer_pub_key}),pk({seller_pub_key})))").parse()?)
 }
 
+pub fn deposit_payout_descriptor_with_extra_leaves(
+    internal_key: &XOnlyPublicKey,
+    buyer_pub_key: &XOnlyPublicKey,
+    seller_pub_key: &XOnlyPublicKey,
+    extra_leaves: &[ScriptBuf],
+) -> Result<Descriptor<DefiniteDescriptorKey>> {
+    let mut tap_tree = format!("and_v(v:pk({buyer_pub_key}),pk({seller_pub_key}))");
+    for leaf in extra_leaves {
+        let leaf_ms = Miniscript::<XOnlyPublicKey, Tap>::parse(leaf)?;
+        tap_tree = format!("{{{tap_tree},{leaf_ms}}}");
+    }
+    Ok(format!("tr({internal_key},{tap_tree})").parse()?)
+}
+
+/// A timelocked script path for dispute resolution, letting the arbitrator alone spend the output
+/// once the given absolute lock time has passed.
+pub fn arbitration_script(arbitrator_pub_key: &XOnlyPublicKey, lock_time: absolute::LockTime) -> ScriptBuf {
+    // Comes from miniscript policy: format!("and(pk({arbitrator_pub_key}),after({lock_time}))")
+    // which compiles to miniscript: format!("and_v(v:pk({arbitrator_pub_key}),after({lock_time}))")
+    script::Builder::new()
+        .push_x_only_key(arbitrator_pub_key)
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .push_lock_time(lock_time)
+        .push_opcode(OP_CLTV)
+        .into_script()
+}
+
+fn deposit_payout_taproot_builder(
+    buyer_pub_key: &XOnlyPublicKey,
+    seller_pub_key: &XOnlyPublicKey,
+    extra_leaves: &[ScriptBuf],
+) -> Result<TaprootBuilder> {
+    let max_depth = extra_leaves.len();
+    let leaves = std::iter::once(multisig_script(buyer_pub_key, seller_pub_key))
+        .chain(extra_leaves.iter().cloned());
+
+    // Build the left-nested tree {{{multisig,leaf_1},leaf_2},...}, in depth-first order, so that
+    // each extra leaf sits one level nearer the root than the previous one.
+    let mut builder = TaprootBuilder::with_capacity(max_depth + 1);
+    for (i, leaf) in leaves.enumerate() {
+        // Check for repeated keys, zero locktime or any other issues when decoding to miniscript:
+        Miniscript::<XOnlyPublicKey, Tap>::parse(&leaf)?;
+        let depth = max_depth + 1 - i.max(1);
+        let depth = u8::try_from(depth).map_err(|_| TaprootBuilderError::InvalidMerkleTreeDepth(depth))?;
+        builder = builder.add_leaf(depth, leaf)?;
+    }
+    Ok(builder)
+}
+
 fn single_path_merkle_root(script: ScriptBuf) -> Result<TapNodeHash> {
     // Check for repeated keys, zero locktime or any other issues when decoding to miniscript:
     Miniscript::<XOnlyPublicKey, Tap>::parse(&script)?;
@@ -106,4 +185,45 @@ mod tests {
         let merkle_root = tr.spend_info().merkle_root().unwrap();
         assert_eq!(merkle_root, deposit_payout_merkle_root(buyer_pub_key, seller_pub_key).unwrap());
     }
+
+    #[test]
+    fn extra_leaves_match_descriptor() {
+        let internal_key =
+            &"0000000000000000000000000000000000000000000000000000000000000001".parse().unwrap();
+        let buyer_pub_key =
+            &"0000000000000000000000000000000000000000000000000000000000000002".parse().unwrap();
+        let seller_pub_key =
+            &"0000000000000000000000000000000000000000000000000000000000000003".parse().unwrap();
+        let arbitrator_pub_key =
+            &"0000000000000000000000000000000000000000000000000000000000000004".parse().unwrap();
+        let extra_leaves = [
+            arbitration_script(arbitrator_pub_key, absolute::LockTime::from_height(900_000).unwrap()),
+            arbitration_script(arbitrator_pub_key, absolute::LockTime::from_height(950_000).unwrap()),
+        ];
+
+        let arbitration_ms = format!("and_v(v:pk({arbitrator_pub_key}),after(900000))")
+            .parse::<Miniscript<XOnlyPublicKey, Tap>>().unwrap();
+        assert_eq!(arbitration_ms.encode(), extra_leaves[0]);
+
+        let desc = deposit_payout_descriptor_with_extra_leaves(
+            internal_key, buyer_pub_key, seller_pub_key, &extra_leaves).unwrap();
+        let Descriptor::Tr(tr) = desc else {
+            panic!("expected Taproot descriptor")
+        };
+        let spend_info = tr.spend_info();
+        let merkle_root = deposit_payout_merkle_root_with_extra_leaves(
+            buyer_pub_key, seller_pub_key, &extra_leaves).unwrap();
+        assert_eq!(spend_info.merkle_root(), Some(merkle_root));
+        assert_ne!(merkle_root, deposit_payout_merkle_root(buyer_pub_key, seller_pub_key).unwrap());
+
+        for leaf in [&extra_leaves[..], &[multisig_script(buyer_pub_key, seller_pub_key)]].concat() {
+            let control_block = deposit_payout_control_block(
+                internal_key, buyer_pub_key, seller_pub_key, &extra_leaves, leaf.clone()).unwrap();
+            assert!(control_block.verify_taproot_commitment(
+                &Secp256k1::verification_only(), spend_info.output_key().to_x_only_public_key(), &leaf));
+        }
+        let unknown_leaf = arbitration_script(arbitrator_pub_key, absolute::LockTime::from_height(1).unwrap());
+        assert!(deposit_payout_control_block(
+            internal_key, buyer_pub_key, seller_pub_key, &extra_leaves, unknown_leaf).is_err());
+    }
 }
@@ -3,12 +3,12 @@ use std::collections::BTreeSet;
 use bdk_wallet::bitcoin::amount::CheckedSum as _;
 use bdk_wallet::bitcoin::policy::MAX_STANDARD_TX_WEIGHT;
 use bdk_wallet::bitcoin::psbt::ExtractTxError;
-use bdk_wallet::bitcoin::sighash::{Prevouts, SighashCache};
+use bdk_wallet::bitcoin::sighash::{Annex, Prevouts, SighashCache};
 use bdk_wallet::bitcoin::taproot::{Signature, TAPROOT_ANNEX_PREFIX};
 use bdk_wallet::bitcoin::transaction::Version;
 use bdk_wallet::bitcoin::{
     Address, Amount, FeeRate, Network, OutPoint, Psbt, Sequence, TapSighash, TapSighashType,
-    Transaction, TxIn, TxOut, Txid, Weight, Witness, absolute, relative, script,
+    Transaction, TxIn, TxOut, Txid, VarInt, Weight, Witness, absolute, relative, script,
 };
 use bdk_wallet::miniscript::DefiniteDescriptorKey;
 use bdk_wallet::miniscript::psbt::PsbtInputExt as _;
@@ -37,6 +37,8 @@ pub const SIGNED_FORWARDING_TX_WEIGHT: Weight = Weight::from_wu(444);
 pub const SIGNED_WARNING_TX_WEIGHT: Weight = Weight::from_wu(846);
 pub const SIGNED_REDIRECT_TX_BASE_WEIGHT: Weight = SIGNED_FORWARDING_TX_WEIGHT;
 pub const SIGNED_CUSTOM_PAYOUT_TX_WEIGHT: Weight = Weight::from_wu(1182);
+/// The maximum size of a taproot input annex, matching the maximum script element size.
+pub const MAX_ANNEX_LEN: usize = 520;
 
 pub trait NetworkParams {
     fn warning_lock_time(&self) -> LockTime;
@@ -213,11 +215,16 @@ trait WithFixedInputs<const N: usize> {
     }
 
     fn key_spend_sighash(&self, tx: &Transaction, input_index: usize) -> Result<TapSighash> {
+        self.key_spend_sighash_with_annex(tx, input_index, None)
+    }
+
+    fn key_spend_sighash_with_annex(&self, tx: &Transaction, input_index: usize, annex: Option<&[u8]>) -> Result<TapSighash> {
         let prevouts = self.inputs()?.map(|input| &input.prevout);
         let prevouts = Prevouts::All(&prevouts);
+        let annex = annex.map(Annex::new).transpose().map_err(|_| TransactionErrorKind::InvalidAnnex)?;
         let mut cache = SighashCache::new(tx);
         // TODO: Report missing validation to rust-bitcoin if index is not correct.
-        Ok(cache.taproot_key_spend_signature_hash(input_index, &prevouts, TapSighashType::Default)?)
+        Ok(cache.taproot_signature_hash(input_index, &prevouts, annex, None, TapSighashType::Default)?)
     }
 }
 
@@ -510,6 +517,7 @@ pub struct ForwardingTxBuilder {
     lock_time: Option<LockTime>,
     fee_rate: Option<FeeRate>,
     input_signature: Option<Signature>,
+    input_annex: Option<Vec<u8>>,
     // Derived fields:
     unsigned_tx: Option<Transaction>,
     signed_tx: Option<Transaction>,
@@ -526,13 +534,37 @@ impl ForwardingTxBuilder {
 
     pub fn disable_lock_time(&mut self) -> &mut Self { self.set_lock_time(LockTime::ZERO) }
 
-    fn payout_amount(input_amount: Amount, fee_rate: FeeRate) -> Option<Amount> {
-        input_amount.checked_sub(fee_rate.checked_mul_by_weight(SIGNED_FORWARDING_TX_WEIGHT)?)
+    pub fn input_annex(&self) -> Option<&[u8]> { self.input_annex.as_deref() }
+
+    /// Set a BIP341 annex on the (key-spend) input, committed to by its sighash and appended to its
+    /// witness, replacing any set before. It must be set before the unsigned tx is computed, as its
+    /// weight is paid for out of the payout. NOTE: Bitcoin Core treats txs with an annex as
+    /// nonstandard, so does not relay them, and the signed tx would currently need to be submitted
+    /// directly to a miner.
+    pub fn set_input_annex(&mut self, annex: Vec<u8>) -> Result<&mut Self> {
+        if annex.first() != Some(&TAPROOT_ANNEX_PREFIX) || annex.len() > MAX_ANNEX_LEN {
+            return Err(TransactionErrorKind::InvalidAnnex);
+        }
+        if self.unsigned_tx.is_some() {
+            return Err(TransactionErrorKind::AnnexAfterUnsignedTx);
+        }
+        self.input_annex = Some(annex);
+        Ok(self)
+    }
+
+    /// The weight of the signed tx, including that of the length-prefixed annex in its witness.
+    fn signed_tx_weight(&self) -> Weight {
+        let annex_len = self.input_annex().map_or(0, |annex| VarInt::from(annex.len()).size() + annex.len());
+        SIGNED_FORWARDING_TX_WEIGHT + Weight::from_wu_usize(annex_len)
+    }
+
+    fn payout_amount(input_amount: Amount, fee_rate: FeeRate, signed_tx_weight: Weight) -> Option<Amount> {
+        input_amount.checked_sub(fee_rate.checked_mul_by_weight(signed_tx_weight)?)
     }
 
     pub fn compute_unsigned_tx(&mut self) -> Result<&mut Self> {
         let output = vec![TxOut {
-            value: Self::payout_amount(self.input()?.prevout.value, *self.fee_rate()?)
+            value: Self::payout_amount(self.input()?.prevout.value, *self.fee_rate()?, self.signed_tx_weight())
                 .ok_or(TransactionErrorKind::Overflow)?,
             script_pubkey: self.payout_address()?.script_pubkey(),
         }];
@@ -548,11 +580,14 @@ impl ForwardingTxBuilder {
     }
 
     pub fn input_sighash(&self) -> Result<TapSighash> {
-        self.key_spend_sighash(self.unsigned_tx()?, 0)
+        self.key_spend_sighash_with_annex(self.unsigned_tx()?, 0, self.input_annex())
     }
 
     pub fn compute_signed_tx(&mut self) -> Result<&mut Self> {
-        let tx = self.unsigned_tx()?.clone().with_key_spend_witness(0, self.input_signature()?);
+        let mut tx = self.unsigned_tx()?.clone().with_key_spend_witness(0, self.input_signature()?);
+        if let Some(annex) = self.input_annex() {
+            tx.input[0].witness.push(annex);
+        }
         self.signed_tx.get_or_insert(tx);
         Ok(self)
     }
@@ -698,15 +733,27 @@ pub enum TransactionErrorKind {
     InvalidWitness,
     #[error("invalid PSBT")]
     InvalidPsbt,
+    #[error("invalid annex")]
+    InvalidAnnex,
+    #[error("annex set after the unsigned tx was computed")]
+    AnnexAfterUnsignedTx,
+    #[error("missing script leaf")]
+    MissingScriptLeaf,
     #[error("dust output of {0} at index {1}")]
     DustOutput(Amount, usize),
     #[error("transaction weight {0} exceeds policy maximum")]
     NonstandardTxWeight(Weight),
+    #[error("missing PSBT field: {0}")]
+    MissingPsbtField(&'static str),
+    #[error("no lock time satisfies the requirements of every PSBT input")]
+    IncompatibleLockTimes,
     AddressParse(#[from] bdk_wallet::bitcoin::address::ParseError),
     Taproot(#[from] bdk_wallet::bitcoin::sighash::TaprootError),
+    TaprootBuilder(#[from] bdk_wallet::bitcoin::taproot::TaprootBuilderError),
     InputsIndex(#[from] bdk_wallet::bitcoin::transaction::InputsIndexError),
     SigFromSlice(#[from] bdk_wallet::bitcoin::taproot::SigFromSliceError),
     Psbt(#[from] bdk_wallet::bitcoin::psbt::Error),
+    Decode(#[from] bdk_wallet::bitcoin::consensus::encode::Error),
     ExtractTx(#[from] Box<ExtractTxError>),
     Miniscript(#[from] bdk_wallet::miniscript::Error),
     Conversion(#[from] bdk_wallet::miniscript::descriptor::ConversionError),
@@ -824,6 +871,33 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_swap_tx_input_annex() -> Result<()> {
+        let plain_builder = filled_swap_tx_builder(&filled_deposit_tx_builder(false)?)?;
+        let mut builder = ForwardingTxBuilder {
+            unsigned_tx: None,
+            signed_tx: None,
+            ..filled_swap_tx_builder(&filled_deposit_tx_builder(false)?)?
+        };
+
+        assert!(builder.set_input_annex(vec![0x51, 1, 2, 3]).is_err());
+        assert!(builder.set_input_annex(vec![TAPROOT_ANNEX_PREFIX; MAX_ANNEX_LEN + 1]).is_err());
+        builder.set_input_annex(vec![TAPROOT_ANNEX_PREFIX; 8])?;
+        builder.set_input_annex(vec![TAPROOT_ANNEX_PREFIX, 1, 2, 3])?;
+        builder.compute_unsigned_tx()?;
+        assert!(matches!(builder.set_input_annex(vec![TAPROOT_ANNEX_PREFIX]),
+            Err(TransactionErrorKind::AnnexAfterUnsignedTx)));
+        assert_ne!(plain_builder.input_sighash()?, builder.input_sighash()?);
+
+        let signed_tx = builder.compute_signed_tx()?.signed_tx()?;
+        assert_eq!(signed_tx.input[0].witness.last(), Some(&[TAPROOT_ANNEX_PREFIX, 1, 2, 3][..]));
+        assert_eq!(signed_tx.key_spend_signature(0)?, sig(SWAP_TX_SIGNATURE));
+        // The payout pays for the 5 weight units of the length-prefixed annex, at 2252 sats per kwu.
+        let fee_increase = plain_builder.signed_tx()?.output[0].value - signed_tx.output[0].value;
+        assert_eq!(fee_increase, FeeRate::from_sat_per_kwu(2252).fee_wu(Weight::from_wu(5)).unwrap());
+        Ok(())
+    }
+
     #[test]
     fn test_warning_tx_builder() -> Result<()> {
         let builder = filled_warning_tx_builder(&filled_deposit_tx_builder(false)?)?;
@@ -60,7 +60,8 @@ fn funded_bmp_wallet(env: &mut TestEnv) -> BMPWallet<Connection> {
 }
 
 fn funded_mem_wallet(env: &mut TestEnv) -> MemWallet {
-    let client = BdkElectrumClient::new(ElectrumClient::new(&env.electrum_url()).unwrap());
+    let electrum_url = env.electrum_url();
+    let client = BdkElectrumClient::new(ElectrumClient::new(&electrum_url).unwrap());
     let mut wallet = MemWallet::new(client).unwrap();
     let address = wallet.next_unused_address();
     let txid = env
@@ -68,7 +69,13 @@ fn funded_mem_wallet(env: &mut TestEnv) -> MemWallet {
         .unwrap();
     env.mine_block().unwrap();
     env.wait_for_tx(txid).unwrap();
-    wallet.sync().unwrap();
+
+    // Any further Electrum servers (comma separated) are tried in turn should the first fail.
+    let fallback_urls = std::env::var("ELECTRUM_FALLBACK_URLS").unwrap_or_default();
+    let urls: Vec<&str> = std::iter::once(electrum_url.as_str())
+        .chain(fallback_urls.split(',').filter(|url| !url.is_empty()))
+        .collect();
+    wallet.sync_with_fallback(&urls).unwrap();
     wallet
 }
 
@@ -292,8 +299,8 @@ fn test_q_tik() -> anyhow::Result<()> {
 
     // let grab the keys and produce new sig
     let q_tik = &mut alice.q_tik;
-    q_tik.set_peers_prv_key(*bob.q_tik.my_key_share()?.prv_key()?)?;
-    let agg_sec = *q_tik.aggregate_prv_key_shares()?;
+    q_tik.set_peers_prv_key(bob.q_tik.my_key_share()?.prv_key()?)?;
+    let agg_sec = q_tik.aggregate_prv_key_shares()?;
     let secp = Secp256k1::new();
     let keypair = Keypair::from_seckey_slice(&secp, &agg_sec.serialize())?;
     let merkle_root = alice.deposit_tx.merkle_root;
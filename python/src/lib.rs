@@ -0,0 +1,109 @@
+//! Python bindings for the trade protocol, for scripting and analysis (with the `python` feature).
+//!
+//! A `PyTradeModel` drives one side of a trade through the same steps as the daemon's gRPC
+//! handlers, by calling the very same step functions (see `rpc::server::steps`). Each step takes
+//! the JSON of the corresponding gRPC request and returns the JSON of its response, so that my
+//! private key share for the peer's payout output is only ever released as the daemon releases it:
+//! in the response to `sign_swap_tx` (by a seller ready to release) or `close_trade`. Build the
+//! module with `maturin develop` from this directory.
+#![cfg(feature = "python")]
+
+use std::fmt::Display;
+
+use bdk_wallet::serde::Serialize;
+use bdk_wallet::serde::de::DeserializeOwned;
+use bdk_wallet::serde_json;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use rpc::pb::musigrpc;
+use rpc::protocol::TradeModel;
+use rpc::server::{Config, steps};
+use tonic::{Code, Status};
+
+#[pyclass]
+pub struct PyTradeModel {
+    config: Config,
+    trade_model: TradeModel,
+}
+
+#[pymethods]
+impl PyTradeModel {
+    /// Start a new trade, given the JSON of a `PubKeySharesRequest`, with the trade limits of a
+    /// daemon of the default config.
+    #[new]
+    fn new(request: &str) -> PyResult<Self> {
+        let request = serde_json::from_str(request).map_err(value_error)?;
+        let trade_model = steps::init_trade(request).map_err(|e| py_error(&e))?;
+        Ok(Self { config: Config::default(), trade_model })
+    }
+
+    #[getter]
+    fn trade_id(&self) -> &str { self.trade_model.trade_id() }
+
+    /// The stage the trade has reached, e.g. `NONCE_SHARES_EXCHANGED`.
+    #[getter]
+    fn state(&self) -> &'static str {
+        musigrpc::TradeState::from(self.trade_model.state()).as_str_name()
+    }
+
+    /// Returns the JSON `PubKeySharesResponse`, with my public key shares.
+    fn pub_key_shares(&self) -> PyResult<String> {
+        to_json(&steps::pub_key_shares_response(&self.config, &self.trade_model).map_err(|e| py_error(&e))?)
+    }
+
+    /// Takes a JSON `NonceSharesRequest`, returning the JSON `NonceSharesMessage` for the peer.
+    fn get_nonce_shares(&mut self, request: &str) -> PyResult<String> {
+        run_step(request, |request| steps::get_nonce_shares(&self.config, request, &mut self.trade_model))
+    }
+
+    /// Takes a JSON `PartialSignaturesRequest`, returning the JSON `PartialSignaturesMessage`.
+    fn get_partial_signatures(&mut self, request: &str) -> PyResult<String> {
+        run_step(request, |request| steps::get_partial_signatures(&self.config, request, &mut self.trade_model))
+    }
+
+    /// Takes a JSON `DepositTxSignatureRequest`, returning the JSON `DepositPsbt`.
+    fn sign_deposit_tx(&mut self, request: &str) -> PyResult<String> {
+        run_step(request, |request| steps::sign_deposit_tx(request, &mut self.trade_model))
+    }
+
+    /// Takes a JSON `SwapTxSignatureRequest`, returning the JSON `SwapTxSignatureResponse`.
+    fn sign_swap_tx(&mut self, request: &str) -> PyResult<String> {
+        run_step(request, |request| steps::sign_swap_tx(request, &mut self.trade_model))
+    }
+
+    /// Takes a JSON `CloseTradeRequest`, with the peer's private key share for my payout output or
+    /// (as buyer) the signed swap tx, returning the JSON `CloseTradeResponse`. There is no wallet
+    /// to broadcast the swap tx with, so a seller can't force-close the trade here.
+    fn close_trade(&mut self, request: &str) -> PyResult<String> {
+        run_step(request, |request| steps::close_trade(request, &mut self.trade_model, None))
+    }
+}
+
+fn run_step<Req, Res, F>(request: &str, step: F) -> PyResult<String>
+    where Req: DeserializeOwned,
+          Res: Serialize,
+          F: FnOnce(Req) -> tonic::Result<Res> {
+    let request = serde_json::from_str(request).map_err(value_error)?;
+    to_json(&step(request).map_err(|e| py_error(&e))?)
+}
+
+fn to_json(response: &impl Serialize) -> PyResult<String> {
+    serde_json::to_string(response).map_err(runtime_error)
+}
+
+/// Map a failed step to a `ValueError` if the request was at fault, else to a `RuntimeError`.
+fn py_error(status: &Status) -> PyErr {
+    match status.code() {
+        Code::InvalidArgument | Code::NotFound | Code::OutOfRange => value_error(status.message()),
+        _ => runtime_error(status.message()),
+    }
+}
+
+fn value_error(e: impl Display) -> PyErr { PyValueError::new_err(e.to_string()) }
+
+fn runtime_error(e: impl Display) -> PyErr { PyRuntimeError::new_err(e.to_string()) }
+
+#[pymodule]
+fn bisq_musig_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyTradeModel>()
+}
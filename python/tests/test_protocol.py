@@ -0,0 +1,109 @@
+"""Runs the full two-party trade protocol between a seller (as maker) and buyer (as taker).
+
+Build and install the module into the current virtualenv first, with `maturin develop` from the
+parent directory, then run with `python -m unittest discover tests` (or pytest).
+"""
+
+import base64
+import json
+import unittest
+
+from bisq_musig_py import PyTradeModel
+
+TRADE_AMOUNT = 200_000
+SECURITY_DEPOSIT = 30_000
+DEPOSIT_TX_FEE_RATE = 12_500
+PREPARED_TX_FEE_RATE = 2_500
+
+REDIRECTION_ADDRESS = "bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz"
+P2TR_OUTPUT_WEIGHT = 172
+
+
+def init_trade(trade_id, role):
+    return PyTradeModel(json.dumps({"tradeId": trade_id, "myRole": role, "protocolVersion": 1}))
+
+
+def nonce_shares_request(peers_pub_key_shares):
+    peers_pub_key_shares = json.loads(peers_pub_key_shares)
+    return json.dumps({
+        "buyerOutputPeersPubKeyShare": peers_pub_key_shares["buyerOutputPubKeyShare"],
+        "sellerOutputPeersPubKeyShare": peers_pub_key_shares["sellerOutputPubKeyShare"],
+        "peersMultisigScriptKey": peers_pub_key_shares["multisigScriptKey"],
+        "depositTxFeeRate": DEPOSIT_TX_FEE_RATE,
+        "preparedTxFeeRate": PREPARED_TX_FEE_RATE,
+        "tradeAmount": TRADE_AMOUNT,
+        "buyersSecurityDeposit": SECURITY_DEPOSIT,
+        "sellersSecurityDeposit": SECURITY_DEPOSIT,
+    })
+
+
+def partial_signatures_request(peers_nonce_shares):
+    """A single redirection receiver taking the whole of the available amount, less its output cost."""
+    peers_nonce_shares = json.loads(peers_nonce_shares)
+    amount = (peers_nonce_shares["redirectionAmountMsat"] - PREPARED_TX_FEE_RATE * P2TR_OUTPUT_WEIGHT) // 1000
+    return json.dumps({
+        "peersNonceShares": peers_nonce_shares,
+        "redirectionReceivers": [{"address": REDIRECTION_ADDRESS, "amount": amount}],
+    })
+
+
+class TestProtocol(unittest.TestCase):
+    def test_unknown_role(self):
+        with self.assertRaises(ValueError):
+            init_trade("py-trade-bad-role", "ARBITRATOR")
+
+    def test_bad_peer_key_share(self):
+        trade = init_trade("py-trade-bad-key", "SELLER_AS_MAKER")
+        bad_key_share = base64.b64encode(b"\x02").decode()
+        request = json.loads(nonce_shares_request(trade.pub_key_shares()))
+        request["buyerOutputPeersPubKeyShare"] = bad_key_share
+        with self.assertRaises(ValueError):
+            trade.get_nonce_shares(json.dumps(request))
+
+    def test_full_protocol(self):
+        seller = init_trade("py-trade-seller", "SELLER_AS_MAKER")
+        buyer = init_trade("py-trade-buyer", "BUYER_AS_TAKER")
+        self.assertEqual(seller.state, "INITIALIZED")
+
+        # Round 1: exchange public key shares.
+        seller_key_shares = seller.pub_key_shares()
+        buyer_key_shares = buyer.pub_key_shares()
+        self.assertEqual(len(base64.b64decode(json.loads(seller_key_shares)["multisigScriptKey"])), 32)  # x-only
+
+        # Round 2: exchange nonce shares (with addresses & half deposit PSBTs).
+        seller_nonce_shares = seller.get_nonce_shares(nonce_shares_request(buyer_key_shares))
+        buyer_nonce_shares = buyer.get_nonce_shares(nonce_shares_request(seller_key_shares))
+        self.assertEqual(seller.state, "NONCE_SHARES_EXCHANGED")
+
+        # Round 3: exchange partial signatures, then sign the deposit tx.
+        seller_partial_signatures = seller.get_partial_signatures(partial_signatures_request(buyer_nonce_shares))
+        buyer_partial_signatures = buyer.get_partial_signatures(partial_signatures_request(seller_nonce_shares))
+        self.assertIn("swapTxInputSighash", seller_partial_signatures)
+        seller_deposit_psbt = seller.sign_deposit_tx(json.dumps({"peersPartialSignatures": json.loads(buyer_partial_signatures)}))
+        buyer_deposit_psbt = buyer.sign_deposit_tx(json.dumps({"peersPartialSignatures": json.loads(seller_partial_signatures)}))
+        for psbt in (seller_deposit_psbt, buyer_deposit_psbt):
+            self.assertTrue(base64.b64decode(json.loads(psbt)["depositPsbt"]).startswith(b"psbt\xff"))
+        self.assertEqual(buyer.state, "DEPOSIT_TX_SIGNED")
+
+        # Once payment has been sent, the buyer releases its partial signature on the swap tx, which
+        # the seller signs, releasing its private key share for the buyer's payout output only once
+        # ready to (on receipt of the payment).
+        buyer_partial_signatures = json.loads(buyer.get_partial_signatures(json.dumps({"buyerReadyToRelease": True})))
+        swap_tx_signature_request = {
+            "swapTxInputPeersPartialSignature": buyer_partial_signatures["swapTxInputPartialSignature"],
+        }
+        response = json.loads(seller.sign_swap_tx(json.dumps(swap_tx_signature_request)))
+        self.assertEqual(response["peerOutputPrvKeyShare"], "")
+        swap_tx_signature_request["sellerReadyToRelease"] = True
+        sellers_prv_key_share = json.loads(seller.sign_swap_tx(json.dumps(swap_tx_signature_request)))["peerOutputPrvKeyShare"]
+
+        # Close cooperatively: the buyer releases its private key share for the seller's payout
+        # output in return for the seller's.
+        buyers_prv_key_share = json.loads(buyer.close_trade(json.dumps({"myOutputPeersPrvKeyShare": sellers_prv_key_share})))["peerOutputPrvKeyShare"]
+        self.assertEqual(json.loads(seller.close_trade(json.dumps({"myOutputPeersPrvKeyShare": buyers_prv_key_share})))["peerOutputPrvKeyShare"],
+                         sellers_prv_key_share)
+        self.assertEqual((seller.state, buyer.state), ("CLOSED", "CLOSED"))
+
+
+if __name__ == "__main__":
+    unittest.main()
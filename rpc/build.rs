@@ -1,17 +1,59 @@
 use std::borrow::Cow;
 
+#[expect(clippy::too_many_lines, reason = "one builder chain, configuring the Serde derives of every proto type")]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_prost_build::configure()
+        // Only generate the clients' 'connect' fns where there is a transport to connect with, as there
+        // is none in WASM builds:
+        .build_transport(std::env::var_os("CARGO_FEATURE_NATIVE").is_some())
+
         // Add Serde serialization for walletrpc request types...
         .serde_serialized_types(&[
-            "WalletBalanceRequest", "NewAddressRequest", "ListUnspentRequest"
+            "WalletBalanceRequest", "NewAddressRequest", "ListUnspentRequest", "SendRequest",
+            "VerifyAddressRequest", "LockWalletRequest", "GetSilentPaymentAddressRequest",
+            "ExportTransactionsCsvRequest", "ImportWatchDescriptorRequest", "SignMessageRequest", "VerifyMessageRequest"
+        ])
+        .serde_serialized_type("CreateWalletRequest", &[
+            secret("descriptor")
+        ])
+        .serde_serialized_type("UnlockWalletRequest", &[
+            secret("passphrase")
         ])
         .serde_serialized_type("ConfRequest", &[
             rev_hex("txId")
         ])
+        .serde_serialized_type("GetUtxoRequest", &[
+            rev_hex("txId")
+        ])
+        .serde_serialized_type("GetTxFeeRequest", &[
+            rev_hex("txId")
+        ])
+        .serde_serialized_type("BroadcastTxRequest", &[
+            hex("rawTx")
+        ])
+        .serde_serialized_type("SignPsbtRequest", &[
+            base64("psbt")
+        ])
 
         // Add Serde serialization for walletrpc response types...
-        .serde_serialized_types(&["WalletBalanceResponse", "NewAddressResponse", "ListUnspentResponse"])
+        .serde_serialized_types(&[
+            "WalletBalanceResponse", "NewAddressResponse", "ListUnspentResponse", "VerifyAddressResponse",
+            "CreateWalletResponse", "LockWalletResponse", "UnlockWalletResponse", "GetSilentPaymentAddressResponse",
+            "ImportWatchDescriptorResponse", "GetUtxoResponse", "GetTxFeeResponse", "SignMessageResponse",
+            "VerifyMessageResponse"
+        ])
+        .serde_serialized_type("SendResponse", &[
+            rev_hex("txId"), hex("tx")
+        ])
+        .serde_serialized_type("BroadcastTxResponse", &[
+            rev_hex("txId")
+        ])
+        .serde_serialized_type("SignPsbtResponse", &[
+            base64("psbt")
+        ])
+        .serde_serialized_type("ExportTransactionsCsvResponse", &[
+            base64("csvData")
+        ])
         .serde_serialized_type("TransactionOutput", &[
             rev_hex("txId"), hex("scriptPubKey")
         ])
@@ -27,21 +69,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .serde_serialized_types(&[
             "ReceiverAddressAndAmount", "PartialSignaturesRequest", "DepositTxSignatureRequest",
             "PublishDepositTxRequest", "SubscribeTxConfirmationStatusRequest", "ContractualTxIds",
-            "CustomPayoutPsbtRequest"
+            "CustomPayoutPsbtRequest", "CancelTradeRequest", "ResetTradeRequest", "ListTradesRequest",
+            "GetTradeRequest", "GetTradeStatsRequest", "GetTradeReceiptRequest", "GetRecoveryTxRequest"
         ])
         .serde_serialized_type("PubKeySharesRequest", &[
             enum_field("myRole", "Role")
         ])
         .serde_serialized_type("NonceSharesRequest", &[
             base64("buyerOutputPeersPubKeyShare"), base64("sellerOutputPeersPubKeyShare"),
-            base64("peersMultisigScriptKey")
+            base64("peersMultisigScriptKey"), opt_base64("adaptorPoint"), opt_base64("adaptorSecret")
         ])
         .serde_serialized_type("NonceSharesMessage", &[
             base64("halfDepositPsbt"), base64("swapTxInputNonceShare"),
             base64("buyersWarningTxBuyerInputNonceShare"), base64("buyersWarningTxSellerInputNonceShare"),
             base64("sellersWarningTxBuyerInputNonceShare"), base64("sellersWarningTxSellerInputNonceShare"),
             base64("buyersRedirectTxInputNonceShare"), base64("sellersRedirectTxInputNonceShare"),
-            base64("buyersClaimTxInputNonceShare"), base64("sellersClaimTxInputNonceShare")
+            base64("buyersClaimTxInputNonceShare"), base64("sellersClaimTxInputNonceShare"),
+            opt_base64("adaptorPoint")
         ])
         .serde_serialized_type("PartialSignaturesMessage", &[
             base64("peersWarningTxBuyerInputPartialSignature"), base64("peersWarningTxSellerInputPartialSignature"),
@@ -52,7 +96,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             base64("depositPsbt")
         ])
         .serde_serialized_type("SwapTxSignatureRequest", &[
-            base64("swapTxInputPeersPartialSignature")
+            base64("swapTxInputPeersPartialSignature"), opt_base64("adaptorSecret")
         ])
         .serde_serialized_type("CloseTradeRequest", &[
             opt_base64("myOutputPeersPrvKeyShare"), opt_hex("swapTx")
@@ -74,7 +118,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             hex("swapTx"), base64("peerOutputPrvKeyShare")
         ])
         .serde_serialized_type("CloseTradeResponse", &[
-            base64("peerOutputPrvKeyShare")
+            base64("peerOutputPrvKeyShare"), rev_hex("swapTxId")
         ])
         .serde_serialized_type("CustomPayoutPsbt", &[
             base64("psbt")
@@ -82,6 +126,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .serde_serialized_type("CustomCloseTradeResponse", &[
             hex("customPayoutTx")
         ])
+        .serde_serialized_type("GetRecoveryTxResponse", &[
+            hex("warningTx"), hex("claimTx")
+        ])
+        .serde_serialized_type("GetDisputeEvidenceRequest", &[
+            hex("arbitratorPubKey")
+        ])
+        .serde_serialized_type("GetDisputeEvidenceResponse", &[
+            base64("evidenceJson"), hex("signature")
+        ])
+        .serde_serialized_types(&[
+            "CancelTradeResponse", "ResetTradeResponse", "ListTradesResponse", "GetTradeResponse", "TradeDetails",
+            "GetTradeStatsResponse", "TradeStats", "GetTradeReceiptResponse"
+        ])
+        .serde_serialized_type("TradeSummary", &[
+            enum_field("role", "Role"), enum_field("state", "TradeState")
+        ])
+        .serde_serialized_enum("TradeState")
+
+        // Add Serde serialization for adminrpc types...
+        .serde_serialized_types(&["SetLogLevelRequest", "SetLogLevelResponse"])
+
+        // Add Serde deserialization for the (unary) request types, for the JSON-RPC interface...
+        .serde_deserialized_request_types()
 
         // Now compile all the protos...
         .compile_protos(
@@ -90,9 +157,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "src/main/proto/wallet.proto",
                 "src/main/proto/bmp_protocol.proto",
                 "src/main/proto/bmp_wallet.proto",
+                "src/main/proto/admin.proto",
             ],
             &["src/main/proto"],
         )?;
+
+    #[cfg(feature = "ffi")]
+    build_ffi()?;
+    Ok(())
+}
+
+/// Generate the C header of the FFI bindings, then compile the C test against it, for the 'ffi'
+/// integration test to link.
+#[cfg(feature = "ffi")]
+fn build_ffi() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=tests/ffi_test.c");
+
+    cbindgen::Builder::new()
+        .with_src("src/ffi.rs")
+        .with_language(cbindgen::Language::C)
+        .with_include_guard("BISQ_MUSIG_H")
+        .with_after_include("\ntypedef struct TradeModel TradeModel;")
+        .generate()?
+        .write_to_file(out_dir.join("bisq_musig.h"));
+
+    cc::Build::new()
+        .file("tests/ffi_test.c")
+        .include(&out_dir)
+        .warnings_into_errors(true)
+        .cargo_metadata(false)
+        .compile("ffi_test");
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
     Ok(())
 }
 
@@ -118,6 +215,11 @@ const fn opt_base64(field: &str) -> CustomField<'_> {
     (field, Cow::Borrowed("#[serde_as(as = \"::core::option::Option<::serde_with::base64::Base64>\")]"))
 }
 
+/// Leave out a field holding secrets (e.g. private keys), so that it is never logged.
+const fn secret(field: &str) -> CustomField<'_> {
+    (field, Cow::Borrowed("#[serde(skip_serializing)]"))
+}
+
 fn enum_field<'a>(field: &'a str, type_name: &'_ str) -> CustomField<'a> {
     (field, Cow::Owned(format!("#[serde_as(as = \"::serde_with::TryFromInto<{type_name}>\")]")))
 }
@@ -133,6 +235,29 @@ trait BuilderEx {
         }
         self
     }
+
+    /// Adds Serde deserialization to an enum already made serializable (in the same format).
+    fn serde_deserialized_enum(self, path: &str) -> Self;
+
+    /// Adds Serde deserialization to types already made serializable (in the same format), with
+    /// absent fields taking their default values, as for proto3.
+    fn serde_deserialized_types(self, paths: &[&str]) -> Self;
+
+    fn serde_deserialized_request_types(self) -> Self where Self: Sized {
+        self.serde_deserialized_enum("Role").serde_deserialized_types(&[
+            "WalletBalanceRequest", "NewAddressRequest", "ListUnspentRequest", "GetUtxoRequest", "GetTxFeeRequest",
+            "SendRequest",
+            "BroadcastTxRequest", "SignPsbtRequest", "VerifyAddressRequest", "CreateWalletRequest", "LockWalletRequest",
+            "GetSilentPaymentAddressRequest", "ExportTransactionsCsvRequest", "ImportWatchDescriptorRequest",
+            "SignMessageRequest", "VerifyMessageRequest",
+            "UnlockWalletRequest", "PubKeySharesRequest", "NonceSharesRequest", "ReceiverAddressAndAmount",
+            "PartialSignaturesRequest", "NonceSharesMessage", "DepositTxSignatureRequest",
+            "PartialSignaturesMessage", "ContractualTxIds", "SwapTxSignatureRequest",
+            "CloseTradeRequest", "CustomPayoutPsbtRequest", "CustomCloseTradeRequest",
+            "CancelTradeRequest", "ResetTradeRequest", "ListTradesRequest", "GetTradeRequest", "GetTradeStatsRequest",
+            "GetTradeReceiptRequest", "GetRecoveryTxRequest", "GetDisputeEvidenceRequest"
+        ])
+    }
 }
 
 impl BuilderEx for tonic_prost_build::Builder {
@@ -151,4 +276,17 @@ impl BuilderEx for tonic_prost_build::Builder {
         }
         self
     }
+
+    fn serde_deserialized_enum(self, path: &str) -> Self {
+        self.enum_attribute(path, "#[derive(::serde::Deserialize)]")
+    }
+
+    fn serde_deserialized_types(mut self, paths: &[&str]) -> Self {
+        for &path in paths {
+            self = self
+                .type_attribute(path, "#[derive(::serde::Deserialize)]")
+                .type_attribute(path, "#[serde(default)]");
+        }
+        self
+    }
 }
@@ -0,0 +1,69 @@
+//! A background task fee-bumping the deposit txs of trades which have gone unconfirmed for too
+//! long, as the mempool fee rate rises above that which the traders agreed on.
+
+use std::sync::Arc;
+
+use tokio::time::{self, Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::protocol::{TRADE_MODELS, TradeModelStore as _, TradeState};
+use crate::wallet::WalletManager;
+
+const POLL_PERIOD: Duration = Duration::from_secs(10);
+
+pub struct TransactionAccelerator {
+    wallet_manager: Arc<WalletManager>,
+    last_block_height: Option<u32>,
+}
+
+impl TransactionAccelerator {
+    /// Fee-bump each trade's deposit tx with the manager's wallet that the trade was created with.
+    pub const fn new(wallet_manager: Arc<WalletManager>) -> Self {
+        Self { wallet_manager, last_block_height: None }
+    }
+
+    /// Check for stuck deposit txs after each new block, until `shutdown` is cancelled.
+    pub async fn run(mut self, shutdown: CancellationToken) {
+        let mut interval = time::interval(POLL_PERIOD);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                () = shutdown.cancelled() => return,
+                _ = interval.tick() => self.poll().await,
+            }
+        }
+    }
+
+    async fn poll(&mut self) {
+        let block_height = self.wallet_manager.default_wallet().block_height();
+        if self.last_block_height.replace(block_height) == Some(block_height) {
+            return;
+        }
+        for trade_model in TRADE_MODELS.trade_models() {
+            // Copy what is needed out of the trade model, so as not to hold its lock across wallet I/O.
+            let (trade_id, wallet_id, txid, deposit_psbt) = {
+                let trade_model = trade_model.lock().await;
+                // Only a published deposit tx of an open trade may need fee-bumping.
+                if trade_model.state().is_pre_deposit() || trade_model.state() >= TradeState::ForceClosing {
+                    continue;
+                }
+                let (Some(txid), Some(deposit_psbt)) = (trade_model.get_deposit_txid(), trade_model.get_deposit_psbt())
+                    else { continue };
+                (trade_model.trade_id().to_owned(), trade_model.wallet_id().to_owned(), txid, deposit_psbt.clone())
+            };
+            let Some(wallet) = self.wallet_manager.get_wallet(&wallet_id) else {
+                warn!(trade_id, wallet_id, "Could not auto-accelerate deposit tx of trade with unknown wallet.");
+                continue;
+            };
+            // The wallet funds only some of the deposit tx inputs, so needs the rest to find its fee.
+            wallet.insert_prevouts(&deposit_psbt);
+            match wallet.auto_accelerate(txid).await {
+                Ok(Some(child_txid)) => info!(trade_id, %txid, %child_txid,
+                    "Auto-accelerated stuck deposit tx with CPFP child tx."),
+                Ok(None) => debug!(trade_id, %txid, "Deposit tx is not stuck."),
+                Err(e) => warn!(trade_id, %txid, "Could not auto-accelerate deposit tx: {e}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! An append-only log of trade events, for dispute resolution and post-mortem analysis.
+//!
+//! Each entry is written as a single line of JSON, so that the log may be tailed or processed with
+//! standard tools, and so that a torn write (say from a crash) can only damage the last entry.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead as _, BufReader, Write as _};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bdk_wallet::bitcoin::{Amount, Txid};
+use bdk_wallet::serde_json;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::pb::musigrpc;
+use crate::protocol::{TradeModel, TradeState};
+
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Open the audit log at the given path for appending, creating it if it doesn't yet exist.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file could not be opened
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// Append an entry to the log, flushing it to the OS straight away.
+    ///
+    /// # Errors
+    /// Will return `Err` if the entry could not be written
+    pub fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        // Write the line in a single call, so that concurrent writers to the file can't interleave.
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+
+    /// Read back all the entries of the audit log at the given path, in the order they were written.
+    /// Any lines that can't be parsed, such as a final line torn by a crash, are skipped with a
+    /// warning.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file could not be opened
+    pub fn replay(path: &Path) -> io::Result<impl Iterator<Item = AuditEntry> + use<>> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(reader.lines().enumerate().filter_map(|(i, line)| {
+            let entry = line.map_err(serde_json::Error::io)
+                .and_then(|line| serde_json::from_str(&line));
+            entry.inspect_err(|e| warn!(line_number = i + 1, "Skipping bad audit log entry: {e}")).ok()
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp_unix_ms: u64,
+    pub trade_id: String,
+    pub event_type: AuditEventType,
+    pub role: musigrpc::Role,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "bdk_wallet::bitcoin::amount::serde::as_sat::opt")]
+    pub trade_amount: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "bdk_wallet::bitcoin::amount::serde::as_sat::opt")]
+    pub buyers_security_deposit: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "bdk_wallet::bitcoin::amount::serde::as_sat::opt")]
+    pub sellers_security_deposit: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deposit_txid: Option<Txid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swap_txid: Option<Txid>,
+}
+
+impl AuditEntry {
+    /// An entry for the given event of the trade, recording the trade's amounts & txids as far as
+    /// they are known at this point.
+    pub(crate) fn new(event_type: AuditEventType, trade_model: &TradeModel) -> Self {
+        Self {
+            timestamp_unix_ms: unix_time_now_ms(),
+            trade_id: trade_model.trade_id().to_owned(),
+            event_type,
+            role: trade_model.my_role().into(),
+            trade_amount: trade_model.trade_amount(),
+            buyers_security_deposit: trade_model.buyers_security_deposit(),
+            sellers_security_deposit: trade_model.sellers_security_deposit(),
+            deposit_txid: trade_model.get_deposit_txid(),
+            swap_txid: trade_model.get_swap_txid(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum AuditEventType {
+    TradeInitiated,
+    NonceSharesExchanged,
+    PartialSignaturesExchanged,
+    DepositTxSigned,
+    DepositBroadcast,
+    SwapTxSigned,
+    TradeForceClosing,
+    TradeClosed,
+    TradeCancelled,
+}
+
+impl From<TradeState> for AuditEventType {
+    fn from(value: TradeState) -> Self {
+        match value {
+            TradeState::Initialized => Self::TradeInitiated,
+            TradeState::NonceSharesExchanged => Self::NonceSharesExchanged,
+            TradeState::PartialSignaturesExchanged => Self::PartialSignaturesExchanged,
+            TradeState::DepositTxSigned => Self::DepositTxSigned,
+            TradeState::DepositTxPublished => Self::DepositBroadcast,
+            TradeState::SwapTxSigned => Self::SwapTxSigned,
+            TradeState::ForceClosing => Self::TradeForceClosing,
+            TradeState::Closed => Self::TradeClosed,
+        }
+    }
+}
+
+fn unix_time_now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn entry(trade_id: &str, event_type: AuditEventType) -> AuditEntry {
+        AuditEntry {
+            timestamp_unix_ms: 1_750_000_000_000,
+            trade_id: trade_id.to_owned(),
+            event_type,
+            role: musigrpc::Role::SellerAsMaker,
+            trade_amount: Some(Amount::from_sat(200_000)),
+            buyers_security_deposit: None,
+            sellers_security_deposit: None,
+            deposit_txid: None,
+            swap_txid: None,
+        }
+    }
+
+    #[test]
+    fn test_append_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit-log.jsonl");
+        let entries = [
+            entry("trade-1", AuditEventType::TradeInitiated),
+            entry("trade-1", AuditEventType::NonceSharesExchanged),
+            entry("trade-2", AuditEventType::TradeCancelled),
+        ];
+
+        let audit_log = AuditLog::open(&path).unwrap();
+        audit_log.append(&entries[0]).unwrap();
+        audit_log.append(&entries[1]).unwrap();
+        drop(audit_log);
+        // Reopening should append to, rather than overwrite, the existing entries.
+        AuditLog::open(&path).unwrap().append(&entries[2]).unwrap();
+
+        let first_line = fs::read_to_string(&path).unwrap().lines().next().unwrap().to_owned();
+        assert_eq!(first_line, r#"{"timestamp_unix_ms":1750000000000,"trade_id":"trade-1","event_type":"TradeInitiated","role":"SELLER_AS_MAKER","trade_amount":200000}"#);
+        assert_eq!(AuditLog::replay(&path).unwrap().collect::<Vec<_>>(), entries);
+
+        // A torn final line is skipped on replay.
+        fs::write(&path, fs::read_to_string(&path).unwrap() + r#"{"timestamp_unix_ms":17"#).unwrap();
+        assert_eq!(AuditLog::replay(&path).unwrap().count(), 3);
+    }
+}
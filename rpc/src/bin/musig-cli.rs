@@ -4,7 +4,7 @@ use clap::{Parser, Subcommand};
 use futures_util::StreamExt as _;
 use rpc::pb::walletrpc::wallet_client::WalletClient;
 use rpc::pb::walletrpc::{
-    ConfRequest, ListUnspentRequest, NewAddressRequest, WalletBalanceRequest,
+    ConfRequest, ListUnspentRequest, NewAddressRequest, SendRequest, WalletBalanceRequest,
 };
 use tonic::Request;
 
@@ -16,6 +16,9 @@ struct Cli {
     /// The port of the MuSig daemon
     #[arg(short, long, default_value_t = 50051)]
     port: u16,
+    /// The ID of the wallet to use, as created on the daemon [default: the daemon's default wallet]
+    #[arg(long, default_value_t)]
+    wallet_id: String,
     #[command(subcommand)]
     commands: Commands,
 }
@@ -30,39 +33,63 @@ enum Commands {
     ListUnspent,
     /// Receive a stream of confidence events for the given txid
     NotifyConfidence { tx_id: String },
+    /// Send the given amount (in sats) to an address
+    Send {
+        address: String,
+        amount: u64,
+        /// Absolute lock time: a block height if below 500000000, else a Unix time
+        #[arg(long)]
+        lock_time: Option<u32>,
+        /// Don't signal opt-in RBF
+        #[arg(long)]
+        no_rbf: bool,
+        /// Spend the coins of greatest coin age (value times confirmations) first
+        #[arg(long)]
+        coin_age_priority: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli: Cli = Cli::parse();
 
+    let wallet_id = cli.wallet_id;
     let mut client = WalletClient::connect(format!("http://127.0.0.1:{}", cli.port)).await?;
 
     match cli.commands {
         Commands::WalletBalance => {
-            let response = client.wallet_balance(Request::new(WalletBalanceRequest {})).await?;
+            let response = client.wallet_balance(Request::new(WalletBalanceRequest { wallet_id })).await?;
             drop(client);
             println!("{}", serde_json::to_string_pretty(&response.into_inner())?);
         }
         Commands::NewAddress => {
-            let response = client.new_address(Request::new(NewAddressRequest {})).await?;
+            let response = client.new_address(Request::new(NewAddressRequest { wallet_id })).await?;
             drop(client);
             println!("{}", serde_json::to_string_pretty(&response.into_inner())?);
         }
         Commands::ListUnspent => {
-            let response = client.list_unspent(Request::new(ListUnspentRequest {})).await?;
+            let response = client.list_unspent(Request::new(ListUnspentRequest { wallet_id })).await?;
             drop(client);
             println!("{}", serde_json::to_string_pretty(&response.into_inner())?);
         }
         Commands::NotifyConfidence { tx_id } => {
             let tx_id = tx_id.parse::<sha256d::Hash>()?.to_byte_array().into();
-            let response = client.register_confidence_ntfn(Request::new(ConfRequest { tx_id })).await?;
+            let response = client.register_confidence_ntfn(Request::new(ConfRequest { tx_id, wallet_id })).await?;
             drop(client);
             let mut stream = response.into_inner();
             while let Some(event_result) = stream.next().await {
                 println!("{}", serde_json::to_string_pretty(&event_result?)?);
             }
         }
+        Commands::Send { address, amount, lock_time, no_rbf, coin_age_priority } => {
+            let enable_rbf = no_rbf.then_some(false);
+            let coin_age_priority = coin_age_priority.then_some(true);
+            let response = client.send(Request::new(SendRequest {
+                address, amount, lock_time, enable_rbf, wallet_id, coin_age_priority,
+            })).await?;
+            drop(client);
+            println!("{}", serde_json::to_string_pretty(&response.into_inner())?);
+        }
     }
     Ok(())
 }
@@ -1,14 +1,41 @@
 use std::error::Error;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use bdk_bitcoind_rpc::bitcoincore_rpc::{Auth, Client as BitcoinCoreClient};
+use bdk_bitcoind_rpc::bitcoincore_rpc::Auth;
+use bdk_wallet::bitcoin::{Amount, FeeRate};
 use bmp_tracing::tracing::info;
 use clap::Parser;
+use rpc::accelerator::TransactionAccelerator;
+use rpc::audit::AuditLog;
 use rpc::bmp_wallet_service::BmpWalletServiceImpl;
+use rpc::dead_mans_switch::DeadMansSwitch;
+use rpc::explorer::BlockExplorerConfig;
+use rpc::fee_alert::{FeeAlertService, FeeAnomalyGuard};
+use rpc::fee_estimator::MempoolSpaceFeeEstimator;
+#[cfg(feature = "jsonrpc")]
+use rpc::jsonrpc::JsonRpcImpl;
+use rpc::message_log::MessageLog;
+#[cfg(feature = "rest")]
+use rpc::rest;
+#[cfg(feature = "metrics")]
+use rpc::stats::metrics;
 use rpc::pb::bmp_wallet::wallet_server::WalletServer as BmpWalletServer;
-use rpc::server::{MusigImpl, MusigServer, WalletImpl, WalletServer};
-use rpc::wallet::WalletServiceImpl;
+use rpc::server::{AdminImpl, AdminServer, Config, MusigImpl, MusigServer, WalletImpl, WalletServer};
+use rpc::wallet::{BitcoindRpcConnectionManager, ElectrumConfig, WalletBackend, WalletManager, WalletService as _, WalletServiceImpl};
+#[cfg(any(feature = "jsonrpc", feature = "metrics", feature = "rest"))]
+use tokio::net::TcpListener;
+use tokio::time::Duration;
+use tokio::{signal, task};
+use tokio_util::sync::CancellationToken;
+use tonic::service::InterceptorLayer;
 use tonic::transport::Server;
+use zeroize::Zeroizing;
+
+const DEFAULT_TOR_PROXY: &str = "127.0.0.1:9050";
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -29,38 +56,310 @@ struct Cli {
     /// Bitcoin Core RPC password
     #[arg(long)]
     bitcoin_rpc_pass: Option<String>,
+
+    /// The maximum number of concurrent Bitcoin Core RPC connections, one of which is held for syncing
+    #[arg(long, default_value_t = BitcoindRpcConnectionManager::DEFAULT_POOL_SIZE,
+        value_parser = clap::value_parser!(u32).range(i64::from(BitcoindRpcConnectionManager::MIN_POOL_SIZE)..))]
+    bitcoin_rpc_pool_size: u32,
+
+    /// Sync the wallet with compact block filters from the P2P node at this address, instead of
+    /// using Bitcoin Core RPC
+    #[arg(long, group = "proxiable_backend")]
+    compact_block_filters_peer: Option<String>,
+
+    /// Sync the wallet from the Electrum server at this URL (tcp:// or ssl://), instead of using
+    /// Bitcoin Core RPC
+    #[arg(long, group = "proxiable_backend")]
+    electrum_url: Option<String>,
+
+    /// Number of consecutive unused addresses after which an Electrum scan of each keychain stops
+    #[arg(long, default_value_t = Config::default().electrum_stop_gap)]
+    electrum_stop_gap: usize,
+
+    /// Number of addresses to look up per request to the Electrum server
+    #[arg(long, default_value_t = Config::default().electrum_batch_size)]
+    electrum_batch_size: usize,
+
+    /// Bitcoin Core ZMQ 'hashblock' endpoint, to be notified of new blocks instead of polling
+    #[arg(long, requires = "zmq_raw_tx_endpoint")]
+    zmq_block_hash_endpoint: Option<String>,
+
+    /// Bitcoin Core ZMQ 'rawtx' endpoint, to be notified of new txs instead of polling
+    #[arg(long, requires = "zmq_block_hash_endpoint")]
+    zmq_raw_tx_endpoint: Option<String>,
+
+    /// Route P2P & Electrum traffic through the Tor SOCKS5 proxy at this address. Bitcoin Core RPC &
+    /// ZMQ connections can't be proxied, so it may only be used along with compact block filters or
+    /// an Electrum server
+    #[arg(long, num_args = 0..=1, default_missing_value = DEFAULT_TOR_PROXY, requires = "proxiable_backend")]
+    tor_proxy: Option<SocketAddr>,
+
+    /// mempool.space instance to get fee rate estimates from when the node has none. Its requests
+    /// can't be routed through Tor, so it may not be used along with a Tor proxy
+    #[arg(long, conflicts_with = "tor_proxy")]
+    mempool_space_url: Option<String>,
+
+    /// Maximum number of times to retry a failed wallet connection [default: unlimited]
+    #[arg(long)]
+    max_connection_retries: Option<u32>,
+
+    /// Unlock the wallet with the passphrase in this file, allowing it to be locked [default: wallet
+    /// can't be locked]
+    #[arg(long)]
+    wallet_passphrase_file: Option<PathBuf>,
+
+    /// Persist each wallet created with `CreateWallet` to a database in this directory, named after
+    /// its wallet ID [default: created wallets aren't persisted]
+    #[arg(long)]
+    wallet_data_dir: Option<PathBuf>,
+
+    /// Append a JSON line to this file for every trade event, for dispute resolution
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Append a JSON line to this file for every trade protocol request & response, for replay with
+    /// `bisq-musig-cli replay-log`, leaving out private key shares (must be private to the daemon's user)
+    #[arg(long)]
+    message_log: Option<PathBuf>,
+
+    /// Minimum trade amount to accept, in sats
+    #[arg(long, default_value_t = Config::default().min_trade_amount.to_sat())]
+    min_trade_amount: u64,
+
+    /// Maximum trade amount to accept, in sats [default: unlimited]
+    #[arg(long)]
+    max_trade_amount: Option<u64>,
+
+    /// Minimum security deposit of each trader to accept, as a fraction of the trade amount
+    #[arg(long, default_value_t = Config::default().min_security_deposit_ratio)]
+    min_security_deposit_ratio: f64,
+
+    /// Maximum difference of the peer's proposed fee rates from ours to accept, in percent
+    #[arg(long, default_value_t = Config::default().fee_rate_tolerance_pct)]
+    fee_rate_tolerance_pct: u8,
+
+    /// Maximum number of trades to hold at once, beyond which new trades are rejected
+    #[arg(long, default_value_t = Config::default().max_concurrent_trades)]
+    max_concurrent_trades: usize,
+
+    /// Require gRPC, JSON-RPC & REST clients to send the key in this file in the x-api-key request
+    /// header [default: no authentication]
+    #[arg(long)]
+    api_key_file: Option<PathBuf>,
+
+    /// Warn when the estimated next-block fee rate exceeds this, in sats per vbyte
+    #[arg(long, default_value_t = 100)]
+    high_fee_threshold: u64,
+
+    /// Log an error when the fee rate has stayed above the threshold for this many seconds
+    #[arg(long, default_value_t = 3600)]
+    fee_alert_duration_secs: u64,
+
+    /// CPFP fee-bump a trade's deposit tx once it has gone unconfirmed for this many seconds, if the
+    /// next-block fee rate has risen above its own
+    #[arg(long, default_value_t = 3600)]
+    stuck_tx_timeout_secs: u64,
+
+    /// As buyer, force-close a trade by broadcasting the warning tx once this many seconds have
+    /// passed since sending the payment without the seller closing it [default: never]
+    #[arg(long)]
+    payment_timeout_secs: Option<u64>,
+
+    /// Pause new trades while the next-block fee rate is over this many times its median
+    #[arg(long, default_value_t = FeeAnomalyGuard::DEFAULT_ANOMALY_FACTOR)]
+    fee_anomaly_factor: u32,
+
+    /// Number of minutely fee rate samples to take the median of [default: 7 days' worth]
+    #[arg(long, default_value_t = FeeAnomalyGuard::DEFAULT_WINDOW_SIZE)]
+    fee_anomaly_window_size: usize,
+
+    /// Root URL of the block explorer to link txs to [default: mempool.space, or local Esplora on regtest]
+    #[arg(long)]
+    block_explorer_url: Option<String>,
+
+    /// The port of the JSON-RPC server
+    #[cfg(feature = "jsonrpc")]
+    #[arg(long, default_value_t = 50052)]
+    jsonrpc_port: u16,
+
+    /// The port of the REST wallet API
+    #[cfg(feature = "rest")]
+    #[arg(long, default_value_t = 8080)]
+    rest_port: u16,
+
+    /// The port to serve Prometheus metrics on, from the path /metrics
+    #[cfg(feature = "metrics")]
+    #[arg(long, default_value_t = 9090)]
+    metrics_port: u16,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let cli: Cli = Cli::parse();
     bmp_tracing::init("info");
-    // Create RPC client. (No connection is made at this point.)
-    let rpc_client = {
-        let auth = if let (Some(user), Some(pass)) = (&cli.bitcoin_rpc_user, &cli.bitcoin_rpc_pass) {
-            Auth::UserPass(user.clone(), pass.clone())
-        } else {
-            Auth::None
-        };
-        BitcoinCoreClient::new(&cli.bitcoin_rpc_url, auth)?
-    };
 
     let addr = format!("127.0.0.1:{}", cli.port).parse()?;
-    let musig = MusigImpl::default();
-    let wallet = WalletImpl {
-        wallet_service: Arc::new(WalletServiceImpl::new()),
+    let mut config = Config {
+        min_trade_amount: Amount::from_sat(cli.min_trade_amount),
+        max_trade_amount: cli.max_trade_amount.map_or(Config::default().max_trade_amount, Amount::from_sat),
+        min_security_deposit_ratio: cli.min_security_deposit_ratio,
+        fee_rate_tolerance_pct: cli.fee_rate_tolerance_pct,
+        max_concurrent_trades: cli.max_concurrent_trades,
+        api_key: cli.api_key_file.as_deref().map(read_secret).transpose()?.map(|api_key| api_key.to_string()),
+        block_explorer: None,
+        tor_proxy: cli.tor_proxy,
+        electrum_stop_gap: cli.electrum_stop_gap,
+        electrum_batch_size: cli.electrum_batch_size,
     };
-    wallet.wallet_service.clone().spawn_connection(Arc::new(rpc_client));
+    let backend = wallet_backend(&cli, &config);
+    let mut wallet_service = WalletServiceImpl::new()
+        .with_tor_proxy(config.tor_proxy)
+        .with_max_retries(cli.max_connection_retries)
+        .with_passphrase(cli.wallet_passphrase_file.as_deref().map(read_secret).transpose()?.as_deref().map(String::as_str))
+        .with_stuck_tx_timeout(Duration::from_secs(cli.stuck_tx_timeout_secs));
+    if let Some(url) = cli.mempool_space_url {
+        info!(url, "Falling back to mempool.space fee estimates.");
+        wallet_service = wallet_service.with_fee_estimator(MempoolSpaceFeeEstimator::new(url));
+    }
+    let wallet_service = Arc::new(wallet_service);
+    config.block_explorer = Some(cli.block_explorer_url.as_deref().map_or_else(
+        || BlockExplorerConfig::for_network(wallet_service.network()), BlockExplorerConfig::new));
+    let shutdown = CancellationToken::new();
+    task::spawn(cancel_on_shutdown_signal(shutdown.clone()));
+    let wallet_manager = Arc::new(WalletManager::new(wallet_service.clone())
+        .with_data_dir(cli.wallet_data_dir)
+        .with_backend(backend.clone(), shutdown.clone())
+        .with_tor_proxy(config.tor_proxy));
+    let api_key_interceptor = config.api_key_interceptor();
+    #[cfg(any(feature = "jsonrpc", feature = "rest"))]
+    let http_config = config.clone();
+    let fee_anomaly_guard = Arc::new(FeeAnomalyGuard::new(cli.fee_anomaly_factor, cli.fee_anomaly_window_size));
+    let mut musig = MusigImpl::new(wallet_service.clone()).with_config(config)
+        .with_wallet_manager(wallet_manager.clone())
+        .with_fee_anomaly_guard(fee_anomaly_guard.clone());
+    if let Some(path) = &cli.audit_log {
+        info!(path = %path.display(), "Writing trade events to audit log.");
+        musig = musig.with_audit_log(AuditLog::open(path)?);
+    }
+    if let Some(path) = &cli.message_log {
+        info!(path = %path.display(), "Writing trade protocol messages to message log.");
+        musig = musig.with_message_log(MessageLog::open(path)?);
+    }
+    let musig = Arc::new(musig);
+    let wallet = Arc::new(WalletImpl::from_wallet_manager(wallet_manager.clone()));
+    let wallet_connection = wallet.wallet_service.clone().spawn_connection(backend, shutdown.clone());
+    let high_fee_threshold = FeeRate::from_sat_per_vb(cli.high_fee_threshold).ok_or("fee rate threshold too high")?;
+    let fee_alert = task::spawn(FeeAlertService::new(wallet.wallet_service.clone(), high_fee_threshold,
+        cli.fee_alert_duration_secs).with_anomaly_guard(fee_anomaly_guard).run(shutdown.clone()));
+
+    let accelerator = task::spawn(TransactionAccelerator::new(wallet_manager.clone()).run(shutdown.clone()));
+    let dead_mans_switch = cli.payment_timeout_secs.map(|secs| task::spawn(
+        DeadMansSwitch::new(wallet_manager.clone(), Duration::from_secs(secs)).run(shutdown.clone())));
 
     let bmp_wallet_service = BmpWalletServiceImpl::default();
 
+    #[cfg(feature = "jsonrpc")]
+    let json_rpc_server = {
+        let json_rpc = JsonRpcImpl { musig: musig.clone(), wallet: wallet.clone() };
+        info!(port = cli.jsonrpc_port, "Starting JSON-RPC server.");
+        spawn_http_server(cli.jsonrpc_port, http_config.with_api_key_check(json_rpc.into_router()), shutdown.clone()).await?
+    };
+    #[cfg(feature = "rest")]
+    let rest_server = {
+        info!(port = cli.rest_port, "Starting REST server.");
+        let router = http_config.with_api_key_check(rest::wallet_router(wallet.clone()));
+        spawn_http_server(cli.rest_port, router, shutdown.clone()).await?
+    };
+    #[cfg(feature = "metrics")]
+    let metrics_server = {
+        info!(port = cli.metrics_port, "Starting metrics server.");
+        spawn_http_server(cli.metrics_port, metrics::metrics_router(), shutdown.clone()).await?
+    };
+
     info!(port = cli.port, "Starting gRPC server.");
+    // In-flight RPCs are allowed to complete after shutdown is signalled, before the server exits.
     Server::builder()
-        .add_service(MusigServer::new(musig))
-        .add_service(WalletServer::new(wallet))
+        .layer(InterceptorLayer::new(api_key_interceptor))
+        .add_service(MusigServer::from_arc(musig))
+        .add_service(WalletServer::from_arc(wallet))
         .add_service(BmpWalletServer::new(bmp_wallet_service))
-        .serve(addr)
+        .add_service(AdminServer::new(AdminImpl))
+        .serve_with_shutdown(addr, shutdown.cancelled_owned())
         .await?;
+    info!("gRPC server shut down.");
 
+    #[cfg(feature = "jsonrpc")]
+    json_rpc_server.await??;
+    #[cfg(feature = "rest")]
+    rest_server.await??;
+    #[cfg(feature = "metrics")]
+    metrics_server.await??;
+
+    fee_alert.await?;
+    accelerator.await?;
+    if let Some(dead_mans_switch) = dead_mans_switch {
+        dead_mans_switch.await?;
+    }
+    wallet_connection.await??;
     Ok(())
 }
+
+fn wallet_backend(cli: &Cli, config: &Config) -> WalletBackend {
+    if let Some(url) = &cli.compact_block_filters_peer {
+        return WalletBackend::CompactBlockFilters { url: url.clone() };
+    }
+    if let Some(url) = &cli.electrum_url {
+        let config = ElectrumConfig { stop_gap: config.electrum_stop_gap, batch_size: config.electrum_batch_size };
+        return WalletBackend::Electrum { url: url.clone(), config };
+    }
+    // Create RPC client pool. (No connection is made at this point.)
+    let auth = if let (Some(user), Some(pass)) = (&cli.bitcoin_rpc_user, &cli.bitcoin_rpc_pass) {
+        Auth::UserPass(user.clone(), pass.clone())
+    } else {
+        Auth::None
+    };
+    let rpc = Arc::new(BitcoindRpcConnectionManager::new(cli.bitcoin_rpc_url.clone(), auth)
+        .into_pool(cli.bitcoin_rpc_pool_size));
+    match (&cli.zmq_block_hash_endpoint, &cli.zmq_raw_tx_endpoint) {
+        (Some(block_hash_endpoint), Some(raw_tx_endpoint)) => WalletBackend::Zmq {
+            rpc, block_hash_endpoint: block_hash_endpoint.clone(), raw_tx_endpoint: raw_tx_endpoint.clone(),
+        },
+        _ => WalletBackend::BitcoindRpc(rpc),
+    }
+}
+
+/// Read a secret (the API key or wallet passphrase) from a file, rather than taking it as an argument,
+/// to keep it out of the process list and shell history.
+fn read_secret(path: &Path) -> io::Result<Zeroizing<String>> {
+    let contents = Zeroizing::new(fs::read_to_string(path)?);
+    Ok(Zeroizing::new(contents.trim_end().to_owned()))
+}
+
+#[cfg(any(feature = "jsonrpc", feature = "metrics", feature = "rest"))]
+async fn spawn_http_server(port: u16, router: axum::Router, shutdown: CancellationToken)
+                           -> io::Result<task::JoinHandle<io::Result<()>>> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    Ok(task::spawn(async move {
+        axum::serve(listener, router)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+    }))
+}
+
+async fn cancel_on_shutdown_signal(shutdown: CancellationToken) {
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("should be able to install SIGTERM handler")
+            .recv().await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = signal::ctrl_c() => result.expect("should be able to listen for SIGINT"),
+        () = terminate => {}
+    }
+    info!("Received shutdown signal.");
+    shutdown.cancel();
+}
@@ -0,0 +1,134 @@
+//! Generic message signing (BIP 322), for proving ownership of a wallet address to a third party,
+//! say an arbitrator. Only the 'simple' signature format is supported, and only for (key-path
+//! spent) taproot addresses, which are all that the wallet hands out.
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+use bdk_wallet::bitcoin::hashes::{Hash as _, HashEngine as _, sha256};
+use bdk_wallet::bitcoin::key::{Secp256k1, XOnlyPublicKey};
+use bdk_wallet::bitcoin::opcodes::OP_0;
+use bdk_wallet::bitcoin::opcodes::all::OP_RETURN;
+use bdk_wallet::bitcoin::script::PushBytesBuf;
+use bdk_wallet::bitcoin::secp256k1::Message;
+use bdk_wallet::bitcoin::sighash::{Prevouts, SighashCache};
+use bdk_wallet::bitcoin::transaction::Version;
+use bdk_wallet::bitcoin::{
+    Address, Amount, OutPoint, Psbt, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut, Witness,
+    absolute, consensus, taproot,
+};
+
+const MESSAGE_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// The tagged hash of the message, committed to by the virtual `to_spend` tx.
+fn message_hash(message: &str) -> sha256::Hash {
+    let tag_hash = sha256::Hash::hash(MESSAGE_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine)
+}
+
+/// The virtual tx paying to the address being proven, whose output the `to_sign` tx spends.
+fn to_spend_tx(address: &Address, message: &str) -> Transaction {
+    let script_sig = ScriptBuf::builder()
+        .push_opcode(OP_0)
+        .push_slice(PushBytesBuf::from(message_hash(message).to_byte_array()))
+        .into_script();
+    Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut { value: Amount::ZERO, script_pubkey: address.script_pubkey() }],
+    }
+}
+
+/// The virtual tx whose (only) input witness forms the signature, as an unsigned PSBT that the
+/// wallet can sign like any other spend of its address.
+pub(crate) fn to_sign_psbt(address: &Address, message: &str) -> Psbt {
+    let to_spend = to_spend_tx(address, message);
+    let to_sign = Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.compute_txid(), 0),
+            sequence: Sequence::ZERO,
+            ..TxIn::default()
+        }],
+        output: vec![TxOut { value: Amount::ZERO, script_pubkey: ScriptBuf::builder().push_opcode(OP_RETURN).into_script() }],
+    };
+    let mut psbt = Psbt::from_unsigned_tx(to_sign).expect("the tx is unsigned");
+    psbt.inputs[0].witness_utxo = Some(to_spend.output[0].clone());
+    psbt
+}
+
+/// Encode the witness of the signed `to_sign` tx as a simple signature.
+pub(crate) fn encode_simple_signature(witness: &Witness) -> String {
+    BASE64_STANDARD.encode(consensus::serialize(witness))
+}
+
+/// Check a base64 simple signature of the given message, made with the key of the given taproot
+/// address. Returns false for a malformed signature or an address of any other type.
+pub fn verify_simple_signature(address: &Address, message: &str, signature: &str) -> bool {
+    let Some(witness) = BASE64_STANDARD.decode(signature).ok()
+        .and_then(|bytes| consensus::deserialize::<Witness>(&bytes).ok()) else { return false };
+    let script_pubkey = address.script_pubkey();
+    if !script_pubkey.is_p2tr() || witness.len() != 1 {
+        return false;
+    }
+    let Ok(output_key) = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]) else { return false };
+    let Ok(sig) = taproot::Signature::from_slice(&witness[0]) else { return false };
+    if !matches!(sig.sighash_type, TapSighashType::Default | TapSighashType::All) {
+        return false;
+    }
+
+    let psbt = to_sign_psbt(address, message);
+    let prevouts = [psbt.inputs[0].witness_utxo.clone().expect("to_sign PSBT has its witness UTXO")];
+    let Ok(sighash) = SighashCache::new(&psbt.unsigned_tx)
+        .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), sig.sighash_type) else { return false };
+    Secp256k1::verification_only()
+        .verify_schnorr(&sig.signature, &Message::from(sighash), &output_key)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //noinspection SpellCheckingInspection
+    #[test]
+    fn test_message_hash() {
+        // Test vectors from BIP 322:
+        assert_eq!(message_hash("").to_string(), "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1");
+        assert_eq!(message_hash("Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a");
+    }
+
+    //noinspection SpellCheckingInspection
+    #[test]
+    fn test_virtual_txids() {
+        // Test vectors from BIP 322, for a P2WPKH address:
+        let address = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l".parse::<Address<_>>().unwrap().assume_checked();
+        assert_eq!(to_spend_tx(&address, "Hello World").compute_txid().to_string(),
+            "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b");
+        assert_eq!(to_sign_psbt(&address, "Hello World").unsigned_tx.compute_txid().to_string(),
+            "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf");
+    }
+
+    //noinspection SpellCheckingInspection
+    #[test]
+    fn test_verify_simple_signature() {
+        // Test vector from BIP 322, for a P2TR address:
+        let address = "bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3"
+            .parse::<Address<_>>().unwrap().assume_checked();
+        let signature = "AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==";
+        assert!(verify_simple_signature(&address, "Hello World", signature));
+        assert!(!verify_simple_signature(&address, "Hello World!", signature));
+        assert!(!verify_simple_signature(&address, "Hello World", "not base64"));
+    }
+}
+
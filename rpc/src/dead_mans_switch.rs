@@ -0,0 +1,87 @@
+//! A background task force-closing, on the buyer's behalf, the trades whose seller has gone silent
+//! after the buyer sent the payment.
+//!
+//! Only the seller ever holds a signed swap tx, so the buyer can't force-close with that. Instead,
+//! once the payment timeout has passed since the buyer signalled readiness to release (having sent
+//! the payment) and the trade is still open, the buyer's signed warning tx is broadcast. That starts
+//! the unilateral path through arbitration: should the seller not answer it with its redirect tx,
+//! the buyer may broadcast its claim tx (see `GetRecoveryTx`) once the warning tx's lock time is up.
+
+use std::sync::Arc;
+
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time::{self, Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::protocol::{TRADE_MODELS, TradeModel, TradeModelStore as _, TradeState};
+use crate::wallet::WalletManager;
+
+const POLL_PERIOD: Duration = Duration::from_mins(1);
+
+pub struct DeadMansSwitch {
+    wallet_manager: Arc<WalletManager>,
+    payment_timeout: Duration,
+}
+
+impl DeadMansSwitch {
+    /// Force-close each trade whose seller hasn't closed it within `payment_timeout` of the buyer
+    /// sending the payment, with the manager's wallet that the trade was created with.
+    pub const fn new(wallet_manager: Arc<WalletManager>, payment_timeout: Duration) -> Self {
+        Self { wallet_manager, payment_timeout }
+    }
+
+    /// Check every trade for an overdue close each minute, until `shutdown` is cancelled.
+    pub async fn run(self, shutdown: CancellationToken) {
+        let mut interval = time::interval(POLL_PERIOD);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                () = shutdown.cancelled() => return,
+                _ = interval.tick() => {
+                    for trade_model in TRADE_MODELS.trade_models() {
+                        self.check_trade(&trade_model).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Broadcast the buyer's warning tx of the given trade, moving it on to `ForceClosing`, if the
+    /// payment timeout has passed without the seller closing the trade. A failed broadcast, say as
+    /// the deposit tx isn't yet confirmed, is retried on the next check.
+    pub async fn check_trade(&self, trade_model: &AsyncMutex<TradeModel>) {
+        // Copy what is needed out of the trade model, so as not to hold its lock across wallet I/O.
+        let (trade_id, wallet_id, warning_tx) = {
+            let trade_model = trade_model.lock().await;
+            let overdue = trade_model.payment_sent_at()
+                .is_some_and(|payment_sent_at| payment_sent_at.elapsed() >= self.payment_timeout);
+            // Only the buyer's open trades with a published deposit tx can be force-closed.
+            let state = trade_model.state();
+            if !overdue || !trade_model.am_buyer() || state.is_pre_deposit() || state >= TradeState::ForceClosing {
+                return;
+            }
+            let warning_tx = match trade_model.get_signed_recovery_txs() {
+                Ok([(warning_tx, _), _]) => warning_tx.clone(),
+                Err(e) => {
+                    warn!(trade_id = trade_model.trade_id(), "Missing warning tx to force-close overdue trade: {e}");
+                    return;
+                }
+            };
+            (trade_model.trade_id().to_owned(), trade_model.wallet_id().to_owned(), warning_tx)
+        };
+        let txid = warning_tx.compute_txid();
+        debug!(trade_id, %txid, "Payment timeout passed without seller closing trade.");
+        let Some(wallet) = self.wallet_manager.get_wallet(&wallet_id) else {
+            warn!(trade_id, wallet_id, "Could not force-close overdue trade with unknown wallet.");
+            return;
+        };
+        match wallet.broadcast_tx(warning_tx) {
+            Ok(txid) => {
+                info!(trade_id, %txid, "Broadcast warning tx to force-close trade with silent seller.");
+                trade_model.lock().await.advance_state(TradeState::ForceClosing);
+            }
+            Err(e) => warn!(trade_id, %txid, "Could not broadcast warning tx of overdue trade: {e}"),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::Write as _;
+
+use crate::protocol::TradeState;
+
+const STATES: [TradeState; 8] = [
+    TradeState::Initialized,
+    TradeState::NonceSharesExchanged,
+    TradeState::PartialSignaturesExchanged,
+    TradeState::DepositTxSigned,
+    TradeState::DepositTxPublished,
+    TradeState::SwapTxSigned,
+    TradeState::ForceClosing,
+    TradeState::Closed,
+];
+
+/// The state transitions made by the musig requests, as `(from, to, request)`, where a target state
+/// of `None` means that the trade is removed altogether.
+const TRANSITIONS: &[(TradeState, Option<TradeState>, &str)] = &[
+    (TradeState::Initialized, Some(TradeState::NonceSharesExchanged), "GetNonceShares"),
+    (TradeState::NonceSharesExchanged, Some(TradeState::PartialSignaturesExchanged), "GetPartialSignatures"),
+    (TradeState::PartialSignaturesExchanged, Some(TradeState::DepositTxSigned), "SignDepositTx"),
+    (TradeState::DepositTxSigned, Some(TradeState::DepositTxPublished), "PublishDepositTx"),
+    (TradeState::DepositTxPublished, Some(TradeState::SwapTxSigned), "SignSwapTx"),
+    (TradeState::DepositTxPublished, Some(TradeState::Closed), "CloseTrade"),
+    (TradeState::DepositTxPublished, Some(TradeState::Closed), "CustomCloseTrade"),
+    (TradeState::SwapTxSigned, Some(TradeState::Closed), "CloseTrade"),
+    (TradeState::SwapTxSigned, Some(TradeState::Closed), "CustomCloseTrade"),
+    (TradeState::SwapTxSigned, Some(TradeState::ForceClosing), "CloseTrade (peer unresponsive)"),
+    (TradeState::ForceClosing, Some(TradeState::Closed), "CloseTrade"),
+    (TradeState::NonceSharesExchanged, Some(TradeState::Initialized), "ResetTrade"),
+    (TradeState::PartialSignaturesExchanged, Some(TradeState::Initialized), "ResetTrade"),
+    (TradeState::DepositTxSigned, Some(TradeState::Initialized), "ResetTrade"),
+    (TradeState::Initialized, None, "CancelTrade"),
+    (TradeState::NonceSharesExchanged, None, "CancelTrade"),
+    (TradeState::PartialSignaturesExchanged, None, "CancelTrade"),
+    (TradeState::DepositTxSigned, None, "CancelTrade"),
+];
+
+/// Render the trade protocol state machine as a Graphviz DOT digraph, for documentation. The
+/// pre-deposit states, from which a trade may still be reset or cancelled, are drawn dashed.
+pub fn state_diagram_dot() -> String {
+    let mut dot = String::from("digraph TradeState {\n    rankdir=TB;\n    node [shape=box, style=rounded];\n\n");
+    for state in STATES {
+        let style = if state.is_pre_deposit() { "rounded,dashed" } else { "rounded" };
+        let shape = if state == TradeState::Closed { "doubleoctagon" } else { "box" };
+        writeln!(dot, "    {state:?} [shape={shape}, style=\"{style}\"];").unwrap();
+    }
+    dot.push_str("    Cancelled [shape=point, label=\"\"];\n\n");
+    for (from, to, request) in TRANSITIONS {
+        let to = to.map_or_else(|| "Cancelled".to_owned(), |to| format!("{to:?}"));
+        writeln!(dot, "    {from:?} -> {to} [label=\"{request}\"];").unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_diagram_dot_snapshot() {
+        // Regenerate with `bisq-musig-cli dump-state-diagram > rpc/docs/trade_states.dot` after any
+        // intended change to the trade states or their transitions.
+        assert_eq!(state_diagram_dot(), include_str!("../docs/trade_states.dot"));
+    }
+}
@@ -0,0 +1,54 @@
+use bdk_wallet::bitcoin::{Address, Network, Txid};
+
+/// A block explorer to link txs and addresses to, so that clients can let the user look them up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockExplorerConfig {
+    /// The root URL of the explorer, e.g. `https://mempool.space/testnet4`, below which each tx or
+    /// address has its page at `/tx/<txid>` or `/address/<address>`, as for mempool.space and
+    /// Esplora.
+    pub base_url: String,
+}
+
+impl BlockExplorerConfig {
+    pub fn new(base_url: &str) -> Self {
+        Self { base_url: base_url.trim_end_matches('/').to_owned() }
+    }
+
+    /// The default explorer for the given network: mempool.space for the public networks, or for
+    /// regtest, the local Esplora instance of a Nigiri setup.
+    pub fn for_network(network: Network) -> Self {
+        Self::new(match network {
+            Network::Bitcoin => "https://mempool.space",
+            Network::Testnet => "https://mempool.space/testnet",
+            Network::Testnet4 => "https://mempool.space/testnet4",
+            Network::Signet => "https://mempool.space/signet",
+            Network::Regtest => "http://localhost:5000",
+        })
+    }
+
+    pub fn tx_url(&self, txid: &Txid) -> String { format!("{}/tx/{txid}", self.base_url) }
+
+    pub fn address_url(&self, address: &Address) -> String { format!("{}/address/{address}", self.base_url) }
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk_wallet::bitcoin::hashes::Hash as _;
+
+    use super::*;
+
+    //noinspection SpellCheckingInspection
+    #[test]
+    fn test_explorer_urls() {
+        let explorer = BlockExplorerConfig::for_network(Network::Testnet4);
+        assert_eq!(explorer.tx_url(&Txid::all_zeros()),
+            "https://mempool.space/testnet4/tx/0000000000000000000000000000000000000000000000000000000000000000");
+
+        let explorer = BlockExplorerConfig::new("http://localhost:5000/");
+        assert_eq!(explorer, BlockExplorerConfig::for_network(Network::Regtest));
+        let address = "bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz"
+            .parse::<Address<_>>().unwrap().assume_checked();
+        assert_eq!(explorer.address_url(&address),
+            "http://localhost:5000/address/bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz");
+    }
+}
@@ -0,0 +1,235 @@
+//! A background task watching the next-block fee rate, to warn the operator when mempool
+//! congestion threatens to hold up the trade txs, and to pause new trades during fee spikes.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bdk_wallet::bitcoin::FeeRate;
+use tokio::time::{self, Duration, Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::wallet::WalletService;
+
+const POLL_PERIOD: Duration = Duration::from_mins(1);
+
+pub struct FeeAlertService {
+    wallet_service: Arc<dyn WalletService + Send + Sync>,
+    state: FeeAlertState,
+    anomaly_guard: Option<Arc<FeeAnomalyGuard>>,
+}
+
+impl FeeAlertService {
+    /// Alert when the 1-block fee rate exceeds `high_fee_threshold`, escalating to an error if it
+    /// stays above the threshold for longer than `fee_alert_duration_secs`.
+    pub fn new(wallet_service: Arc<dyn WalletService + Send + Sync>, high_fee_threshold: FeeRate,
+               fee_alert_duration_secs: u64) -> Self {
+        let state = FeeAlertState::new(high_fee_threshold, Duration::from_secs(fee_alert_duration_secs));
+        Self { wallet_service, state, anomaly_guard: None }
+    }
+
+    /// Feed every fee rate polled to the given guard, so that it may flag fee spikes.
+    #[must_use]
+    pub fn with_anomaly_guard(self, anomaly_guard: Arc<FeeAnomalyGuard>) -> Self {
+        Self { anomaly_guard: Some(anomaly_guard), ..self }
+    }
+
+    /// Poll the fee rate estimate every minute until `shutdown` is cancelled.
+    pub async fn run(mut self, shutdown: CancellationToken) {
+        let mut interval = time::interval(POLL_PERIOD);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                () = shutdown.cancelled() => return,
+                now = interval.tick() => self.poll(now).await,
+            }
+        }
+    }
+
+    async fn poll(&mut self, now: Instant) {
+        let fee_rate = match self.wallet_service.estimate_fee_rate(1).await {
+            Ok(Some(fee_rate)) => fee_rate,
+            // There is nothing to alert on without an estimate, nor before the wallet connects.
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Could not estimate fee rate: {e}");
+                return;
+            }
+        };
+        #[cfg(feature = "metrics")]
+        metrics::FEE_RATE_GAUGE.set(i64::try_from(fee_rate.to_sat_per_kwu()).unwrap_or(i64::MAX));
+
+        if let Some(anomaly_guard) = &self.anomaly_guard {
+            let was_anomalous = anomaly_guard.is_anomalous();
+            match (was_anomalous, anomaly_guard.record(fee_rate)) {
+                (false, true) => warn!(fee_rate_sat_per_vb = fee_rate.to_sat_per_vb_ceil(),
+                    "Next-block fee rate is far above its median; pausing new trades."),
+                (true, false) => info!(fee_rate_sat_per_vb = fee_rate.to_sat_per_vb_ceil(),
+                    "Next-block fee rate is back near its median; resuming new trades."),
+                _ => {}
+            }
+        }
+
+        let fee_rate_sat_per_vb = fee_rate.to_sat_per_vb_ceil();
+        let threshold_sat_per_vb = self.state.high_fee_threshold.to_sat_per_vb_ceil();
+        match self.state.update(fee_rate, now) {
+            Some(FeeAlert::High) =>
+                warn!(fee_rate_sat_per_vb, threshold_sat_per_vb, "Next-block fee rate is above threshold."),
+            Some(FeeAlert::Prolonged) => error!(fee_rate_sat_per_vb, threshold_sat_per_vb,
+                duration_secs = self.state.alert_duration.as_secs(),
+                "Next-block fee rate has stayed above threshold; trade txs may be stuck."),
+            Some(FeeAlert::Cleared) =>
+                info!(fee_rate_sat_per_vb, threshold_sat_per_vb, "Next-block fee rate is back below threshold."),
+            None => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FeeAlert {
+    High,
+    Prolonged,
+    Cleared,
+}
+
+/// Tracks how long the fee rate has been above the threshold, so that each alert is raised once,
+/// when the fee rate first crosses the threshold and when it has stayed above it for too long.
+#[derive(Debug)]
+struct FeeAlertState {
+    high_fee_threshold: FeeRate,
+    alert_duration: Duration,
+    high_since: Option<Instant>,
+    escalated: bool,
+}
+
+impl FeeAlertState {
+    const fn new(high_fee_threshold: FeeRate, alert_duration: Duration) -> Self {
+        Self { high_fee_threshold, alert_duration, high_since: None, escalated: false }
+    }
+
+    fn update(&mut self, fee_rate: FeeRate, now: Instant) -> Option<FeeAlert> {
+        if fee_rate <= self.high_fee_threshold {
+            self.escalated = false;
+            return self.high_since.take().map(|_| FeeAlert::Cleared);
+        }
+        let Some(high_since) = self.high_since else {
+            self.high_since = Some(now);
+            return Some(FeeAlert::High);
+        };
+        if !self.escalated && now.duration_since(high_since) > self.alert_duration {
+            self.escalated = true;
+            return Some(FeeAlert::Prolonged);
+        }
+        None
+    }
+}
+
+/// Keeps a rolling window of fee rate samples, flagging a fee spike while the latest sample is more
+/// than `anomaly_factor` times the median of the samples before it. At one sample per minute, the
+/// default window covers the past 7 days.
+#[derive(Debug)]
+pub struct FeeAnomalyGuard {
+    anomaly_factor: u32,
+    window_size: usize,
+    samples: Mutex<VecDeque<FeeRate>>,
+    is_anomalous: AtomicBool,
+}
+
+impl Default for FeeAnomalyGuard {
+    fn default() -> Self { Self::new(Self::DEFAULT_ANOMALY_FACTOR, Self::DEFAULT_WINDOW_SIZE) }
+}
+
+impl FeeAnomalyGuard {
+    pub const DEFAULT_ANOMALY_FACTOR: u32 = 10;
+    pub const DEFAULT_WINDOW_SIZE: usize = 7 * 24 * 60;
+
+    pub fn new(anomaly_factor: u32, window_size: usize) -> Self {
+        Self {
+            anomaly_factor,
+            window_size: window_size.max(1),
+            samples: Mutex::default(),
+            is_anomalous: AtomicBool::new(false),
+        }
+    }
+
+    /// Add a fee rate sample to the window, returning whether it is anomalous, that is, whether
+    /// it exceeds the median of the prior samples by more than the anomaly factor.
+    pub fn record(&self, fee_rate: FeeRate) -> bool {
+        let mut samples = self.samples.lock().unwrap();
+        let is_anomalous = median(&samples).is_some_and(|median| u128::from(fee_rate.to_sat_per_kwu())
+            > u128::from(median.to_sat_per_kwu()) * u128::from(self.anomaly_factor));
+        if samples.len() == self.window_size {
+            samples.pop_front();
+        }
+        samples.push_back(fee_rate);
+        self.is_anomalous.store(is_anomalous, Ordering::Relaxed);
+        is_anomalous
+    }
+
+    /// Whether the latest fee rate sample was anomalous, so that new trades should be paused.
+    pub fn is_anomalous(&self) -> bool { self.is_anomalous.load(Ordering::Relaxed) }
+}
+
+/// The median of the given fee rates (taking the lower of the middle two of an even number of
+/// them), or `None` if there are none.
+fn median(fee_rates: &VecDeque<FeeRate>) -> Option<FeeRate> {
+    let mut fee_rates: Vec<_> = fee_rates.iter().copied().collect();
+    let mid = fee_rates.len().checked_sub(1)? / 2;
+    Some(*fee_rates.select_nth_unstable(mid).1)
+}
+
+#[cfg(feature = "metrics")]
+mod metrics {
+    use std::sync::LazyLock;
+
+    use prometheus::{IntGauge, register_int_gauge};
+
+    pub(super) static FEE_RATE_GAUGE: LazyLock<IntGauge> = LazyLock::new(|| register_int_gauge!(
+        "musig_next_block_fee_rate_sat_per_kwu", "Estimated fee rate to confirm within one block").unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_alert_state() {
+        let mut state = FeeAlertState::new(FeeRate::from_sat_per_vb_u32(50), Duration::from_mins(10));
+        let (low, high) = (FeeRate::from_sat_per_vb_u32(10), FeeRate::from_sat_per_vb_u32(80));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(state.update(low, at(0)), None);
+        assert_eq!(state.update(high, at(60)), Some(FeeAlert::High));
+        assert_eq!(state.update(high, at(120)), None);
+        assert_eq!(state.update(high, at(660)), None);
+        assert_eq!(state.update(high, at(720)), Some(FeeAlert::Prolonged));
+        assert_eq!(state.update(high, at(780)), None);
+        assert_eq!(state.update(low, at(840)), Some(FeeAlert::Cleared));
+        assert_eq!(state.update(low, at(900)), None);
+        assert_eq!(state.update(high, at(960)), Some(FeeAlert::High));
+    }
+
+    #[test]
+    fn test_fee_anomaly_guard() {
+        let guard = FeeAnomalyGuard::new(10, 4);
+        let sat_per_vb = FeeRate::from_sat_per_vb_u32;
+
+        // Nothing is anomalous without prior samples.
+        assert!(!guard.record(sat_per_vb(1_000)));
+        assert!(!guard.record(sat_per_vb(2)));
+        assert!(!guard.record(sat_per_vb(5)));
+        assert!(!guard.record(sat_per_vb(3)));
+        assert!(!guard.is_anomalous());
+
+        // The median of the window [1000, 2, 5, 3] is 3 sat/vB, so only above 30 sat/vB is anomalous.
+        assert!(!guard.record(sat_per_vb(30)));
+        // The first sample has now been dropped, leaving the window [2, 5, 3, 30], still of median 3.
+        assert!(guard.record(sat_per_vb(31)));
+        assert!(guard.is_anomalous());
+        // The spike itself joins the window [5, 3, 30, 31], raising the median to 5.
+        assert!(!guard.record(sat_per_vb(40)));
+        assert!(!guard.is_anomalous());
+    }
+}
@@ -0,0 +1,116 @@
+//! External sources of fee rate estimates, for when the wallet's own backend gives none (as with
+//! compact block filters, or a Bitcoin Core node without enough mempool history).
+
+use std::io;
+
+use bdk_wallet::bitcoin::FeeRate;
+use serde::Deserialize;
+use tokio::task;
+
+use crate::wallet::{Result, WalletErrorKind};
+
+/// The timeout on each fee estimate request, in seconds.
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+#[tonic::async_trait]
+pub trait FeeEstimator {
+    /// Estimate the fee rate needed for a tx to confirm within `target_blocks` blocks. This is async,
+    /// as the estimate is fetched over the network, so that a slow estimator (up to the request
+    /// timeout) never holds up a runtime worker thread meanwhile.
+    ///
+    /// # Errors
+    /// Will return `Err` if the estimate could not be fetched or is invalid
+    async fn estimate(&self, target_blocks: u16) -> Result<FeeRate>;
+}
+
+/// Gets fee rate estimates from the REST API of a mempool.space instance, such as
+/// `https://mempool.space` or a self-hosted one.
+#[derive(Clone, Debug)]
+pub struct MempoolSpaceFeeEstimator {
+    pub url: String,
+}
+
+impl MempoolSpaceFeeEstimator {
+    pub fn new(url: impl Into<String>) -> Self { Self { url: url.into() } }
+}
+
+#[tonic::async_trait]
+impl FeeEstimator for MempoolSpaceFeeEstimator {
+    async fn estimate(&self, target_blocks: u16) -> Result<FeeRate> {
+        let url = format!("{}/api/v1/fees/recommended", self.url.trim_end_matches('/'));
+        // The HTTP client only makes blocking requests, so make this one on the blocking thread pool.
+        let response = task::spawn_blocking(|| minreq::get(url).with_timeout(REQUEST_TIMEOUT_SECS).send())
+            .await.map_err(io::Error::from)??;
+        if response.status_code != 200 {
+            return Err(WalletErrorKind::InvalidFeeEstimate(format!("HTTP status {}", response.status_code)));
+        }
+        response.json::<RecommendedFees>()?.fee_rate(target_blocks)
+    }
+}
+
+/// The fee rates recommended by mempool.space, in sats per vbyte.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+struct RecommendedFees {
+    #[serde(rename = "fastestFee")]
+    fastest: u64,
+    #[serde(rename = "halfHourFee")]
+    half_hour: u64,
+    #[serde(rename = "hourFee")]
+    hour: u64,
+}
+
+impl RecommendedFees {
+    /// Pick the recommendation for the given confirmation target, taking blocks to come every ten
+    /// minutes: the fastest for the next block, then that for half an hour, then for an hour.
+    fn fee_rate(&self, target_blocks: u16) -> Result<FeeRate> {
+        let sats_per_vb = match target_blocks {
+            0..=1 => self.fastest,
+            2..=3 => self.half_hour,
+            _ => self.hour,
+        };
+        FeeRate::from_sat_per_vb(sats_per_vb)
+            .ok_or_else(|| WalletErrorKind::InvalidFeeEstimate(format!("fee rate {sats_per_vb} sat/vB is too high")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk_wallet::serde_json;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    // A single-threaded runtime, on which blocking in place would panic.
+    #[tokio::test]
+    async fn test_mempool_space_estimate() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let len = stream.read(&mut request).await.unwrap();
+            assert!(request[..len].starts_with(b"GET /api/v1/fees/recommended "));
+            let body = r#"{"fastestFee":25,"halfHourFee":18,"hourFee":12}"#;
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}", body.len());
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let fee_rate = MempoolSpaceFeeEstimator::new(url + "/").estimate(2).await.unwrap();
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb_u32(18));
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_recommended_fees() {
+        let json = r#"{"fastestFee":25,"halfHourFee":18,"hourFee":12,"economyFee":6,"minimumFee":3}"#;
+        let fees: RecommendedFees = serde_json::from_str(json).unwrap();
+        assert_eq!(fees, RecommendedFees { fastest: 25, half_hour: 18, hour: 12 });
+
+        for (target_blocks, sats_per_vb) in [(0, 25), (1, 25), (2, 18), (3, 18), (6, 12), (144, 12)] {
+            assert_eq!(fees.fee_rate(target_blocks).unwrap(), FeeRate::from_sat_per_vb_u32(sats_per_vb));
+        }
+        let fees = RecommendedFees { fastest: u64::MAX, ..fees };
+        assert!(matches!(fees.fee_rate(1), Err(WalletErrorKind::InvalidFeeEstimate(_))));
+    }
+}
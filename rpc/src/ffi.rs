@@ -0,0 +1,143 @@
+//! C bindings of the trade protocol, for embedding in non-Rust host applications (with the `ffi`
+//! feature). The build script generates the matching header, `bisq_musig.h`, into `OUT_DIR`. Link
+//! against a static or dynamic build of this crate, e.g. from
+//! `cargo rustc -p rpc --lib --release --features ffi --crate-type staticlib`.
+//!
+//! A `TradeModel` is created with `bisq_trade_model_new` and owned by the host until it is passed
+//! to `bisq_trade_model_free`. The fallible calls return `BISQ_OK`, or else one of the negative
+//! `BISQ_ERR_*` codes. Key shares are passed as fixed length byte arrays: 33-byte compressed
+//! pubkeys for the buyer & seller payout outputs and a 32-byte x-only pubkey for the multisig script.
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::ptr;
+
+use crate::pb::convert::TryProtoInto as _;
+use crate::pb::musigrpc;
+use crate::protocol::{ExchangedKeys, ProtocolErrorKind, TradeModel};
+
+pub const BISQ_OK: c_int = 0;
+pub const BISQ_ERR_NULL_ARGUMENT: c_int = -1;
+pub const BISQ_ERR_INVALID_ARGUMENT: c_int = -2;
+pub const BISQ_ERR_PROTOCOL: c_int = -3;
+
+pub const BISQ_PUB_KEY_LEN: usize = 33;
+pub const BISQ_X_ONLY_PUB_KEY_LEN: usize = 32;
+
+/// Start a new trade with the given (nul-terminated, UTF-8) id, in the given role, numbered as in
+/// the gRPC `Role` enum (`SELLER_AS_MAKER = 0`, etc.). Returns null if either argument is invalid.
+///
+/// # Safety
+/// `trade_id` must be null or point to a nul-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bisq_trade_model_new(trade_id: *const c_char, role: c_int) -> *mut TradeModel {
+    if trade_id.is_null() {
+        return ptr::null_mut();
+    }
+    let Ok(trade_id) = unsafe { CStr::from_ptr(trade_id) }.to_str() else { return ptr::null_mut() };
+    let Ok(role) = musigrpc::Role::try_from(role) else { return ptr::null_mut() };
+    Box::into_raw(Box::new(TradeModel::new(trade_id.to_owned(), role.into())))
+}
+
+/// Free a trade model created by `bisq_trade_model_new`. Does nothing if given null.
+///
+/// # Safety
+/// `trade_model` must be null or a pointer returned by `bisq_trade_model_new` that hasn't yet been
+/// freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bisq_trade_model_free(trade_model: *mut TradeModel) {
+    if !trade_model.is_null() {
+        drop(unsafe { Box::from_raw(trade_model) });
+    }
+}
+
+/// Returns a copy of the trade id, to be freed with `bisq_string_free`, or null if given null.
+///
+/// # Safety
+/// `trade_model` must be null or a live pointer returned by `bisq_trade_model_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bisq_trade_model_trade_id(trade_model: *const TradeModel) -> *mut c_char {
+    let Some(trade_model) = (unsafe { trade_model.as_ref() }) else { return ptr::null_mut() };
+    // The id was valid UTF-8 without interior nuls on the way in, so this can't fail.
+    CString::new(trade_model.trade_id()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Free a string returned by one of the other calls. Does nothing if given null.
+///
+/// # Safety
+/// `s` must be null or a string returned by one of the calls in this module, not yet freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bisq_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Generate my key shares, if not already done.
+///
+/// # Safety
+/// `trade_model` must be null or a live pointer returned by `bisq_trade_model_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bisq_trade_init_key_shares(trade_model: *mut TradeModel) -> c_int {
+    let Some(trade_model) = (unsafe { trade_model.as_mut() }) else { return BISQ_ERR_NULL_ARGUMENT };
+    status(trade_model.init_my_key_shares())
+}
+
+/// Copy my public key shares into the given buffers, once they have been generated.
+///
+/// # Safety
+/// `trade_model` must be null or a live pointer returned by `bisq_trade_model_new`. Each buffer
+/// must be null or writable for `BISQ_PUB_KEY_LEN` bytes (`BISQ_X_ONLY_PUB_KEY_LEN` bytes for
+/// `multisig_script`).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bisq_trade_get_my_key_shares(trade_model: *const TradeModel, buyer_output: *mut u8,
+                                                      seller_output: *mut u8, multisig_script: *mut u8) -> c_int {
+    let Some(trade_model) = (unsafe { trade_model.as_ref() }) else { return BISQ_ERR_NULL_ARGUMENT };
+    if buyer_output.is_null() || seller_output.is_null() || multisig_script.is_null() {
+        return BISQ_ERR_NULL_ARGUMENT;
+    }
+    let Some(keys) = trade_model.get_my_key_shares() else { return BISQ_ERR_PROTOCOL };
+    unsafe {
+        ptr::copy_nonoverlapping(keys.buyer_payout.serialize().as_ptr(), buyer_output, BISQ_PUB_KEY_LEN);
+        ptr::copy_nonoverlapping(keys.seller_payout.serialize().as_ptr(), seller_output, BISQ_PUB_KEY_LEN);
+        ptr::copy_nonoverlapping(keys.multisig_script.serialize().as_ptr(), multisig_script, BISQ_X_ONLY_PUB_KEY_LEN);
+    }
+    BISQ_OK
+}
+
+/// Set the peer's public key shares, in the same format as returned by `bisq_trade_get_my_key_shares`.
+///
+/// # Safety
+/// `trade_model` must be null or a live pointer returned by `bisq_trade_model_new`. Each buffer
+/// must be null or readable for `BISQ_PUB_KEY_LEN` bytes (`BISQ_X_ONLY_PUB_KEY_LEN` bytes for
+/// `multisig_script`).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bisq_trade_set_peer_key_shares(trade_model: *mut TradeModel, buyer_output: *const u8,
+                                                        seller_output: *const u8, multisig_script: *const u8) -> c_int {
+    let Some(trade_model) = (unsafe { trade_model.as_mut() }) else { return BISQ_ERR_NULL_ARGUMENT };
+    if buyer_output.is_null() || seller_output.is_null() || multisig_script.is_null() {
+        return BISQ_ERR_NULL_ARGUMENT;
+    }
+    let (buyer_output, seller_output, multisig_script) = unsafe {(
+        std::slice::from_raw_parts(buyer_output, BISQ_PUB_KEY_LEN),
+        std::slice::from_raw_parts(seller_output, BISQ_PUB_KEY_LEN),
+        std::slice::from_raw_parts(multisig_script, BISQ_X_ONLY_PUB_KEY_LEN),
+    )};
+    let (Ok(buyer_payout), Ok(seller_payout), Ok(multisig_script)) =
+        (buyer_output.try_proto_into(), seller_output.try_proto_into(), multisig_script.try_proto_into())
+    else { return BISQ_ERR_INVALID_ARGUMENT };
+    trade_model.set_peer_key_shares(&ExchangedKeys { buyer_payout, seller_payout, multisig_script });
+    BISQ_OK
+}
+
+/// Aggregate my & the peer's key shares, once both have been set.
+///
+/// # Safety
+/// `trade_model` must be null or a live pointer returned by `bisq_trade_model_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn bisq_trade_aggregate_key_shares(trade_model: *mut TradeModel) -> c_int {
+    let Some(trade_model) = (unsafe { trade_model.as_mut() }) else { return BISQ_ERR_NULL_ARGUMENT };
+    status(trade_model.aggregate_key_shares())
+}
+
+fn status<T>(result: Result<T, ProtocolErrorKind>) -> c_int {
+    result.map_or(BISQ_ERR_PROTOCOL, |_| BISQ_OK)
+}
@@ -0,0 +1,165 @@
+//! A JSON-RPC 2.0 interface to the same handlers as the gRPC services, served over HTTP.
+//!
+//! Each unary gRPC method is exposed as a JSON-RPC method named after its service and (camelCase)
+//! method name, e.g. `musig_initTrade` or `wallet_walletBalance`, taking the JSON form of the gRPC
+//! request message as its (by-name) params and returning the JSON form of the response message.
+//! The server-streaming methods are only available through gRPC.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use bdk_wallet::serde_json::{self, Value};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tonic::{Request, Response, Status};
+use tracing::debug;
+
+use crate::pb::musigrpc::musig_server::Musig as _;
+use crate::pb::walletrpc::wallet_server::Wallet as _;
+use crate::server::{MusigError, MusigImpl, WalletImpl};
+
+const PARSE_ERROR: i32 = -32_700;
+const INVALID_REQUEST: i32 = -32_600;
+const METHOD_NOT_FOUND: i32 = -32_601;
+const INVALID_PARAMS: i32 = -32_602;
+/// Start of the range of codes reserved for implementation-defined server errors. The gRPC status
+/// code of a failed call is subtracted from it, so that clients may tell apart kinds of failure.
+const SERVER_ERROR: i32 = -32_000;
+
+#[derive(Clone)]
+pub struct JsonRpcImpl {
+    pub musig: Arc<MusigImpl>,
+    pub wallet: Arc<WalletImpl>,
+}
+
+impl JsonRpcImpl {
+    /// An HTTP router accepting JSON-RPC requests, sent by POST to the root path.
+    pub fn into_router(self) -> Router {
+        Router::new()
+            .route("/", post(handle_json_rpc_request))
+            .with_state(self)
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, JsonRpcError> {
+        let musig = &*self.musig;
+        let wallet = &*self.wallet;
+        match method {
+            "musig_initTrade" => call_unary(params, |r| musig.init_trade(r)).await,
+            "musig_getNonceShares" => call_unary(params, |r| musig.get_nonce_shares(r)).await,
+            "musig_getPartialSignatures" => call_unary(params, |r| musig.get_partial_signatures(r)).await,
+            "musig_signDepositTx" => call_unary(params, |r| musig.sign_deposit_tx(r)).await,
+            "musig_signSwapTx" => call_unary(params, |r| musig.sign_swap_tx(r)).await,
+            "musig_closeTrade" => call_unary(params, |r| musig.close_trade(r)).await,
+            "musig_signCustomPayoutTx" => call_unary(params, |r| musig.sign_custom_payout_tx(r)).await,
+            "musig_customCloseTrade" => call_unary(params, |r| musig.custom_close_trade(r)).await,
+            "musig_cancelTrade" => call_unary(params, |r| musig.cancel_trade(r)).await,
+            "musig_resetTrade" => call_unary(params, |r| musig.reset_trade(r)).await,
+            "musig_listTrades" => call_unary(params, |r| musig.list_trades(r)).await,
+            "musig_getTrade" => call_unary(params, |r| musig.get_trade(r)).await,
+            "musig_getTradeStats" => call_unary(params, |r| musig.get_trade_stats(r)).await,
+            "musig_getTradeReceipt" => call_unary(params, |r| musig.get_trade_receipt(r)).await,
+            "musig_getRecoveryTx" => call_unary(params, |r| musig.get_recovery_tx(r)).await,
+            "musig_getDisputeEvidence" => call_unary(params, |r| musig.get_dispute_evidence(r)).await,
+            "wallet_walletBalance" => call_unary(params, |r| wallet.wallet_balance(r)).await,
+            "wallet_newAddress" => call_unary(params, |r| wallet.new_address(r)).await,
+            "wallet_listUnspent" => call_unary(params, |r| wallet.list_unspent(r)).await,
+            "wallet_getUtxo" => call_unary(params, |r| wallet.get_utxo(r)).await,
+            "wallet_getTxFee" => call_unary(params, |r| wallet.get_tx_fee(r)).await,
+            "wallet_send" => call_unary(params, |r| wallet.send(r)).await,
+            "wallet_broadcastTx" => call_unary(params, |r| wallet.broadcast_tx(r)).await,
+            "wallet_signPsbt" => call_unary(params, |r| wallet.sign_psbt(r)).await,
+            "wallet_verifyAddress" => call_unary(params, |r| wallet.verify_address(r)).await,
+            "wallet_signMessage" => call_unary(params, |r| wallet.sign_message(r)).await,
+            "wallet_verifyMessage" => call_unary(params, |r| wallet.verify_message(r)).await,
+            "wallet_createWallet" => call_unary(params, |r| wallet.create_wallet(r)).await,
+            "wallet_lockWallet" => call_unary(params, |r| wallet.lock_wallet(r)).await,
+            "wallet_unlockWallet" => call_unary(params, |r| wallet.unlock_wallet(r)).await,
+            "wallet_getSilentPaymentAddress" => call_unary(params, |r| wallet.get_silent_payment_address(r)).await,
+            "wallet_exportTransactionsCsv" => call_unary(params, |r| wallet.export_transactions_csv(r)).await,
+            "wallet_importWatchDescriptor" => call_unary(params, |r| wallet.import_watch_descriptor(r)).await,
+            _ => Err(JsonRpcError::new(METHOD_NOT_FOUND, format!("method not found: {method}"))),
+        }
+    }
+}
+
+async fn call_unary<Req, Res, F, Fut>(params: Value, handler: F) -> Result<Value, JsonRpcError>
+    where Req: DeserializeOwned,
+          Res: Serialize,
+          F: FnOnce(Request<Req>) -> Fut,
+          Fut: Future<Output = tonic::Result<Response<Res>>> {
+    // Absent params are treated the same as an empty object, i.e. a request with all fields default.
+    let params = if params.is_null() { Value::Object(serde_json::Map::new()) } else { params };
+    let request = serde_json::from_value(params)
+        .map_err(|e| JsonRpcError::new(INVALID_PARAMS, format!("invalid params: {e}")))?;
+    let response = handler(Request::new(request)).await?;
+    serde_json::to_value(response.into_inner())
+        .map_err(|e| Status::from(MusigError::from(e)).into())
+}
+
+async fn handle_json_rpc_request(State(json_rpc): State<JsonRpcImpl>, body: String) -> Json<JsonRpcResponse> {
+    let request: JsonRpcRequest = match serde_json::from_str::<Value>(&body) {
+        Err(e) => return Json(JsonRpcResponse::error(Value::Null, JsonRpcError::new(PARSE_ERROR, e.to_string()))),
+        Ok(value) => match serde_json::from_value(value) {
+            Err(e) => return Json(JsonRpcResponse::error(Value::Null, JsonRpcError::new(INVALID_REQUEST, e.to_string()))),
+            Ok(request) => request,
+        },
+    };
+    if request.jsonrpc != "2.0" {
+        let error = JsonRpcError::new(INVALID_REQUEST, "unsupported JSON-RPC version".to_owned());
+        return Json(JsonRpcResponse::error(request.id, error));
+    }
+    debug!(method = request.method, "Got a JSON-RPC request.");
+    Json(match json_rpc.call(&request.method, request.params).await {
+        Ok(result) => JsonRpcResponse::result(request.id, result),
+        Err(error) => JsonRpcResponse::error(request.id, error),
+    })
+}
+
+#[derive(Deserialize)]
+struct JsonRpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    id: Value,
+}
+
+#[derive(Serialize)]
+pub struct JsonRpcResponse {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonRpcError>,
+    id: Value,
+}
+
+impl JsonRpcResponse {
+    const fn result(id: Value, result: Value) -> Self {
+        Self { jsonrpc: "2.0", result: Some(result), error: None, id }
+    }
+
+    const fn error(id: Value, error: JsonRpcError) -> Self {
+        Self { jsonrpc: "2.0", result: None, error: Some(error), id }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonRpcError {
+    code: i32,
+    message: String,
+}
+
+impl JsonRpcError {
+    const fn new(code: i32, message: String) -> Self { Self { code, message } }
+}
+
+impl From<Status> for JsonRpcError {
+    fn from(value: Status) -> Self {
+        Self::new(SERVER_ERROR - value.code() as i32, value.message().to_owned())
+    }
+}
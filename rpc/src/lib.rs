@@ -1,4 +1,5 @@
 pub mod pb {
+    pub mod adminrpc;
     pub mod bmp_converter;
     pub mod bmp_protocol;
     pub mod bmp_wallet;
@@ -7,9 +8,43 @@ pub mod pb {
     pub mod walletrpc;
 }
 
+#[cfg(feature = "native")]
+pub mod accelerator;
+#[cfg(feature = "native")]
+pub mod audit;
+#[cfg(feature = "native")]
+pub mod bip322;
 pub mod bmp_wallet_service;
+#[cfg(feature = "native")]
+pub mod dead_mans_switch;
+pub mod docs;
+pub mod explorer;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "native")]
+pub mod fee_alert;
+#[cfg(feature = "native")]
+pub mod fee_estimator;
+#[cfg(feature = "jsonrpc")]
+pub mod jsonrpc;
+#[cfg(feature = "native")]
+pub mod message_log;
+#[cfg(feature = "native")]
 mod observable;
-mod protocol;
+#[cfg(feature = "native")]
+pub mod peer;
+pub mod protocol;
+pub mod receipt;
+#[cfg(feature = "rest")]
+pub mod rest;
 pub mod server;
+pub mod silent_payments;
+pub mod spv;
+#[cfg(feature = "native")]
+pub mod stats;
 mod storage;
+pub mod tx_policy;
+pub mod validation;
+pub mod verification;
+#[cfg(feature = "native")]
 pub mod wallet;
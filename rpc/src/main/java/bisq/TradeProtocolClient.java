@@ -8,11 +8,17 @@ import io.grpc.InsecureChannelCredentials;
 import musigrpc.MusigGrpc;
 import musigrpc.Rpc.*;
 
+import java.util.HashMap;
 import java.util.List;
+import java.util.Map;
 import java.util.stream.Collectors;
 
 public class TradeProtocolClient {
+    /** The trade protocol version this client supports, sent with each new trade. **/
+    private static final int PROTOCOL_VERSION = 1;
+
     private final MusigGrpc.MusigBlockingStub stub;
+    private final Map<String, Long> sequenceNumbers = new HashMap<>();
 
     public TradeProtocolClient(Channel channel) {
         this.stub = MusigGrpc.newBlockingStub(channel);
@@ -37,6 +43,13 @@ public class TradeProtocolClient {
         }
     }
 
+    /**
+     * Each request for a given trade must carry the next sequence number, starting from zero.
+     **/
+    private long nextSequenceNumber(String tradeId) {
+        return sequenceNumbers.merge(tradeId, 0L, (last, zero) -> last + 1);
+    }
+
     private enum TradeType {
         TAKER_IS_BUYER, TAKER_IS_SELLER
     }
@@ -71,7 +84,9 @@ public class TradeProtocolClient {
     private void setupTakerIsBuyerTrade(String buyerTradeId, String sellerTradeId) {
         var buyerPubKeyShareResponse = stub.initTrade(PubKeySharesRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setMyRole(Role.BUYER_AS_TAKER)
+                .setProtocolVersion(PROTOCOL_VERSION)
                 .build());
         System.out.println("Got reply: " + buyerPubKeyShareResponse);
 
@@ -79,12 +94,15 @@ public class TradeProtocolClient {
 
         var sellerPubKeyShareResponse = stub.initTrade(PubKeySharesRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setMyRole(Role.SELLER_AS_MAKER)
+                .setProtocolVersion(PROTOCOL_VERSION)
                 .build());
         System.out.println("Got reply: " + sellerPubKeyShareResponse);
 
         var sellerNonceShareMessage = stub.getNonceShares(NonceSharesRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setBuyerOutputPeersPubKeyShare(buyerPubKeyShareResponse.getBuyerOutputPubKeyShare())
                 .setSellerOutputPeersPubKeyShare(buyerPubKeyShareResponse.getSellerOutputPubKeyShare())
                 .setPeersMultisigScriptKey(buyerPubKeyShareResponse.getMultisigScriptKey())
@@ -101,6 +119,7 @@ public class TradeProtocolClient {
 
         var buyerNonceShareMessage = stub.getNonceShares(NonceSharesRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setBuyerOutputPeersPubKeyShare(sellerPubKeyShareResponse.getBuyerOutputPubKeyShare())
                 .setSellerOutputPeersPubKeyShare(sellerPubKeyShareResponse.getSellerOutputPubKeyShare())
                 .setPeersMultisigScriptKey(sellerPubKeyShareResponse.getMultisigScriptKey())
@@ -115,6 +134,7 @@ public class TradeProtocolClient {
 
         var buyerPartialSignatureMessage = stub.getPartialSignatures(PartialSignaturesRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setPeersNonceShares(sellerNonceShareMessage)
                 .addAllRedirectionReceivers(mockRedirectionReceivers(buyerNonceShareMessage.getRedirectionAmountMsat()))
                 .build());
@@ -124,6 +144,7 @@ public class TradeProtocolClient {
 
         var sellerPartialSignatureMessage = stub.getPartialSignatures(PartialSignaturesRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setPeersNonceShares(buyerNonceShareMessage)
                 .addAllRedirectionReceivers(mockRedirectionReceivers(sellerNonceShareMessage.getRedirectionAmountMsat()))
                 .build());
@@ -131,6 +152,7 @@ public class TradeProtocolClient {
 
         var sellerDepositPsbt = stub.signDepositTx(DepositTxSignatureRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 // Don't include contract-forming (Deposit/Warning/Redirect) txids, as they are ignored:
                 .setPeersPartialSignatures(buyerPartialSignatureMessage.toBuilder().clearContractualTxIds())
                 .build());
@@ -139,12 +161,14 @@ public class TradeProtocolClient {
         // Seller subscribes to be notified of Deposit Tx confirmation:
         var sellerDepositTxConfirmationIter = stub.subscribeTxConfirmationStatus(SubscribeTxConfirmationStatusRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .build());
 
         // Seller sends Message D to buyer. (Seller's swapTxInputPartialSignature is NOT withheld from it.)
 
         var buyerDepositPsbt = stub.signDepositTx(DepositTxSignatureRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 // Don't include contract-forming (Deposit/Warning/Redirect) txids, as they are ignored:
                 .setPeersPartialSignatures(sellerPartialSignatureMessage.toBuilder().clearContractualTxIds())
                 .build());
@@ -153,6 +177,7 @@ public class TradeProtocolClient {
         // *** BUYER BROADCASTS DEPOSIT TX ***
         var buyerDepositTxConfirmationIter = stub.publishDepositTx(PublishDepositTxRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setPeersDepositPsbt(sellerDepositPsbt)
                 .build());
         // ***********************************
@@ -166,7 +191,9 @@ public class TradeProtocolClient {
     private void setupTakerIsSellerTrade(String buyerTradeId, String sellerTradeId) {
         var sellerPubKeyShareResponse = stub.initTrade(PubKeySharesRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setMyRole(Role.SELLER_AS_TAKER)
+                .setProtocolVersion(PROTOCOL_VERSION)
                 .build());
         System.out.println("Got reply: " + sellerPubKeyShareResponse);
 
@@ -174,12 +201,15 @@ public class TradeProtocolClient {
 
         var buyerPubKeyShareResponse = stub.initTrade(PubKeySharesRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setMyRole(Role.BUYER_AS_MAKER)
+                .setProtocolVersion(PROTOCOL_VERSION)
                 .build());
         System.out.println("Got reply: " + buyerPubKeyShareResponse);
 
         var buyerNonceShareMessage = stub.getNonceShares(NonceSharesRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setBuyerOutputPeersPubKeyShare(sellerPubKeyShareResponse.getBuyerOutputPubKeyShare())
                 .setSellerOutputPeersPubKeyShare(sellerPubKeyShareResponse.getSellerOutputPubKeyShare())
                 .setPeersMultisigScriptKey(sellerPubKeyShareResponse.getMultisigScriptKey())
@@ -196,6 +226,7 @@ public class TradeProtocolClient {
 
         var sellerNonceShareMessage = stub.getNonceShares(NonceSharesRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setBuyerOutputPeersPubKeyShare(buyerPubKeyShareResponse.getBuyerOutputPubKeyShare())
                 .setSellerOutputPeersPubKeyShare(buyerPubKeyShareResponse.getSellerOutputPubKeyShare())
                 .setPeersMultisigScriptKey(buyerPubKeyShareResponse.getMultisigScriptKey())
@@ -210,6 +241,7 @@ public class TradeProtocolClient {
 
         var sellerPartialSignatureMessage = stub.getPartialSignatures(PartialSignaturesRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setPeersNonceShares(buyerNonceShareMessage)
                 .addAllRedirectionReceivers(mockRedirectionReceivers(sellerNonceShareMessage.getRedirectionAmountMsat()))
                 .build());
@@ -219,6 +251,7 @@ public class TradeProtocolClient {
 
         var buyerPartialSignatureMessage = stub.getPartialSignatures(PartialSignaturesRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setPeersNonceShares(sellerNonceShareMessage)
                 .addAllRedirectionReceivers(mockRedirectionReceivers(buyerNonceShareMessage.getRedirectionAmountMsat()))
                 .build());
@@ -226,6 +259,7 @@ public class TradeProtocolClient {
 
         var buyerDepositPsbt = stub.signDepositTx(DepositTxSignatureRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 // Don't include contract-forming (Deposit/Warning/Redirect) txids, as they are ignored:
                 .setPeersPartialSignatures(sellerPartialSignatureMessage.toBuilder().clearContractualTxIds())
                 .build());
@@ -234,12 +268,14 @@ public class TradeProtocolClient {
         // Buyer subscribes to be notified of Deposit Tx confirmation:
         var buyerDepositTxConfirmationIter = stub.subscribeTxConfirmationStatus(SubscribeTxConfirmationStatusRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .build());
 
         // Buyer sends Message D to seller. (Buyer's swapTxInputPartialSignature is withheld from it.)
 
         var sellerDepositPsbt = stub.signDepositTx(DepositTxSignatureRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 // Don't include contract-forming (Deposit/Warning/Redirect) txids, as they are ignored:
                 .setPeersPartialSignatures(buyerPartialSignatureMessage.toBuilder().clearContractualTxIds())
                 .build());
@@ -248,6 +284,7 @@ public class TradeProtocolClient {
         // *** SELLER BROADCASTS DEPOSIT TX ***
         var sellerDepositTxConfirmationIter = stub.publishDepositTx(PublishDepositTxRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setPeersDepositPsbt(buyerDepositPsbt)
                 .build());
         // ***********************************
@@ -271,6 +308,7 @@ public class TradeProtocolClient {
         // fields besides the trade ID may be left as their default values.)
         var buyerPartialSignatureMessage = stub.getPartialSignatures(PartialSignaturesRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setBuyerReadyToRelease(true)
                 .build());
         System.out.println("Got reply: " + buyerPartialSignatureMessage);
@@ -281,6 +319,7 @@ public class TradeProtocolClient {
         // end of the trade, to make sure that there's no problem with it and raise a dispute ASAP otherwise.)
         var blankSwapTxSignatureResponse = stub.signSwapTx(SwapTxSignatureRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setSwapTxInputPeersPartialSignature(buyerPartialSignatureMessage.getSwapTxInputPartialSignature())
                 .build());
         System.out.println("Got reply: " + blankSwapTxSignatureResponse);
@@ -292,6 +331,7 @@ public class TradeProtocolClient {
         // All other request proto fields besides the trade ID may be left as their default values.)
         var swapTxSignatureResponse = stub.signSwapTx(SwapTxSignatureRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setSellerReadyToRelease(true)
                 .build());
         System.out.println("Got reply: " + swapTxSignatureResponse);
@@ -302,6 +342,7 @@ public class TradeProtocolClient {
             // *** BUYER CLOSES TRADE ***
             var buyersCloseTradeResponse = stub.closeTrade(CloseTradeRequest.newBuilder()
                     .setTradeId(buyerTradeId)
+                    .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                     .setMyOutputPeersPrvKeyShare(swapTxSignatureResponse.getPeerOutputPrvKeyShare())
                     .build());
             System.out.println("Got reply: " + buyersCloseTradeResponse);
@@ -312,6 +353,7 @@ public class TradeProtocolClient {
             // *** SELLER CLOSES TRADE ***
             var sellersCloseTradeResponse = stub.closeTrade(CloseTradeRequest.newBuilder()
                     .setTradeId(sellerTradeId)
+                    .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                     .setMyOutputPeersPrvKeyShare(buyersCloseTradeResponse.getPeerOutputPrvKeyShare())
                     .build());
             System.out.println("Got reply: " + sellersCloseTradeResponse);
@@ -324,6 +366,7 @@ public class TradeProtocolClient {
             // *** SELLER FORCE-CLOSES TRADE ***
             var sellersCloseTradeResponse = stub.closeTrade(CloseTradeRequest.newBuilder()
                     .setTradeId(sellerTradeId)
+                    .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                     .build());
             System.out.println("Got reply: " + sellersCloseTradeResponse);
             // *********************************
@@ -333,6 +376,7 @@ public class TradeProtocolClient {
             // *** BUYER CLOSES TRADE ***
             var buyersCloseTradeResponse = stub.closeTrade(CloseTradeRequest.newBuilder()
                     .setTradeId(buyerTradeId)
+                    .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                     .setSwapTx(swapTxSignatureResponse.getSwapTx())
                     .build());
             System.out.println("Got reply: " + buyersCloseTradeResponse);
@@ -345,6 +389,7 @@ public class TradeProtocolClient {
 
         var buyersCustomPayoutPsbt = stub.signCustomPayoutTx(CustomPayoutPsbtRequest.newBuilder()
                 .setTradeId(buyerTradeId)
+                .setSequenceNumber(nextSequenceNumber(buyerTradeId))
                 .setSellersPayoutAmountExcludingFee(245_000)
                 .setFeeRate(3_750) // 15.0 sats per vbyte
                 .build());
@@ -356,6 +401,7 @@ public class TradeProtocolClient {
 
         var sellersCustomPayoutPsbt = stub.signCustomPayoutTx(CustomPayoutPsbtRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setSellersPayoutAmountExcludingFee(245_000)
                 .setFeeRate(3_750) // 15.0 sats per vbyte
                 .build());
@@ -364,6 +410,7 @@ public class TradeProtocolClient {
         // *** SELLER CUSTOM-CLOSES TRADE ***
         var sellersCustomCloseTradeResponse = stub.customCloseTrade(CustomCloseTradeRequest.newBuilder()
                 .setTradeId(sellerTradeId)
+                .setSequenceNumber(nextSequenceNumber(sellerTradeId))
                 .setPeersCustomPayoutPsbt(buyersCustomPayoutPsbt.getPsbt())
                 .build());
         System.out.println("Got reply: " + sellersCustomCloseTradeResponse);
@@ -0,0 +1,299 @@
+//! A log of every trade protocol request the daemon receives, along with its response, so that the
+//! requests leading up to an incident may be replayed against a fresh daemon to reproduce it.
+//!
+//! Like the audit log, each entry is written as a single line of JSON. Unlike the audit log, the
+//! entries hold the full request & response messages, save for the private key shares & adaptor
+//! secrets, which are left out. They still reveal the trades' keys, amounts & addresses, so the log
+//! should be kept private, and it may not be opened if accessible to other users.
+//!
+//! A replay can't reproduce the keys & nonces that the daemon generated the first time around, so it
+//! only checks that each request has the same outcome: success, or failure with the same status.
+//! Once a replayed trade's fresh keys come into play, say in checking the peer's partial signatures,
+//! its outcomes will thus start to differ from the recorded ones. (So will those of any request
+//! with a secret left out.)
+
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufRead as _, BufReader, Write as _};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bdk_wallet::serde_json::{self, Value};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tonic::{Request, Response, Status};
+use tracing::warn;
+
+use crate::pb::musigrpc::musig_server::Musig as _;
+use crate::server::MusigImpl;
+
+/// The (camelCase) names of the message fields holding secrets, which are left out of the log.
+const SECRET_FIELDS: &[&str] = &["peerOutputPrvKeyShare", "myOutputPeersPrvKeyShare", "adaptorSecret"];
+
+pub struct MessageLog {
+    file: Mutex<File>,
+}
+
+impl MessageLog {
+    /// Open the message log at the given path for appending, creating it if it doesn't yet exist. On
+    /// unix, a newly created log is only readable & writable by its owner, and an existing log must
+    /// be likewise.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file could not be opened, or (on unix) is accessible to other users
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&path)?;
+        #[cfg(unix)]
+        {
+            let mode = std::os::unix::fs::PermissionsExt::mode(&file.metadata()?.permissions());
+            if mode & 0o077 != 0 {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!(
+                    "message log {} is accessible to other users (mode {:o}), but should have mode 600",
+                    path.as_ref().display(), mode & 0o777)));
+            }
+        }
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// Append an entry to the log, flushing it to the OS straight away.
+    ///
+    /// # Errors
+    /// Will return `Err` if the entry could not be written
+    pub fn append(&self, entry: &MessageLogEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        // Write the line in a single call, so that concurrent writers to the file can't interleave.
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+
+    /// Read back all the entries of the message log at the given path, in the order they were
+    /// written. Any lines that can't be parsed, such as a final line torn by a crash, are skipped
+    /// with a warning.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file could not be opened
+    pub fn read_entries(path: &Path) -> io::Result<impl Iterator<Item = MessageLogEntry> + use<>> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(reader.lines().enumerate().filter_map(|(i, line)| {
+            let entry = line.map_err(serde_json::Error::io)
+                .and_then(|line| serde_json::from_str(&line));
+            entry.inspect_err(|e| warn!(line_number = i + 1, "Skipping bad message log entry: {e}")).ok()
+        }))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MessageLogEntry {
+    pub timestamp_unix_ms: u64,
+    /// The method called, named as in the JSON-RPC interface, e.g. `musig_initTrade`.
+    pub method: String,
+    pub request: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<LoggedStatus>,
+}
+
+impl MessageLogEntry {
+    pub(crate) fn new<Req: Serialize, Res: Serialize>(method: &str, request: &Req, response: &tonic::Result<Response<Res>>)
+                                                      -> serde_json::Result<Self> {
+        let (response, error) = match response {
+            Ok(response) => (Some(without_secrets(serde_json::to_value(response.get_ref())?)), None),
+            Err(status) => (None, Some(status.into())),
+        };
+        Ok(Self {
+            timestamp_unix_ms: unix_time_now_ms(),
+            method: method.to_owned(),
+            request: without_secrets(serde_json::to_value(request)?),
+            response,
+            error,
+        })
+    }
+}
+
+/// Remove the [`SECRET_FIELDS`] from the given message, at any depth.
+fn without_secrets(mut message: Value) -> Value {
+    match &mut message {
+        Value::Object(fields) => {
+            fields.retain(|name, _| !SECRET_FIELDS.contains(&&**name));
+            for value in fields.values_mut() {
+                *value = without_secrets(value.take());
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                *value = without_secrets(value.take());
+            }
+        }
+        _ => {}
+    }
+    message
+}
+
+/// The gRPC status of a failed request, with its numeric code.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LoggedStatus {
+    pub code: i32,
+    pub message: String,
+}
+
+impl From<&Status> for LoggedStatus {
+    fn from(value: &Status) -> Self {
+        Self { code: value.code().into(), message: value.message().to_owned() }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ReplayOutcome {
+    /// The request succeeded again, or failed again with the same status.
+    Matched,
+    /// The request succeeded where it failed before, or vice versa, or failed with another status.
+    Mismatched { recorded: Option<LoggedStatus>, replayed: Option<LoggedStatus> },
+    /// The request was to a server-streaming method, or one that isn't recognised, so not replayed.
+    Skipped,
+}
+
+/// Send the logged request to the given trade protocol service, comparing its outcome with the
+/// recorded one.
+pub async fn replay_entry(musig: &MusigImpl, entry: &MessageLogEntry) -> ReplayOutcome {
+    let request = entry.request.clone();
+    let replayed = match &*entry.method {
+        "musig_initTrade" => call(request, |r| musig.init_trade(r)).await,
+        "musig_getNonceShares" => call(request, |r| musig.get_nonce_shares(r)).await,
+        "musig_getPartialSignatures" => call(request, |r| musig.get_partial_signatures(r)).await,
+        "musig_signDepositTx" => call(request, |r| musig.sign_deposit_tx(r)).await,
+        "musig_signSwapTx" => call(request, |r| musig.sign_swap_tx(r)).await,
+        "musig_closeTrade" => call(request, |r| musig.close_trade(r)).await,
+        "musig_signCustomPayoutTx" => call(request, |r| musig.sign_custom_payout_tx(r)).await,
+        "musig_customCloseTrade" => call(request, |r| musig.custom_close_trade(r)).await,
+        "musig_cancelTrade" => call(request, |r| musig.cancel_trade(r)).await,
+        "musig_resetTrade" => call(request, |r| musig.reset_trade(r)).await,
+        _ => return ReplayOutcome::Skipped,
+    };
+    if replayed == entry.error {
+        ReplayOutcome::Matched
+    } else {
+        ReplayOutcome::Mismatched { recorded: entry.error.clone(), replayed }
+    }
+}
+
+/// Call the handler with the logged request, returning the status if it fails.
+async fn call<Req, Res, F, Fut>(request: Value, handler: F) -> Option<LoggedStatus>
+    where Req: DeserializeOwned,
+          F: FnOnce(Request<Req>) -> Fut,
+          Fut: Future<Output = tonic::Result<Response<Res>>> {
+    let request = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(e) => return Some((&Status::invalid_argument(format!("invalid logged request: {e}"))).into()),
+    };
+    handler(Request::new(request)).await.err().as_ref().map(LoggedStatus::from)
+}
+
+fn unix_time_now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use bdk_wallet::serde_json::json;
+    use tonic::Code;
+
+    use super::*;
+    use crate::pb::musigrpc::{CancelTradeRequest, PubKeySharesRequest, Role};
+    use crate::protocol::{MAX_SUPPORTED_VERSION, TRADE_MODELS, TradeModelStore as _};
+    use crate::wallet::WalletServiceImpl;
+
+    fn musig() -> MusigImpl {
+        MusigImpl::new(Arc::new(WalletServiceImpl::new()))
+    }
+
+    fn init_trade_request(trade_id: &str) -> Request<PubKeySharesRequest> {
+        Request::new(PubKeySharesRequest {
+            trade_id: trade_id.to_owned(),
+            my_role: Role::SellerAsMaker.into(),
+            sequence_number: 0,
+            protocol_version: MAX_SUPPORTED_VERSION,
+            wallet_id: String::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_log_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("message-log.jsonl");
+
+        let musig_with_log = musig().with_message_log(MessageLog::open(&path).unwrap());
+        musig_with_log.init_trade(init_trade_request("message-log-test")).await.unwrap();
+        musig_with_log.init_trade(init_trade_request("bad trade id")).await.unwrap_err();
+        // Out of order, as the trade was initiated with sequence number 0:
+        let request = CancelTradeRequest { trade_id: "message-log-test".to_owned(), sequence_number: 0 };
+        musig_with_log.cancel_trade(Request::new(request)).await.unwrap_err();
+        drop(musig_with_log);
+
+        let entries: Vec<_> = MessageLog::read_entries(&path).unwrap().collect();
+        let methods: Vec<_> = entries.iter().map(|entry| &*entry.method).collect();
+        assert_eq!(methods, ["musig_initTrade", "musig_initTrade", "musig_cancelTrade"]);
+        assert_eq!(entries[0].request["tradeId"], "message-log-test");
+        assert!(entries[0].response.as_ref().unwrap()["multisigScriptKey"].is_string());
+        assert_eq!(entries[1].error, Some(LoggedStatus {
+            code: Code::InvalidArgument.into(),
+            message: "trade id has invalid chars".to_owned(),
+        }));
+        assert_eq!(entries[2].error.as_ref().map(|status| status.code), Some(Code::FailedPrecondition.into()));
+
+        // The trade models are shared by every daemon in the process, so drop the recorded trade to have
+        // the replay initiate it afresh, rather than take the idempotent path of a repeated request.
+        assert!(TRADE_MODELS.remove_trade_model("message-log-test").is_some());
+        let musig = musig();
+        for entry in &entries {
+            assert_eq!(replay_entry(&musig, entry).await, ReplayOutcome::Matched, "{entry:?}");
+        }
+        assert!(TRADE_MODELS.get_trade_model("message-log-test").is_some());
+        let tampered_entry = MessageLogEntry { error: None, ..entries[1].clone() };
+        assert_eq!(replay_entry(&musig, &tampered_entry).await, ReplayOutcome::Mismatched {
+            recorded: None,
+            replayed: entries[1].error.clone(),
+        });
+        let streaming_entry = MessageLogEntry { method: "musig_publishDepositTx".to_owned(), ..entries[2].clone() };
+        assert_eq!(replay_entry(&musig, &streaming_entry).await, ReplayOutcome::Skipped);
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_secrets_left_out() {
+        let message = json!({
+            "tradeId": "trade",
+            "peerOutputPrvKeyShare": "c2VjcmV0",
+            "nested": [{"adaptorSecret": "c2VjcmV0", "adaptorPoint": "cG9pbnQ="}],
+        });
+        assert_eq!(without_secrets(message), json!({"tradeId": "trade", "nested": [{"adaptorPoint": "cG9pbnQ="}]}));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_rejects_wide_permissions() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("message-log.jsonl");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let e = MessageLog::open(&path).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(MessageLog::open(&path).is_ok());
+    }
+}
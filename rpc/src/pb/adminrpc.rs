@@ -0,0 +1,3 @@
+#![allow(unused_qualifications)]
+#![allow(clippy::all, clippy::pedantic, clippy::restriction, clippy::nursery)]
+tonic::include_proto!("adminrpc");
@@ -1,33 +1,42 @@
 use bdk_wallet::bitcoin::address::NetworkUnchecked;
 use bdk_wallet::bitcoin::hashes::Hash as _;
+use bdk_wallet::bitcoin::secp256k1::PublicKey;
 use bdk_wallet::bitcoin::{
-    Address, Amount, Psbt, TapSighash, Transaction, Txid, XOnlyPublicKey, consensus,
+    Address, Amount, FeeRate, Psbt, TapSighash, Transaction, Txid, XOnlyPublicKey, consensus,
 };
+#[cfg(feature = "native")]
 use bdk_wallet::chain::ChainPosition;
 use bdk_wallet::{Balance, LocalOutput};
 use musig2::PubNonce;
 use musig2::secp::{MaybeScalar, Point, Scalar};
 use prost::UnknownEnumValue;
+use protocol::psbt_v2::{self, PsbtV2};
 use protocol::receiver::Receiver;
 use tonic::{Result, Status};
 
 use crate::pb::musigrpc::{
-    self, NonceSharesMessage, PartialSignaturesMessage, ReceiverAddressAndAmount,
-};
-use crate::pb::walletrpc::{
-    ConfEvent, ConfidenceType, ConfirmationBlockTime, TransactionOutput, WalletBalanceResponse,
+    self, NonceSharesMessage, PartialSignaturesMessage, ReceiverAddressAndAmount, TradeDetails,
+    TradeSummary,
 };
+use crate::pb::walletrpc::{TransactionOutput, WalletBalanceResponse};
+#[cfg(feature = "native")]
+use crate::pb::walletrpc::{ConfEvent, ConfidenceType, ConfirmationBlockTime};
 use crate::protocol::{
     ContractualTxids, ExchangedAddresses, ExchangedNonces, ExchangedSigs, ProtocolErrorKind, Role,
+    TradeModel, TradeState,
 };
+use crate::server::MusigError;
+#[cfg(feature = "native")]
+use crate::stats::TradeStats;
 use crate::storage::{ByRef, ByVal};
-use crate::wallet::TxConfidence;
+#[cfg(feature = "native")]
+use crate::wallet::{TxConfidence, WalletErrorKind};
 
 pub(crate) mod hex {
-    use serde::Serializer;
-    use serde_with::SerializeAs;
+    use serde::{Deserializer, Serializer};
     use serde_with::formats::Lowercase;
     use serde_with::hex::Hex;
+    use serde_with::{DeserializeAs, SerializeAs};
 
     pub struct ByteReversedHex;
 
@@ -38,6 +47,14 @@ pub(crate) mod hex {
             Hex::<Lowercase>::serialize_as(&source, serializer)
         }
     }
+
+    impl<'de> DeserializeAs<'de, Vec<u8>> for ByteReversedHex {
+        fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+            let mut bytes: Vec<u8> = Hex::<Lowercase>::deserialize_as(deserializer)?;
+            bytes.reverse();
+            Ok(bytes)
+        }
+    }
 }
 
 pub trait CheckInSignedRange: Sized {
@@ -81,9 +98,22 @@ impl_try_proto_into_for_slice!(MaybeScalar, MaybeScalar::try_from, "scalar");
 impl_try_proto_into_for_slice!(Txid, Txid::from_slice, "txid");
 impl_try_proto_into_for_slice!(TapSighash, TapSighash::from_slice, "sighash");
 impl_try_proto_into_for_slice!(XOnlyPublicKey, XOnlyPublicKey::from_slice, "x-only pubkey");
+impl_try_proto_into_for_slice!(PublicKey, PublicKey::from_slice, "pubkey");
 impl_try_proto_into_for_slice!(Transaction, consensus::deserialize, "transaction");
 impl_try_proto_into_for_slice!(Psbt, Psbt::deserialize, "PSBT");
 
+/// A PSBT paired with the version of its format: 0 (the default) or 2 (BIP370).
+impl TryProtoInto<Psbt> for (&[u8], u32) {
+    fn try_proto_into(self) -> Result<Psbt> {
+        match self {
+            (bytes, 0) => bytes.try_proto_into(),
+            (bytes, 2) => PsbtV2::deserialize(bytes).map(psbt_v2::psbt_v2_to_v0)
+                .map_err(|e| Status::invalid_argument(format!("could not decode PSBT v2: {e}"))),
+            (_, version) => Err(Status::invalid_argument(format!("unsupported PSBT version: {version}"))),
+        }
+    }
+}
+
 impl TryProtoInto<Role> for i32 {
     fn try_proto_into(self) -> Result<Role> {
         TryInto::<musigrpc::Role>::try_into(self)
@@ -193,12 +223,84 @@ impl From<musigrpc::Role> for Role {
     }
 }
 
+impl From<Role> for musigrpc::Role {
+    fn from(value: Role) -> Self {
+        match value {
+            Role::SellerAsMaker => Self::SellerAsMaker,
+            Role::SellerAsTaker => Self::SellerAsTaker,
+            Role::BuyerAsMaker => Self::BuyerAsMaker,
+            Role::BuyerAsTaker => Self::BuyerAsTaker
+        }
+    }
+}
+
+impl From<TradeState> for musigrpc::TradeState {
+    fn from(value: TradeState) -> Self {
+        match value {
+            TradeState::Initialized => Self::Initialized,
+            TradeState::NonceSharesExchanged => Self::NonceSharesExchanged,
+            TradeState::PartialSignaturesExchanged => Self::PartialSignaturesExchanged,
+            TradeState::DepositTxSigned => Self::DepositTxSigned,
+            TradeState::DepositTxPublished => Self::DepositTxPublished,
+            TradeState::SwapTxSigned => Self::SwapTxSigned,
+            TradeState::ForceClosing => Self::ForceClosing,
+            TradeState::Closed => Self::Closed
+        }
+    }
+}
+
+impl From<&TradeModel> for TradeSummary {
+    fn from(value: &TradeModel) -> Self {
+        Self {
+            trade_id: value.trade_id().to_owned(),
+            role: musigrpc::Role::from(value.my_role()).into(),
+            state: musigrpc::TradeState::from(value.state()).into(),
+            trade_amount: value.trade_amount().map_or(0, Amount::to_sat),
+            created_at: value.created_at(),
+        }
+    }
+}
+
+impl From<&TradeModel> for TradeDetails {
+    fn from(value: &TradeModel) -> Self {
+        Self {
+            summary: Some(value.into()),
+            deposit_tx_id: value.get_deposit_txid().map(|txid| txid.to_string()),
+            swap_tx_id: value.get_swap_txid().map(|txid| txid.to_string()),
+            buyers_security_deposit: value.buyers_security_deposit().map_or(0, Amount::to_sat),
+            sellers_security_deposit: value.sellers_security_deposit().map_or(0, Amount::to_sat),
+            deposit_tx_fee_rate: value.deposit_tx_fee_rate().map_or(0, FeeRate::to_sat_per_kwu),
+            prepared_tx_fee_rate: value.prepared_tx_fee_rate().map_or(0, FeeRate::to_sat_per_kwu),
+            fee_bump_addresses: value.get_my_fee_bump_addresses().map(Address::to_string).collect(),
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl From<&TradeStats> for musigrpc::TradeStats {
+    fn from(value: &TradeStats) -> Self {
+        Self {
+            total_trade_count: value.total_trade_count,
+            total_volume_sats: value.total_volume_sats,
+            completed_count: value.completed_count,
+            failed_count: value.failed_count,
+            avg_duration_seconds: value.avg_duration_seconds,
+        }
+    }
+}
+
 impl From<SentAddressesNoncesPair<'_>> for NonceSharesMessage {
     fn from((addresses, nonces): SentAddressesNoncesPair) -> Self {
         Self {
-            // Use default value for the PSBT & redirection amount fields. TODO: A little hacky; consider refactoring proto.
+            // Use default value for the PSBT, redirection amount, adaptor point, proposed fee rate &
+            // net amount fields. TODO: A little hacky; consider refactoring proto.
             half_deposit_psbt: Vec::default(),
             redirection_amount_msat: 0,
+            adaptor_point: None,
+            proposed_deposit_tx_fee_rate: None,
+            proposed_prepared_tx_fee_rate: None,
+            buyers_net_amount_sats: 0,
+            sellers_net_amount_sats: 0,
             // Addresses...
             warning_tx_fee_bump_address: addresses.warning_tx_fee_bump.to_string(),
             redirect_tx_fee_bump_address: addresses.redirect_tx_fee_bump.to_string(),
@@ -281,8 +383,9 @@ impl From<LocalOutput> for TransactionOutput {
     }
 }
 
+#[cfg(feature = "native")]
 impl From<TxConfidence> for ConfEvent {
-    fn from(TxConfidence { wallet_tx, num_confirmations }: TxConfidence) -> Self {
+    fn from(TxConfidence { wallet_tx, num_confirmations, broadcast_failed }: TxConfidence) -> Self {
         let raw_tx = Some(consensus::serialize(&wallet_tx.tx));
         let (confidence_type, confirmation_block_time) = match wallet_tx.chain_position {
             ChainPosition::Confirmed { anchor, .. } =>
@@ -291,6 +394,7 @@ impl From<TxConfidence> for ConfEvent {
                     block_height: anchor.block_id.height,
                     confirmation_time: anchor.confirmation_time,
                 })),
+            ChainPosition::Unconfirmed { .. } if broadcast_failed => (ConfidenceType::BroadcastFailed, None),
             ChainPosition::Unconfirmed { .. } => (ConfidenceType::Unconfirmed, None)
         };
         Self {
@@ -298,19 +402,88 @@ impl From<TxConfidence> for ConfEvent {
             confidence_type: confidence_type.into(),
             num_confirmations,
             confirmation_block_time,
+            signals_rbf: wallet_tx.signals_rbf,
         }
     }
 }
 
 impl From<ProtocolErrorKind> for Status {
     fn from(value: ProtocolErrorKind) -> Self {
-        Self::internal(value.to_string())
+        match value {
+            ProtocolErrorKind::InvalidAdaptorPoint | ProtocolErrorKind::MismatchedAdaptorPoint =>
+                Self::invalid_argument(value.to_string()),
+            ProtocolErrorKind::TradeNotCompleted | ProtocolErrorKind::TradeResetTooLate(_) =>
+                Self::failed_precondition(value.to_string()),
+            ProtocolErrorKind::MissingTradeWallet | ProtocolErrorKind::MissingScriptKey
+            | ProtocolErrorKind::BuyerOnly | ProtocolErrorKind::MissingDepositTx
+            | ProtocolErrorKind::SighashAlreadyComputed | ProtocolErrorKind::UnsupportedLeafVersion(_)
+            | ProtocolErrorKind::KeySharesAlreadyAggregated | ProtocolErrorKind::MissingAdaptorSecret
+            | ProtocolErrorKind::TxOptionsNotRespected(_) | ProtocolErrorKind::InsufficientRedirectionFunds { .. }
+            | ProtocolErrorKind::ExcessRedirectionFunds { .. } | ProtocolErrorKind::AddressParse(_)
+            | ProtocolErrorKind::Transaction(_) | ProtocolErrorKind::TxSize(_) | ProtocolErrorKind::Multisig(_)
+            | ProtocolErrorKind::Wallet(_) => Self::internal(value.to_string()),
+        }
+    }
+}
+
+impl From<MusigError> for Status {
+    fn from(value: MusigError) -> Self {
+        match value {
+            MusigError::InvalidTradeId(_) | MusigError::InvalidInput { .. } => Self::invalid_argument(value.to_string()),
+            MusigError::TradeNotFound(_) => Self::not_found(value.to_string()),
+            MusigError::InvalidState { .. } => Self::failed_precondition(value.to_string()),
+            MusigError::RoleNotPermitted { .. } => Self::permission_denied(value.to_string()),
+            MusigError::Protocol(e) => e.into(),
+            #[cfg(feature = "native")]
+            MusigError::Wallet(e) => e.into(),
+            MusigError::MissingTradeData(_) | MusigError::CryptoError(_) | MusigError::Serialization(_) =>
+                Self::internal(value.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl From<WalletErrorKind> for Status {
+    fn from(value: WalletErrorKind) -> Self {
+        match value {
+            WalletErrorKind::LockTimeNotInFuture { .. } | WalletErrorKind::AddressParse(_)
+            | WalletErrorKind::InvalidAddress(_) | WalletErrorKind::AddressNetworkMismatch(_)
+            | WalletErrorKind::CannotSignMessage(_)
+            | WalletErrorKind::Descriptor(_) | WalletErrorKind::PrivateKeyInWatchOnlyDescriptor
+            | WalletErrorKind::InvalidWalletId(_) | WalletErrorKind::LoadWallet(_) =>
+                Self::invalid_argument(value.to_string()),
+            WalletErrorKind::WalletExists(_) | WalletErrorKind::DoubleSpend(_) => Self::already_exists(value.to_string()),
+            WalletErrorKind::WalletLocked | WalletErrorKind::IncorrectPassphrase =>
+                Self::permission_denied(value.to_string()),
+            WalletErrorKind::NoPassphrase | WalletErrorKind::TxSize(_) | WalletErrorKind::TorProxyUnsupported =>
+                Self::failed_precondition(value.to_string()),
+            WalletErrorKind::NotConnected | WalletErrorKind::BitcoindRpcPool(_) | WalletErrorKind::BitcoindRpc(_)
+            | WalletErrorKind::CompactBlockFilterClient(_) | WalletErrorKind::Electrum(_)
+            | WalletErrorKind::FeeEstimatorRequest(_) => Self::unavailable(value.to_string()),
+            WalletErrorKind::TxNotInBlock(..) => Self::not_found(value.to_string()),
+            WalletErrorKind::KeyRangeExhausted(_) => Self::resource_exhausted(value.to_string()),
+            WalletErrorKind::Timeout => Self::deadline_exceeded(value.to_string()),
+            WalletErrorKind::WatchOnly => Self::unimplemented(value.to_string()),
+            WalletErrorKind::ApplyHeader(_) | WalletErrorKind::CannotConnect(_) | WalletErrorKind::PeerAddressParse(_)
+            | WalletErrorKind::CompactBlockFilterBuilder(_) | WalletErrorKind::CompactBlockFilterUpdate(_)
+            | WalletErrorKind::MalformedZmqMessage | WalletErrorKind::Zmq(_) | WalletErrorKind::Decode(_)
+            | WalletErrorKind::WalletDatabase(_) | WalletErrorKind::Io(_) | WalletErrorKind::InvalidFeeEstimate(_)
+            | WalletErrorKind::CreateTx(_) | WalletErrorKind::Signer(_) | WalletErrorKind::ExtractTx(_)
+            | WalletErrorKind::Bip32(_) | WalletErrorKind::Csv(_) | WalletErrorKind::AddUtxo(_)
+            | WalletErrorKind::CalculateFee(_) => Self::internal(value.to_string()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use bdk_electrum::electrum_client;
+    use tonic::{Code, Status};
+
     use crate::pb::walletrpc::{ConfEvent, ConfidenceType};
+    use crate::protocol::{ProtocolErrorKind, TradeState};
+    use crate::server::MusigError;
+    use crate::wallet::WalletErrorKind;
 
     #[test]
     fn conf_event_default() {
@@ -319,7 +492,28 @@ mod tests {
             confidence_type: ConfidenceType::Missing.into(),
             num_confirmations: 0,
             confirmation_block_time: None,
+            signals_rbf: false,
         };
         assert_eq!(ConfEvent::default(), missing_tx_conf_event);
     }
+
+    #[test]
+    fn musig_error_status_codes() {
+        let cases = [
+            (MusigError::InvalidTradeId("empty trade id".to_owned()), Code::InvalidArgument),
+            (MusigError::TradeNotFound("unknown-trade".to_owned()), Code::NotFound),
+            (MusigError::InvalidState { expected: TradeState::DepositTxPublished, actual: TradeState::Initialized },
+                Code::FailedPrecondition),
+            (MusigError::MissingTradeData("key shares"), Code::Internal),
+            (MusigError::Protocol(ProtocolErrorKind::TradeNotCompleted), Code::FailedPrecondition),
+            (MusigError::Wallet(WalletErrorKind::Electrum(electrum_client::Error::AllAttemptsErrored(vec![]))),
+                Code::Unavailable),
+            (MusigError::Wallet(WalletErrorKind::TorProxyUnsupported), Code::FailedPrecondition),
+        ];
+        for (error, code) in cases {
+            let message = error.to_string();
+            let status = Status::from(error);
+            assert_eq!((status.code(), status.message()), (code, &message[..]));
+        }
+    }
 }
@@ -0,0 +1,110 @@
+//! A minimal direct messaging layer between two trade peers, so that Rust integration tests can
+//! pass the trade protocol messages from one node to the other over a real connection, as the Java
+//! clients would, rather than just handing them over in memory.
+//!
+//! Each message is sent as its protobuf encoding, prefixed by its length as a big-endian `u32`.
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+
+use prost::Message;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::pb::musigrpc::{NonceSharesMessage, PartialSignaturesMessage, PubKeySharesResponse};
+
+/// The largest frame accepted from the peer, well above the size of any protocol message.
+pub const MAX_FRAME_LEN: u32 = 1 << 20;
+
+/// Connect to a peer listening at the given address.
+pub async fn connect_peer(addr: SocketAddr) -> io::Result<PeerConn> {
+    let stream = TcpStream::connect(addr).await?;
+    stream.set_nodelay(true)?;
+    Ok(PeerConn { stream })
+}
+
+/// Accept the next connection from a peer on the given listener.
+pub async fn accept_peer(listener: &TcpListener) -> io::Result<PeerConn> {
+    let (stream, _) = listener.accept().await?;
+    stream.set_nodelay(true)?;
+    Ok(PeerConn { stream })
+}
+
+/// A connection to the trade peer, with a send & receive method for each protocol message.
+#[derive(Debug)]
+pub struct PeerConn {
+    stream: TcpStream,
+}
+
+impl PeerConn {
+    pub async fn send_key_shares(&mut self, key_shares: &PubKeySharesResponse) -> io::Result<()> {
+        self.send(key_shares).await
+    }
+
+    pub async fn recv_key_shares(&mut self) -> io::Result<PubKeySharesResponse> { self.recv().await }
+
+    pub async fn send_nonce_shares(&mut self, nonce_shares: &NonceSharesMessage) -> io::Result<()> {
+        self.send(nonce_shares).await
+    }
+
+    pub async fn recv_nonce_shares(&mut self) -> io::Result<NonceSharesMessage> { self.recv().await }
+
+    pub async fn send_partial_signatures(&mut self, partial_signatures: &PartialSignaturesMessage) -> io::Result<()> {
+        self.send(partial_signatures).await
+    }
+
+    pub async fn recv_partial_signatures(&mut self) -> io::Result<PartialSignaturesMessage> { self.recv().await }
+
+    async fn send(&mut self, message: &impl Message) -> io::Result<()> {
+        let len = u32::try_from(message.encoded_len()).ok().filter(|&len| len <= MAX_FRAME_LEN)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "message too large"))?;
+        let mut frame = Vec::with_capacity(4 + message.encoded_len());
+        frame.extend_from_slice(&len.to_be_bytes());
+        message.encode(&mut frame)?;
+        self.stream.write_all(&frame).await
+    }
+
+    async fn recv<M: Message + Default>(&mut self) -> io::Result<M> {
+        let len = self.stream.read_u32().await?;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("frame too large: {len} bytes")));
+        }
+        let mut buf = vec![0; len as usize];
+        self.stream.read_exact(&mut buf).await?;
+        M::decode(&buf[..]).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn connected_pair() -> (PeerConn, PeerConn) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (accepted, connected) = tokio::join!(accept_peer(&listener), connect_peer(addr));
+        (accepted.unwrap(), connected.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_peer_round_trip() {
+        let (mut a, mut b) = connected_pair().await;
+        let key_shares = PubKeySharesResponse {
+            buyer_output_pub_key_share: vec![2; 33],
+            seller_output_pub_key_share: vec![3; 33],
+            multisig_script_key: vec![4; 32],
+            ..PubKeySharesResponse::default()
+        };
+        a.send_key_shares(&key_shares).await.unwrap();
+        a.send_key_shares(&PubKeySharesResponse::default()).await.unwrap();
+        assert_eq!(b.recv_key_shares().await.unwrap(), key_shares);
+        assert_eq!(b.recv_key_shares().await.unwrap(), PubKeySharesResponse::default());
+    }
+
+    #[tokio::test]
+    async fn test_peer_rejects_oversized_frame() {
+        let (mut a, mut b) = connected_pair().await;
+        a.stream.write_u32(MAX_FRAME_LEN + 1).await.unwrap();
+        let err = b.recv_nonce_shares().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -1,43 +1,113 @@
 use std::collections::BTreeMap;
-use std::sync::{Arc, LazyLock, Mutex};
+use std::mem;
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
 
 use bdk_wallet::bitcoin::address::{NetworkChecked, NetworkUnchecked, NetworkValidation};
+use bdk_wallet::bitcoin::amount::CheckedSum as _;
+use bdk_wallet::bitcoin::hashes::Hash as _;
+use bdk_wallet::bitcoin::taproot::LeafVersion;
 use bdk_wallet::bitcoin::{
-    Address, Amount, FeeRate, Network, Psbt, TapSighash, Transaction, Txid, XOnlyPublicKey,
+    Address, Amount, FeeRate, Network, Psbt, ScriptBuf, TapSighash, Transaction, Txid, Witness,
+    XOnlyPublicKey, absolute, relative,
 };
+use bdk_wallet::miniscript::{Miniscript, Tap};
 use guardian::ArcMutexGuardian;
 use musig2::secp::{MaybeScalar, Point, Scalar};
 use musig2::{PartialSignature, PubNonce};
-use protocol::multisig::{KeyCtx, KeyPair, PointExt as _, SigCtx};
+use protocol::multisig::{KeyCtx, KeyPair, NoncePool, PointExt as _, SigCtx};
 use protocol::receiver::{Receiver, ReceiverList};
 use protocol::transaction::{
     CustomPayoutTxBuilder, DepositTxBuilder, ForwardingTxBuilder, NetworkParams as _,
-    RedirectTxBuilder, TransactionExt as _, WarningTxBuilder,
+    RedirectTxBuilder, SIGNED_FORWARDING_TX_WEIGHT, TransactionErrorKind, TransactionExt as _,
+    WarningTxBuilder,
 };
 use protocol::{mocks, script_paths};
 use thiserror::Error;
+use tokio::sync::Mutex as AsyncMutex;
 use wallet::protocol_wallet_api::ProtocolWalletApi;
+use web_time::Instant;
 
+use crate::receipt::TradeReceipt;
 use crate::storage::{ByRef, ByVal, Storage};
-
+use crate::tx_policy::{SendOptions, TxSizeError, unix_time_now, validate_tx_size};
+use crate::verification;
+
+// The deposit & swap txs have no absolute lock time by protocol. (The warning, redirect & claim txs
+// get their network-dependent relative lock times via their builders, set in 'TradeModel::new'.)
+// All of the deposit, swap & warning txs must signal RBF, so that they can be fee-bumped. Unlike
+// the wallet's own txs, they are not given a BIP69 ordering, as their input & output order is fixed
+// by the protocol: the deposit tx is shuffled with entropy from both peers, and the later txs spend
+// its outputs by index.
+const DEPOSIT_TX_OPTIONS: SendOptions = SendOptions::new().with_locktime(absolute::LockTime::ZERO).with_rbf(true);
+const SWAP_TX_OPTIONS: SendOptions = SendOptions::new().with_locktime(absolute::LockTime::ZERO).with_rbf(true);
+const WARNING_TX_OPTIONS: SendOptions = SendOptions::new().with_locktime(absolute::LockTime::ZERO).with_rbf(true);
+
+/// The oldest and latest versions of the trade protocol this daemon can run. A client supporting a
+/// later version than the latest is still served, with the trade run at the latest version.
+pub const MIN_SUPPORTED_VERSION: u32 = 1;
+pub const MAX_SUPPORTED_VERSION: u32 = 1;
+
+/// Each trade model is behind its own async lock, so that a request waiting on a trade busy with
+/// another (possibly slow) request yields its Tokio worker thread, instead of blocking it.
 pub trait TradeModelStore {
-    fn add_trade_model(&self, trade_model: TradeModel);
-    fn get_trade_model(&self, trade_id: &str) -> Option<Arc<Mutex<TradeModel>>>;
+    /// Add the trade model, unless one with the same trade ID or `max_trade_count` trade models are
+    /// held already. Both are checked under the same lock as the trade model is added, so that
+    /// concurrent calls can neither overwrite each other's trade models nor exceed the limit.
+    ///
+    /// # Errors
+    /// Will return `Err` if the trade ID is taken or the limit has been reached
+    fn add_trade_model(&self, trade_model: TradeModel, max_trade_count: usize) -> Result<(), AddTradeModelError>;
+    fn get_trade_model(&self, trade_id: &str) -> Option<Arc<AsyncMutex<TradeModel>>>;
+    fn remove_trade_model(&self, trade_id: &str) -> Option<Arc<AsyncMutex<TradeModel>>>;
+    fn trade_models(&self) -> Vec<Arc<AsyncMutex<TradeModel>>>;
+    fn trade_count(&self) -> usize;
 }
 
-type TradeModelMemoryStore = Mutex<BTreeMap<String, Arc<Mutex<TradeModel>>>>;
+#[derive(Error, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum AddTradeModelError {
+    #[error("trade_id taken by a concurrently initiated trade")]
+    TradeIdTaken,
+    #[error("too many concurrent trades")]
+    TooManyTrades,
+}
+
+type TradeModelMemoryStore = Mutex<BTreeMap<String, Arc<AsyncMutex<TradeModel>>>>;
 
 impl TradeModelStore for TradeModelMemoryStore {
-    fn add_trade_model(&self, trade_model: TradeModel) {
-        // TODO: Maybe use try_insert (or similar), to disallow overwriting a trade model with the same ID.
-        self.lock().unwrap().insert(trade_model.trade_id.clone(), Arc::new(Mutex::new(trade_model)));
+    fn add_trade_model(&self, trade_model: TradeModel, max_trade_count: usize) -> Result<(), AddTradeModelError> {
+        let mut trade_models = self.lock().unwrap();
+        if trade_models.contains_key(&trade_model.trade_id) {
+            return Err(AddTradeModelError::TradeIdTaken);
+        }
+        if trade_models.len() >= max_trade_count {
+            return Err(AddTradeModelError::TooManyTrades);
+        }
+        trade_models.insert(trade_model.trade_id.clone(), Arc::new(AsyncMutex::new(trade_model)));
+        Ok(())
     }
 
-    fn get_trade_model(&self, trade_id: &str) -> Option<Arc<Mutex<TradeModel>>> {
+    fn get_trade_model(&self, trade_id: &str) -> Option<Arc<AsyncMutex<TradeModel>>> {
         self.lock().unwrap().get(trade_id).map(Arc::clone)
     }
+
+    fn remove_trade_model(&self, trade_id: &str) -> Option<Arc<AsyncMutex<TradeModel>>> {
+        self.lock().unwrap().remove(trade_id)
+    }
+
+    fn trade_models(&self) -> Vec<Arc<AsyncMutex<TradeModel>>> {
+        self.lock().unwrap().values().map(Arc::clone).collect()
+    }
+
+    fn trade_count(&self) -> usize {
+        self.lock().unwrap().len()
+    }
 }
 
+/// The number of signing sessions with the buyer's & seller's payout key shares, respectively.
+const BUYER_PAYOUT_KEY_SIG_CTX_COUNT: usize = 4;
+const SELLER_PAYOUT_KEY_SIG_CTX_COUNT: usize = 5;
+
 pub static TRADE_MODELS: LazyLock<TradeModelMemoryStore> = LazyLock::new(|| Mutex::new(BTreeMap::new()));
 
 #[derive(Default)]
@@ -46,14 +116,55 @@ pub struct TradeModel {
     my_role: Role,
     trade_wallet: Option<Arc<Mutex<dyn ProtocolWalletApi + Send + 'static>>>,
     keys: Keys,
+    nonce_pool: NoncePool,
     deposit_tx: DepositTx,
     swap_tx: SwapTx,
     custom_payout_tx: CustomPayoutTx,
     buyer_txs: ArbitrationTxs,
     seller_txs: ArbitrationTxs,
+    last_sequence_number: u64,
+    protocol_version: u32,
+    wallet_id: String,
+    state: TradeState,
+    created_at: u64,
+    completed_at: Option<u64>,
+    payment_sent_at: Option<Instant>,
+}
+
+/// The stage a trade has reached, as of the last successful musig request on it. The variants are
+/// in protocol order, so that states may be compared to tell how far the trade has progressed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+#[non_exhaustive]
+pub enum TradeState {
+    #[default] Initialized,
+    NonceSharesExchanged,
+    PartialSignaturesExchanged,
+    DepositTxSigned,
+    DepositTxPublished,
+    SwapTxSigned,
+    ForceClosing,
+    Closed,
 }
 
-#[derive(Default, Eq, PartialEq)]
+impl TradeState {
+    /// Whether the deposit tx has yet to be published, so that the trade may be safely abandoned.
+    pub const fn is_pre_deposit(self) -> bool {
+        matches!(self, Self::Initialized | Self::NonceSharesExchanged
+            | Self::PartialSignaturesExchanged | Self::DepositTxSigned)
+    }
+}
+
+/// A non-fatal problem with the data stored in a trade model, as found by
+/// [`TradeModel::check_integrity`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum IntegrityWarning {
+    /// The named amount is set, but to zero.
+    ZeroAmount(&'static str),
+}
+
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+#[expect(clippy::exhaustive_enums)]
 pub enum Role {
     #[default] SellerAsMaker,
     SellerAsTaker,
@@ -80,13 +191,17 @@ struct ArbitrationTxs {
 #[derive(Default)]
 struct DepositTx {
     builder: DepositTxBuilder,
+    extra_script_leaves: Vec<ScriptBuf>,
 }
 
 #[derive(Default)]
 struct SwapTx {
     builder: ForwardingTxBuilder,
     input_sighash: Option<TapSighash>,
+    computed_input_sighash: OnceLock<TapSighash>,
     input_sig_ctx: SigCtx,
+    adaptor_point: Option<Point>,
+    adaptor_secret: Option<Scalar>,
 }
 
 #[derive(Default)]
@@ -135,8 +250,6 @@ impl<'a> ExchangedAddresses<'a, ByVal, NetworkUnchecked> {
     }
 }
 
-#[expect(clippy::struct_field_names,
-reason = "removing common suffix probably wouldn't make things clearer")]
 pub struct ExchangedNonces<'a, S: Storage> {
     pub swap_tx_input: S::Store<'a, PubNonce>,
     pub buyers_warning_tx_buyer_input: S::Store<'a, PubNonce>,
@@ -169,20 +282,149 @@ pub struct ContractualTxids {
 
 impl TradeModel {
     pub fn new(trade_id: String, my_role: Role) -> Self {
-        let mut trade_model = Self { trade_id, my_role, ..Default::default() };
-        let network = trade_model.trade_wallet.insert(if trade_model.am_buyer() {
+        let mut trade_model = Self { trade_id, my_role, created_at: unix_time_now(), ..Default::default() };
+        let network = trade_model.init_mock_trade_wallet();
+        trade_model.init_tx_lock_times(network);
+        trade_model.keys.am_buyer = trade_model.am_buyer();
+        trade_model
+    }
+
+    fn init_mock_trade_wallet(&mut self) -> Network {
+        self.trade_wallet.insert(if self.am_buyer() {
             Arc::new(Mutex::new(mocks::mock_buyer_trade_wallet()))
         } else {
             Arc::new(Mutex::new(mocks::mock_seller_trade_wallet()))
-        }).lock().unwrap().network();
-        for txs in [&mut trade_model.buyer_txs, &mut trade_model.seller_txs] {
+        }).lock().unwrap().network()
+    }
+
+    fn init_tx_lock_times(&mut self, network: Network) {
+        for txs in [&mut self.buyer_txs, &mut self.seller_txs] {
             txs.warning.builder.set_lock_time(network.warning_lock_time());
             txs.redirect.builder.set_lock_time(network.redirect_lock_time());
             txs.claim.builder.set_lock_time(network.claim_lock_time());
         }
-        trade_model.swap_tx.builder.disable_lock_time();
-        trade_model.keys.am_buyer = trade_model.am_buyer();
-        trade_model
+        self.swap_tx.builder.disable_lock_time();
+    }
+
+    /// Take the trade back to its state just after `init_trade`, so that the exchange of key and
+    /// nonce shares may be retried, say after the peer sent invalid ones. My key shares, the trade
+    /// amounts and any extra script leaves are kept, but the peer's key shares and everything
+    /// since built from them, such as the nonce shares and partial signatures, are cleared. Fresh
+    /// nonce shares will be made on retry, so none is ever reused.
+    ///
+    /// # Errors
+    /// Will return `Err` if the deposit tx has been signed, when it is no longer safe to abandon the
+    /// txs already built, as the peer may publish the deposit tx with our signature
+    pub fn reset_to_key_shares(&mut self) -> Result<()> {
+        if !matches!(self.state, TradeState::Initialized | TradeState::NonceSharesExchanged
+            | TradeState::PartialSignaturesExchanged) {
+            return Err(ProtocolErrorKind::TradeResetTooLate(self.state));
+        }
+        // TODO: Unreserve the inputs of our discarded half deposit PSBT instead, once the trade wallet
+        //  reserves the UTXOs it selects. (For now, the mock trade wallet is replaced, restoring the
+        //  coins and addresses that it hands out.)
+        let network = self.init_mock_trade_wallet();
+        let (trade_amount, buyers_security_deposit, sellers_security_deposit) =
+            (self.trade_amount(), self.buyers_security_deposit(), self.sellers_security_deposit());
+
+        self.keys.buyer_payout_ctx.clear_peers_key_share();
+        self.keys.seller_payout_ctx.clear_peers_key_share();
+        self.keys.peers_multisig_script_key = None;
+        self.deposit_tx = DepositTx {
+            extra_script_leaves: mem::take(&mut self.deposit_tx.extra_script_leaves),
+            ..DepositTx::default()
+        };
+        self.swap_tx = SwapTx::default();
+        self.custom_payout_tx = CustomPayoutTx::default();
+        self.buyer_txs = ArbitrationTxs::default();
+        self.seller_txs = ArbitrationTxs::default();
+        self.init_tx_lock_times(network);
+        self.state = TradeState::Initialized;
+
+        if let Some(trade_amount) = trade_amount {
+            self.set_trade_amount(trade_amount);
+        }
+        if let Some(buyers_security_deposit) = buyers_security_deposit {
+            self.set_buyers_security_deposit(buyers_security_deposit);
+        }
+        if let Some(sellers_security_deposit) = sellers_security_deposit {
+            self.set_sellers_security_deposit(sellers_security_deposit);
+        }
+        Ok(())
+    }
+
+    /// Check that the stored data is self-consistent: that each aggregated key and nonce matches its
+    /// shares, and that each stored partial signature verifies under them. Meant to be run on a
+    /// trade model restored from storage, before it is used to sign anything further.
+    ///
+    /// # Errors
+    /// Will return `Err` if any of the above fails to hold, in which case the trade model cannot be
+    /// trusted. Lesser problems, such as zero amounts, are returned as warnings instead
+    pub fn check_integrity(&self) -> Result<Vec<IntegrityWarning>> {
+        self.keys.buyer_payout_ctx.check_integrity()?;
+        self.keys.seller_payout_ctx.check_integrity()?;
+        for sig_ctx in self.all_sig_ctxs() {
+            sig_ctx.check_integrity()?;
+        }
+        let amounts = [
+            ("trade amount", self.trade_amount()),
+            ("buyer's security deposit", self.buyers_security_deposit()),
+            ("seller's security deposit", self.sellers_security_deposit()),
+        ];
+        Ok(amounts.into_iter()
+            .filter(|&(_, amount)| amount == Some(Amount::ZERO))
+            .map(|(name, _)| IntegrityWarning::ZeroAmount(name))
+            .collect())
+    }
+
+    /// Whether the given sequence number is the one expected for the next request on this trade.
+    pub fn is_next_sequence_number(&self, sequence_number: u64) -> bool {
+        self.last_sequence_number.checked_add(1) == Some(sequence_number)
+    }
+
+    pub const fn set_last_sequence_number(&mut self, sequence_number: u64) {
+        self.last_sequence_number = sequence_number;
+    }
+
+    /// The trade protocol version negotiated with the client, which later steps of the trade may
+    /// branch on, once there is more than one.
+    pub const fn protocol_version(&self) -> u32 { self.protocol_version }
+
+    pub const fn set_protocol_version(&mut self, protocol_version: u32) {
+        self.protocol_version = protocol_version;
+    }
+
+    /// The ID of the daemon's wallet that the trade's txs are broadcast & tracked with, or empty for
+    /// the default wallet.
+    pub fn wallet_id(&self) -> &str { &self.wallet_id }
+
+    pub fn set_wallet_id(&mut self, wallet_id: String) { self.wallet_id = wallet_id; }
+
+    pub fn trade_id(&self) -> &str { &self.trade_id }
+
+    pub const fn my_role(&self) -> Role { self.my_role }
+
+    /// The time this trade model was created, in seconds since the Unix epoch.
+    pub const fn created_at(&self) -> u64 { self.created_at }
+
+    pub const fn state(&self) -> TradeState { self.state }
+
+    /// Record that the buyer has sent the payment, starting the clock on the seller's closing of the
+    /// trade. Only the first call counts, so that repeating it can't put off a force-close.
+    pub fn mark_payment_sent(&mut self) {
+        self.payment_sent_at.get_or_insert_with(Instant::now);
+    }
+
+    /// When the buyer marked the payment sent, if it has.
+    pub const fn payment_sent_at(&self) -> Option<Instant> { self.payment_sent_at }
+
+    /// Moves the trade on to the given state, unless it has already got that far. (Some requests
+    /// may be repeated, so this shouldn't take the trade back to an earlier state.)
+    pub fn advance_state(&mut self, state: TradeState) {
+        if self.state < TradeState::ForceClosing && state >= TradeState::ForceClosing {
+            self.completed_at = Some(unix_time_now());
+        }
+        self.state = self.state.max(state);
     }
 
     pub const fn am_buyer(&self) -> bool {
@@ -194,10 +436,22 @@ impl TradeModel {
             .ok_or(ProtocolErrorKind::MissingTradeWallet)?).unwrap())
     }
 
+    pub fn trade_amount(&self) -> Option<Amount> {
+        self.deposit_tx.builder.trade_amount().ok().copied()
+    }
+
     pub fn set_trade_amount(&mut self, trade_amount: Amount) {
         self.deposit_tx.builder.set_trade_amount(trade_amount);
     }
 
+    pub fn buyers_security_deposit(&self) -> Option<Amount> {
+        self.deposit_tx.builder.buyers_security_deposit().ok().copied()
+    }
+
+    pub fn sellers_security_deposit(&self) -> Option<Amount> {
+        self.deposit_tx.builder.sellers_security_deposit().ok().copied()
+    }
+
     pub fn set_buyers_security_deposit(&mut self, buyers_security_deposit: Amount) {
         self.deposit_tx.builder.set_buyers_security_deposit(buyers_security_deposit);
     }
@@ -210,7 +464,9 @@ impl TradeModel {
         self.deposit_tx.builder.set_fee_rate(fee_rate);
     }
 
-    fn prepared_tx_fee_rate(&self) -> Result<FeeRate> { Ok(*self.swap_tx.builder.fee_rate()?) }
+    pub fn deposit_tx_fee_rate(&self) -> Result<FeeRate> { Ok(*self.deposit_tx.builder.fee_rate()?) }
+
+    pub fn prepared_tx_fee_rate(&self) -> Result<FeeRate> { Ok(*self.swap_tx.builder.fee_rate()?) }
 
     pub fn set_prepared_tx_fee_rate(&mut self, fee_rate: FeeRate) {
         for txs in [&mut self.buyer_txs, &mut self.seller_txs] {
@@ -228,6 +484,22 @@ impl TradeModel {
         Ok(())
     }
 
+    /// Estimate the amounts the buyer and seller respectively end up with from a normally closed
+    /// trade, net of fees: their deposit tx payouts, less any trade fees (paid by the seller) and
+    /// the cost of sweeping each payout into their wallet at the prepared tx fee rate. Each side's
+    /// share of the deposit tx fee is left out, as it depends on the inputs the peer funds it with.
+    pub fn estimate_net_amounts(&self) -> Result<[Amount; 2]> {
+        let builder = &self.deposit_tx.builder;
+        let buyers_payout = builder.buyers_security_deposit()?.checked_add(*builder.trade_amount()?);
+        let sellers_payout = *builder.sellers_security_deposit()?;
+        let trade_fees = builder.trade_fee_receivers()?.iter().map(|r| r.amount).checked_sum();
+        let sweep_fee = self.prepared_tx_fee_rate()?.checked_mul_by_weight(SIGNED_FORWARDING_TX_WEIGHT);
+        (|| Some([
+            buyers_payout?.checked_sub(sweep_fee?)?,
+            sellers_payout.checked_sub(trade_fees?)?.checked_sub(sweep_fee?)?,
+        ]))().ok_or_else(|| TransactionErrorKind::Overflow.into())
+    }
+
     pub fn redirection_amount_msat(&self) -> Result<u64> {
         let split_input_amounts = [
             *self.deposit_tx.builder.trade_amount()?,
@@ -255,22 +527,65 @@ impl TradeModel {
         })
     }
 
+    /// Set the peer's public key shares, as passed on by the client. The shares travel between the
+    /// peers in the clear, but the payout keys aggregated from them are blinded (see
+    /// [`Self::aggregate_key_shares`]), so that they can't be linked to the trade's txs on chain.
     pub fn set_peer_key_shares(&mut self, keys: &ExchangedKeys<ByVal>) {
         self.keys.buyer_payout_ctx.set_peers_pub_key(keys.buyer_payout);
         self.keys.seller_payout_ctx.set_peers_pub_key(keys.seller_payout);
         self.keys.peers_multisig_script_key.get_or_insert(keys.multisig_script);
     }
 
+    /// Add an extra leaf to the script tree of both deposit payout outputs, alongside the multisig
+    /// leaf, such as a timelocked `script_paths::arbitration_script` for dispute resolution. This
+    /// changes the taproot tweak of the aggregated payout keys, so it must be called (by both peers,
+    /// with the same leaves in the same order) before the key shares are aggregated. Only miniscript
+    /// compatible tapscript leaves are supported, so that the custom payout tx can still be signed.
+    pub fn add_script_path_leaf(&mut self, script: ScriptBuf, leaf_version: LeafVersion) -> Result<()> {
+        if leaf_version != LeafVersion::TapScript {
+            return Err(ProtocolErrorKind::UnsupportedLeafVersion(leaf_version));
+        }
+        if self.keys.buyer_payout_ctx.aggregated_key().is_ok() {
+            return Err(ProtocolErrorKind::KeySharesAlreadyAggregated);
+        }
+        Miniscript::<XOnlyPublicKey, Tap>::parse(&script).map_err(TransactionErrorKind::from)?;
+        self.deposit_tx.extra_script_leaves.push(script);
+        Ok(())
+    }
+
+    /// Build the witness spending the buyer's or seller's deposit payout output via the given extra
+    /// script leaf, for arbitration, given the stack items satisfying the leaf script (such as the
+    /// arbitrator's signature). Any lock time required by the leaf must be set on the spending tx.
+    pub fn spend_via_script_path(&self, buyer_output: bool, leaf_script: &ScriptBuf, stack: &[Vec<u8>]) -> Result<Witness> {
+        let [buyer_pub_key, seller_pub_key] = self.keys.multisig_script_keys()?;
+        let [buyer_internal_key, seller_internal_key] = self.keys.internal_keys()?;
+        let internal_key = if buyer_output { buyer_internal_key } else { seller_internal_key };
+        let control_block = script_paths::deposit_payout_control_block(&internal_key, buyer_pub_key,
+            seller_pub_key, &self.deposit_tx.extra_script_leaves, leaf_script.clone())?;
+
+        let mut witness = Witness::from_slice(stack);
+        witness.push(leaf_script.as_bytes());
+        witness.push(control_block.serialize());
+        Ok(witness)
+    }
+
+    /// Aggregate the key shares of each payout output, blinded with a tweak derived from the
+    /// Diffie-Hellman secret of my key share & the peer's. Only the two traders can compute the
+    /// tweak, so only they can tell which keys on chain belong to the trade, even to whoever relays
+    /// the key shares between them. (The multisig script keys of the deposit tx's script path are
+    /// not blinded, but are only ever revealed on chain by a script path spend.)
     // TODO: Try to refactor this method:
     pub fn aggregate_key_shares(&mut self) -> Result<()> {
         let network = self.trade_wallet()?.network();
-        self.keys.buyer_payout_ctx.aggregate_pub_key_shares()?;
-        self.keys.seller_payout_ctx.aggregate_pub_key_shares()?;
+        self.keys.buyer_payout_ctx.aggregate_blinded_key_shares()?;
+        self.keys.seller_payout_ctx.aggregate_blinded_key_shares()?;
 
         let [buyer_pub_key, seller_pub_key] = self.keys.multisig_script_keys()?;
         let [buyer_internal_key, seller_internal_key] = self.keys.internal_keys()?;
 
-        let deposit_merkle_root = script_paths::deposit_payout_merkle_root(buyer_pub_key, seller_pub_key)?;
+        let extra_leaves = &self.deposit_tx.extra_script_leaves;
+        let deposit_merkle_root = script_paths::deposit_payout_merkle_root_with_extra_leaves(
+            buyer_pub_key, seller_pub_key, extra_leaves)?;
         let buyer_payout_tweaked_key_ctx = self.keys.buyer_payout_ctx.with_taproot_tweak(
             Some(&deposit_merkle_root))?;
         let seller_payout_tweaked_key_ctx = self.keys.seller_payout_ctx.with_taproot_tweak(
@@ -280,10 +595,10 @@ impl TradeModel {
             .set_buyer_payout_address(buyer_payout_tweaked_key_ctx.p2tr_address(network))
             .set_seller_payout_address(seller_payout_tweaked_key_ctx.p2tr_address(network));
         self.custom_payout_tx.builder
-            .set_buyer_input_descriptor(script_paths::deposit_payout_descriptor(
-                &buyer_internal_key, buyer_pub_key, seller_pub_key)?)
-            .set_seller_input_descriptor(script_paths::deposit_payout_descriptor(
-                &seller_internal_key, buyer_pub_key, seller_pub_key)?);
+            .set_buyer_input_descriptor(script_paths::deposit_payout_descriptor_with_extra_leaves(
+                &buyer_internal_key, buyer_pub_key, seller_pub_key, extra_leaves)?)
+            .set_seller_input_descriptor(script_paths::deposit_payout_descriptor_with_extra_leaves(
+                &seller_internal_key, buyer_pub_key, seller_pub_key, extra_leaves)?);
 
         self.buyer_txs.warning.buyer_input_sig_ctx.set_tweaked_key_ctx(buyer_payout_tweaked_key_ctx.clone());
         self.seller_txs.warning.buyer_input_sig_ctx.set_tweaked_key_ctx(buyer_payout_tweaked_key_ctx);
@@ -311,6 +626,60 @@ impl TradeModel {
         Ok(())
     }
 
+    /// Lock the swap tx signature to an additional adaptor point `T`, for atomic swap scenarios.
+    /// The swap tx input is then signed with the combined adaptor point `S + T`, where `S` is the
+    /// seller's key share for the buyer's payout output, so that the published swap tx reveals
+    /// `s + t`, rather than `s` alone.
+    ///
+    /// `T` is the point locking the buyer's side of the swap on the other chain, so its scalar `t`
+    /// is the buyer's, and is revealed to the seller there once the buyer claims that side. Only
+    /// then can the seller complete the signature and publish the swap tx, from which the buyer,
+    /// knowing `t` from the outset, recovers `s` with no further message from the seller (see
+    /// [`Self::recover_seller_private_key_share_for_buyer_output`]). The buyer must therefore set
+    /// `t` with [`Self::set_adaptor_secret`] before partially signing the swap tx.
+    ///
+    /// This must be called after the key shares are aggregated and before the nonce shares are
+    /// initialized, and both peers must use the same point, which is checked against the one the
+    /// peer echoes in its `NonceSharesMessage`.
+    ///
+    /// SECURITY: The seller must check out-of-band that `T` really locks the buyer's side of the
+    ///  swap on the other chain, as a point chosen relative to `S` would let the buyer learn `s`
+    ///  without the seller ever learning `t`, breaking the atomicity of the swap.
+    pub fn init_adaptor_point(&mut self, adaptor_point: Point) -> Result<()> {
+        let combined_point = (*self.keys.adaptor_key_share()?.pub_key() + adaptor_point).not_inf()
+            .map_err(|_| ProtocolErrorKind::InvalidAdaptorPoint)?;
+        self.swap_tx.input_sig_ctx.set_adaptor_point(combined_point)?;
+        self.swap_tx.adaptor_point = Some(adaptor_point);
+        Ok(())
+    }
+
+    pub const fn get_adaptor_point(&self) -> Option<&Point> { self.swap_tx.adaptor_point.as_ref() }
+
+    pub fn check_peer_adaptor_point(&self, peers_adaptor_point: Option<Point>) -> Result<()> {
+        if self.swap_tx.adaptor_point != peers_adaptor_point {
+            return Err(ProtocolErrorKind::MismatchedAdaptorPoint);
+        }
+        Ok(())
+    }
+
+    /// Set the scalar `t` corresponding to the additional adaptor point. The buyer holds it from the
+    /// outset, to recover the seller's key share from the swap tx signature, while the seller only
+    /// learns it from the other chain, and needs it to complete its swap tx signature.
+    pub fn set_adaptor_secret(&mut self, adaptor_secret: Scalar) -> Result<()> {
+        if self.swap_tx.adaptor_point != Some(adaptor_secret.base_point_mul()) {
+            return Err(ProtocolErrorKind::MismatchedAdaptorPoint);
+        }
+        self.swap_tx.adaptor_secret = Some(adaptor_secret);
+        Ok(())
+    }
+
+    /// My partial signature on the swap tx input, adapted with the swap tx adaptor point (including
+    /// any additional adaptor point). This is made by `sign_partial` for the seller, but only by
+    /// `sign_swap_tx_input_partial` for the buyer, since it needs the seller's sighash.
+    pub fn sign_partial_adaptor(&self) -> Result<PartialSignature> {
+        Ok(*self.swap_tx.input_sig_ctx.my_partial_sig()?)
+    }
+
     pub fn init_my_addresses(&mut self) -> Result<()> {
         let mut wallet = self.trade_wallet()?;
         let my_txs = if self.am_buyer() { &mut self.buyer_txs } else { &mut self.seller_txs };
@@ -324,6 +693,13 @@ impl TradeModel {
         Ok(())
     }
 
+    /// The addresses of my anchor outputs on my warning & redirect txs, for any later CPFP fee bump.
+    pub fn get_my_fee_bump_addresses(&self) -> impl Iterator<Item = &Address> {
+        let my_txs = if self.am_buyer() { &self.buyer_txs } else { &self.seller_txs };
+        [my_txs.warning.builder.anchor_address(), my_txs.redirect.builder.anchor_address()]
+            .into_iter().flatten()
+    }
+
     pub fn get_my_addresses(&self) -> Option<ExchangedAddresses<'_, ByRef>> {
         let my_txs = if self.am_buyer() { &self.buyer_txs } else { &self.seller_txs };
         Some(ExchangedAddresses {
@@ -369,6 +745,7 @@ impl TradeModel {
 
     pub fn compute_unsigned_deposit_tx(&mut self) -> Result<()> {
         self.deposit_tx.builder.compute_unsigned_tx()?;
+        check_tx_options(DEPOSIT_TX_OPTIONS, &self.deposit_tx.builder.psbt()?.unsigned_tx)?;
         let buyer_payout = self.deposit_tx.builder.buyer_payout()?;
         let seller_payout = self.deposit_tx.builder.seller_payout()?;
 
@@ -388,16 +765,20 @@ impl TradeModel {
         if !self.am_buyer() {
             // Only the seller has all the params necessary to compute the unsigned swap tx.
             self.swap_tx.builder.compute_unsigned_tx()?;
+            check_tx_options(SWAP_TX_OPTIONS, self.swap_tx.builder.unsigned_tx()?)?;
+            validate_tx_size(self.swap_tx.builder.unsigned_tx()?)?;
         }
         let [mut txs, mut peer_txs] = [&mut self.buyer_txs, &mut self.seller_txs];
         txs.warning.builder.compute_unsigned_tx()?;
         peer_txs.warning.builder.compute_unsigned_tx()?;
+        check_tx_options(WARNING_TX_OPTIONS, txs.warning.builder.unsigned_tx()?)?;
+        check_tx_options(WARNING_TX_OPTIONS, peer_txs.warning.builder.unsigned_tx()?)?;
         for _ in 0..2 {
             txs.redirect.builder.set_input(peer_txs.warning.builder.escrow()?.clone());
             txs.redirect.builder.compute_unsigned_tx()?;
             txs.claim.builder.set_input(txs.warning.builder.escrow()?.clone());
             txs.claim.builder.compute_unsigned_tx()?;
-            std::mem::swap(&mut txs, &mut peer_txs);
+            mem::swap(&mut txs, &mut peer_txs);
         }
         Ok(())
     }
@@ -446,13 +827,26 @@ impl TradeModel {
         Ok(())
     }
 
-    pub fn init_my_nonce_shares(&mut self) -> Result<()> {
-        for ctx in self.all_sig_ctxs_mut() {
-            ctx.init_my_nonce_share()?;
-        }
+    /// Generate the nonce shares of all the signing sessions of the trade in advance, to be drawn on
+    /// by [`Self::init_my_nonce_shares`], so that it need not make them while the peer waits. This
+    /// needs my key shares, to which each nonce is bound.
+    pub fn pre_generate_nonces(&mut self) -> Result<()> {
+        // See 'aggregate_key_shares' for which signing sessions use which key share:
+        self.nonce_pool.fill(self.keys.buyer_payout_ctx.my_key_share()?, BUYER_PAYOUT_KEY_SIG_CTX_COUNT)?;
+        self.nonce_pool.fill(self.keys.seller_payout_ctx.my_key_share()?, SELLER_PAYOUT_KEY_SIG_CTX_COUNT)?;
         Ok(())
     }
 
+    /// Initialize my nonce share for each signing session, drawing from the nonces generated in
+    /// advance, if any remain, else generating them on demand.
+    pub fn init_my_nonce_shares(&mut self) -> Result<()> {
+        let mut nonce_pool = mem::take(&mut self.nonce_pool);
+        let result = self.all_sig_ctxs_mut().into_iter()
+            .try_for_each(|ctx| ctx.init_my_nonce_share_from_pool(&mut nonce_pool));
+        self.nonce_pool = nonce_pool;
+        Ok(result?)
+    }
+
     pub fn get_my_nonce_shares(&self) -> Option<ExchangedNonces<'_, ByRef>> {
         Some(ExchangedNonces {
             swap_tx_input:
@@ -476,6 +870,20 @@ impl TradeModel {
         })
     }
 
+    const fn all_sig_ctxs(&self) -> [&SigCtx; 9] {
+        [
+            &self.swap_tx.input_sig_ctx,
+            &self.buyer_txs.warning.buyer_input_sig_ctx,
+            &self.buyer_txs.warning.seller_input_sig_ctx,
+            &self.seller_txs.warning.buyer_input_sig_ctx,
+            &self.seller_txs.warning.seller_input_sig_ctx,
+            &self.buyer_txs.redirect.input_sig_ctx,
+            &self.seller_txs.redirect.input_sig_ctx,
+            &self.buyer_txs.claim.input_sig_ctx,
+            &self.seller_txs.claim.input_sig_ctx
+        ]
+    }
+
     const fn all_sig_ctxs_mut(&mut self) -> [&mut SigCtx; 9] {
         [
             &mut self.swap_tx.input_sig_ctx,
@@ -525,12 +933,45 @@ impl TradeModel {
             // Unlike the other multisig sighashes, only the seller is able to independently compute
             // the swap-tx-input sighash. The buyer must wait for the next round, when the deposit
             // tx is signed, to partially sign the swap tx using the sighash passed by the seller.
-            self.sign_swap_tx_input_partial(self.swap_tx.builder.input_sighash()?)?;
+            self.sign_swap_tx_input_partial(TapSighash::from_byte_array(self.compute_deposit_input_sighash()?))?;
         }
         Ok(())
     }
 
+    /// The taproot key-spend sighash (with `SIGHASH_DEFAULT`) of the multisig input spending the
+    /// deposit tx, that is the swap tx input, which the seller's partial signature commits to. The
+    /// buyer cannot compute it independently, so gets the sighash passed by the seller instead. The
+    /// result is cached, so that repeated calls do not recompute it.
+    pub fn compute_deposit_input_sighash(&self) -> Result<[u8; 32]> {
+        if let Some(sighash) = self.swap_tx.input_sighash.or_else(|| self.swap_tx.computed_input_sighash.get().copied()) {
+            return Ok(sighash.to_byte_array());
+        }
+        if self.deposit_tx.builder.psbt().is_err() {
+            return Err(ProtocolErrorKind::MissingDepositTx);
+        }
+        let sighash = self.swap_tx.builder.input_sighash()?;
+        Ok(self.swap_tx.computed_input_sighash.get_or_init(|| sighash).to_byte_array())
+    }
+
+    /// Set a BIP341 annex on the swap tx input (starting with `0x50` and at most 520 bytes), such as
+    /// a commitment to the trade metadata, to be covered by its sighash, replacing any set before.
+    /// Only the seller computes the sighash and publishes the swap tx, so this must be set by the
+    /// seller, before the unsigned swap tx is computed, as the annex adds to its fee. Note that a
+    /// swap tx with an annex is nonstandard, so is not relayed by Bitcoin Core nodes.
+    pub fn set_input_annex(&mut self, annex: Vec<u8>) -> Result<()> {
+        if self.swap_tx.input_sighash.is_some() || self.swap_tx.computed_input_sighash.get().is_some() {
+            return Err(ProtocolErrorKind::SighashAlreadyComputed);
+        }
+        self.swap_tx.builder.set_input_annex(annex)?;
+        Ok(())
+    }
+
     pub fn sign_swap_tx_input_partial(&mut self, sighash: TapSighash) -> Result<()> {
+        // A buyer without the adaptor secret could not recover the seller's key share for its payout
+        // output from the swap tx, so must not sign it, lest a force-close by the seller strand it.
+        if self.am_buyer() && self.swap_tx.adaptor_point.is_some() && self.swap_tx.adaptor_secret.is_none() {
+            return Err(ProtocolErrorKind::MissingAdaptorSecret);
+        }
         let sighash = self.swap_tx.input_sighash.insert(sighash);
         self.swap_tx.input_sig_ctx.sign_partial(*sighash)?;
         Ok(())
@@ -626,11 +1067,26 @@ impl TradeModel {
 
     pub fn get_deposit_psbt(&self) -> Option<&Psbt> { self.deposit_tx.builder.psbt().ok() }
 
+    /// My signed warning & claim txs, along with their relative lock times, which let me recover my
+    /// funds from the deposit tx unilaterally (subject to the peer's redirect tx), should the peer
+    /// become unresponsive. The locktimes & claim payout address are fixed when the txs are signed.
+    pub fn get_signed_recovery_txs(&self) -> Result<[(&Transaction, relative::LockTime); 2]> {
+        let my_txs = if self.am_buyer() { &self.buyer_txs } else { &self.seller_txs };
+        Ok([
+            (my_txs.warning.builder.signed_tx()?, *my_txs.warning.builder.lock_time()?),
+            (my_txs.claim.builder.signed_tx()?, *my_txs.claim.builder.lock_time()?),
+        ])
+    }
+
     pub fn combine_deposit_psbts(&mut self, other: Psbt) -> Result<()> {
         self.deposit_tx.builder.combine_psbts(other)?;
         Ok(())
     }
 
+    pub fn get_deposit_txid(&self) -> Option<Txid> {
+        Some(self.deposit_tx.builder.psbt().ok()?.unsigned_tx.compute_txid())
+    }
+
     pub fn get_signed_deposit_tx(&self) -> Option<Transaction> {
         self.deposit_tx.builder.signed_tx().ok()
     }
@@ -644,28 +1100,96 @@ impl TradeModel {
         Ok(())
     }
 
-    pub fn get_my_private_key_share_for_peer_output(&self) -> Option<&Scalar> {
+    pub fn get_my_private_key_share_for_peer_output(&self) -> Option<Scalar> {
         // FIXME: Check that it's actually safe to release the funds at this point.
         self.keys.peers_payout_ctx().my_key_share().ok()?.prv_key().ok()
     }
 
+    /// Sign a tamper-evident record that the buyer has made the fiat payment with the given
+    /// reference, using its private key share for its payout output. The signature can be checked
+    /// with `verification::verify_proof_of_payment`. For the buyer only.
+    pub fn generate_proof_of_payment(&self, payment_tx_reference: &str) -> Result<Vec<u8>> {
+        if !self.am_buyer() {
+            return Err(ProtocolErrorKind::BuyerOnly);
+        }
+        let prv_key_share = self.keys.buyer_payout_ctx.my_key_share()?.prv_key()?;
+        let message = verification::proof_of_payment_message(&self.trade_id, payment_tx_reference);
+        let signature: [u8; 64] = musig2::sign_solo(prv_key_share, message, rand::random::<[u8; 32]>());
+        Ok(signature.to_vec())
+    }
+
+    /// Summarise the completed trade, signing the tagged hash of the receipt with my private key
+    /// share for my payout output (whose public key was sent to the peer in the first round), so
+    /// that it may be checked with `verification::verify_trade_receipt`.
+    pub fn generate_receipt(&self) -> Result<TradeReceipt> {
+        let completion_timestamp = self.completed_at.ok_or(ProtocolErrorKind::TradeNotCompleted)?;
+        let deposit_psbt = self.deposit_tx.builder.psbt()?;
+        let mut total_fees = deposit_psbt.fee().map_err(TransactionErrorKind::from)?;
+        let mut payout_txid = None;
+        if let Some(custom_payout_tx) = self.get_signed_custom_payout_tx() {
+            let custom_payout_psbt = self.custom_payout_tx.builder.psbt()?;
+            total_fees += custom_payout_psbt.fee().map_err(TransactionErrorKind::from)?;
+            payout_txid = Some(custom_payout_tx.compute_txid());
+        } else if let Some(swap_tx) = self.get_signed_swap_tx() {
+            let output_amount: Amount = swap_tx.output.iter().map(|o| o.value).sum();
+            total_fees += self.swap_tx.builder.input()?.prevout.value.checked_sub(output_amount)
+                .ok_or(TransactionErrorKind::Overflow)?;
+            payout_txid = Some(swap_tx.compute_txid());
+        }
+
+        let mut receipt = TradeReceipt {
+            trade_id: self.trade_id.clone(),
+            role: self.my_role.into(),
+            trade_amount_sats: self.deposit_tx.builder.trade_amount()?.to_sat(),
+            deposit_txid: deposit_psbt.unsigned_tx.compute_txid(),
+            payout_txid,
+            completion_timestamp,
+            total_fees_sats: total_fees.to_sat(),
+            signature: Vec::new(),
+        };
+        let receipt_json = receipt.to_json().expect("receipt fields should be serializable");
+        receipt.signature = self.sign_with_my_payout_key_share(verification::trade_receipt_message(&receipt_json))?;
+        Ok(receipt)
+    }
+
+    /// Sign the tagged hash of the (unencrypted) JSON of the evidence handed to an arbitrator in a
+    /// dispute, with my private key share for my payout output, as with trade receipts, so that it
+    /// may be checked with `verification::verify_dispute_evidence`.
+    pub fn sign_dispute_evidence(&self, evidence_json: &[u8]) -> Result<Vec<u8>> {
+        self.sign_with_my_payout_key_share(verification::dispute_evidence_message(evidence_json))
+    }
+
+    fn sign_with_my_payout_key_share(&self, message: impl AsRef<[u8]>) -> Result<Vec<u8>> {
+        let my_payout_ctx = if self.am_buyer() { &self.keys.buyer_payout_ctx } else { &self.keys.seller_payout_ctx };
+        let signature: [u8; 64] = musig2::sign_solo(my_payout_ctx.my_key_share()?.prv_key()?, message,
+            rand::random::<[u8; 32]>());
+        Ok(signature.to_vec())
+    }
+
     pub fn set_peer_private_key_share_for_my_output(&mut self, prv_key_share: Scalar) -> Result<()> {
         self.keys.my_payout_ctx_mut().set_peers_prv_key(prv_key_share)?;
         Ok(())
     }
 
-    pub fn aggregate_private_keys_for_my_output(&mut self) -> Result<&Scalar> {
+    pub fn aggregate_private_keys_for_my_output(&mut self) -> Result<Scalar> {
         Ok(self.keys.my_payout_ctx_mut().aggregate_prv_key_shares()?)
     }
 
     pub fn compute_signed_swap_tx(&mut self) -> Result<()> {
-        let adaptor_secret = (*self.keys.adaptor_key_share()?.prv_key()?).into();
+        let mut adaptor_secret: MaybeScalar = self.keys.adaptor_key_share()?.prv_key()?.into();
+        if self.swap_tx.adaptor_point.is_some() {
+            adaptor_secret += self.swap_tx.adaptor_secret.ok_or(ProtocolErrorKind::MissingAdaptorSecret)?;
+        }
         self.swap_tx.builder
             .set_input_signature(self.swap_tx.input_sig_ctx.compute_taproot_signature(adaptor_secret)?)
             .compute_signed_tx()?;
         Ok(())
     }
 
+    pub fn get_swap_txid(&self) -> Option<Txid> {
+        Some(self.swap_tx.builder.unsigned_tx().ok()?.compute_txid())
+    }
+
     pub fn get_signed_swap_tx(&self) -> Option<&Transaction> {
         self.swap_tx.builder.signed_tx().ok()
     }
@@ -674,7 +1198,12 @@ impl TradeModel {
         if self.am_buyer() {
             let swap_tx_input = self.deposit_tx.builder.seller_payout()?;
             let input_signature = swap_tx.find_key_spend_signature(swap_tx_input)?;
-            let adaptor_secret = self.swap_tx.input_sig_ctx.reveal_adaptor_secret(input_signature)?;
+            let mut adaptor_secret = self.swap_tx.input_sig_ctx.reveal_adaptor_secret(input_signature)?;
+            if self.swap_tx.adaptor_point.is_some() {
+                let swap_secret = self.swap_tx.adaptor_secret.ok_or(ProtocolErrorKind::MissingAdaptorSecret)?;
+                adaptor_secret = (adaptor_secret - swap_secret).not_zero()
+                    .map_err(|_| ProtocolErrorKind::MismatchedAdaptorPoint)?;
+            }
             self.keys.buyer_payout_ctx.set_peers_prv_key(adaptor_secret)?;
         }
         Ok(())
@@ -715,6 +1244,13 @@ impl TradeModel {
     }
 }
 
+fn check_tx_options(options: SendOptions, tx: &Transaction) -> Result<()> {
+    if !options.is_respected_by(tx) {
+        return Err(ProtocolErrorKind::TxOptionsNotRespected(tx.compute_txid()));
+    }
+    Ok(())
+}
+
 impl Keys {
     const fn my_payout_ctx_mut(&mut self) -> &mut KeyCtx {
         if self.am_buyer { &mut self.buyer_payout_ctx } else { &mut self.seller_payout_ctx }
@@ -764,6 +1300,28 @@ pub enum ProtocolErrorKind {
     MissingTradeWallet,
     #[error("missing script key")]
     MissingScriptKey,
+    #[error("operation only available for buyer")]
+    BuyerOnly,
+    #[error("trade not yet completed")]
+    TradeNotCompleted,
+    #[error("deposit tx not yet built")]
+    MissingDepositTx,
+    #[error("sighash already computed")]
+    SighashAlreadyComputed,
+    #[error("unsupported tap leaf version {0}")]
+    UnsupportedLeafVersion(LeafVersion),
+    #[error("key shares already aggregated")]
+    KeySharesAlreadyAggregated,
+    #[error("invalid adaptor point")]
+    InvalidAdaptorPoint,
+    #[error("mismatched adaptor point")]
+    MismatchedAdaptorPoint,
+    #[error("missing adaptor secret")]
+    MissingAdaptorSecret,
+    #[error("cannot reset trade in state {0:?}, once the deposit tx is signed")]
+    TradeResetTooLate(TradeState),
+    #[error("tx {0} does not respect protocol-mandated options")]
+    TxOptionsNotRespected(Txid),
     #[error("insufficient redirection funds (available {available_msat:?} msat, used {used_msat:?} msat)")]
     InsufficientRedirectionFunds {
         available_msat: u64,
@@ -775,7 +1333,8 @@ pub enum ProtocolErrorKind {
         used_msat: u64,
     },
     AddressParse(#[from] bdk_wallet::bitcoin::address::ParseError),
-    Transaction(#[from] protocol::transaction::TransactionErrorKind),
+    Transaction(#[from] TransactionErrorKind),
+    TxSize(#[from] TxSizeError),
     Multisig(#[from] protocol::multisig::MultisigErrorKind),
     Wallet(#[from] wallet::protocol_wallet_api::WalletErrorKind),
 }
@@ -0,0 +1,35 @@
+//! A signed summary of a completed trade, for the trader's own records.
+
+use bdk_wallet::bitcoin::Txid;
+use bdk_wallet::serde_json;
+use serde::{Deserialize, Serialize};
+
+use crate::pb::musigrpc;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TradeReceipt {
+    pub trade_id: String,
+    pub role: musigrpc::Role,
+    pub trade_amount_sats: u64,
+    pub deposit_txid: Txid,
+    /// The custom payout tx or swap tx, if the trade was paid out by either. (A cooperatively
+    /// closed trade has no payout tx of its own, as each trader is left to sweep its own output.)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payout_txid: Option<Txid>,
+    /// The unix time in seconds at which the trade was closed, or started force-closing.
+    pub completion_timestamp: u64,
+    /// The mining fees of the deposit tx & any payout tx, paid by both traders together.
+    pub total_fees_sats: u64,
+    /// A BIP340 signature over the JSON of all the other fields, made with the trader's private key
+    /// share for its own payout output.
+    #[serde(skip)]
+    pub signature: Vec<u8>,
+}
+
+impl TradeReceipt {
+    /// The JSON of the receipt, that is the message signed by its signature.
+    ///
+    /// # Errors
+    /// Will return `Err` if serialization fails
+    pub fn to_json(&self) -> serde_json::Result<String> { serde_json::to_string(self) }
+}
@@ -0,0 +1,157 @@
+//! A plain HTTP API for the wallet operations, for web frontends & tools that can't speak gRPC.
+//!
+//! Each route calls the same handler as the corresponding gRPC method, taking & returning the JSON
+//! form of its request & response messages. Failures are returned as an HTTP error status with a
+//! JSON body of the form `{"error": <message>}`. A wallet created with `CreateWallet` is selected
+//! with the `walletId` query parameter (or, for a send, the field of the request body), the default
+//! wallet being used without one.
+
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use bdk_wallet::bitcoin::Txid;
+use bdk_wallet::serde_json::{self, json};
+use futures_util::stream::{BoxStream, StreamExt as _};
+use futures_util::SinkExt as _;
+use serde::Deserialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task;
+use tonic::{Code, Request, Status};
+use tracing::{debug, warn};
+
+use crate::pb::walletrpc::wallet_server::Wallet as _;
+use crate::pb::walletrpc::{
+    ConfEvent, ListUnspentRequest, ListUnspentResponse, NewAddressRequest, NewAddressResponse, SendRequest,
+    SendResponse, WalletBalanceRequest, WalletBalanceResponse,
+};
+use crate::server::WalletImpl;
+use crate::wallet::TxConfidence;
+
+/// The maximum number of confidence updates queued for sending to a WebSocket client. Any further
+/// updates are dropped until the client catches up, as only the latest confidence matters.
+const WS_SEND_BUFFER_SIZE: usize = 16;
+
+pub fn wallet_router(wallet: Arc<WalletImpl>) -> Router {
+    Router::new()
+        .route("/wallet/balance", get(wallet_balance))
+        .route("/wallet/address", post(new_address))
+        .route("/wallet/utxos", get(list_unspent))
+        .route("/wallet/send", post(send))
+        .route("/ws/tx/{txid}/confirmations", get(tx_confirmations))
+        .with_state(wallet)
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct WalletIdQuery {
+    wallet_id: String,
+}
+
+type Result<T, E = RestError> = std::result::Result<Json<T>, E>;
+
+async fn wallet_balance(
+    State(wallet): State<Arc<WalletImpl>>,
+    Query(WalletIdQuery { wallet_id }): Query<WalletIdQuery>,
+) -> Result<WalletBalanceResponse> {
+    Ok(Json(wallet.wallet_balance(Request::new(WalletBalanceRequest { wallet_id })).await?.into_inner()))
+}
+
+async fn new_address(
+    State(wallet): State<Arc<WalletImpl>>,
+    Query(WalletIdQuery { wallet_id }): Query<WalletIdQuery>,
+) -> Result<NewAddressResponse> {
+    Ok(Json(wallet.new_address(Request::new(NewAddressRequest { wallet_id })).await?.into_inner()))
+}
+
+async fn list_unspent(
+    State(wallet): State<Arc<WalletImpl>>,
+    Query(WalletIdQuery { wallet_id }): Query<WalletIdQuery>,
+) -> Result<ListUnspentResponse> {
+    Ok(Json(wallet.list_unspent(Request::new(ListUnspentRequest { wallet_id })).await?.into_inner()))
+}
+
+async fn send(State(wallet): State<Arc<WalletImpl>>, Json(request): Json<SendRequest>) -> Result<SendResponse> {
+    Ok(Json(wallet.send(Request::new(request)).await?.into_inner()))
+}
+
+async fn tx_confirmations(
+    State(wallet): State<Arc<WalletImpl>>,
+    Path(txid): Path<String>,
+    Query(WalletIdQuery { wallet_id }): Query<WalletIdQuery>,
+    ws: WebSocketUpgrade,
+) -> std::result::Result<Response, RestError> {
+    let txid: Txid = txid.parse()
+        .map_err(|e| Status::invalid_argument(format!("could not parse txid: {e}")))?;
+    let conf_events = wallet.wallet(&wallet_id)?.get_tx_confidence_stream(txid);
+    Ok(ws.on_upgrade(move |socket| send_conf_events(socket, conf_events)))
+}
+
+async fn send_conf_events(socket: WebSocket, mut conf_events: BoxStream<'static, Option<TxConfidence>>) {
+    let (mut sender, mut receiver) = socket.split();
+    let (buffer_tx, mut buffer_rx) = mpsc::channel(WS_SEND_BUFFER_SIZE);
+    let send_task = task::spawn(async move {
+        while let Some(message) = buffer_rx.recv().await {
+            if sender.send(message).await.is_err() {
+                return;
+            }
+        }
+        // The confidence stream has ended, so let the client know there will be no more updates.
+        _ = sender.send(Message::Close(None)).await;
+    });
+
+    loop {
+        tokio::select! {
+            conf_event = conf_events.next() => {
+                let Some(conf_event) = conf_event else { break };
+                let conf_event: ConfEvent = conf_event.map(Into::into).unwrap_or_default();
+                let json = match serde_json::to_string(&conf_event) {
+                    Ok(json) => json,
+                    Err(e) => { warn!("Could not serialize confidence update: {e}"); break; }
+                };
+                match buffer_tx.try_send(Message::text(json)) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => debug!("WebSocket send buffer full; dropped a confidence update."),
+                    Err(TrySendError::Closed(_)) => return,
+                }
+            }
+            message = receiver.next() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => {
+                    debug!("WebSocket client disconnected.");
+                    send_task.abort();
+                    return;
+                }
+                // Nothing is expected from the client; pings are answered automatically.
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    drop(buffer_tx);
+    _ = send_task.await;
+}
+
+#[derive(Debug)]
+pub struct RestError(Status);
+
+impl From<Status> for RestError {
+    fn from(value: Status) -> Self { Self(value) }
+}
+
+impl IntoResponse for RestError {
+    fn into_response(self) -> Response {
+        let status_code = match self.0.code() {
+            Code::InvalidArgument | Code::OutOfRange => StatusCode::BAD_REQUEST,
+            Code::NotFound => StatusCode::NOT_FOUND,
+            Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+            Code::FailedPrecondition => StatusCode::PRECONDITION_FAILED,
+            Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status_code, Json(json!({ "error": self.0.message() }))).into_response()
+    }
+}
@@ -1,427 +1,193 @@
-use std::fmt::{Display, Formatter};
-use std::marker::{Send, Sync};
-use std::pin::Pin;
-use std::sync::Arc;
-use std::task::{Context, Poll};
+use std::net::SocketAddr;
 
-use bdk_wallet::bitcoin::{Amount, FeeRate, consensus};
+use bdk_wallet::bitcoin::Amount;
 use bdk_wallet::serde_json;
-use drop_stream::DropStreamExt as _;
-use futures_util::stream::{self, BoxStream, Stream, StreamExt as _, TryStream, TryStreamExt as _};
-use serde::Serialize;
-use tokio::time::{self, Duration};
-use tonic::{Request, Response, Result, Status};
-use tracing::{Span, debug, error, info, instrument, trace};
-
-use crate::pb::convert::{CheckInSignedRange as _, TryProtoInto};
-pub use crate::pb::musigrpc::musig_server::MusigServer;
-use crate::pb::musigrpc::{
-    CloseTradeRequest, CloseTradeResponse, CustomCloseTradeRequest, CustomCloseTradeResponse,
-    CustomPayoutPsbt, CustomPayoutPsbtRequest, DepositPsbt, DepositTxSignatureRequest,
-    NonceSharesMessage, NonceSharesRequest, PartialSignaturesMessage, PartialSignaturesRequest,
-    PubKeySharesRequest, PubKeySharesResponse, PublishDepositTxRequest,
-    SubscribeTxConfirmationStatusRequest, SwapTxSignatureRequest, SwapTxSignatureResponse,
-    TxConfirmationStatus, musig_server,
-};
-pub use crate::pb::walletrpc::wallet_server::WalletServer;
-use crate::pb::walletrpc::{
-    ConfEvent, ConfRequest, ListUnspentRequest, ListUnspentResponse, NewAddressRequest,
-    NewAddressResponse, WalletBalanceRequest, WalletBalanceResponse, wallet_server,
+use thiserror::Error;
+use tonic::{Result, Status};
+use tracing::warn;
+use wallet::protocol_wallet_api::ElectrumConfig;
+
+use crate::explorer::BlockExplorerConfig;
+use crate::protocol::{MAX_SUPPORTED_VERSION, MIN_SUPPORTED_VERSION, ProtocolErrorKind, TradeModel, TradeState};
+#[cfg(feature = "native")]
+use crate::wallet::WalletErrorKind;
+
+/// The gRPC services of the daemon, which need its wallets & a Tokio runtime, so are left out of the
+/// WASM bindings.
+#[cfg(feature = "native")]
+mod service;
+pub mod steps;
+
+#[cfg(feature = "native")]
+pub use service::{
+    AdminImpl, AdminServer, BoundedDropStream, MusigImpl, MusigServer, TracedResultStream, TracedResultStreamExt,
+    WalletImpl, WalletServer, API_KEY_HEADER, check_api_key,
 };
-use crate::protocol::{ExchangedKeys, TRADE_MODELS, TradeModel, TradeModelStore as _};
-use crate::wallet::WalletService;
-
-#[derive(Debug, Default)]
-pub struct MusigImpl {}
 
-#[tonic::async_trait]
-impl musig_server::Musig for MusigImpl {
-    #[instrument(skip_all)]
-    async fn init_trade(&self, request: Request<PubKeySharesRequest>) -> Result<Response<PubKeySharesResponse>> {
-        handle_request(request, move |request| {
-            let mut trade_model = TradeModel::new(request.trade_id, request.my_role.try_proto_into()?);
-            trade_model.init_my_key_shares()?;
-            let my_key_shares = trade_model.get_my_key_shares()
-                .ok_or_else(|| Status::internal("missing key shares"))?;
-            let response = PubKeySharesResponse {
-                buyer_output_pub_key_share: my_key_shares.buyer_payout.serialize().into(),
-                seller_output_pub_key_share: my_key_shares.seller_payout.serialize().into(),
-                multisig_script_key: my_key_shares.multisig_script.serialize().into(),
-                current_block_height: 900_000,
-            };
-            TRADE_MODELS.add_trade_model(trade_model);
-
-            Ok(response)
-        })
-    }
-
-    #[instrument(skip_all)]
-    async fn get_nonce_shares(&self, request: Request<NonceSharesRequest>) -> Result<Response<NonceSharesMessage>> {
-        handle_musig_request(request, move |request, trade_model| {
-            trade_model.set_peer_key_shares(&ExchangedKeys {
-                buyer_payout: request.buyer_output_peers_pub_key_share.try_proto_into()?,
-                seller_payout: request.seller_output_peers_pub_key_share.try_proto_into()?,
-                multisig_script: request.peers_multisig_script_key.try_proto_into()?,
-            });
-            trade_model.aggregate_key_shares()?;
-            trade_model.set_trade_amount(
-                Amount::from_sat(request.trade_amount.check_in_signed_range()?));
-            trade_model.set_buyers_security_deposit(
-                Amount::from_sat(request.buyers_security_deposit.check_in_signed_range()?));
-            trade_model.set_sellers_security_deposit(
-                Amount::from_sat(request.sellers_security_deposit.check_in_signed_range()?));
-            trade_model.set_deposit_tx_fee_rate(
-                FeeRate::from_sat_per_kwu(request.deposit_tx_fee_rate.check_in_signed_range()?));
-            trade_model.set_prepared_tx_fee_rate(
-                FeeRate::from_sat_per_kwu(request.prepared_tx_fee_rate.check_in_signed_range()?));
-            trade_model.set_trade_fee_receiver(request.trade_fee_receiver.try_proto_into()?)?;
-            trade_model.init_my_addresses()?;
-            trade_model.init_my_half_deposit_psbt()?;
-            trade_model.init_my_nonce_shares()?;
-
-            let redirection_amount_msat = trade_model.redirection_amount_msat()?
-                .check_in_signed_range()?;
-            let my_addresses = trade_model.get_my_addresses()
-                .ok_or_else(|| Status::internal("missing addresses"))?;
-            let my_half_deposit_psbt = trade_model.get_my_half_deposit_psbt()
-                .ok_or_else(|| Status::internal("missing half deposit PSBT"))?;
-            let my_nonce_shares = trade_model.get_my_nonce_shares()
-                .ok_or_else(|| Status::internal("missing nonce shares"))?;
-
-            Ok(NonceSharesMessage {
-                half_deposit_psbt: my_half_deposit_psbt.serialize(),
-                redirection_amount_msat,
-                ..(my_addresses, my_nonce_shares).into()
-            })
-        })
-    }
-
-    #[instrument(skip_all)]
-    async fn get_partial_signatures(&self, request: Request<PartialSignaturesRequest>) -> Result<Response<PartialSignaturesMessage>> {
-        handle_musig_request(request, move |request, trade_model| {
-            if let Some(my_partial_signatures) = trade_model
-                .get_my_partial_signatures_on_peer_txs(request.buyer_ready_to_release) {
-                // Ignore receiver list and peer's nonce shares, as they have already been set
-                // (otherwise we wouldn't already have the partial signatures on the peer's txs).
-                return Ok(my_partial_signatures.into());
-            }
-            let peer_nonce_shares = request.peers_nonce_shares
-                .ok_or_else(|| Status::not_found("missing request.peers_nonce_shares"))?;
-            trade_model.set_peer_half_deposit_psbt((&peer_nonce_shares.half_deposit_psbt[..]).try_proto_into()?);
-            trade_model.compute_unsigned_deposit_tx()?;
-            trade_model.set_redirection_receivers(request.redirection_receivers.into_iter().map(TryProtoInto::try_proto_into))?;
-            trade_model.check_redirect_tx_params()?;
-            let (addresses, nonce_shares) = peer_nonce_shares.try_proto_into()?;
-            trade_model.set_peer_addresses(addresses)?;
-            trade_model.compute_unsigned_prepared_txs()?;
-            trade_model.set_peer_nonce_shares(nonce_shares);
-            trade_model.aggregate_nonce_shares()?;
-            trade_model.sign_partial()?;
-            let my_partial_signatures = trade_model
-                .get_my_partial_signatures_on_peer_txs(request.buyer_ready_to_release)
-                .ok_or_else(|| Status::internal("missing partial signatures"))?;
-
-            Ok(my_partial_signatures.into())
-        })
-    }
-
-    #[instrument(skip_all)]
-    async fn sign_deposit_tx(&self, request: Request<DepositTxSignatureRequest>) -> Result<Response<DepositPsbt>> {
-        handle_musig_request(request, move |request, trade_model| {
-            let peers_partial_signatures = request.peers_partial_signatures
-                .ok_or_else(|| Status::not_found("missing request.peers_partial_signatures"))?;
-            if trade_model.am_buyer() {
-                let sighash = peers_partial_signatures.swap_tx_input_sighash.as_ref()
-                    .ok_or_else(|| Status::not_found("missing request.peers_partial_signatures.swap_tx_input_sighash"))?;
-                trade_model.sign_swap_tx_input_partial((&sighash[..]).try_proto_into()?)?;
-            }
-            trade_model.set_peer_partial_signatures_on_my_txs(&peers_partial_signatures.try_proto_into()?);
-            trade_model.aggregate_partial_signatures()?;
-            trade_model.compute_my_signed_prepared_txs()?;
-            trade_model.sign_deposit_psbt()?;
-            let deposit_psbt = trade_model.get_deposit_psbt()
-                .ok_or_else(|| Status::internal("missing deposit PSBT"))?;
-
-            Ok(DepositPsbt { deposit_psbt: deposit_psbt.serialize() })
-        })
-    }
-
-    type PublishDepositTxStream = TracedResultStream<TxConfirmationStatus>;
+/// A failure of a trade protocol request, mapped to a status code by its `From` impl. Errors of the
+/// trade model & wallet are wrapped as is, so as to keep their own status code mappings.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum MusigError {
+    #[error("{0}")]
+    InvalidTradeId(String),
+    #[error("missing trade with id: {0}")]
+    TradeNotFound(String),
+    #[error("trade is in state {actual:?}, expected {expected:?}")]
+    InvalidState { expected: TradeState, actual: TradeState },
+    #[error("invalid {field}: {reason}")]
+    InvalidInput { field: String, reason: String },
+    #[error("this operation is only valid for the {required:?} role")]
+    RoleNotPermitted { required: TraderRole },
+    /// Trade data that a just-completed protocol step should have produced is absent.
+    #[error("missing {0}")]
+    MissingTradeData(&'static str),
+    #[error("could not encrypt: {0}")]
+    CryptoError(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("could not serialize response: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error(transparent)]
+    Protocol(#[from] ProtocolErrorKind),
+    #[cfg(feature = "native")]
+    #[error(transparent)]
+    Wallet(#[from] WalletErrorKind),
+}
 
-    #[instrument(skip_all)]
-    async fn publish_deposit_tx(&self, request: Request<PublishDepositTxRequest>) -> Result<Response<Self::PublishDepositTxStream>> {
-        handle_musig_request(request, move |request, trade_model| {
-            let peers_deposit_psbt = request.peers_deposit_psbt
-                .ok_or_else(|| Status::not_found("missing request.peers_deposit_psbt"))?;
-            trade_model.combine_deposit_psbts(peers_deposit_psbt.deposit_psbt.try_proto_into()?)?;
-            let deposit_tx = trade_model.get_signed_deposit_tx()
-                .ok_or_else(|| Status::internal("missing signed deposit tx"))?;
+/// The side of a trade that some requests are restricted to, whether as maker or taker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[expect(clippy::exhaustive_enums)]
+pub enum TraderRole {
+    Buyer,
+    Seller,
+}
 
-            info!("*** BROADCAST DEPOSIT TX ***"); // TODO: Implement broadcast.
+/// The trade limits the daemon enforces on incoming trades.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub min_trade_amount: Amount,
+    pub max_trade_amount: Amount,
+    /// The least each security deposit may be, as a fraction of the trade amount.
+    pub min_security_deposit_ratio: f64,
+    /// How far, in percent of our own, the peer's proposed fee rates may be from ours.
+    pub fee_rate_tolerance_pct: u8,
+    /// The block explorer to link txs to in status updates, if any.
+    pub block_explorer: Option<BlockExplorerConfig>,
+    /// The most trades the daemon will hold at once, beyond which new trades are rejected.
+    pub max_concurrent_trades: usize,
+    /// The key that clients must send in the `x-api-key` header, or `None` to disable authentication.
+    pub api_key: Option<String>,
+    /// The Tor SOCKS5 proxy to route the wallets' network traffic through, if any. Only compact
+    /// block filter & Electrum connections can be proxied, so the wallets refuse to connect to any
+    /// other backend while it is set.
+    pub tor_proxy: Option<SocketAddr>,
+    /// The number of consecutive unused script pubkeys after which the wallets' Electrum scans of
+    /// each keychain stop.
+    pub electrum_stop_gap: usize,
+    /// The number of script pubkeys that the wallets look up per request to the Electrum server.
+    pub electrum_batch_size: usize,
+}
 
-            Ok(mock_tx_confirmation_status_stream(request.trade_id,
-                consensus::serialize(&deposit_tx)).box_traced())
-        })
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            min_trade_amount: Amount::from_sat(10_000),
+            max_trade_amount: Amount::MAX_MONEY,
+            min_security_deposit_ratio: 0.15,
+            fee_rate_tolerance_pct: 20,
+            block_explorer: None,
+            max_concurrent_trades: 1000,
+            api_key: None,
+            tor_proxy: None,
+            electrum_stop_gap: ElectrumConfig::default().stop_gap,
+            electrum_batch_size: ElectrumConfig::default().batch_size,
+        }
     }
+}
 
-    type SubscribeTxConfirmationStatusStream = TracedResultStream<TxConfirmationStatus>;
-
-    #[instrument(skip_all)]
-    async fn subscribe_tx_confirmation_status(&self, request: Request<SubscribeTxConfirmationStatusRequest>)
-                                              -> Result<Response<Self::SubscribeTxConfirmationStatusStream>> {
-        handle_musig_request(request, move |request, _trade_model| {
-            Ok(mock_tx_confirmation_status_stream(request.trade_id,
-                b"signed_deposit_tx".into()).box_traced())
-        })
+impl Config {
+    fn check_trade_amount(&self, trade_amount: Amount) -> Result<()> {
+        let (amount, min, max) = (trade_amount.to_sat(), self.min_trade_amount.to_sat(), self.max_trade_amount.to_sat());
+        if amount < min {
+            return Err(Status::invalid_argument(format!("trade amount {amount} sats is below minimum {min} sats")));
+        }
+        if amount > max {
+            return Err(Status::invalid_argument(format!("trade amount {amount} sats is above maximum {max} sats")));
+        }
+        Ok(())
     }
+}
 
-    #[instrument(skip_all)]
-    async fn sign_swap_tx(&self, request: Request<SwapTxSignatureRequest>) -> Result<Response<SwapTxSignatureResponse>> {
-        handle_musig_request(request, move |request, trade_model| {
-            if trade_model.am_buyer() {
-                return Err(Status::failed_precondition("operation only available for seller"));
-            }
-            let swap_tx = if let Some(swap_tx) = trade_model.get_signed_swap_tx() { swap_tx } else {
-                trade_model.set_swap_tx_input_peers_partial_signature(
-                    request.swap_tx_input_peers_partial_signature.try_proto_into()?);
-                trade_model.aggregate_swap_tx_partial_signatures()?;
-                trade_model.compute_signed_swap_tx()?;
-                trade_model.get_signed_swap_tx()
-                    .ok_or_else(|| Status::internal("missing signed swap tx"))?
-            };
-            let prv_key_share = trade_model.get_my_private_key_share_for_peer_output()
-                .ok_or_else(|| Status::internal("missing private key share"))?;
+const MAX_TRADE_ID_LEN: usize = 64;
 
-            if !request.seller_ready_to_release {
-                return Ok(SwapTxSignatureResponse::default());
-            }
-            Ok(SwapTxSignatureResponse {
-                swap_tx: consensus::serialize(swap_tx),
-                peer_output_prv_key_share: prv_key_share.serialize().into(),
-            })
-        })
+/// Checks that a trade ID supplied by the client is of the same form as a Bisq offer ID, that is,
+/// a non-empty string of at most 64 ASCII alphanumeric characters and hyphens.
+fn validate_trade_id(id: &str) -> Result<(), MusigError> {
+    if id.is_empty() {
+        return Err(MusigError::InvalidTradeId("empty trade id".to_owned()));
     }
-
-    #[instrument(skip_all)]
-    async fn close_trade(&self, request: Request<CloseTradeRequest>) -> Result<Response<CloseTradeResponse>> {
-        handle_musig_request(request, move |request, trade_model| {
-            if let Some(peer_prv_key_share) = request.my_output_peers_prv_key_share.try_proto_into()? {
-                // Trader receives the private key share from a cooperative peer, closing our trade.
-                trade_model.set_peer_private_key_share_for_my_output(peer_prv_key_share)?;
-                trade_model.aggregate_private_keys_for_my_output()?;
-            } else if let Some(swap_tx) = request.swap_tx.try_proto_into()? {
-                // Buyer supplies a signed swap tx to the Rust server, to close our trade. (Mainly for
-                // testing -- normally the tx would be picked up from the bitcoin network by the server.)
-                trade_model.recover_seller_private_key_share_for_buyer_output(&swap_tx)?;
-                trade_model.aggregate_private_keys_for_my_output()?;
-            } else {
-                // Peer unresponsive -- force-close our trade by publishing the swap tx. For seller only.
-                trade_model.get_signed_swap_tx()
-                    .ok_or_else(|| Status::internal("missing signed swap tx"))?;
-
-                info!("*** BROADCAST SWAP TX ***"); // TODO: Implement broadcast.
-            }
-            let my_prv_key_share = trade_model.get_my_private_key_share_for_peer_output()
-                .ok_or_else(|| Status::internal("missing private key share"))?;
-
-            Ok(CloseTradeResponse { peer_output_prv_key_share: my_prv_key_share.serialize().into() })
-        })
+    if id.len() > MAX_TRADE_ID_LEN {
+        return Err(MusigError::InvalidTradeId(format!("trade id longer than {MAX_TRADE_ID_LEN} chars")));
     }
-
-    #[instrument(skip_all)]
-    async fn sign_custom_payout_tx(&self, request: Request<CustomPayoutPsbtRequest>) -> Result<Response<CustomPayoutPsbt>> {
-        handle_musig_request(request, move |request, trade_model| {
-            trade_model.set_sellers_custom_payout_amount_excluding_fee(
-                Amount::from_sat(request.sellers_payout_amount_excluding_fee.check_in_signed_range()?));
-            trade_model.set_custom_payout_tx_fee_rate(
-                FeeRate::from_sat_per_kwu(request.fee_rate.check_in_signed_range()?));
-            trade_model.compute_custom_payout_tx()?;
-            trade_model.sign_custom_payout_psbt()?;
-            let psbt = trade_model.get_custom_payout_psbt()
-                .ok_or_else(|| Status::internal("missing custom payout PSBT"))?;
-
-            Ok(CustomPayoutPsbt {
-                psbt: psbt.serialize(),
-                tx_id: psbt.unsigned_tx.compute_txid().to_string(),
-                buyers_payout_amount_including_fee: psbt.unsigned_tx.output[0].value.to_sat(),
-                sellers_payout_amount_including_fee: psbt.unsigned_tx.output[1].value.to_sat(),
-            })
-        })
+    if !id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+        return Err(MusigError::InvalidTradeId("trade id has invalid chars".to_owned()));
     }
-
-    #[instrument(skip_all)]
-    async fn custom_close_trade(&self, request: Request<CustomCloseTradeRequest>) -> Result<Response<CustomCloseTradeResponse>> {
-        handle_musig_request(request, move |request, trade_model| {
-            let peers_psbt = request.peers_custom_payout_psbt.try_proto_into()?;
-            trade_model.combine_custom_payout_psbts(peers_psbt)?;
-            // Sign custom payout PSBT again to finalize it:
-            trade_model.sign_custom_payout_psbt()?;
-            let custom_payout_tx = trade_model.get_signed_custom_payout_tx()
-                .ok_or_else(|| Status::internal("missing signed custom payout tx"))?;
-
-            info!("*** BROADCAST CUSTOM PAYOUT TX ***"); // TODO: Implement broadcast.
-
-            Ok(CustomCloseTradeResponse { custom_payout_tx: consensus::serialize(&custom_payout_tx) })
-        })
-    }
-}
-
-fn mock_tx_confirmation_status_stream(trade_id: String, tx: Vec<u8>) -> impl Stream<Item = Result<TxConfirmationStatus>> {
-    let confirmation_event = TxConfirmationStatus {
-        tx,
-        current_block_height: 900_001,
-        num_confirmations: 1,
-    };
-    stream::once(async {
-        time::sleep(Duration::from_secs(5)).await;
-        Ok(confirmation_event)
-    }).on_drop(move || debug!(trade_id, "Deposit tx confirmation status stream has been dropped."))
-}
-
-pub struct WalletImpl {
-    pub wallet_service: Arc<dyn WalletService + Send + Sync>,
+    Ok(())
 }
 
-#[tonic::async_trait]
-impl wallet_server::Wallet for WalletImpl {
-    #[instrument(skip_all)]
-    async fn wallet_balance(&self, request: Request<WalletBalanceRequest>) -> Result<Response<WalletBalanceResponse>> {
-        handle_request(request, |_request| Ok(self.wallet_service.balance().into()))
+/// Check that we are on the given side of the trade, for requests only valid for that side.
+fn require_role(trade_model: &TradeModel, required: TraderRole) -> Result<(), MusigError> {
+    let actual = if trade_model.am_buyer() { TraderRole::Buyer } else { TraderRole::Seller };
+    if actual != required {
+        return Err(MusigError::RoleNotPermitted { required });
     }
+    Ok(())
+}
 
-    #[instrument(skip_all)]
-    async fn new_address(&self, request: Request<NewAddressRequest>) -> Result<Response<NewAddressResponse>> {
-        handle_request(request, |_request| {
-            let address = self.wallet_service.reveal_next_address();
-
-            Ok(NewAddressResponse {
-                address: address.address.to_string(),
-                derivation_path: format!("m/86'/1'/0'/0/{}", address.index),
-            })
-        })
+/// Picks the trade protocol version to run, given the latest one the client supports: that version,
+/// or our latest if the client's is newer. Clients too old to be served are rejected.
+fn negotiate_protocol_version(client_version: u32) -> Result<u32> {
+    if client_version < MIN_SUPPORTED_VERSION {
+        return Err(Status::unimplemented("client version too old"));
     }
-
-    #[instrument(skip_all)]
-    async fn list_unspent(&self, request: Request<ListUnspentRequest>) -> Result<Response<ListUnspentResponse>> {
-        handle_request(request, |_request| {
-            let utxos: Vec<_> = self.wallet_service.list_unspent().into_iter()
-                .map(Into::into)
-                .collect();
-
-            Ok(ListUnspentResponse { utxos })
-        })
+    if client_version > MAX_SUPPORTED_VERSION {
+        warn!(client_version, MAX_SUPPORTED_VERSION, "Client protocol version is newer than supported.");
     }
+    Ok(client_version.min(MAX_SUPPORTED_VERSION))
+}
 
-    type RegisterConfidenceNtfnStream = TracedResultStream<ConfEvent>;
+#[cfg(test)]
+mod tests {
+    use tonic::Code;
 
-    #[instrument(skip_all)]
-    async fn register_confidence_ntfn(&self, request: Request<ConfRequest>) -> Result<Response<Self::RegisterConfidenceNtfnStream>> {
-        handle_request(request, move |request| {
-            let txid = request.tx_id.try_proto_into()?;
-            let conf_events = self.wallet_service.get_tx_confidence_stream(txid)
-                .map(|o| Ok(o.map(Into::into).unwrap_or_default()))
-                .box_traced();
+    use super::*;
 
-            Ok(conf_events)
-        })
+    #[test]
+    fn test_validate_trade_id() {
+        validate_trade_id("buyer-trade-1").unwrap();
+        validate_trade_id("R7gPFmQ-8e2a4b1c-46a1-4f5c-9d1b-0b6c1e3f2a7d-138").unwrap();
+        validate_trade_id(&"x".repeat(MAX_TRADE_ID_LEN)).unwrap();
     }
-}
-
-struct LazyJson<T>(T);
 
-impl<T: Serialize> Display for LazyJson<T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let s = if f.alternate() {
-            serde_json::to_string_pretty(&self.0)
-        } else {
-            serde_json::to_string(&self.0)
-        };
-        write!(f, "{}", s.as_deref().unwrap_or("<<SERIALIZATION ERROR>>"))
+    #[test]
+    fn test_validate_trade_id_empty() {
+        let status: Status = validate_trade_id("").unwrap_err().into();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "empty trade id");
     }
-}
-
-#[derive(Serialize)]
-pub struct TracedResultStream<T> {
-    #[serde(skip)]
-    inner: BoxStream<'static, Result<T>>,
-    #[serde(skip)]
-    span: Span,
-}
-
-impl<T> Stream for TracedResultStream<T> {
-    type Item = Result<T>;
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.get_mut();
-        let _enter = this.span.enter();
-        Pin::new(&mut this.inner).poll_next(cx)
+    #[test]
+    fn test_validate_trade_id_too_long() {
+        let status: Status = validate_trade_id(&"x".repeat(MAX_TRADE_ID_LEN + 1)).unwrap_err().into();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "trade id longer than 64 chars");
     }
-}
 
-pub trait TracedResultStreamExt<T: Serialize>: TryStream<Ok = T, Error = Status> + Sized + Send + 'static {
-    fn box_traced(self) -> TracedResultStream<T> {
-        TracedResultStream {
-            inner: Box::pin(self.inspect_ok(move |event| {
-                let message = LazyJson(event);
-                trace!(%message, "Streaming event.");
-            })),
-            span: Span::current(),
+    #[test]
+    fn test_validate_trade_id_invalid_chars() {
+        for id in ["mock_trade_id", "trade id", "trade/../1", "tr\u{e4}de", "trade\n"] {
+            let status: Status = validate_trade_id(id).unwrap_err().into();
+            assert_eq!(status.code(), Code::InvalidArgument, "{id:?}");
+            assert_eq!(status.message(), "trade id has invalid chars");
         }
     }
 }
-
-impl<S> TracedResultStreamExt<S::Ok> for S
-    where S: TryStream<Error = Status> + Sized + Send + 'static,
-          S::Ok: Serialize {}
-
-trait MusigRequest: Serialize {
-    fn trade_id(&self) -> &str;
-}
-
-macro_rules! impl_musig_req {
-    ($request_type:ty) => {
-        impl MusigRequest for $request_type {
-            fn trade_id(&self) -> &str { &self.trade_id }
-        }
-    };
-}
-
-impl_musig_req!(PartialSignaturesRequest);
-impl_musig_req!(NonceSharesRequest);
-impl_musig_req!(DepositTxSignatureRequest);
-impl_musig_req!(PublishDepositTxRequest);
-impl_musig_req!(SubscribeTxConfirmationStatusRequest);
-impl_musig_req!(SwapTxSignatureRequest);
-impl_musig_req!(CloseTradeRequest);
-impl_musig_req!(CustomPayoutPsbtRequest);
-impl_musig_req!(CustomCloseTradeRequest);
-
-// TODO: These wrapper fns don't work with async handlers, and should eventually be changed to do so:
-
-fn handle_musig_request<Req, Res, F>(request: Request<Req>, handler: F) -> Result<Response<Res>>
-    where Req: MusigRequest,
-          Res: Serialize,
-          F: FnOnce(Req, &mut TradeModel) -> Result<Res> {
-    handle_request(request, move |request| {
-        let trade_model = TRADE_MODELS.get_trade_model(request.trade_id())
-            .ok_or_else(|| Status::not_found(format!("missing trade with id: {}", request.trade_id())))?;
-        let response = handler(request, &mut trade_model.lock().unwrap())?;
-
-        Ok(response)
-    })
-}
-
-fn handle_request<Req, Res, F>(request: Request<Req>, handler: F) -> Result<Response<Res>>
-    where Req: Serialize,
-          Res: Serialize,
-          F: FnOnce(Req) -> Result<Res> {
-    let message = LazyJson(request.get_ref());
-    debug!(%message, "Got a request.");
-
-    let response = handler(request.into_inner())
-        .inspect_err(|e| error!("Error response: {e}"))?;
-
-    let message = LazyJson(&response);
-    trace!(%message, "Sending response.");
-    Ok(Response::new(response))
-}
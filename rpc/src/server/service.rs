@@ -0,0 +1,1580 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::marker::{Send, Sync};
+use std::pin::{Pin, pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bdk_wallet::bitcoin::hashes::Hash as _;
+use bdk_wallet::bitcoin::hex::DisplayHex as _;
+use bdk_wallet::bitcoin::secp256k1::PublicKey;
+use bdk_wallet::bitcoin::{Amount, FeeRate, OutPoint, Transaction, Txid, absolute, consensus};
+use bdk_wallet::chain::ChainPosition;
+use bdk_wallet::serde_json;
+use bmp_tracing::SetLogLevelError;
+use drop_stream::DropStreamExt as _;
+use futures_util::future;
+use futures_util::stream::{self, BoxStream, Stream, StreamExt as _, TryStream, TryStreamExt as _};
+use serde::Serialize;
+use tokio::sync::OwnedMutexGuard;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::{self, JoinHandle};
+use tokio::time::{self, Duration};
+use tonic::{Request, Response, Result, Status};
+use tracing::{Level, Span, debug, error, info, instrument, trace, warn};
+
+use super::{Config, MusigError, TraderRole, require_role, steps, validate_trade_id};
+use crate::audit::{AuditEntry, AuditEventType, AuditLog};
+use crate::bip322;
+use crate::fee_alert::FeeAnomalyGuard;
+use crate::message_log::{MessageLog, MessageLogEntry};
+pub use crate::pb::adminrpc::admin_server::AdminServer;
+use crate::pb::adminrpc::{SetLogLevelRequest, SetLogLevelResponse, admin_server};
+use crate::pb::convert::{CheckInSignedRange as _, TryProtoInto as _};
+pub use crate::pb::musigrpc::musig_server::MusigServer;
+use crate::pb::musigrpc::{
+    CancelTradeRequest, CancelTradeResponse, CloseTradeRequest, CloseTradeResponse,
+    CustomCloseTradeRequest, CustomCloseTradeResponse, CustomPayoutPsbt, CustomPayoutPsbtRequest,
+    DepositPsbt, DepositTxSignatureRequest, GetDisputeEvidenceRequest, GetDisputeEvidenceResponse, GetRecoveryTxRequest, GetRecoveryTxResponse, GetTradeReceiptRequest, GetTradeReceiptResponse,
+    GetTradeRequest, GetTradeResponse, GetTradeStatsRequest, GetTradeStatsResponse, ListTradesRequest,
+    ListTradesResponse, NonceSharesMessage, NonceSharesRequest, PartialSignaturesMessage,
+    PartialSignaturesRequest, PubKeySharesRequest, PubKeySharesResponse, PublishDepositTxRequest,
+    ResetTradeRequest, ResetTradeResponse, SubscribeTxConfirmationStatusRequest,
+    SwapTxSignatureRequest, SwapTxSignatureResponse, TradeDetails, TradeSummary, TxConfirmationStatus,
+    musig_server,
+};
+pub use crate::pb::walletrpc::wallet_server::WalletServer;
+use crate::pb::walletrpc::{
+    BroadcastTxRequest, BroadcastTxResponse, ConfEvent, ConfRequest, CreateWalletRequest,
+    CreateWalletResponse, ExportTransactionsCsvRequest, ExportTransactionsCsvResponse, GetSilentPaymentAddressRequest, GetSilentPaymentAddressResponse,
+    GetTxFeeRequest, GetTxFeeResponse, GetUtxoRequest, GetUtxoResponse,
+    ImportWatchDescriptorRequest, ImportWatchDescriptorResponse, ListUnspentRequest, ListUnspentResponse, LockWalletRequest,
+    LockWalletResponse, NewAddressRequest, NewAddressResponse, SendRequest, SendResponse, SignMessageRequest,
+    SignMessageResponse, SignPsbtRequest, SignPsbtResponse, UnlockWalletRequest, UnlockWalletResponse,
+    VerifyAddressRequest, VerifyAddressResponse, VerifyMessageRequest, VerifyMessageResponse, WalletBalanceRequest,
+    WalletBalanceResponse,
+    wallet_server,
+};
+use crate::protocol::{
+    AddTradeModelError, TRADE_MODELS, TradeModel, TradeModelStore as _, TradeState,
+};
+use crate::stats::TradeStats;
+use crate::wallet::{
+    self, KeychainPurpose, SendOptions, WalletManager, WalletService, WalletTx, unix_time_now,
+};
+
+
+/// The number of confirmations of the deposit tx after which its status stream is ended, if the
+/// request doesn't say, and the most that may be asked for.
+const DEFAULT_REQUIRED_CONFIRMATIONS: u32 = 1;
+const MAX_REQUIRED_CONFIRMATIONS: u32 = 100;
+/// The number of tx confirmation statuses buffered for a slow client, beyond which the oldest are
+/// dropped.
+const TX_CONFIRMATION_STATUS_BUFFER_CAPACITY: usize = 16;
+/// How long to wait for each trade to be free to summarize, when listing trades, beyond which a trade
+/// busy with a request is left out of the list.
+const LIST_TRADES_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl Config {
+    /// An interceptor applying [`check_api_key`] to every request, or letting all requests through
+    /// if no API key is configured.
+    pub fn api_key_interceptor(&self) -> impl FnMut(Request<()>) -> Result<Request<()>> + Clone + use<> {
+        let api_key = self.api_key.clone();
+        move |request| {
+            if let Some(expected) = &api_key {
+                check_api_key(&request, expected)?;
+            }
+            Ok(request)
+        }
+    }
+
+    /// Apply the same check as [`Config::api_key_interceptor`] to every request to the given HTTP
+    /// router (of the JSON-RPC or REST server), answering `401 Unauthorized` on failure.
+    #[cfg(any(feature = "jsonrpc", feature = "rest"))]
+    pub fn with_api_key_check(&self, router: axum::Router) -> axum::Router {
+        use axum::extract::{self, State};
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::{IntoResponse as _, Response};
+
+        async fn require_api_key(State(expected): State<Arc<str>>, req: extract::Request, next: Next) -> Response {
+            match req.headers().get(API_KEY_HEADER) {
+                Some(api_key) if constant_time_eq(api_key.as_bytes(), expected.as_bytes()) => next.run(req).await,
+                api_key => {
+                    warn!(uri = %req.uri(), missing = api_key.is_none(), "Refused HTTP request with bad API key.");
+                    (StatusCode::UNAUTHORIZED, "missing or invalid API key").into_response()
+                }
+            }
+        }
+
+        match &self.api_key {
+            Some(api_key) => router.layer(middleware::from_fn_with_state(Arc::from(&**api_key), require_api_key)),
+            None => router,
+        }
+    }
+}
+
+/// The request metadata header carrying the pre-shared API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Check that the request carries the expected pre-shared key in its `x-api-key` header.
+pub fn check_api_key(req: &Request<()>, expected: &str) -> Result<()> {
+    match req.metadata().get(API_KEY_HEADER) {
+        Some(api_key) if constant_time_eq(api_key.as_bytes(), expected.as_bytes()) => Ok(()),
+        api_key => {
+            warn!(addr = ?req.remote_addr(), missing = api_key.is_none(), "Refused request with bad API key.");
+            Err(Status::unauthenticated("missing or invalid API key"))
+        }
+    }
+}
+
+/// Compare in time independent of the position of the first differing byte, so as not to leak the key.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub struct MusigImpl {
+    /// The default wallet, used for trades with an empty wallet ID.
+    pub wallet_service: Arc<dyn WalletService + Send + Sync>,
+    wallet_manager: Option<Arc<WalletManager>>,
+    config: Config,
+    audit_log: Option<AuditLog>,
+    message_log: Option<MessageLog>,
+    trade_stats: Mutex<TradeStats>,
+    fee_anomaly_guard: Option<Arc<FeeAnomalyGuard>>,
+}
+
+impl MusigImpl {
+    pub fn new(wallet_service: Arc<dyn WalletService + Send + Sync>) -> Self {
+        Self {
+            wallet_service,
+            wallet_manager: None,
+            config: Config::default(),
+            audit_log: None,
+            message_log: None,
+            trade_stats: Mutex::default(),
+            fee_anomaly_guard: None,
+        }
+    }
+
+    /// Enforce the given trade limits, in place of the defaults.
+    #[must_use]
+    pub fn with_config(self, config: Config) -> Self { Self { config, ..self } }
+
+    /// Let each trade choose, by its wallet ID, one of the given manager's wallets to broadcast &
+    /// track its txs with, the manager's default wallet taking the place of this one. Without a
+    /// manager, every trade uses the default wallet.
+    #[must_use]
+    pub fn with_wallet_manager(self, wallet_manager: Arc<WalletManager>) -> Self {
+        Self { wallet_service: wallet_manager.default_wallet().clone(), wallet_manager: Some(wallet_manager), ..self }
+    }
+
+    fn wallet(&self, wallet_id: &str) -> Result<Arc<dyn WalletService + Send + Sync>> {
+        select_wallet(&self.wallet_service, self.wallet_manager.as_deref(), wallet_id)
+    }
+
+    /// Record every trade event (that is, every change of trade state) to the given audit log.
+    #[must_use]
+    pub fn with_audit_log(self, audit_log: AuditLog) -> Self { Self { audit_log: Some(audit_log), ..self } }
+
+    /// Record every trade protocol request & its response to the given message log, for replay.
+    #[must_use]
+    pub fn with_message_log(self, message_log: MessageLog) -> Self { Self { message_log: Some(message_log), ..self } }
+
+    /// Pause the initiation of new trades while the given guard flags a fee spike, as the deposit
+    /// tx fees would then be prohibitive.
+    #[must_use]
+    pub fn with_fee_anomaly_guard(self, fee_anomaly_guard: Arc<FeeAnomalyGuard>) -> Self {
+        Self { fee_anomaly_guard: Some(fee_anomaly_guard), ..self }
+    }
+
+    fn audit(&self, event_type: AuditEventType, trade_model: &TradeModel) {
+        if self.audit_log.is_some() {
+            self.append_audit_entry(&AuditEntry::new(event_type, trade_model));
+        }
+    }
+
+    fn append_audit_entry(&self, entry: &AuditEntry) {
+        if let Some(audit_log) = &self.audit_log {
+            if let Err(e) = audit_log.append(entry) {
+                error!(trade_id = entry.trade_id, event_type = ?entry.event_type, "Could not write to audit log: {e}");
+            }
+        }
+    }
+
+    /// Append the request, cloned before handling it if there is a message log, and the response.
+    fn log_message<Req: MusigRequest, Res: Serialize>(&self, request: Option<Req>, response: &Result<Response<Res>>) {
+        if let (Some(message_log), Some(request)) = (&self.message_log, request) {
+            let entry = MessageLogEntry::new(Req::METHOD, &request, response).map_err(io::Error::from);
+            if let Err(e) = entry.and_then(|entry| message_log.append(&entry)) {
+                error!(trade_id = request.trade_id(), method = Req::METHOD, "Could not write to message log: {e}");
+            }
+        }
+    }
+
+    fn update_trade_stats(&self, update: impl FnOnce(&mut TradeStats)) {
+        let mut trade_stats = self.trade_stats.lock().unwrap();
+        update(&mut trade_stats);
+        #[cfg(feature = "metrics")]
+        trade_stats.export();
+    }
+
+    /// Count the given trade as completed, as it is closed, unless it was already closing.
+    fn record_trade_completed(&self, trade_model: &TradeModel, old_state: TradeState) {
+        if old_state >= TradeState::ForceClosing {
+            return;
+        }
+        let trade_amount = trade_model.trade_amount().unwrap_or_default();
+        let duration_seconds = unix_time_now().saturating_sub(trade_model.created_at());
+        self.update_trade_stats(|stats| stats.record_completed(trade_amount, duration_seconds));
+    }
+
+    /// On the client resuming monitoring of the deposit tx after a reconnect, get the deposit tx to
+    /// broadcast if the wallet doesn't already have it (say if the publish request failed to broadcast
+    /// it), but never otherwise, so that resuming is idempotent.
+    fn deposit_tx_to_rebroadcast(&self, trade_model: &TradeModel, txid: Txid) -> Result<Option<Transaction>> {
+        if self.wallet(trade_model.wallet_id())?.contains_tx(txid) {
+            debug!(trade_id = trade_model.trade_id(), %txid, "Deposit tx already in wallet; skipping broadcast.");
+            return Ok(None);
+        }
+        let deposit_tx = trade_model.get_signed_deposit_tx()
+            .ok_or_else(|| MusigError::InvalidState { expected: TradeState::DepositTxPublished, actual: trade_model.state() })?;
+        Ok(Some(deposit_tx))
+    }
+
+    async fn handle_musig_request<Req, Res, F>(&self, request: Request<Req>, handler: F) -> Result<Response<Res>>
+        where Req: MusigRequest,
+              Res: Serialize,
+              F: FnOnce(Req, &mut TradeModel) -> Result<Res> {
+        let logged_request = self.message_log.as_ref().map(|_| request.get_ref().clone());
+        let trade_model = lock_trade_model(request.get_ref().trade_id()).await;
+        let response = handle_request(request, move |request| {
+            let mut trade_model = trade_model?;
+            // Reject replayed or out-of-order requests. The sequence number is only consumed if the
+            // request succeeds, so that a failed request may be retried.
+            let sequence_number = request.sequence_number();
+            if !trade_model.is_next_sequence_number(sequence_number) {
+                return Err(Status::failed_precondition("out-of-order sequence number"));
+            }
+            let old_state = trade_model.state();
+            let response = handler(request, &mut trade_model)?;
+            trade_model.set_last_sequence_number(sequence_number);
+            if trade_model.state() != old_state {
+                self.audit(trade_model.state().into(), &trade_model);
+            }
+
+            Ok(response)
+        });
+        self.log_message(logged_request, &response);
+        response
+    }
+
+    /// Like [`Self::handle_musig_request`], but with a wallet call which may need network I/O, such as
+    /// a broadcast, made between two handlers with the trade model unlocked, so that a slow wallet
+    /// backend can't hold up other requests on the trade. The `prepare` handler returns the input of
+    /// the wallet call, if one is needed, without consuming the sequence number of the request, and
+    /// the `complete` handler gets its output as the request is handled in full.
+    async fn handle_musig_request_with_wallet_call<Req, Res, T, U, F, C, G>(
+        &self, request: Request<Req>, prepare: F, wallet_call: C, complete: G,
+    ) -> Result<Response<Res>>
+        where Req: MusigRequest,
+              Res: Serialize,
+              F: FnOnce(&Req, &mut TradeModel) -> Result<Option<T>>,
+              C: FnOnce(T) -> Result<U>,
+              G: FnOnce(Req, &mut TradeModel, Option<U>) -> Result<Res> {
+        let prepared = async {
+            let mut trade_model = lock_trade_model(request.get_ref().trade_id()).await?;
+            if !trade_model.is_next_sequence_number(request.get_ref().sequence_number()) {
+                return Err(Status::failed_precondition("out-of-order sequence number"));
+            }
+            prepare(request.get_ref(), &mut trade_model)
+        }.await;
+        let called = prepared.and_then(|input| input.map(wallet_call).transpose());
+        self.handle_musig_request(request, move |request, trade_model| complete(request, trade_model, called?)).await
+    }
+}
+
+#[tonic::async_trait]
+impl musig_server::Musig for MusigImpl {
+    #[instrument(skip_all)]
+    async fn init_trade(&self, request: Request<PubKeySharesRequest>) -> Result<Response<PubKeySharesResponse>> {
+        // A client may retry the request (say after a timeout), so reply to a repeated one with the
+        // key shares already generated for the trade, rather than starting it afresh.
+        let existing_trade_model = match TRADE_MODELS.get_trade_model(request.get_ref().trade_id()) {
+            Some(trade_model) => Some(trade_model.lock_owned().await),
+            None => None,
+        };
+        let logged_request = self.message_log.as_ref().map(|_| request.get_ref().clone());
+        let response = handle_request(request, move |request| {
+            let my_role = request.my_role.try_proto_into()?;
+            if let Some(trade_model) = existing_trade_model {
+                if trade_model.my_role() != my_role {
+                    return Err(Status::already_exists("trade_id taken with different role"));
+                }
+                debug!(trade_id = request.trade_id, "Trade already initiated.");
+                return steps::pub_key_shares_response(&self.config, &trade_model);
+            }
+            if self.fee_anomaly_guard.as_ref().is_some_and(|guard| guard.is_anomalous()) {
+                return Err(Status::unavailable("high fee environment, try later"));
+            }
+            self.wallet(&request.wallet_id)?;
+            let trade_model = steps::init_trade(request)?;
+            let response = steps::pub_key_shares_response(&self.config, &trade_model)?;
+            let audit_entry = AuditEntry::new(AuditEventType::TradeInitiated, &trade_model);
+            // The trade may have been initiated by a concurrent request since the lookup above, in
+            // which case it is left in place, for the client to retry and get its key shares.
+            TRADE_MODELS.add_trade_model(trade_model, self.config.max_concurrent_trades).map_err(|e| match e {
+                AddTradeModelError::TradeIdTaken => Status::aborted(e.to_string()),
+                AddTradeModelError::TooManyTrades => {
+                    #[cfg(feature = "metrics")]
+                    crate::stats::metrics::REJECTED_TRADE_COUNT.inc();
+                    Status::resource_exhausted(e.to_string())
+                }
+            })?;
+            #[cfg(feature = "metrics")]
+            export_active_trade_count();
+            self.append_audit_entry(&audit_entry);
+            self.update_trade_stats(TradeStats::record_initiated);
+
+            Ok(response)
+        });
+        self.log_message(logged_request, &response);
+        response
+    }
+
+    #[instrument(skip_all)]
+    async fn get_nonce_shares(&self, request: Request<NonceSharesRequest>) -> Result<Response<NonceSharesMessage>> {
+        self.handle_musig_request(request, move |request, trade_model| {
+            steps::get_nonce_shares(&self.config, request, trade_model)
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_partial_signatures(&self, request: Request<PartialSignaturesRequest>) -> Result<Response<PartialSignaturesMessage>> {
+        self.handle_musig_request(request, move |request, trade_model| {
+            steps::get_partial_signatures(&self.config, request, trade_model)
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn sign_deposit_tx(&self, request: Request<DepositTxSignatureRequest>) -> Result<Response<DepositPsbt>> {
+        self.handle_musig_request(request, move |request, trade_model| {
+            steps::sign_deposit_tx(request, trade_model)
+        }).await
+    }
+
+    type PublishDepositTxStream = TracedResultStream<TxConfirmationStatus>;
+
+    #[instrument(skip_all)]
+    async fn publish_deposit_tx(&self, request: Request<PublishDepositTxRequest>) -> Result<Response<Self::PublishDepositTxStream>> {
+        self.handle_musig_request_with_wallet_call(request, |request, trade_model| {
+            let required_confirmations = match request.required_confirmations {
+                0 => DEFAULT_REQUIRED_CONFIRMATIONS,
+                n @ ..=MAX_REQUIRED_CONFIRMATIONS => n,
+                n => return Err(MusigError::InvalidInput {
+                    field: "required_confirmations".to_owned(),
+                    reason: format!("{n} exceeds maximum of {MAX_REQUIRED_CONFIRMATIONS}"),
+                }.into()),
+            };
+            let peers_deposit_psbt = request.peers_deposit_psbt.as_ref()
+                .ok_or_else(|| Status::not_found("missing request.peers_deposit_psbt"))?;
+            trade_model.combine_deposit_psbts(
+                (&peers_deposit_psbt.deposit_psbt[..], peers_deposit_psbt.psbt_version).try_proto_into()?)?;
+            let deposit_tx = trade_model.get_signed_deposit_tx()
+                .ok_or(MusigError::MissingTradeData("signed deposit tx"))?;
+            Ok(Some((self.wallet(trade_model.wallet_id())?, deposit_tx, required_confirmations)))
+        }, |(wallet, deposit_tx, required_confirmations)| {
+            Ok((wallet.broadcast_tx(deposit_tx)?, required_confirmations))
+        }, move |request, trade_model, broadcast| {
+            let (txid, required_confirmations) = broadcast.ok_or(MusigError::MissingTradeData("deposit txid"))?;
+            info!(trade_id = request.trade_id, %txid, "Broadcast deposit tx.");
+            trade_model.advance_state(TradeState::DepositTxPublished);
+
+            let explorer_url = self.config.block_explorer.as_ref().map(|explorer| explorer.tx_url(&txid));
+            let statuses = tx_confirmation_status_stream(self.wallet(trade_model.wallet_id())?, txid, explorer_url);
+            let statuses = BoundedDropStream::new(statuses, TX_CONFIRMATION_STATUS_BUFFER_CAPACITY, txid)
+                .filter_map(future::ready);
+            Ok(until_confirmed(statuses, required_confirmations)
+                .map(Ok)
+                .on_drop(move || debug!(trade_id = request.trade_id, "Deposit tx confirmation status stream has been dropped."))
+                .box_traced())
+        }).await
+    }
+
+    type SubscribeTxConfirmationStatusStream = TracedResultStream<TxConfirmationStatus>;
+
+    #[instrument(skip_all)]
+    async fn subscribe_tx_confirmation_status(&self, request: Request<SubscribeTxConfirmationStatusRequest>)
+                                              -> Result<Response<Self::SubscribeTxConfirmationStatusStream>> {
+        self.handle_musig_request_with_wallet_call(request, |request, trade_model| {
+            let txid = trade_model.get_deposit_txid()
+                .ok_or_else(|| Status::failed_precondition("missing deposit tx"))?;
+            if !request.resume_deposit_monitoring {
+                return Ok(None);
+            }
+            let wallet = self.wallet(trade_model.wallet_id())?;
+            Ok(self.deposit_tx_to_rebroadcast(trade_model, txid)?.map(|deposit_tx| (wallet, deposit_tx)))
+        }, |(wallet, deposit_tx)| Ok(wallet.broadcast_tx(deposit_tx)?), move |request, trade_model, broadcast_txid| {
+            let txid = trade_model.get_deposit_txid()
+                .ok_or_else(|| Status::failed_precondition("missing deposit tx"))?;
+            if request.resume_deposit_monitoring {
+                if broadcast_txid.is_some() {
+                    info!(trade_id = request.trade_id, %txid, "Broadcast deposit tx on resuming monitoring.");
+                }
+                // Either way, the deposit tx is now out, even if the publish request never said so.
+                trade_model.advance_state(TradeState::DepositTxPublished);
+            }
+            let explorer_url = self.config.block_explorer.as_ref().map(|explorer| explorer.tx_url(&txid));
+            let statuses = tx_confirmation_status_stream(self.wallet(trade_model.wallet_id())?, txid, explorer_url);
+            let statuses = BoundedDropStream::new(statuses, TX_CONFIRMATION_STATUS_BUFFER_CAPACITY, txid);
+
+            Ok(until_tx_dropped(statuses)
+                .map(Ok)
+                .on_drop(move || debug!(trade_id = request.trade_id, "Tx confirmation status stream has been dropped."))
+                .box_traced())
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn sign_swap_tx(&self, request: Request<SwapTxSignatureRequest>) -> Result<Response<SwapTxSignatureResponse>> {
+        self.handle_musig_request(request, move |request, trade_model| {
+            steps::sign_swap_tx(request, trade_model)
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn close_trade(&self, request: Request<CloseTradeRequest>) -> Result<Response<CloseTradeResponse>> {
+        self.handle_musig_request_with_wallet_call(request, |request, trade_model| {
+            if request.swap_tx.is_some() || request.my_output_peers_prv_key_share.is_some() {
+                return Ok(None);
+            }
+            // Peer unresponsive -- force-close our trade by publishing the swap tx. For seller only.
+            require_role(trade_model, TraderRole::Seller)?;
+            let swap_tx = trade_model.get_signed_swap_tx()
+                .ok_or(MusigError::MissingTradeData("signed swap tx"))?;
+            Ok(Some((self.wallet(trade_model.wallet_id())?, swap_tx.clone())))
+        }, |(wallet, swap_tx)| Ok(wallet.broadcast_tx(swap_tx)?), move |request, trade_model, swap_txid| {
+            let old_state = trade_model.state();
+            let response = steps::close_trade(request, trade_model, swap_txid)?;
+            self.record_trade_completed(trade_model, old_state);
+
+            Ok(response)
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn sign_custom_payout_tx(&self, request: Request<CustomPayoutPsbtRequest>) -> Result<Response<CustomPayoutPsbt>> {
+        self.handle_musig_request(request, move |request, trade_model| {
+            trade_model.set_sellers_custom_payout_amount_excluding_fee(
+                Amount::from_sat(request.sellers_payout_amount_excluding_fee.check_in_signed_range()?));
+            trade_model.set_custom_payout_tx_fee_rate(
+                FeeRate::from_sat_per_kwu(request.fee_rate.check_in_signed_range()?));
+            trade_model.compute_custom_payout_tx()?;
+            trade_model.sign_custom_payout_psbt()?;
+            let psbt = trade_model.get_custom_payout_psbt()
+                .ok_or(MusigError::MissingTradeData("custom payout PSBT"))?;
+
+            Ok(CustomPayoutPsbt {
+                psbt: psbt.serialize(),
+                tx_id: psbt.unsigned_tx.compute_txid().to_string(),
+                buyers_payout_amount_including_fee: psbt.unsigned_tx.output[0].value.to_sat(),
+                sellers_payout_amount_including_fee: psbt.unsigned_tx.output[1].value.to_sat(),
+            })
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn custom_close_trade(&self, request: Request<CustomCloseTradeRequest>) -> Result<Response<CustomCloseTradeResponse>> {
+        self.handle_musig_request(request, move |request, trade_model| {
+            let peers_psbt = request.peers_custom_payout_psbt.try_proto_into()?;
+            trade_model.combine_custom_payout_psbts(peers_psbt)?;
+            // Sign custom payout PSBT again to finalize it:
+            trade_model.sign_custom_payout_psbt()?;
+            let custom_payout_tx = trade_model.get_signed_custom_payout_tx()
+                .ok_or(MusigError::MissingTradeData("signed custom payout tx"))?;
+
+            info!("*** BROADCAST CUSTOM PAYOUT TX ***"); // TODO: Implement broadcast.
+            let old_state = trade_model.state();
+            trade_model.advance_state(TradeState::Closed);
+            self.record_trade_completed(trade_model, old_state);
+
+            Ok(CustomCloseTradeResponse { custom_payout_tx: consensus::serialize(&custom_payout_tx) })
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn cancel_trade(&self, request: Request<CancelTradeRequest>) -> Result<Response<CancelTradeResponse>> {
+        self.handle_musig_request(request, move |request, trade_model| {
+            // Once the deposit tx is out, the funds can only be recovered by completing the trade
+            // (or via the arbitration txs), so it is no longer safe to simply forget it.
+            if !trade_model.state().is_pre_deposit() {
+                return Err(Status::failed_precondition("deposit tx has already been published"));
+            }
+            // TODO: Unreserve the inputs of our half deposit PSBT, once the trade wallet reserves the
+            //  UTXOs it selects. (The current mock trade wallets are dropped along with the trade.)
+            TRADE_MODELS.remove_trade_model(&request.trade_id);
+            #[cfg(feature = "metrics")]
+            export_active_trade_count();
+            info!(trade_id = request.trade_id, state = ?trade_model.state(), "Trade cancelled.");
+            self.audit(AuditEventType::TradeCancelled, trade_model);
+            // TODO: Also count trades abandoned by the peer as failed, once stale trades expire.
+            self.update_trade_stats(TradeStats::record_failed);
+
+            Ok(CancelTradeResponse {})
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn reset_trade(&self, request: Request<ResetTradeRequest>) -> Result<Response<ResetTradeResponse>> {
+        self.handle_musig_request(request, move |request, trade_model| {
+            let old_state = trade_model.state();
+            trade_model.reset_to_key_shares()?;
+            info!(trade_id = request.trade_id, ?old_state, "Trade reset to key shares.");
+
+            Ok(ResetTradeResponse {})
+        }).await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_trades(&self, request: Request<ListTradesRequest>) -> Result<Response<ListTradesResponse>> {
+        // Wait for the trades concurrently, so that one busy trade can't hold up the listing.
+        let trade_models = TRADE_MODELS.trade_models();
+        let summaries = future::join_all(trade_models.iter().map(|trade_model| async {
+            let trade_model = time::timeout(LIST_TRADES_LOCK_TIMEOUT, trade_model.lock()).await.ok()?;
+            Some(TradeSummary::from(&*trade_model))
+        })).await;
+        let busy_trade_count = summaries.iter().filter(|summary| summary.is_none()).count();
+        if busy_trade_count > 0 {
+            warn!(busy_trade_count, "Left busy trades out of the trade list.");
+        }
+        let mut trades: Vec<TradeSummary> = summaries.into_iter().flatten().collect();
+        handle_request(request, move |_request| {
+            trades.sort_by_key(|trade| trade.created_at);
+
+            Ok(ListTradesResponse { trades })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn get_trade(&self, request: Request<GetTradeRequest>) -> Result<Response<GetTradeResponse>> {
+        let trade_model = lock_trade_model(&request.get_ref().trade_id).await;
+        handle_request(request, move |_request| {
+            let trade = (&*trade_model?).into();
+
+            Ok(GetTradeResponse { trade: Some(trade) })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn get_trade_stats(&self, request: Request<GetTradeStatsRequest>) -> Result<Response<GetTradeStatsResponse>> {
+        handle_request(request, |_request| {
+            let stats = (&*self.trade_stats.lock().unwrap()).into();
+
+            Ok(GetTradeStatsResponse { stats: Some(stats) })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn get_trade_receipt(&self, request: Request<GetTradeReceiptRequest>) -> Result<Response<GetTradeReceiptResponse>> {
+        let trade_model = lock_trade_model(&request.get_ref().trade_id).await;
+        handle_request(request, move |_request| {
+            let receipt = trade_model?.generate_receipt()?;
+            let receipt_json = receipt.to_json().map_err(MusigError::from)?;
+
+            Ok(GetTradeReceiptResponse { receipt_json, signature_hex: receipt.signature.to_lower_hex_string() })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn get_recovery_tx(&self, request: Request<GetRecoveryTxRequest>) -> Result<Response<GetRecoveryTxResponse>> {
+        let trade_model = lock_trade_model(&request.get_ref().trade_id).await;
+        handle_request(request, move |_request| {
+            let trade_model = trade_model?;
+            let [(warning_tx, warning_lock_time), (claim_tx, claim_lock_time)] = trade_model.get_signed_recovery_txs()?;
+
+            Ok(GetRecoveryTxResponse {
+                warning_tx: consensus::serialize(warning_tx),
+                warning_lock_time: warning_lock_time.to_consensus_u32(),
+                claim_tx: consensus::serialize(claim_tx),
+                claim_lock_time: claim_lock_time.to_consensus_u32(),
+            })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn get_dispute_evidence(&self, request: Request<GetDisputeEvidenceRequest>)
+        -> Result<Response<GetDisputeEvidenceResponse>> {
+        let trade_model = lock_trade_model(&request.get_ref().trade_id).await;
+        handle_request(request, move |request| {
+            let trade_model = trade_model?;
+            let arbitrator_pub_key: PublicKey = request.arbitrator_pub_key.try_proto_into()?;
+            let trade_details: TradeDetails = (&*trade_model).into();
+            let evidence_json = serde_json::to_vec(&trade_details).map_err(MusigError::from)?;
+            let signature = trade_model.sign_dispute_evidence(&evidence_json)?;
+            let evidence_json = ecies::encrypt(&arbitrator_pub_key.serialize(), &evidence_json)
+                .map_err(|e| MusigError::CryptoError(e.into()))?;
+
+            Ok(GetDisputeEvidenceResponse { evidence_json, signature })
+        })
+    }
+}
+
+/// Stream the confirmation status of the given tx, from the wallet's view of it, whenever that
+/// changes. The status is `None` while the tx is missing from the wallet's tx graph.
+fn tx_confirmation_status_stream(
+    wallet_service: Arc<dyn WalletService + Send + Sync>,
+    txid: Txid,
+    explorer_url: Option<String>,
+) -> impl Stream<Item = Option<TxConfirmationStatus>> {
+    let spv_wallet_service = wallet_service.clone();
+    wallet_service.get_tx_confidence_stream(txid)
+        .scan(false, move |merkle_proof_checked, conf| {
+            // Check the Merkle proof once, as the tx first confirms, halting the stream if it is
+            // invalid. If the proof can't be fetched, try again with the next status.
+            if let Some(conf) = conf.as_ref().filter(|conf| conf.num_confirmations > 0 && !*merkle_proof_checked) {
+                match check_merkle_proof(&*spv_wallet_service, &conf.wallet_tx) {
+                    Ok(true) => *merkle_proof_checked = true,
+                    Ok(false) => return future::ready(None),
+                    Err(e) => warn!(%txid, "Could not fetch Merkle proof of confirmed tx: {e}"),
+                }
+            }
+            future::ready(Some(conf))
+        })
+        .map(move |conf| conf.map(|conf| TxConfirmationStatus {
+            tx: consensus::serialize(&conf.wallet_tx.tx),
+            current_block_height: wallet_service.block_height(),
+            num_confirmations: conf.num_confirmations,
+            broadcast_failed: conf.broadcast_failed,
+            signals_rbf: conf.wallet_tx.signals_rbf,
+            explorer_url: explorer_url.clone(),
+        }))
+}
+
+/// Independently verify the inclusion of a confirmed tx in its block, against the Merkle root in the
+/// block header, with a proof fetched through the wallet's backend. Returns `true` if the proof is
+/// valid, or if the tx is unconfirmed or the backend can't give a proof.
+fn check_merkle_proof(wallet_service: &(dyn WalletService + Send + Sync), wallet_tx: &WalletTx) -> wallet::Result<bool> {
+    let ChainPosition::Confirmed { anchor, .. } = wallet_tx.chain_position else { return Ok(true) };
+    let Some(merkle_proof) = wallet_service.get_merkle_proof(wallet_tx.txid, anchor.block_id.hash)? else {
+        debug!(txid = %wallet_tx.txid, "Backend gives no Merkle proof; skipping SPV check.");
+        return Ok(true);
+    };
+    // A 64-byte tx could pass for an inner node of the Merkle tree, so never accept one.
+    let valid = merkle_proof.verify(&wallet_tx.txid) && wallet_tx.tx.base_size() != 64;
+    if !valid {
+        warn!(txid = %wallet_tx.txid, block_hash = %anchor.block_id.hash, "Invalid Merkle proof of confirmed tx; halting.");
+    }
+    Ok(valid)
+}
+
+/// End the stream with the first status with the required confirmations, without waiting on the
+/// inner stream for another status, which may never come.
+fn until_confirmed<S>(statuses: S, required_confirmations: u32) -> impl Stream<Item = TxConfirmationStatus>
+    where S: Stream<Item = TxConfirmationStatus> + Unpin
+{
+    stream::unfold(Some(statuses), move |statuses| async move {
+        let mut statuses = statuses?;
+        let status = statuses.next().await?;
+        let confirmed = status.num_confirmations >= required_confirmations;
+        Some((status, (!confirmed).then_some(statuses)))
+    })
+}
+
+/// Skip any missing statuses until the tx is first seen, then end the stream with a final status of
+/// zero confirmations if the tx goes missing again, say by being dropped from the mempool.
+fn until_tx_dropped<S>(statuses: S) -> impl Stream<Item = TxConfirmationStatus>
+    where S: Stream<Item = Option<TxConfirmationStatus>> + Unpin
+{
+    stream::unfold(Some((statuses, None)), |state| async move {
+        let (mut statuses, mut last_status) = state?;
+        loop {
+            match statuses.next().await? {
+                Some(status) => return Some((status.clone(), Some((statuses, Some(status))))),
+                None => if let Some(status) = last_status.take() {
+                    return Some((TxConfirmationStatus { num_confirmations: 0, ..status }, None));
+                },
+            }
+        }
+    })
+}
+
+/// A stream relaying the items of another through a buffer of fixed capacity, so that a producer
+/// faster than the consumer can't make it grow without bound. When the buffer is full, the oldest
+/// item is dropped to make room for the newest. The inner stream is polled by a spawned task, which
+/// is aborted as soon as this stream is dropped.
+pub struct BoundedDropStream<T> {
+    items: BoxStream<'static, T>,
+    forwarder: JoinHandle<()>,
+}
+
+impl<T: Clone + Send + 'static> BoundedDropStream<T> {
+    /// Buffer at most `capacity` items of the `inner` stream of statuses of the given tx.
+    ///
+    /// # Panics
+    /// Will panic if `capacity` is zero, or if called outside the context of a Tokio runtime
+    pub fn new<S>(inner: S, capacity: usize, txid: Txid) -> Self
+        where S: Stream<Item = T> + Send + 'static
+    {
+        let (sender, receiver) = broadcast::channel(capacity);
+        let forwarder = task::spawn(async move {
+            let mut inner = pin!(inner);
+            while let Some(item) = inner.next().await {
+                if sender.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        let items = stream::unfold(receiver, move |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(item) => return Some((item, receiver)),
+                    Err(RecvError::Lagged(count)) =>
+                        debug!(count, "dropped tx confirmation status message for txid: {txid}"),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }).boxed();
+        Self { items, forwarder }
+    }
+}
+
+impl<T> Stream for BoundedDropStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().items.poll_next_unpin(cx)
+    }
+}
+
+impl<T> Drop for BoundedDropStream<T> {
+    fn drop(&mut self) { self.forwarder.abort(); }
+}
+
+pub struct WalletImpl {
+    /// The default wallet, used for requests with an empty wallet ID.
+    pub wallet_service: Arc<dyn WalletService + Send + Sync>,
+    pub wallet_manager: Option<Arc<WalletManager>>,
+}
+
+impl WalletImpl {
+    pub const fn new(wallet_service: Arc<dyn WalletService + Send + Sync>) -> Self {
+        Self { wallet_service, wallet_manager: None }
+    }
+
+    /// Serve the given manager's wallets, including its default wallet, and create new wallets with
+    /// it. (Without a manager, only the default wallet is available.)
+    pub fn from_wallet_manager(wallet_manager: Arc<WalletManager>) -> Self {
+        Self { wallet_service: wallet_manager.default_wallet().clone(), wallet_manager: Some(wallet_manager) }
+    }
+
+    /// The wallet with the given ID, or the default wallet if the ID is empty.
+    ///
+    /// # Errors
+    /// Will return a `NotFound` status if there is no wallet with the given ID
+    pub(crate) fn wallet(&self, wallet_id: &str) -> Result<Arc<dyn WalletService + Send + Sync>> {
+        select_wallet(&self.wallet_service, self.wallet_manager.as_deref(), wallet_id)
+    }
+}
+
+/// The wallet with the given ID, or the default wallet if the ID is empty.
+fn select_wallet(
+    default_wallet: &Arc<dyn WalletService + Send + Sync>, wallet_manager: Option<&WalletManager>, wallet_id: &str,
+) -> Result<Arc<dyn WalletService + Send + Sync>> {
+    wallet_manager.map_or_else(|| wallet_id.is_empty().then(|| default_wallet.clone()),
+        |wallet_manager| wallet_manager.get_wallet(wallet_id))
+        .ok_or_else(|| Status::not_found(format!("unknown wallet id: {wallet_id}")))
+}
+
+#[tonic::async_trait]
+impl wallet_server::Wallet for WalletImpl {
+    #[instrument(skip_all)]
+    async fn wallet_balance(&self, request: Request<WalletBalanceRequest>) -> Result<Response<WalletBalanceResponse>> {
+        handle_request(request, |request| Ok(self.wallet(&request.wallet_id)?.balance().into()))
+    }
+
+    #[instrument(skip_all)]
+    async fn new_address(&self, request: Request<NewAddressRequest>) -> Result<Response<NewAddressResponse>> {
+        handle_request(request, |request| {
+            let address = self.wallet(&request.wallet_id)?.reveal_next_address(KeychainPurpose::External)?;
+
+            Ok(NewAddressResponse {
+                address: address.address.to_string(),
+                derivation_path: format!("m/86'/1'/0'/0/{}", address.index),
+            })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn list_unspent(&self, request: Request<ListUnspentRequest>) -> Result<Response<ListUnspentResponse>> {
+        handle_request(request, |request| {
+            let utxos: Vec<_> = self.wallet(&request.wallet_id)?.list_unspent().into_iter()
+                .map(Into::into)
+                .collect();
+
+            Ok(ListUnspentResponse { utxos })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn get_utxo(&self, request: Request<GetUtxoRequest>) -> Result<Response<GetUtxoResponse>> {
+        handle_request(request, |request| {
+            let outpoint = OutPoint::new(request.tx_id.try_proto_into()?, request.vout);
+            let utxo = self.wallet(&request.wallet_id)?.get_utxo(outpoint)
+                .ok_or_else(|| Status::not_found(format!("no wallet utxo with outpoint: {outpoint}")))?;
+
+            Ok(GetUtxoResponse { utxo: Some(utxo.into()) })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn get_tx_fee(&self, request: Request<GetTxFeeRequest>) -> Result<Response<GetTxFeeResponse>> {
+        handle_request(request, |request| {
+            let txid = request.tx_id.try_proto_into()?;
+            let fee = self.wallet(&request.wallet_id)?.get_tx_fee(txid)
+                .ok_or_else(|| Status::not_found(format!("unknown fee of tx: {txid}")))?;
+
+            Ok(GetTxFeeResponse { fee_sats: fee.to_sat() })
+        })
+    }
+
+    type RegisterConfidenceNtfnStream = TracedResultStream<ConfEvent>;
+
+    #[instrument(skip_all)]
+    async fn register_confidence_ntfn(&self, request: Request<ConfRequest>) -> Result<Response<Self::RegisterConfidenceNtfnStream>> {
+        handle_request(request, move |request| {
+            let txid = request.tx_id.try_proto_into()?;
+            let conf_events = self.wallet(&request.wallet_id)?.get_tx_confidence_stream(txid)
+                .map(|o| Ok(o.map(Into::into).unwrap_or_default()))
+                .box_traced();
+
+            Ok(conf_events)
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn send(&self, request: Request<SendRequest>) -> Result<Response<SendResponse>> {
+        handle_request(request, |request| {
+            let mut options = SendOptions::new();
+            if let Some(lock_time) = request.lock_time {
+                options = options.with_locktime(absolute::LockTime::from_consensus(lock_time));
+            }
+            if let Some(enable_rbf) = request.enable_rbf {
+                options = options.with_rbf(enable_rbf);
+            }
+            if let Some(coin_age_priority) = request.coin_age_priority {
+                options = options.with_coin_age_priority(coin_age_priority);
+            }
+            let tx = self.wallet(&request.wallet_id)?.send(request.address.try_proto_into()?,
+                Amount::from_sat(request.amount.check_in_signed_range()?), options)?;
+
+            Ok(SendResponse {
+                tx_id: tx.compute_txid().to_byte_array().into(),
+                tx: consensus::serialize(&tx),
+            })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn broadcast_tx(&self, request: Request<BroadcastTxRequest>) -> Result<Response<BroadcastTxResponse>> {
+        handle_request(request, |request| {
+            let txid = self.wallet(&request.wallet_id)?.broadcast_tx(request.raw_tx.try_proto_into()?)?;
+
+            Ok(BroadcastTxResponse { tx_id: txid.to_byte_array().into() })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn sign_psbt(&self, request: Request<SignPsbtRequest>) -> Result<Response<SignPsbtResponse>> {
+        handle_request(request, |request| {
+            let mut psbt = (&request.psbt[..], request.psbt_version).try_proto_into()?;
+            let is_finalized = self.wallet(&request.wallet_id)?.sign_psbt(&mut psbt)?;
+
+            Ok(SignPsbtResponse { psbt: psbt.serialize(), is_finalized })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn get_silent_payment_address(&self, request: Request<GetSilentPaymentAddressRequest>)
+        -> Result<Response<GetSilentPaymentAddressResponse>> {
+        handle_request(request, |request| {
+            if !cfg!(feature = "silent-payments") {
+                return Err(Status::unimplemented("silent payment addresses are experimental, as payments to them aren't tracked"));
+            }
+            let address = self.wallet(&request.wallet_id)?.derive_silent_payment_address()?;
+
+            Ok(GetSilentPaymentAddressResponse { address: address.to_string() })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn export_transactions_csv(&self, request: Request<ExportTransactionsCsvRequest>)
+        -> Result<Response<ExportTransactionsCsvResponse>> {
+        handle_request(request, |request| {
+            let heights = request.start_height..=request.end_height;
+            let csv_data = self.wallet(&request.wallet_id)?.export_transactions_csv(heights)?;
+
+            Ok(ExportTransactionsCsvResponse { csv_data })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn import_watch_descriptor(&self, request: Request<ImportWatchDescriptorRequest>)
+        -> Result<Response<ImportWatchDescriptorResponse>> {
+        handle_request(request, |request| {
+            self.wallet(&request.wallet_id)?.import_watch_descriptor(&request.descriptor)?;
+
+            Ok(ImportWatchDescriptorResponse {})
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn verify_address(&self, request: Request<VerifyAddressRequest>) -> Result<Response<VerifyAddressResponse>> {
+        handle_request(request, |request| {
+            let wallet = self.wallet(&request.wallet_id)?;
+            wallet.verify_address(&request.address)?;
+
+            Ok(VerifyAddressResponse { is_valid: true, network: wallet.network().to_string() })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn sign_message(&self, request: Request<SignMessageRequest>) -> Result<Response<SignMessageResponse>> {
+        let signature = async {
+            let SignMessageRequest { address, message, wallet_id } = request.get_ref();
+            let wallet = self.wallet(wallet_id)?;
+            let address = wallet.verify_address(address)?;
+            Ok::<_, Status>(wallet.sign_message(&address, message).await?)
+        }.await;
+        handle_request(request, move |_| Ok(SignMessageResponse { signature: signature? }))
+    }
+
+    #[instrument(skip_all)]
+    async fn verify_message(&self, request: Request<VerifyMessageRequest>) -> Result<Response<VerifyMessageResponse>> {
+        handle_request(request, |request| {
+            let address = self.wallet(&request.wallet_id)?.verify_address(&request.address)?;
+            let is_valid = bip322::verify_simple_signature(&address, &request.message, &request.signature);
+
+            Ok(VerifyMessageResponse { is_valid })
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn create_wallet(&self, request: Request<CreateWalletRequest>) -> Result<Response<CreateWalletResponse>> {
+        handle_request(request, |request| {
+            if request.wallet_id.is_empty() {
+                return Err(Status::invalid_argument("missing wallet id"));
+            }
+            let wallet_manager = self.wallet_manager.as_ref()
+                .ok_or_else(|| Status::unimplemented("multiple wallets not enabled"))?;
+            wallet_manager.create_wallet(&request.wallet_id, &request.descriptor)?;
+
+            Ok(CreateWalletResponse {})
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn lock_wallet(&self, request: Request<LockWalletRequest>) -> Result<Response<LockWalletResponse>> {
+        handle_request(request, |request| {
+            self.wallet(&request.wallet_id)?.lock()?;
+
+            Ok(LockWalletResponse {})
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn unlock_wallet(&self, request: Request<UnlockWalletRequest>) -> Result<Response<UnlockWalletResponse>> {
+        handle_request(request, |request| {
+            self.wallet(&request.wallet_id)?.unlock(&request.passphrase)?;
+
+            Ok(UnlockWalletResponse {})
+        })
+    }
+}
+
+/// Administrative operations on the daemon itself, which are refused unless made from a loopback
+/// connection, so that they can't be used remotely even if the gRPC port is exposed.
+#[derive(Default)]
+pub struct AdminImpl;
+
+#[tonic::async_trait]
+impl admin_server::Admin for AdminImpl {
+    #[instrument(skip_all)]
+    async fn set_log_level(&self, request: Request<SetLogLevelRequest>) -> Result<Response<SetLogLevelResponse>> {
+        check_loopback(&request)?;
+        handle_request(request, |request| {
+            let level: Level = request.level.parse()
+                .map_err(|_| Status::invalid_argument(format!("invalid log level: {}", request.level)))?;
+            bmp_tracing::set_log_level(&request.module, level).map_err(|e| match e {
+                SetLogLevelError::InvalidModule(_) | SetLogLevelError::Directive(_) =>
+                    Status::invalid_argument(e.to_string()),
+                SetLogLevelError::NotInitialized | SetLogLevelError::Reload(_) => Status::internal(e.to_string()),
+            })?;
+            info!(module = request.module, %level, "Log level changed.");
+
+            Ok(SetLogLevelResponse {})
+        })
+    }
+}
+
+fn check_loopback<T>(request: &Request<T>) -> Result<()> {
+    match request.remote_addr() {
+        Some(addr) if addr.ip().is_loopback() => Ok(()),
+        addr => {
+            warn!(?addr, "Refused admin request from non-loopback address.");
+            Err(Status::permission_denied("admin requests are only accepted from loopback connections"))
+        }
+    }
+}
+
+struct LazyJson<T>(T);
+
+impl<T: Serialize> Display for LazyJson<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = if f.alternate() {
+            serde_json::to_string_pretty(&self.0)
+        } else {
+            serde_json::to_string(&self.0)
+        };
+        write!(f, "{}", s.as_deref().unwrap_or("<<SERIALIZATION ERROR>>"))
+    }
+}
+
+#[derive(Serialize)]
+pub struct TracedResultStream<T> {
+    #[serde(skip)]
+    inner: BoxStream<'static, Result<T>>,
+    #[serde(skip)]
+    span: Span,
+}
+
+impl<T> Stream for TracedResultStream<T> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let _enter = this.span.enter();
+        Pin::new(&mut this.inner).poll_next(cx)
+    }
+}
+
+pub trait TracedResultStreamExt<T: Serialize>: TryStream<Ok = T, Error = Status> + Sized + Send + 'static {
+    fn box_traced(self) -> TracedResultStream<T> {
+        TracedResultStream {
+            inner: Box::pin(self.inspect_ok(move |event| {
+                let message = LazyJson(event);
+                trace!(%message, "Streaming event.");
+            })),
+            span: Span::current(),
+        }
+    }
+}
+
+impl<S> TracedResultStreamExt<S::Ok> for S
+    where S: TryStream<Error = Status> + Sized + Send + 'static,
+          S::Ok: Serialize {}
+
+trait MusigRequest: Serialize + Clone {
+    /// The method the request is for, named as in the JSON-RPC interface.
+    const METHOD: &'static str;
+
+    fn trade_id(&self) -> &str;
+    fn sequence_number(&self) -> u64;
+}
+
+macro_rules! impl_musig_req {
+    ($request_type:ty, $method:literal) => {
+        impl MusigRequest for $request_type {
+            const METHOD: &'static str = $method;
+
+            fn trade_id(&self) -> &str { &self.trade_id }
+            fn sequence_number(&self) -> u64 { self.sequence_number }
+        }
+    };
+}
+
+impl_musig_req!(PubKeySharesRequest, "musig_initTrade");
+impl_musig_req!(PartialSignaturesRequest, "musig_getPartialSignatures");
+impl_musig_req!(NonceSharesRequest, "musig_getNonceShares");
+impl_musig_req!(DepositTxSignatureRequest, "musig_signDepositTx");
+impl_musig_req!(PublishDepositTxRequest, "musig_publishDepositTx");
+impl_musig_req!(SubscribeTxConfirmationStatusRequest, "musig_subscribeTxConfirmationStatus");
+impl_musig_req!(SwapTxSignatureRequest, "musig_signSwapTx");
+impl_musig_req!(CloseTradeRequest, "musig_closeTrade");
+impl_musig_req!(CustomPayoutPsbtRequest, "musig_signCustomPayoutTx");
+impl_musig_req!(CustomCloseTradeRequest, "musig_customCloseTrade");
+impl_musig_req!(CancelTradeRequest, "musig_cancelTrade");
+impl_musig_req!(ResetTradeRequest, "musig_resetTrade");
+
+/// Set the Prometheus gauge of active trades to the number of trade models held, after adding or
+/// removing one.
+#[cfg(feature = "metrics")]
+fn export_active_trade_count() {
+    crate::stats::metrics::ACTIVE_TRADE_COUNT.set(i64::try_from(TRADE_MODELS.trade_count()).unwrap_or(i64::MAX));
+}
+
+// TODO: These wrapper fns don't work with async handlers, and should eventually be changed to do so:
+
+/// Look up the trade with the given ID and wait for exclusive access to it, without blocking the
+/// thread. The returned guard should be moved into the (synchronous) request handler, so that the
+/// trade is only locked until the handler returns, not while any response stream is consumed.
+async fn lock_trade_model(trade_id: &str) -> Result<OwnedMutexGuard<TradeModel>> {
+    validate_trade_id(trade_id)?;
+    let trade_model = TRADE_MODELS.get_trade_model(trade_id)
+        .ok_or_else(|| MusigError::TradeNotFound(trade_id.to_owned()))?;
+    Ok(trade_model.lock_owned().await)
+}
+
+fn handle_request<Req, Res, F>(request: Request<Req>, handler: F) -> Result<Response<Res>>
+    where Req: Serialize,
+          Res: Serialize,
+          F: FnOnce(Req) -> Result<Res> {
+    let message = LazyJson(request.get_ref());
+    debug!(%message, "Got a request.");
+
+    let response = handler(request.into_inner())
+        .inspect_err(|e| error!("Error response: {e}"))?;
+
+    let message = LazyJson(&response);
+    trace!(%message, "Sending response.");
+    Ok(Response::new(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk_wallet::bitcoin::{Transaction, TxIn, transaction};
+    use musig_server::Musig as _;
+    use admin_server::Admin as _;
+    use bmp_tracing::LogConfig;
+    use tokio::sync::oneshot;
+    use tonic::Code;
+    use tonic::transport::server::TcpConnectInfo;
+    use wallet_server::Wallet as _;
+
+    use super::*;
+    use crate::pb::musigrpc::{self, Role};
+    use crate::protocol::{MAX_SUPPORTED_VERSION, MIN_SUPPORTED_VERSION};
+    use crate::wallet::WalletServiceImpl;
+
+    fn musig() -> MusigImpl {
+        MusigImpl::new(Arc::new(WalletServiceImpl::new()))
+    }
+
+    async fn init_trade(trade_id: &str) {
+        init_trade_as(trade_id, Role::SellerAsMaker).await;
+    }
+
+    async fn init_trade_as(trade_id: &str, my_role: Role) {
+        musig().init_trade(init_trade_request(trade_id, my_role)).await.unwrap();
+    }
+
+    fn init_trade_request(trade_id: &str, my_role: Role) -> Request<PubKeySharesRequest> {
+        Request::new(PubKeySharesRequest {
+            trade_id: trade_id.to_owned(),
+            my_role: my_role.into(),
+            sequence_number: 0,
+            protocol_version: MAX_SUPPORTED_VERSION,
+            wallet_id: String::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_seller_only_requests_rejected_for_buyer() {
+        init_trade_as("buyer-role-test", Role::BuyerAsTaker).await;
+
+        let request = SwapTxSignatureRequest {
+            trade_id: "buyer-role-test".to_owned(),
+            sequence_number: 1,
+            ..SwapTxSignatureRequest::default()
+        };
+        let status = musig().sign_swap_tx(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(status.message(), "this operation is only valid for the Seller role");
+
+        // A close request with neither the peer's key share nor a swap tx is a seller's force-close.
+        let request = CloseTradeRequest {
+            trade_id: "buyer-role-test".to_owned(),
+            sequence_number: 1,
+            ..CloseTradeRequest::default()
+        };
+        let status = musig().close_trade(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+    }
+
+    fn cancel_trade_request(trade_id: &str) -> Request<CancelTradeRequest> {
+        Request::new(CancelTradeRequest { trade_id: trade_id.to_owned(), sequence_number: 1 })
+    }
+
+    #[tokio::test]
+    async fn test_cancel_trade() {
+        init_trade("cancel-trade-test").await;
+
+        musig().cancel_trade(cancel_trade_request("cancel-trade-test")).await.unwrap();
+        assert!(TRADE_MODELS.get_trade_model("cancel-trade-test").is_none());
+
+        let status = musig().cancel_trade(cancel_trade_request("cancel-trade-test"))
+            .await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_list_trades() {
+        init_trade("list-trades-test-1").await;
+        init_trade("list-trades-test-2").await;
+
+        let response = musig().list_trades(Request::new(ListTradesRequest {}))
+            .await.unwrap().into_inner();
+        assert!(response.trades.is_sorted_by_key(|trade| trade.created_at));
+        let trades: Vec<_> = response.trades.iter()
+            .filter(|trade| trade.trade_id.starts_with("list-trades-test-"))
+            .collect();
+        assert_eq!(trades.len(), 2);
+        for trade in trades {
+            assert_eq!(trade.role(), Role::SellerAsMaker);
+            assert_eq!(trade.state(), musigrpc::TradeState::Initialized);
+            assert_eq!(trade.trade_amount, 0);
+            assert!(trade.created_at > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_trades_leaves_out_busy_trade() {
+        init_trade("list-trades-busy-test").await;
+        let trade_model = TRADE_MODELS.get_trade_model("list-trades-busy-test").unwrap();
+        let guard = trade_model.lock().await;
+
+        let musig = musig();
+        let list_trades = musig.list_trades(Request::new(ListTradesRequest {}));
+        let response = time::timeout(LIST_TRADES_LOCK_TIMEOUT * 2, list_trades).await
+            .expect("listing should not wait on the busy trade").unwrap().into_inner();
+        assert!(!response.trades.iter().any(|trade| trade.trade_id == "list-trades-busy-test"));
+        drop(guard);
+    }
+
+    #[tokio::test]
+    async fn test_get_trade() {
+        init_trade("get-trade-test").await;
+
+        let request = GetTradeRequest { trade_id: "get-trade-test".to_owned() };
+        let trade = musig().get_trade(Request::new(request))
+            .await.unwrap().into_inner().trade.unwrap();
+        let summary = trade.summary.unwrap();
+        assert_eq!(summary.trade_id, "get-trade-test");
+        assert_eq!(summary.state(), musigrpc::TradeState::Initialized);
+        assert_eq!(trade.deposit_tx_id, None);
+        assert_eq!(trade.swap_tx_id, None);
+        assert_eq!(trade.deposit_tx_fee_rate, 0);
+        assert!(trade.fee_bump_addresses.is_empty());
+
+        let request = GetTradeRequest { trade_id: "unknown-trade".to_owned() };
+        let status = musig().get_trade(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_locked_trade_yields_thread() {
+        init_trade("locked-trade-test").await;
+        let trade_model = TRADE_MODELS.get_trade_model("locked-trade-test").unwrap();
+        let guard = trade_model.lock().await;
+
+        // On this single-threaded runtime, the timeout could never fire if waiting for the trade
+        // blocked the thread.
+        let musig = musig();
+        let get_trade = || musig.get_trade(Request::new(GetTradeRequest { trade_id: "locked-trade-test".to_owned() }));
+        assert!(time::timeout(Duration::from_millis(50), get_trade()).await.is_err());
+        drop(guard);
+        assert!(get_trade().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_trade_stats() {
+        let musig = musig();
+        for trade_id in ["trade-stats-test-1", "trade-stats-test-2"] {
+            musig.init_trade(init_trade_request(trade_id, Role::SellerAsMaker)).await.unwrap();
+        }
+        musig.cancel_trade(cancel_trade_request("trade-stats-test-1")).await.unwrap();
+
+        let stats = musig.get_trade_stats(Request::new(GetTradeStatsRequest {}))
+            .await.unwrap().into_inner().stats.unwrap();
+        assert_eq!(stats, musigrpc::TradeStats {
+            total_trade_count: 2,
+            failed_count: 1,
+            ..Default::default()
+        });
+    }
+
+    #[tokio::test]
+    async fn test_init_trade_idempotent() {
+        let request = |my_role| init_trade_request("init-trade-idempotent-test", my_role);
+        let response = musig().init_trade(request(Role::SellerAsMaker)).await.unwrap().into_inner();
+        let retried_response = musig().init_trade(request(Role::SellerAsMaker)).await.unwrap().into_inner();
+        assert_eq!(retried_response, response);
+
+        let status = musig().init_trade(request(Role::BuyerAsTaker)).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::AlreadyExists, "trade_id taken with different role"));
+    }
+
+    #[test]
+    fn test_add_trade_model_if_absent() {
+        let trade_model = || TradeModel::new("add-trade-model-test".to_owned(), Role::SellerAsMaker.into());
+        TRADE_MODELS.add_trade_model(trade_model(), usize::MAX).unwrap();
+        let first_trade_model = TRADE_MODELS.get_trade_model("add-trade-model-test").unwrap();
+        assert_eq!(TRADE_MODELS.add_trade_model(trade_model(), usize::MAX), Err(AddTradeModelError::TradeIdTaken));
+        assert!(Arc::ptr_eq(&TRADE_MODELS.get_trade_model("add-trade-model-test").unwrap(), &first_trade_model));
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_trades() {
+        // The trade models are shared by all the tests, so set a limit of zero to force a rejection.
+        let musig = musig().with_config(Config { max_concurrent_trades: 0, ..Config::default() });
+        let request = init_trade_request("max-concurrent-trades-test", Role::SellerAsMaker);
+        let status = musig.init_trade(request).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::ResourceExhausted, "too many concurrent trades"));
+        assert!(TRADE_MODELS.get_trade_model("max-concurrent-trades-test").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_init_trade_paused_during_fee_spike() {
+        let guard = Arc::new(FeeAnomalyGuard::new(10, 10));
+        guard.record(FeeRate::from_sat_per_vb_u32(2));
+        guard.record(FeeRate::from_sat_per_vb_u32(21));
+        let musig = musig().with_fee_anomaly_guard(guard.clone());
+        let request = || init_trade_request("fee-spike-test", Role::SellerAsMaker);
+        let status = musig.init_trade(request()).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::Unavailable, "high fee environment, try later"));
+        assert!(TRADE_MODELS.get_trade_model("fee-spike-test").is_none());
+
+        guard.record(FeeRate::from_sat_per_vb_u32(3));
+        musig.init_trade(request()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_trade_after_deposit_published() {
+        init_trade("cancel-published-trade-test").await;
+        TRADE_MODELS.get_trade_model("cancel-published-trade-test").unwrap()
+            .lock().await.advance_state(TradeState::DepositTxPublished);
+
+        let status = musig().cancel_trade(cancel_trade_request("cancel-published-trade-test"))
+            .await.unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert!(TRADE_MODELS.get_trade_model("cancel-published-trade-test").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_trade_amount_limits() {
+        let musig = musig().with_config(Config {
+            min_trade_amount: Amount::from_sat(10_000),
+            max_trade_amount: Amount::from_sat(1_000_000),
+            ..Config::default()
+        });
+        let request = init_trade_request("trade-amount-limits-test", Role::SellerAsMaker);
+        let response = musig.init_trade(request).await.unwrap().into_inner();
+        assert_eq!((response.min_trade_amount, response.max_trade_amount), (10_000, 1_000_000));
+
+        for (trade_amount, message) in [
+            (500, "trade amount 500 sats is below minimum 10000 sats"),
+            (2_000_000, "trade amount 2000000 sats is above maximum 1000000 sats"),
+        ] {
+            let request = NonceSharesRequest {
+                trade_id: "trade-amount-limits-test".to_owned(),
+                trade_amount,
+                sequence_number: 1,
+                ..Default::default()
+            };
+            let status = musig.get_nonce_shares(Request::new(request)).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+            assert_eq!(status.message(), message);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protocol_version_negotiation() {
+        let musig = musig();
+        let init_trade = |trade_id: &str, protocol_version| {
+            let mut request = init_trade_request(trade_id, Role::SellerAsMaker);
+            request.get_mut().protocol_version = protocol_version;
+            musig.init_trade(request)
+        };
+
+        let status = init_trade("old-client-version-test", MIN_SUPPORTED_VERSION - 1).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unimplemented);
+        assert!(TRADE_MODELS.get_trade_model("old-client-version-test").is_none());
+
+        let response = init_trade("new-client-version-test", MAX_SUPPORTED_VERSION + 1).await.unwrap();
+        assert_eq!(response.into_inner().protocol_version, MAX_SUPPORTED_VERSION);
+        let trade_model = TRADE_MODELS.get_trade_model("new-client-version-test").unwrap();
+        assert_eq!(trade_model.lock().await.protocol_version(), MAX_SUPPORTED_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_bounded_drop_stream_drops_oldest() {
+        // Signal once the forwarder has sent every item, having run ahead of the (as yet idle)
+        // consumer and overfilled the buffer.
+        let (exhausted_tx, exhausted_rx) = oneshot::channel();
+        let inner = stream::iter(0..100)
+            .chain(stream::once(async { exhausted_tx.send(()).unwrap() }).filter_map(|()| future::ready(None)));
+        let stream = BoundedDropStream::new(inner, 4, Txid::all_zeros());
+        exhausted_rx.await.unwrap();
+        assert_eq!(stream.collect::<Vec<_>>().await, [96, 97, 98, 99]);
+
+        let stream = BoundedDropStream::new(stream::iter(0..3), 4, Txid::all_zeros());
+        assert_eq!(stream.collect::<Vec<_>>().await, [0, 1, 2]);
+    }
+
+    fn broadcast_tx_request(raw_tx: Vec<u8>) -> Request<BroadcastTxRequest> {
+        Request::new(BroadcastTxRequest { raw_tx, ..Default::default() })
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_tx_bad_raw_tx() {
+        let wallet = WalletImpl::new(Arc::new(WalletServiceImpl::new()));
+
+        let status = wallet.broadcast_tx(broadcast_tx_request(vec![0x02, 0x00])).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert!(status.message().starts_with("could not decode transaction"), "{status:?}");
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_tx_not_connected() {
+        let wallet = WalletImpl::new(Arc::new(WalletServiceImpl::new()));
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![],
+        };
+
+        let status = wallet.broadcast_tx(broadcast_tx_request(consensus::serialize(&tx))).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+    }
+
+    //noinspection SpellCheckingInspection
+    #[tokio::test]
+    async fn test_verify_address() {
+        let wallet = WalletImpl::new(Arc::new(WalletServiceImpl::new()));
+        let verify_address = |address: &str| wallet.verify_address(Request::new(VerifyAddressRequest {
+            address: address.to_owned(),
+            ..Default::default()
+        }));
+
+        let response = verify_address("bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz")
+            .await.unwrap().into_inner();
+        assert_eq!(response, VerifyAddressResponse { is_valid: true, network: "regtest".to_owned() });
+
+        let status = verify_address("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "address is not valid on the wallet's network (regtest)");
+
+        let status = verify_address("not-an-address").await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert!(status.message().starts_with("could not parse address"), "{status:?}");
+    }
+
+    //noinspection SpellCheckingInspection
+    const TRADER_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdrjwWCyXqqJ4YqcyG4DmKtjjsRt29v1PtD3r3PuFJAjWytzcvSTKnZAGAkPSmnrdnu\
+        HWxCAwy3i1iPhrtKAfXRH7dVCNGp6/86'/1'/1'/0/*)";
+
+    #[tokio::test]
+    async fn test_create_wallet() {
+        let wallet = WalletImpl::new(Arc::new(WalletServiceImpl::new()));
+        let status = wallet.create_wallet(Request::new(CreateWalletRequest {
+            wallet_id: "trader-1".to_owned(),
+            descriptor: TRADER_DESCRIPTOR.to_owned(),
+        })).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unimplemented);
+
+        let wallet = WalletImpl::from_wallet_manager(Arc::new(WalletManager::new(wallet.wallet_service)));
+        let create_wallet = |wallet_id: &str, descriptor: &str| wallet.create_wallet(Request::new(CreateWalletRequest {
+            wallet_id: wallet_id.to_owned(),
+            descriptor: descriptor.to_owned(),
+        }));
+        let new_address = |wallet_id: &str| wallet.new_address(Request::new(NewAddressRequest {
+            wallet_id: wallet_id.to_owned(),
+        }));
+
+        let status = new_address("trader-1").await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "unknown wallet id: trader-1");
+
+        create_wallet("trader-1", TRADER_DESCRIPTOR).await.unwrap();
+        let address = new_address("trader-1").await.unwrap().into_inner().address;
+        let default_address = new_address("").await.unwrap().into_inner().address;
+        assert_ne!(address, default_address);
+
+        let status = create_wallet("trader-1", TRADER_DESCRIPTOR).await.unwrap_err();
+        assert_eq!(status.code(), Code::AlreadyExists);
+        let status = create_wallet("trader-2", "tr(not-a-key)").await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let status = create_wallet("", TRADER_DESCRIPTOR).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_trade_wallet_selection() {
+        let wallet_manager = Arc::new(WalletManager::new(Arc::new(WalletServiceImpl::new())));
+        let musig = musig().with_wallet_manager(wallet_manager.clone());
+        let request = || {
+            let mut request = init_trade_request("trade-wallet-test", Role::SellerAsMaker);
+            request.get_mut().wallet_id = "trader-1".to_owned();
+            request
+        };
+
+        // A trade can't be started with an unknown wallet...
+        let status = musig.init_trade(request()).await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+        assert!(TRADE_MODELS.get_trade_model("trade-wallet-test").is_none());
+
+        // ...but once created, the trade is bound to it.
+        wallet_manager.create_wallet("trader-1", TRADER_DESCRIPTOR).unwrap();
+        musig.init_trade(request()).await.unwrap();
+        let trade_model = TRADE_MODELS.get_trade_model("trade-wallet-test").unwrap();
+        assert_eq!(trade_model.lock().await.wallet_id(), "trader-1");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_tx_confirmation_status_before_deposit_tx() {
+        init_trade("subscribe-early-test").await;
+
+        let request = SubscribeTxConfirmationStatusRequest {
+            trade_id: "subscribe-early-test".to_owned(),
+            sequence_number: 1,
+            resume_deposit_monitoring: false,
+        };
+        let Err(status) = musig().subscribe_tx_confirmation_status(Request::new(request)).await else {
+            panic!("expected subscription to fail before the deposit tx is built");
+        };
+        assert_eq!(status.code(), Code::FailedPrecondition);
+    }
+
+    fn set_log_level_request(remote_addr: &str, module: &str, level: &str) -> Request<SetLogLevelRequest> {
+        let mut request = Request::new(SetLogLevelRequest { module: module.to_owned(), level: level.to_owned() });
+        request.extensions_mut().insert(TcpConnectInfo {
+            local_addr: Some("127.0.0.1:50051".parse().unwrap()),
+            remote_addr: Some(remote_addr.parse().unwrap()),
+        });
+        request
+    }
+
+    #[tokio::test]
+    async fn test_set_log_level() {
+        // Log to a file, rather than interleaving log lines with the output of the other tests.
+        let log_dir = tempfile::tempdir().unwrap();
+        bmp_tracing::init_with_config("warn", LogConfig::File(log_dir.path().join("rpc-set-log-level-test.log")));
+
+        let request = set_log_level_request("192.168.0.2:40000", "rpc::server", "debug");
+        let status = AdminImpl.set_log_level(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        let request = set_log_level_request("127.0.0.1:40000", "rpc::server", "verbose");
+        let status = AdminImpl.set_log_level(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let request = set_log_level_request("127.0.0.1:40000", "rpc::server[span]", "debug");
+        let status = AdminImpl.set_log_level(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let request = set_log_level_request("[::1]:40000", "rpc::server", "DEBUG");
+        AdminImpl.set_log_level(request).await.unwrap();
+        assert!(tracing::enabled!(target: "rpc::server", Level::DEBUG));
+        assert!(!tracing::enabled!(target: "rpc::wallet", Level::DEBUG));
+    }
+}
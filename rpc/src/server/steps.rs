@@ -0,0 +1,223 @@
+//! The steps of the trade protocol run by the `Musig` service on each request, as functions of the
+//! request & the trade model alone, so that the language bindings (which have no gRPC server, wallet
+//! or trade model store) drive a trade through exactly the same logic as the daemon. The service
+//! wraps each of them with the trade model lookup, sequence numbering, auditing & stats.
+
+use bdk_wallet::bitcoin::hashes::Hash as _;
+use bdk_wallet::bitcoin::{Amount, FeeRate, Txid, consensus};
+use tonic::{Result, Status};
+use tracing::info;
+
+use super::{Config, MusigError, TraderRole, negotiate_protocol_version, require_role, validate_trade_id};
+use crate::pb::convert::{CheckInSignedRange as _, TryProtoInto};
+use crate::pb::musigrpc::{
+    CloseTradeRequest, CloseTradeResponse, DepositPsbt, DepositTxSignatureRequest, NonceSharesMessage,
+    NonceSharesRequest, PartialSignaturesMessage, PartialSignaturesRequest, PubKeySharesRequest, PubKeySharesResponse,
+    SwapTxSignatureRequest, SwapTxSignatureResponse,
+};
+use crate::protocol::{ExchangedKeys, TradeModel, TradeState};
+use crate::validation::{validate_fee_rate, validate_security_deposits};
+
+/// Start a new trade in the requested role, generating my key shares & the nonces to come.
+pub fn init_trade(request: PubKeySharesRequest) -> Result<TradeModel> {
+    validate_trade_id(&request.trade_id)?;
+    let my_role = request.my_role.try_proto_into()?;
+    let protocol_version = negotiate_protocol_version(request.protocol_version)?;
+    let mut trade_model = TradeModel::new(request.trade_id, my_role);
+    trade_model.set_last_sequence_number(request.sequence_number);
+    trade_model.set_protocol_version(protocol_version);
+    trade_model.set_wallet_id(request.wallet_id);
+    trade_model.init_my_key_shares()?;
+    trade_model.pre_generate_nonces()?;
+    Ok(trade_model)
+}
+
+pub fn pub_key_shares_response(config: &Config, trade_model: &TradeModel) -> Result<PubKeySharesResponse> {
+    let my_key_shares = trade_model.get_my_key_shares()
+        .ok_or(MusigError::MissingTradeData("key shares"))?;
+    Ok(PubKeySharesResponse {
+        buyer_output_pub_key_share: my_key_shares.buyer_payout.serialize().into(),
+        seller_output_pub_key_share: my_key_shares.seller_payout.serialize().into(),
+        multisig_script_key: my_key_shares.multisig_script.serialize().into(),
+        current_block_height: 900_000,
+        min_trade_amount: config.min_trade_amount.to_sat(),
+        max_trade_amount: config.max_trade_amount.to_sat(),
+        protocol_version: trade_model.protocol_version(),
+    })
+}
+
+pub fn get_nonce_shares(config: &Config, request: NonceSharesRequest, trade_model: &mut TradeModel) -> Result<NonceSharesMessage> {
+    // Validate all the amounts before setting any, as the trade model's amounts are write-once,
+    // so that a corrected request may be retried after a rejected one:
+    let trade_amount = Amount::from_sat(request.trade_amount.check_in_signed_range()?);
+    config.check_trade_amount(trade_amount)?;
+    let buyers_security_deposit = Amount::from_sat(request.buyers_security_deposit.check_in_signed_range()?);
+    let sellers_security_deposit = Amount::from_sat(request.sellers_security_deposit.check_in_signed_range()?);
+    validate_security_deposits(trade_amount, buyers_security_deposit, sellers_security_deposit,
+        config.min_security_deposit_ratio)?;
+    let deposit_tx_fee_rate = FeeRate::from_sat_per_kwu(request.deposit_tx_fee_rate.check_in_signed_range()?);
+    let prepared_tx_fee_rate = FeeRate::from_sat_per_kwu(request.prepared_tx_fee_rate.check_in_signed_range()?);
+
+    trade_model.set_peer_key_shares(&ExchangedKeys {
+        buyer_payout: request.buyer_output_peers_pub_key_share.try_proto_into()?,
+        seller_payout: request.seller_output_peers_pub_key_share.try_proto_into()?,
+        multisig_script: request.peers_multisig_script_key.try_proto_into()?,
+    });
+    trade_model.aggregate_key_shares()?;
+    if let Some(adaptor_point) = request.adaptor_point.try_proto_into()? {
+        trade_model.init_adaptor_point(adaptor_point)?;
+    }
+    if let Some(adaptor_secret) = request.adaptor_secret.try_proto_into()? {
+        // The seller may only learn the secret from the other chain, to sign the swap tx.
+        require_role(trade_model, TraderRole::Buyer)?;
+        trade_model.set_adaptor_secret(adaptor_secret)?;
+    }
+    trade_model.set_trade_amount(trade_amount);
+    trade_model.set_buyers_security_deposit(buyers_security_deposit);
+    trade_model.set_sellers_security_deposit(sellers_security_deposit);
+    trade_model.set_deposit_tx_fee_rate(deposit_tx_fee_rate);
+    trade_model.set_prepared_tx_fee_rate(prepared_tx_fee_rate);
+    trade_model.set_trade_fee_receiver(request.trade_fee_receiver.try_proto_into()?)?;
+    trade_model.init_my_addresses()?;
+    trade_model.init_my_half_deposit_psbt()?;
+    trade_model.init_my_nonce_shares()?;
+    trade_model.advance_state(TradeState::NonceSharesExchanged);
+
+    let redirection_amount_msat = trade_model.redirection_amount_msat()?
+        .check_in_signed_range()?;
+    let my_addresses = trade_model.get_my_addresses()
+        .ok_or(MusigError::MissingTradeData("addresses"))?;
+    let my_half_deposit_psbt = trade_model.get_my_half_deposit_psbt()
+        .ok_or(MusigError::MissingTradeData("half deposit PSBT"))?;
+    let my_nonce_shares = trade_model.get_my_nonce_shares()
+        .ok_or(MusigError::MissingTradeData("nonce shares"))?;
+    let [buyers_net_amount, sellers_net_amount] = trade_model.estimate_net_amounts()?;
+
+    Ok(NonceSharesMessage {
+        half_deposit_psbt: my_half_deposit_psbt.serialize(),
+        redirection_amount_msat,
+        adaptor_point: trade_model.get_adaptor_point().map(|p| p.serialize().into()),
+        proposed_deposit_tx_fee_rate: Some(trade_model.deposit_tx_fee_rate()?.to_sat_per_kwu()),
+        proposed_prepared_tx_fee_rate: Some(trade_model.prepared_tx_fee_rate()?.to_sat_per_kwu()),
+        buyers_net_amount_sats: buyers_net_amount.to_sat(),
+        sellers_net_amount_sats: sellers_net_amount.to_sat(),
+        ..(my_addresses, my_nonce_shares).into()
+    })
+}
+
+pub fn get_partial_signatures(config: &Config, request: PartialSignaturesRequest, trade_model: &mut TradeModel) -> Result<PartialSignaturesMessage> {
+    if let Some(my_partial_signatures) = trade_model
+        .get_my_partial_signatures_on_peer_txs(request.buyer_ready_to_release) {
+        // Ignore receiver list and peer's nonce shares, as they have already been set
+        // (otherwise we wouldn't already have the partial signatures on the peer's txs).
+        let my_partial_signatures: PartialSignaturesMessage = my_partial_signatures.into();
+        // The buyer is only ready to release its swap tx signature once it has sent the payment.
+        if request.buyer_ready_to_release && trade_model.am_buyer() {
+            trade_model.mark_payment_sent();
+        }
+        return Ok(my_partial_signatures);
+    }
+    let peer_nonce_shares = request.peers_nonce_shares
+        .ok_or_else(|| Status::not_found("missing request.peers_nonce_shares"))?;
+    trade_model.check_peer_adaptor_point(peer_nonce_shares.adaptor_point.as_deref().try_proto_into()?)?;
+    // A peer not proposing fee rates can't be taken to agree with ours, so is rejected.
+    let peers_deposit_tx_fee_rate = peer_nonce_shares.proposed_deposit_tx_fee_rate
+        .ok_or_else(|| Status::invalid_argument("missing request.peers_nonce_shares.proposed_deposit_tx_fee_rate"))?;
+    let peers_prepared_tx_fee_rate = peer_nonce_shares.proposed_prepared_tx_fee_rate
+        .ok_or_else(|| Status::invalid_argument("missing request.peers_nonce_shares.proposed_prepared_tx_fee_rate"))?;
+    validate_fee_rate(trade_model.deposit_tx_fee_rate()?, FeeRate::from_sat_per_kwu(peers_deposit_tx_fee_rate),
+        config.fee_rate_tolerance_pct)?;
+    validate_fee_rate(trade_model.prepared_tx_fee_rate()?, FeeRate::from_sat_per_kwu(peers_prepared_tx_fee_rate),
+        config.fee_rate_tolerance_pct)?;
+    trade_model.set_peer_half_deposit_psbt((&peer_nonce_shares.half_deposit_psbt[..]).try_proto_into()?);
+    trade_model.compute_unsigned_deposit_tx()?;
+    trade_model.set_redirection_receivers(request.redirection_receivers.into_iter().map(TryProtoInto::try_proto_into))?;
+    trade_model.check_redirect_tx_params()?;
+    let (addresses, nonce_shares) = peer_nonce_shares.try_proto_into()?;
+    trade_model.set_peer_addresses(addresses)?;
+    trade_model.compute_unsigned_prepared_txs()?;
+    trade_model.set_peer_nonce_shares(nonce_shares);
+    trade_model.aggregate_nonce_shares()?;
+    trade_model.sign_partial()?;
+    trade_model.advance_state(TradeState::PartialSignaturesExchanged);
+    let my_partial_signatures = trade_model
+        .get_my_partial_signatures_on_peer_txs(request.buyer_ready_to_release)
+        .ok_or(MusigError::MissingTradeData("partial signatures"))?;
+
+    Ok(my_partial_signatures.into())
+}
+
+pub fn sign_deposit_tx(request: DepositTxSignatureRequest, trade_model: &mut TradeModel) -> Result<DepositPsbt> {
+    let peers_partial_signatures = request.peers_partial_signatures
+        .ok_or_else(|| Status::not_found("missing request.peers_partial_signatures"))?;
+    if trade_model.am_buyer() {
+        let sighash = peers_partial_signatures.swap_tx_input_sighash.as_ref()
+            .ok_or_else(|| Status::not_found("missing request.peers_partial_signatures.swap_tx_input_sighash"))?;
+        trade_model.sign_swap_tx_input_partial((&sighash[..]).try_proto_into()?)?;
+    }
+    trade_model.set_peer_partial_signatures_on_my_txs(&peers_partial_signatures.try_proto_into()?);
+    trade_model.aggregate_partial_signatures()?;
+    trade_model.compute_my_signed_prepared_txs()?;
+    trade_model.sign_deposit_psbt()?;
+    trade_model.advance_state(TradeState::DepositTxSigned);
+    let deposit_psbt = trade_model.get_deposit_psbt()
+        .ok_or(MusigError::MissingTradeData("deposit PSBT"))?;
+
+    Ok(DepositPsbt { deposit_psbt: deposit_psbt.serialize(), psbt_version: 0 })
+}
+
+pub fn sign_swap_tx(request: SwapTxSignatureRequest, trade_model: &mut TradeModel) -> Result<SwapTxSignatureResponse> {
+    require_role(trade_model, TraderRole::Seller)?;
+    let swap_tx = if let Some(swap_tx) = trade_model.get_signed_swap_tx() { swap_tx } else {
+        if let Some(adaptor_secret) = request.adaptor_secret.try_proto_into()? {
+            trade_model.set_adaptor_secret(adaptor_secret)?;
+        }
+        trade_model.set_swap_tx_input_peers_partial_signature(
+            request.swap_tx_input_peers_partial_signature.try_proto_into()?);
+        trade_model.aggregate_swap_tx_partial_signatures()?;
+        trade_model.compute_signed_swap_tx()?;
+        trade_model.advance_state(TradeState::SwapTxSigned);
+        trade_model.get_signed_swap_tx()
+            .ok_or(MusigError::MissingTradeData("signed swap tx"))?
+    };
+    let prv_key_share = trade_model.get_my_private_key_share_for_peer_output()
+        .ok_or(MusigError::MissingTradeData("private key share"))?;
+
+    if !request.seller_ready_to_release {
+        return Ok(SwapTxSignatureResponse::default());
+    }
+    Ok(SwapTxSignatureResponse {
+        swap_tx: consensus::serialize(swap_tx),
+        peer_output_prv_key_share: prv_key_share.serialize().into(),
+    })
+}
+
+/// Close the trade, given the peer's private key share for my payout output, or (for the buyer)
+/// the signed swap tx to recover it from, or else the txid of the swap tx just broadcast by the
+/// seller to force-close the trade, releasing my private key share for the peer's payout output.
+pub fn close_trade(request: CloseTradeRequest, trade_model: &mut TradeModel, swap_txid: Option<Txid>) -> Result<CloseTradeResponse> {
+    if let Some(peer_prv_key_share) = request.my_output_peers_prv_key_share.try_proto_into()? {
+        // Trader receives the private key share from a cooperative peer, closing our trade.
+        trade_model.set_peer_private_key_share_for_my_output(peer_prv_key_share)?;
+        trade_model.aggregate_private_keys_for_my_output()?;
+    } else if let Some(swap_tx) = request.swap_tx.try_proto_into()? {
+        // Buyer supplies a signed swap tx to the Rust server, to close our trade. (Mainly for
+        // testing -- normally the tx would be picked up from the bitcoin network by the server.)
+        require_role(trade_model, TraderRole::Buyer)?;
+        trade_model.recover_seller_private_key_share_for_buyer_output(&swap_tx)?;
+        trade_model.aggregate_private_keys_for_my_output()?;
+    } else {
+        let txid = swap_txid.ok_or(MusigError::MissingTradeData("swap txid"))?;
+        info!(trade_id = request.trade_id, %txid, "Broadcast swap tx to force-close trade.");
+    }
+    let my_prv_key_share = trade_model.get_my_private_key_share_for_peer_output()
+        .ok_or(MusigError::MissingTradeData("private key share"))?;
+    // A force-closed trade stays open until the swap tx confirms, after which the buyer may
+    // recover the seller's private key share for the buyer's payout output from it.
+    trade_model.advance_state(if swap_txid.is_some() { TradeState::ForceClosing } else { TradeState::Closed });
+
+    Ok(CloseTradeResponse {
+        peer_output_prv_key_share: my_prv_key_share.serialize().into(),
+        swap_tx_id: swap_txid.map_or_else(Vec::new, |txid| txid.to_byte_array().into()),
+    })
+}
@@ -0,0 +1,116 @@
+//! Simplified payment verification of confirmed txs, checking a tx's inclusion in a block against
+//! the Merkle root committed to in its header, independently of the backend that reported it.
+
+use bdk_wallet::bitcoin::block::Header;
+use bdk_wallet::bitcoin::hashes::{Hash as _, HashEngine as _, sha256d};
+use bdk_wallet::bitcoin::{TxMerkleNode, Txid};
+
+/// The evidence that a tx is included in a block: the block's header, together with the sibling
+/// hashes along the path from the tx to the Merkle root, bottom up, and the tx's position in the block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MerkleProof {
+    pub block_header: Header,
+    pub merkle_path: Vec<Txid>,
+    pub tx_index: u32,
+}
+
+impl MerkleProof {
+    /// Build the proof of inclusion of the tx at `tx_index`, given the txids of the whole block in
+    /// order, or `None` if the index is out of range.
+    pub fn from_block_txids(block_header: Header, txids: &[Txid], tx_index: u32) -> Option<Self> {
+        let mut index = usize::try_from(tx_index).ok()?;
+        if index >= txids.len() {
+            return None;
+        }
+        let mut level = txids.to_vec();
+        let mut merkle_path = Vec::new();
+        while level.len() > 1 {
+            if level.len() % 2 == 1 {
+                // An odd number of nodes is padded by duplicating the last, as in Bitcoin Core.
+                level.push(level[level.len() - 1]);
+            }
+            merkle_path.push(level[index ^ 1]);
+            level = level.chunks_exact(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+            index /= 2;
+        }
+        Some(Self { block_header, merkle_path, tx_index })
+    }
+
+    pub fn verify(&self, txid: &Txid) -> bool {
+        verify_merkle_inclusion(txid, &self.block_header, self.merkle_path.clone(), self.tx_index)
+    }
+}
+
+/// Checks that hashing `txid` up the given Merkle path, turning left or right at each level
+/// according to the bits of `tx_index`, arrives at the Merkle root of `block_header`.
+///
+/// NOTE: This does not guard against a 64-byte tx being passed off as an inner node of the tree,
+///  which the caller can rule out by checking that the tx in question is not 64 bytes long.
+pub fn verify_merkle_inclusion(txid: &Txid, block_header: &Header, merkle_path: Vec<Txid>, tx_index: u32) -> bool {
+    let depth = merkle_path.len();
+    // Any bits of the index beyond the depth of the path would go unchecked, so reject them.
+    if u32::try_from(depth).is_ok_and(|depth| depth < u32::BITS && tx_index >> depth != 0) {
+        return false;
+    }
+    let (root, _) = merkle_path.into_iter().fold((*txid, tx_index), |(hash, index), sibling| {
+        let parent = if index % 2 == 0 { hash_pair(&hash, &sibling) } else { hash_pair(&sibling, &hash) };
+        (parent, index / 2)
+    });
+    TxMerkleNode::from_byte_array(root.to_byte_array()) == block_header.merkle_root
+}
+
+fn hash_pair(left: &Txid, right: &Txid) -> Txid {
+    let mut engine = sha256d::Hash::engine();
+    engine.input(left.as_byte_array());
+    engine.input(right.as_byte_array());
+    Txid::from_raw_hash(sha256d::Hash::from_engine(engine))
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk_wallet::bitcoin::block::Version;
+    use bdk_wallet::bitcoin::{BlockHash, CompactTarget};
+
+    use super::*;
+
+    fn txids(n: u8) -> Vec<Txid> {
+        (0..n).map(|i| Txid::from_byte_array([i; 32])).collect()
+    }
+
+    fn header_with_root(txids: &[Txid]) -> Header {
+        // Bitcoin's own Merkle root computation serves as an independent reference.
+        let root = bdk_wallet::bitcoin::merkle_tree::calculate_root(txids.iter().copied()).unwrap();
+        Header {
+            version: Version::TWO,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::from_byte_array(root.to_byte_array()),
+            time: 0,
+            bits: CompactTarget::from_consensus(0),
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn test_verify_merkle_inclusion() {
+        for n in [1, 2, 5, 8] {
+            let txids = txids(n);
+            let header = header_with_root(&txids);
+            for (index, txid) in (0..).zip(&txids) {
+                let proof = MerkleProof::from_block_txids(header, &txids, index).unwrap();
+                assert!(proof.verify(txid), "tx {index} of {n}");
+                assert!(!proof.verify(&Txid::from_byte_array([0xff; 32])));
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_merkle_inclusion_wrong_index() {
+        let txids = txids(5);
+        let header = header_with_root(&txids);
+        let proof = MerkleProof::from_block_txids(header, &txids, 2).unwrap();
+
+        assert!(!verify_merkle_inclusion(&txids[2], &header, proof.merkle_path.clone(), 3));
+        assert!(!verify_merkle_inclusion(&txids[2], &header, proof.merkle_path, 2 + 8));
+        assert!(MerkleProof::from_block_txids(header, &txids, 5).is_none());
+    }
+}
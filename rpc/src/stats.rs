@@ -0,0 +1,136 @@
+//! Statistics aggregated over the trades handled since the daemon started, optionally exported as
+//! Prometheus gauges (with the `metrics` feature), along with the number of trades currently active.
+
+use bdk_wallet::bitcoin::Amount;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TradeStats {
+    /// The number of trades initiated.
+    pub total_trade_count: u64,
+    /// The sum of the trade amounts of the completed trades.
+    pub total_volume_sats: u64,
+    pub completed_count: u64,
+    /// The number of trades abandoned before the deposit tx was published.
+    pub failed_count: u64,
+    /// The mean time from initiation to close of the completed trades.
+    pub avg_duration_seconds: f64,
+}
+
+impl TradeStats {
+    pub(crate) const fn record_initiated(&mut self) {
+        self.total_trade_count += 1;
+    }
+
+    #[expect(clippy::cast_precision_loss, reason = "durations & counts are far below 2^52")]
+    pub(crate) fn record_completed(&mut self, trade_amount: Amount, duration_seconds: u64) {
+        self.total_volume_sats += trade_amount.to_sat();
+        self.completed_count += 1;
+        // Keep a running mean, so that the total duration needn't be stored as well.
+        self.avg_duration_seconds +=
+            (duration_seconds as f64 - self.avg_duration_seconds) / self.completed_count as f64;
+    }
+
+    pub(crate) const fn record_failed(&mut self) {
+        self.failed_count += 1;
+    }
+
+    /// Set the Prometheus gauges of the default registry to these stats.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn export(&self) {
+        metrics::GAUGES.set(self);
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub mod metrics {
+    use std::sync::LazyLock;
+
+    use axum::Router;
+    use axum::routing::get;
+    use prometheus::{
+        Gauge, IntCounter, IntGauge, TextEncoder, register_gauge, register_int_counter, register_int_gauge,
+    };
+
+    use super::TradeStats;
+
+    pub(super) static GAUGES: LazyLock<TradeStatsGauges> = LazyLock::new(TradeStatsGauges::register);
+
+    /// The number of trade models currently held by the daemon.
+    pub(crate) static ACTIVE_TRADE_COUNT: LazyLock<IntGauge> = LazyLock::new(|| register_int_gauge!(
+        "musig_active_trades", "Number of trades currently held by the daemon").unwrap());
+
+    /// The number of trades turned away for exceeding the concurrent trade limit.
+    pub(crate) static REJECTED_TRADE_COUNT: LazyLock<IntCounter> = LazyLock::new(|| register_int_counter!(
+        "musig_trades_rejected_total", "Number of trades rejected for exceeding the concurrent trade limit").unwrap());
+
+    pub(super) struct TradeStatsGauges {
+        total_trade_count: IntGauge,
+        total_volume_sats: IntGauge,
+        completed_count: IntGauge,
+        failed_count: IntGauge,
+        avg_duration_seconds: Gauge,
+    }
+
+    impl TradeStatsGauges {
+        fn register() -> Self {
+            Self {
+                total_trade_count: register_int_gauge!(
+                    "musig_trades_total", "Number of trades initiated").unwrap(),
+                total_volume_sats: register_int_gauge!(
+                    "musig_trade_volume_sats", "Sum of the trade amounts of the completed trades").unwrap(),
+                completed_count: register_int_gauge!(
+                    "musig_trades_completed", "Number of trades completed").unwrap(),
+                failed_count: register_int_gauge!(
+                    "musig_trades_failed", "Number of trades abandoned before the deposit tx was published").unwrap(),
+                avg_duration_seconds: register_gauge!(
+                    "musig_trade_avg_duration_seconds", "Mean time from initiation to close of the completed trades").unwrap(),
+            }
+        }
+
+        pub(super) fn set(&self, stats: &TradeStats) {
+            let to_i64 = |n: u64| i64::try_from(n).unwrap_or(i64::MAX);
+            self.total_trade_count.set(to_i64(stats.total_trade_count));
+            self.total_volume_sats.set(to_i64(stats.total_volume_sats));
+            self.completed_count.set(to_i64(stats.completed_count));
+            self.failed_count.set(to_i64(stats.failed_count));
+            self.avg_duration_seconds.set(stats.avg_duration_seconds);
+        }
+    }
+
+    /// An HTTP router serving the metrics of the default Prometheus registry, in the text
+    /// exposition format, from the path `/metrics`.
+    pub fn metrics_router() -> Router {
+        // Register the trade stats gauges & counters up front, so that they are scraped (as zero) before any
+        // trade has been initiated.
+        LazyLock::force(&GAUGES);
+        LazyLock::force(&ACTIVE_TRADE_COUNT);
+        LazyLock::force(&REJECTED_TRADE_COUNT);
+        Router::new().route("/metrics", get(|| async {
+            TextEncoder::new().encode_to_string(&prometheus::gather()).unwrap_or_default()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_completed() {
+        let mut stats = TradeStats::default();
+        stats.record_initiated();
+        stats.record_initiated();
+        stats.record_initiated();
+        stats.record_completed(Amount::from_sat(200_000), 600);
+        stats.record_completed(Amount::from_sat(100_000), 1_200);
+        stats.record_failed();
+
+        assert_eq!(stats, TradeStats {
+            total_trade_count: 3,
+            total_volume_sats: 300_000,
+            completed_count: 2,
+            failed_count: 1,
+            avg_duration_seconds: 900.0,
+        });
+    }
+}
@@ -0,0 +1,81 @@
+//! The rules that the txs we build are held to, by both the wallet and the trade protocol: the
+//! options each is built with and the standard relay size limit.
+
+use bdk_wallet::bitcoin::{absolute, Transaction};
+use thiserror::Error;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// Options for building the txs made by [`WalletService::send`].
+///
+/// [`WalletService::send`]: crate::wallet::WalletService::send
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SendOptions {
+    lock_time: Option<absolute::LockTime>,
+    rbf: bool,
+    coin_age_priority: bool,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self { Self::new() }
+}
+
+impl SendOptions {
+    pub const fn new() -> Self { Self { lock_time: None, rbf: true, coin_age_priority: false } }
+
+    #[must_use]
+    pub const fn with_locktime(self, lock_time: absolute::LockTime) -> Self {
+        Self { lock_time: Some(lock_time), ..self }
+    }
+
+    /// Whether every input should signal opt-in RBF (BIP125), which is the default.
+    #[must_use]
+    pub const fn with_rbf(self, rbf: bool) -> Self { Self { rbf, ..self } }
+
+    /// Whether to select coins with [`CoinAgePrioritySelector`], spending those of greatest coin
+    /// age (value times confirmations) first, instead of by branch and bound, which is the default.
+    ///
+    /// [`CoinAgePrioritySelector`]: wallet::coin_selection::CoinAgePrioritySelector
+    #[must_use]
+    pub const fn with_coin_age_priority(self, coin_age_priority: bool) -> Self {
+        Self { coin_age_priority, ..self }
+    }
+
+    pub const fn lock_time(self) -> Option<absolute::LockTime> { self.lock_time }
+
+    pub const fn rbf(self) -> bool { self.rbf }
+
+    pub const fn coin_age_priority(self) -> bool { self.coin_age_priority }
+
+    /// Whether the given tx was built in accordance with these options.
+    pub fn is_respected_by(self, tx: &Transaction) -> bool {
+        self.lock_time.is_none_or(|lock_time| lock_time == tx.lock_time)
+            && tx.input.iter().all(|input| input.sequence.is_rbf() == self.rbf)
+    }
+}
+
+/// The largest tx that nodes relay under the standard policy, in virtual bytes.
+pub const MAX_STANDARD_TX_VSIZE: usize = 100_000;
+
+/// Check that the given tx is within the standard relay size limit, to catch the accidental
+/// construction of a bloated tx (say one spending a great many dust UTXOs) before it is signed.
+/// Note that witness data, absent before signing, is not counted towards the size.
+pub fn validate_tx_size(tx: &Transaction) -> Result<(), TxSizeError> {
+    let vsize = tx.vsize();
+    if vsize > MAX_STANDARD_TX_VSIZE {
+        return Err(TxSizeError::ExceedsMaxStandardSize { vsize });
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Error)]
+#[non_exhaustive]
+pub enum TxSizeError {
+    #[error("tx vsize {vsize} exceeds the standard maximum of {MAX_STANDARD_TX_VSIZE} vbytes")]
+    ExceedsMaxStandardSize { vsize: usize },
+}
+
+/// The current Unix time in seconds, against which time-based lock times are checked. (This reads
+/// the JavaScript clock in WASM builds, where the system clock is unavailable.)
+pub(crate) fn unix_time_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,70 @@
+//! Validation of the trade parameters sent by the client, against the limits the daemon enforces.
+
+use bdk_wallet::bitcoin::{Amount, Denomination, FeeRate};
+use tonic::Status;
+
+/// Check that the buyer's and seller's security deposits are each at least `min_ratio` of the trade
+/// amount, so that neither trader can cheaply walk away from the trade.
+pub fn validate_security_deposits(
+    trade_amount: Amount,
+    buyers_sd: Amount,
+    sellers_sd: Amount,
+    min_ratio: f64,
+) -> Result<(), Status> {
+    let min_security_deposit = trade_amount.to_float_in(Denomination::Satoshi) * min_ratio;
+    for (trader, security_deposit) in [("buyer", buyers_sd), ("seller", sellers_sd)] {
+        if security_deposit.to_float_in(Denomination::Satoshi) < min_security_deposit {
+            return Err(Status::invalid_argument(format!(
+                "{trader}'s security deposit {} sats is below the minimum ratio {min_ratio} of the \
+                trade amount {} sats", security_deposit.to_sat(), trade_amount.to_sat())));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the fee rate proposed by the peer is within `tolerance_pct` percent of our own, so
+/// that both traders agree on the fees of the txs they sign.
+pub fn validate_fee_rate(my_fee_rate: FeeRate, peers_fee_rate: FeeRate, tolerance_pct: u8) -> Result<(), Status> {
+    let (mine, peers) = (my_fee_rate.to_sat_per_kwu(), peers_fee_rate.to_sat_per_kwu());
+    if u128::from(mine.abs_diff(peers)) * 100 > u128::from(tolerance_pct) * u128::from(mine) {
+        return Err(Status::failed_precondition("fee rate mismatch"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Code;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_security_deposits() {
+        let sats = Amount::from_sat;
+        validate_security_deposits(sats(200_000), sats(30_000), sats(30_000), 0.15).unwrap();
+        validate_security_deposits(sats(200_000), sats(40_000), sats(100_000), 0.15).unwrap();
+        validate_security_deposits(sats(200_000), Amount::ZERO, Amount::ZERO, 0.0).unwrap();
+
+        let status = validate_security_deposits(sats(200_000), sats(29_999), sats(30_000), 0.15).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(),
+            "buyer's security deposit 29999 sats is below the minimum ratio 0.15 of the trade amount 200000 sats");
+
+        let status = validate_security_deposits(sats(200_000), sats(30_000), sats(20_000), 0.15).unwrap_err();
+        assert_eq!(status.message(),
+            "seller's security deposit 20000 sats is below the minimum ratio 0.15 of the trade amount 200000 sats");
+    }
+
+    #[test]
+    fn test_validate_fee_rate() {
+        let sat_per_kwu = FeeRate::from_sat_per_kwu;
+        validate_fee_rate(sat_per_kwu(2500), sat_per_kwu(2500), 0).unwrap();
+        validate_fee_rate(sat_per_kwu(2500), sat_per_kwu(2000), 20).unwrap();
+        validate_fee_rate(sat_per_kwu(2500), sat_per_kwu(3000), 20).unwrap();
+
+        let status = validate_fee_rate(sat_per_kwu(2500), sat_per_kwu(3001), 20).unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(status.message(), "fee rate mismatch");
+        validate_fee_rate(sat_per_kwu(2500), sat_per_kwu(1999), 20).unwrap_err();
+    }
+}
@@ -0,0 +1,64 @@
+//! Verification of the signed statements a trader may hand to the peer or to an arbitrator, such
+//! as the buyer's proof of payment.
+
+use bdk_wallet::bitcoin::hashes::{Hash as _, HashEngine as _, sha256};
+use bdk_wallet::bitcoin::secp256k1::PublicKey;
+use musig2::secp::Point;
+
+/// The message signed by the buyer's proof of payment, binding the payment reference to the trade.
+pub fn proof_of_payment_message(trade_id: &str, payment_tx_reference: &str) -> String {
+    format!("bisq-trade-payment:{trade_id}:{payment_tx_reference}")
+}
+
+/// The message signed by a trade receipt: the tagged hash of its JSON, so that the signature can't
+/// be passed off as one over anything else signed with the same key share.
+pub fn trade_receipt_message(receipt_json: &str) -> [u8; 32] {
+    tagged_hash(b"bisq-trade-receipt", receipt_json.as_bytes())
+}
+
+/// The message signed by dispute evidence: the tagged hash of its JSON, with a tag distinct from
+/// that of trade receipts.
+pub fn dispute_evidence_message(evidence_json: &[u8]) -> [u8; 32] {
+    tagged_hash(b"bisq-dispute-evidence", evidence_json)
+}
+
+/// The BIP 340 style tagged hash of the message.
+fn tagged_hash(tag: &[u8], message: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine.input(message);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Check a proof of payment made by `TradeModel::generate_proof_of_payment`, a BIP340 signature
+/// over the `proof_of_payment_message`. Note that the proof is signed by the buyer's key share for
+/// its payout output, rather than the aggregated key, so `pub_key` must be that key share, as sent
+/// by the buyer in its `PubKeySharesResponse`.
+pub fn verify_proof_of_payment(pub_key: &PublicKey, proof: &[u8], trade_id: &str, reference: &str) -> bool {
+    let Ok(pub_key) = Point::try_from(&pub_key.serialize()[..]) else {
+        return false;
+    };
+    musig2::verify_single(pub_key, proof, proof_of_payment_message(trade_id, reference)).is_ok()
+}
+
+/// Check the signature of a `TradeReceipt` made by `TradeModel::generate_receipt`, over the tagged
+/// hash of its JSON, as returned by `TradeReceipt::to_json`. The `pub_key` is the trader's key share
+/// for its own payout output.
+pub fn verify_trade_receipt(pub_key: &PublicKey, receipt_json: &str, signature: &[u8]) -> bool {
+    let Ok(pub_key) = Point::try_from(&pub_key.serialize()[..]) else {
+        return false;
+    };
+    musig2::verify_single(pub_key, signature, trade_receipt_message(receipt_json)).is_ok()
+}
+
+/// Check the signature of dispute evidence made by `TradeModel::sign_dispute_evidence`, over the
+/// tagged hash of the evidence JSON once decrypted by the arbitrator. The `pub_key` is the trader's key share for its
+/// own payout output.
+pub fn verify_dispute_evidence(pub_key: &PublicKey, evidence_json: &[u8], signature: &[u8]) -> bool {
+    let Ok(pub_key) = Point::try_from(&pub_key.serialize()[..]) else {
+        return false;
+    };
+    musig2::verify_single(pub_key, signature, dispute_evidence_message(evidence_json)).is_ok()
+}
@@ -1,21 +1,61 @@
 #![cfg_attr(feature = "unimock", expect(clippy::ignored_unit_patterns, reason = "macro-generated code"))]
 
-use std::sync::{Arc, Mutex, RwLock};
+use std::collections::HashMap;
+use std::fmt;
+use std::future;
+use std::io;
+use std::net::SocketAddr;
+use std::ops::{Deref, DerefMut, Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
 
+use aes_gcm::aead::{Aead as _, KeyInit as _};
+use aes_gcm::Aes256Gcm;
+use argon2::Argon2;
 use bdk_bitcoind_rpc::Emitter;
-use bdk_bitcoind_rpc::bitcoincore_rpc::{Client, RpcApi as _};
-use bdk_wallet::bitcoin::{Network, Transaction, Txid};
+use bdk_bitcoind_rpc::bitcoincore_rpc::{Auth, Client, RpcApi as _};
+use bdk_electrum::BdkElectrumClient;
+use bdk_electrum::electrum_client::{self, ConfigBuilder, ElectrumApi as _, Socks5Config};
+use bdk_kyoto::bip157::Builder;
+use bdk_kyoto::{BuilderExt as _, Info, LoggingSubscribers, Receiver, RejectPayload, Requester, ScanType,
+    UnboundedReceiver, Warning};
+use chrono::{DateTime, SecondsFormat};
+use bdk_wallet::bitcoin::address::NetworkUnchecked;
+use bdk_wallet::bitcoin::bip32::{ChainCode, Xpriv};
+use bdk_wallet::bitcoin::psbt::ExtractTxError;
+use bdk_wallet::bitcoin::consensus;
+use bdk_wallet::bitcoin::hashes::Hash as _;
+use bdk_wallet::bitcoin::{Address, Amount, BlockHash, FeeRate, Network, OutPoint, Psbt, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Weight,
+    absolute};
 use bdk_wallet::chain::{ChainPosition, CheckPoint, ConfirmationBlockTime};
-use bdk_wallet::{AddressInfo, Balance, KeychainKind, LocalOutput, Wallet};
+use bdk_wallet::descriptor::IntoWalletDescriptor as _;
+use bdk_wallet::miniscript::descriptor::{DescriptorSecretKey, KeyMap};
+use bdk_wallet::rusqlite::{self, Connection};
+use bdk_wallet::signer::SignerError;
+use bdk_wallet::{AddressInfo, Balance, KeychainKind, LoadError, LocalOutput, SignOptions, TxBuilder, TxOrdering, Wallet, WalletPersister};
 use drop_stream::DropStreamExt as _;
 use futures_util::never::Never;
 use futures_util::stream::{BoxStream, StreamExt as _};
 use thiserror::Error;
+use tokio::runtime::Handle;
 use tokio::task::{self, JoinHandle};
 use tokio::time::{self, Duration, MissedTickBehavior};
-use tracing::{debug, error, info, trace};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, trace, warn};
+pub use wallet::protocol_wallet_api::ElectrumConfig;
+use wallet::coin_selection::CoinAgePrioritySelector;
+use wallet::electrum_tx_cache::ElectrumTxCache;
+use wallet::protocol_wallet_api::SignOptionsExt as _;
+use zeroize::Zeroizing;
+use zeromq::{Socket as _, SocketRecv as _, SubSocket, ZmqMessage};
 
+use crate::bip322;
+use crate::fee_estimator::FeeEstimator;
 use crate::observable::ObservableHashMap;
+use crate::silent_payments::SilentPaymentAddress;
+use crate::spv::MerkleProof;
+pub use crate::tx_policy::{MAX_STANDARD_TX_VSIZE, SendOptions, TxSizeError, validate_tx_size};
+pub(crate) use crate::tx_policy::unix_time_now;
 
 //noinspection SpellCheckingInspection
 const EXTERNAL_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdrjwWCyXqqJ4YqcyG4DmKtjjsRt29v1PtD3r3PuFJAj\
@@ -24,26 +64,133 @@ const EXTERNAL_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdrjwWCyXqqJ4YqcyG4DmKtjjsR
 const INTERNAL_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdrjwWCyXqqJ4YqcyG4DmKtjjsRt29v1PtD3r3PuFJAj\
     WytzcvSTKnZAGAkPSmnrdnuHWxCAwy3i1iPhrtKAfXRH7dVCNGp6/86'/1'/0'/1/*)#e3rjrmea";
 const BITCOIND_POLLING_PERIOD: Duration = Duration::from_secs(1);
+const BROADCAST_GRACE_PERIOD_BLOCKS: u32 = 3;
+const STUCK_TX_TIMEOUT: Duration = Duration::from_hours(1);
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_mins(1);
 
 #[cfg_attr(feature = "unimock", unimock::unimock(api = WalletServiceMock))]
 #[tonic::async_trait]
 pub trait WalletService {
+    /// Connect and continually sync the wallet until `shutdown` is cancelled, when it returns `Ok`.
+    ///
     /// # Errors
     /// Will return `Err` if connection or continual sync fails at any point
-    async fn connect(&self, rpc: Arc<Client>) -> Result<Never>;
+    async fn connect(&self, backend: WalletBackend, shutdown: CancellationToken) -> Result<()>;
 
     fn balance(&self) -> Balance;
-    fn reveal_next_address(&self) -> AddressInfo;
+    /// The height of the wallet's chain tip, as of its last sync.
+    fn block_height(&self) -> u32;
+    /// Reveal the next address for the given purpose, from the range of derivation indices that
+    /// the wallet's [`KeyRangeRegistry`] reserves for it.
+    fn reveal_next_address(&self, purpose: KeychainPurpose) -> Result<AddressInfo>;
     fn list_unspent(&self) -> Vec<LocalOutput>;
+    /// Look up the wallet's unspent output at the given outpoint, or `None` if there is no such
+    /// output or it is already spent.
+    fn get_utxo(&self, outpoint: OutPoint) -> Option<LocalOutput>;
+    /// Whether the tx is in the wallet's tx graph, say from having been broadcast through it.
+    fn contains_tx(&self, txid: Txid) -> bool;
+    /// The fee paid by the given tx in the wallet's tx graph, or `None` if the tx is missing or the
+    /// value of any of its inputs is unknown (as for inputs from outside the wallet).
+    fn get_tx_fee(&self, txid: Txid) -> Option<Amount>;
     fn get_tx_confidence_stream(&self, txid: Txid) -> BoxStream<'static, Option<TxConfidence>>;
 
+    /// Wait until the given tx has at least `required` confirmations, for callers that would rather
+    /// not consume the tx confidence stream themselves. Returns the number of confirmations reached.
+    ///
+    /// # Errors
+    /// Will return `Err` if the tx doesn't reach the required confirmations within `timeout`
+    async fn wait_for_confirmations(&self, txid: Txid, required: u32, timeout: Duration) -> Result<u32>;
+
+    /// Export the wallet's txs confirmed at heights within the given range as CSV, for accounting,
+    /// with columns `txid`, `block_height`, `confirmation_date`, `amount_sats` (the net change in
+    /// the wallet's balance), `fee_sats` (left empty for receives) and `type` (`send`, `receive` or
+    /// `internal`, the latter paying only to the wallet itself), in order of confirmation.
+    fn export_transactions_csv(&self, heights: RangeInclusive<u32>) -> Result<Vec<u8>>;
+
+    /// Build and sign a tx paying `amount` to `address`, then broadcast it if its lock time (if
+    /// any) is already satisfied. A tx that is not yet final is returned signed but unbroadcast.
+    fn send(&self, address: Address<NetworkUnchecked>, amount: Amount, options: SendOptions) -> Result<Transaction>;
+
+    /// Add an already signed tx to the wallet's tx graph as unconfirmed, and broadcast it through
+    /// the connected backend, so that its confidence may be tracked like that of the wallet's own.
+    fn broadcast_tx(&self, tx: Transaction) -> Result<Txid>;
+
+    /// Sign whichever inputs of an externally built PSBT (say from a hardware signer or air-gapped
+    /// setup) the wallet owns, finalizing them where possible. Returns whether the PSBT is now
+    /// finalized, so that its tx may be extracted and broadcast with [`Self::broadcast_tx`].
+    fn sign_psbt(&self, psbt: &mut Psbt) -> Result<bool>;
+
+    /// Record the outputs spent by the inputs of the given PSBT in the wallet's tx graph, so that
+    /// the fee of its tx may be computed even where the wallet owns only some of its inputs, as
+    /// with a deposit tx.
+    fn insert_prevouts(&self, psbt: &Psbt);
+
+    /// CPFP fee-bump the given tx if it is stuck, that is, if it has gone unconfirmed for longer than
+    /// the stuck tx timeout and its fee rate is below the current next-block fee rate estimate. The
+    /// child tx spends a wallet output of the stuck tx, paying enough to raise the fee rate of the
+    /// pair to the estimate. Returns the txid of the child tx broadcast, or `None` if the tx is not
+    /// stuck or has no wallet output to spend.
+    ///
+    /// # Errors
+    /// Will return `Err` if the wallet doesn't know all the prevouts of the tx, or the child tx
+    /// cannot be built, signed or broadcast (say if the output spent is too small to pay its fee)
+    async fn auto_accelerate(&self, txid: Txid) -> Result<Option<Txid>>;
+
+    /// Estimate the fee rate needed for a tx to confirm within `target_blocks` blocks, through the
+    /// connected backend, falling back to the external fee estimator (if any) where the backend has
+    /// no estimate (compact block filters don't give one). Returns `None` if neither has one.
+    async fn estimate_fee_rate(&self, target_blocks: u16) -> Result<Option<FeeRate>>;
+
+    /// Fetch the proof of inclusion of a tx in the block with the given hash, through the connected
+    /// backend, or `None` if the backend can't give one (compact block filters don't).
+    fn get_merkle_proof(&self, txid: Txid, block_hash: BlockHash) -> Result<Option<MerkleProof>>;
+
+    /// Parse an address given by the client, say one received from the trading peer, checking that
+    /// it is for the wallet's network.
+    fn verify_address(&self, address_str: &str) -> Result<Address>;
+
+    /// Sign a message with the key of the given wallet address, as proof of its ownership, returning
+    /// a base64 BIP 322 simple signature. Only the wallet's own (already revealed) taproot addresses
+    /// are supported.
+    async fn sign_message(&self, address: &Address, message: &str) -> Result<String>;
+
+    /// The network the wallet is on.
+    fn network(&self) -> Network;
+
+    /// Derive the wallet's silent payment (BIP 352) address, from its master private key. The wallet
+    /// doesn't yet scan for payments to the address, so doesn't track any funds sent to it.
+    fn derive_silent_payment_address(&self) -> Result<SilentPaymentAddress>;
+
+    /// Watch the txs paying to or spending from the given public descriptor, say the counterparty's
+    /// payout address, without controlling it. The descriptor is tracked apart from the wallet's own
+    /// keychains, scanned for from genesis on import, then synced along with the wallet. Only a
+    /// Bitcoin Core backend syncs watched descriptors. Importing one already watched is a no-op.
+    ///
+    /// # Errors
+    /// Will return `Err` if the descriptor is invalid or contains any private keys, or the initial
+    /// scan fails
+    fn import_watch_descriptor(&self, descriptor: &str) -> Result<()>;
+
+    /// Clear the wallet's private keys from memory, so that it cannot sign until unlocked.
+    ///
+    /// # Errors
+    /// Will return `Err` if the wallet has no passphrase set
+    fn lock(&self) -> Result<()>;
+
+    /// Re-derive the wallet's private keys, so that it may sign again, if the passphrase is correct.
+    ///
+    /// # Errors
+    /// Will return `Err` if the wallet has no passphrase set or the passphrase is incorrect
+    fn unlock(&self, passphrase: &str) -> Result<()>;
+
     /// # Panics
     /// Will panic if called outside the context of a Tokio runtime
-    fn spawn_connection(self: Arc<Self>, client: Arc<Client>) -> JoinHandle<Result<Never>>
+    fn spawn_connection(self: Arc<Self>, backend: WalletBackend, shutdown: CancellationToken) -> JoinHandle<Result<()>>
         where Self: Send + Sync + 'static
     {
         task::spawn(async move {
-            self.connect(client).await
+            self.connect(backend, shutdown).await
                 .inspect_err(|e| error!("Wallet connection error: {e}"))
         })
     }
@@ -51,13 +198,305 @@ pub trait WalletService {
 
 pub struct WalletServiceImpl {
     // NOTE: To avoid deadlocks, must be careful to acquire these locks in consistent order. At
-    //  present, the lock on 'wallet' is acquired first, then the lock on 'tx_confidence_map'.
+    //  present, the lock on 'wallet' is acquired first, then the lock on 'signing_key',
+    //  'key_ranges' or 'broadcast_txs', then the lock on 'tx_confidence_map'. The lock on 'db' is only
+    //  ever taken last, with that on 'wallet' held, to persist the wallet's changes or its reserved
+    //  key indices.
     // TODO: Consider using async locks here, as wallet operations have nontrivial cost:
     wallet: RwLock<Wallet>,
+    key_ranges: Mutex<KeyRangeRegistry>,
     tx_confidence_map: Mutex<ObservableHashMap<Txid, TxConfidence>>,
+    // Txs broadcast by this service, tracked until confirmed, to detect any that fail to propagate:
+    broadcast_txs: Mutex<HashMap<Txid, BroadcastTx>>,
+    // The means of reaching the network obtained in 'connect', kept for broadcasting txs:
+    broadcaster: RwLock<Option<Broadcaster>>,
+    // Wallets of the watch-only descriptors imported at runtime, kept apart from the wallet's own
+    // keychains, as a BDK wallet can't take more descriptors once created:
+    watched_wallets: RwLock<Vec<Wallet>>,
+    // The master key the wallet signs with, cleared while the wallet is locked:
+    signing_key: Arc<Mutex<Option<Xpriv>>>,
+    // The private descriptors of each keychain, from which the signing key is re-derived on unlock,
+    // held only encrypted under the passphrase (if one is set):
+    encrypted_descriptors: Option<EncryptedDescriptors>,
+    mode: WalletMode,
+    // The database that the wallet's changes are persisted to, if any:
+    db: Option<Mutex<Connection>>,
+    // The txs downloaded from Electrum, kept in the wallet's database (if it has one), so that they
+    // aren't all fetched again after a restart:
+    tx_cache: Option<Mutex<ElectrumTxCache>>,
 
     // Make the following RPC parameters configurable for testing:
     poll_period: Duration,
+    broadcast_grace_period: u32,
+    stuck_tx_timeout: Duration,
+    tor_proxy: Option<SocketAddr>,
+    max_retries: Option<u32>,
+    fee_estimator: Option<Arc<dyn FeeEstimator + Send + Sync>>,
+}
+
+/// The chain data source that [`WalletService::connect`] syncs the wallet from.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum WalletBackend {
+    BitcoindRpc(Arc<BitcoindRpcPool>),
+    /// Sync using compact block filters (BIP157/158) served by the P2P node at the given address,
+    /// downloading only the blocks whose filters match the wallet's scripts. Unlike with Electrum,
+    /// this does not reveal the wallet's addresses to the server.
+    CompactBlockFilters { url: String },
+    /// Sync from Bitcoin Core, as with [`WalletBackend::BitcoindRpc`], but driven by its ZMQ
+    /// `hashblock` and `rawtx` notifications on the given endpoints, instead of by polling.
+    Zmq { rpc: Arc<BitcoindRpcPool>, block_hash_endpoint: String, raw_tx_endpoint: String },
+    /// Sync by looking up the wallet's script pubkeys on the Electrum server at the given URL
+    /// (`tcp://` or `ssl://`), scanning each keychain as far as the config's stop gap when first
+    /// connected, then polling the revealed script pubkeys for changes.
+    Electrum { url: String, config: ElectrumConfig },
+}
+
+impl From<Arc<BitcoindRpcPool>> for WalletBackend {
+    fn from(value: Arc<BitcoindRpcPool>) -> Self { Self::BitcoindRpc(value) }
+}
+
+/// A pool of Bitcoin Core RPC clients, so that concurrent requests (say broadcasts & fee estimates
+/// from many gRPC calls at once) aren't serialised behind the single HTTP connection of one client.
+pub type BitcoindRpcPool = r2d2::Pool<BitcoindRpcConnectionManager>;
+
+pub struct BitcoindRpcConnectionManager {
+    url: String,
+    auth: Auth,
+}
+
+impl BitcoindRpcConnectionManager {
+    pub const DEFAULT_POOL_SIZE: u32 = 4;
+    /// The smallest usable pool, as the wallet's sync loop holds one client for as long as it runs,
+    /// so that with any fewer, every other RPC call would wait for a free client forever.
+    pub const MIN_POOL_SIZE: u32 = 2;
+
+    pub const fn new(url: String, auth: Auth) -> Self { Self { url, auth } }
+
+    /// Create a pool of up to `max_size` clients of the node at the given URL. As with creating a
+    /// single client, no connection is made at this point.
+    ///
+    /// # Panics
+    /// Will panic if `max_size` is less than [`Self::MIN_POOL_SIZE`]
+    pub fn into_pool(self, max_size: u32) -> BitcoindRpcPool {
+        assert!(max_size >= Self::MIN_POOL_SIZE, "RPC pool size {max_size} is below the minimum of {}",
+            Self::MIN_POOL_SIZE);
+        r2d2::Pool::builder().max_size(max_size).min_idle(Some(0)).build_unchecked(self)
+    }
+}
+
+impl r2d2::ManageConnection for BitcoindRpcConnectionManager {
+    type Connection = Client;
+    type Error = bdk_bitcoind_rpc::bitcoincore_rpc::Error;
+
+    fn connect(&self) -> Result<Client, Self::Error> { Client::new(&self.url, self.auth.clone()) }
+
+    // The client's HTTP transport reconnects by itself as needed, so there is nothing to check here.
+    fn is_valid(&self, _conn: &mut Client) -> Result<(), Self::Error> { Ok(()) }
+
+    fn has_broken(&self, _conn: &mut Client) -> bool { false }
+}
+
+impl fmt::Debug for BitcoindRpcConnectionManager {
+    // Leave out the auth, so as not to log the RPC password.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BitcoindRpcConnectionManager").field("url", &self.url).finish_non_exhaustive()
+    }
+}
+
+/// Whether a [`WalletServiceImpl`] holds the private keys of its wallet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum WalletMode {
+    Full,
+    /// Track the wallet's txs and balance from its public descriptor alone, say on an audit
+    /// server, refusing to sign or to reveal new addresses.
+    WatchOnly,
+}
+
+/// What an address revealed by the wallet is for. Each purpose takes the (external keychain)
+/// derivation indices of its addresses from its own range, so that revealing addresses for one
+/// purpose never consumes indices expected to be free for another.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum KeychainPurpose {
+    /// Ordinary receiving addresses, as handed out to the user.
+    External,
+    /// Anchor output addresses, for fee-bumping the buyer's warning and redirect txs.
+    BuyerFeeBump,
+    /// Anchor output addresses, for fee-bumping the seller's warning and redirect txs.
+    SellerFeeBump,
+}
+
+/// Non-overlapping ranges of external keychain derivation indices, reserved for each
+/// [`KeychainPurpose`], together with the next index to reveal from each.
+#[derive(Clone, Debug)]
+pub struct KeyRangeRegistry {
+    ranges: Vec<(KeychainPurpose, Range<u32>)>,
+    next_indices: HashMap<KeychainPurpose, u32>,
+}
+
+impl KeyRangeRegistry {
+    /// # Panics
+    /// Will panic if any two of the given ranges overlap
+    pub fn new(ranges: Vec<(KeychainPurpose, Range<u32>)>) -> Self {
+        for (i, (purpose, range)) in ranges.iter().enumerate() {
+            for (other_purpose, other_range) in &ranges[i + 1..] {
+                assert!(range.end <= other_range.start || other_range.end <= range.start,
+                    "key ranges for {purpose:?} and {other_purpose:?} overlap: {range:?}, {other_range:?}");
+            }
+        }
+        Self { ranges, next_indices: HashMap::new() }
+    }
+
+    pub fn range(&self, purpose: KeychainPurpose) -> Option<&Range<u32>> {
+        self.ranges.iter().find_map(|(p, range)| (*p == purpose).then_some(range))
+    }
+
+    /// Take the next unrevealed index from the range for the given purpose.
+    ///
+    /// # Errors
+    /// Will return `Err` if the range is used up, or there is none for the purpose
+    pub fn reserve_next_index(&mut self, purpose: KeychainPurpose) -> Result<u32> {
+        let range = self.range(purpose).cloned().ok_or(WalletErrorKind::KeyRangeExhausted(purpose))?;
+        let next_index = self.next_indices.entry(purpose).or_insert(range.start);
+        if !range.contains(next_index) {
+            return Err(WalletErrorKind::KeyRangeExhausted(purpose));
+        }
+        *next_index += 1;
+        Ok(*next_index - 1)
+    }
+
+    /// Skip past the given indices, revealed or used outside this registry (say in an earlier run
+    /// on the same wallet), so that each range resumes after them rather than handing out their
+    /// addresses again.
+    pub fn resume_after(&mut self, indices: impl IntoIterator<Item = u32>) {
+        for index in indices {
+            if let Some((purpose, range)) = self.ranges.iter().find(|(_, range)| range.contains(&index)) {
+                let next_index = self.next_indices.entry(*purpose).or_insert(range.start);
+                *next_index = (*next_index).max(index + 1);
+            }
+        }
+    }
+}
+
+impl Default for KeyRangeRegistry {
+    fn default() -> Self {
+        Self::new(vec![
+            (KeychainPurpose::External, 0..1000),
+            (KeychainPurpose::BuyerFeeBump, 1000..2000),
+            (KeychainPurpose::SellerFeeBump, 2000..3000),
+        ])
+    }
+}
+
+/// The private descriptors of the wallet's keychains, encrypted with AES-256-GCM under a key derived
+/// from the wallet passphrase with Argon2, so that a locked wallet holds no private keys in plaintext.
+struct EncryptedDescriptors {
+    keychains: Vec<KeychainKind>,
+    salt: [u8; 16],
+    nonce: [u8; 12],
+    // The newline-separated descriptors of each keychain in turn:
+    ciphertext: Vec<u8>,
+}
+
+impl EncryptedDescriptors {
+    fn encrypt(private_descriptors: &[(KeychainKind, Zeroizing<String>)], passphrase: &str) -> Self {
+        let keychains = private_descriptors.iter().map(|(keychain, _)| *keychain).collect();
+        let plaintext = Zeroizing::new(private_descriptors.iter()
+            .map(|(_, descriptor)| descriptor.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"));
+        let (salt, nonce): ([u8; 16], [u8; 12]) = (rand::random(), rand::random());
+        let ciphertext = Self::cipher(passphrase, &salt).encrypt(&nonce.into(), plaintext.as_bytes())
+            .expect("encryption into a Vec should not fail");
+        Self { keychains, salt, nonce, ciphertext }
+    }
+
+    fn decrypt(&self, passphrase: &str) -> Result<Vec<(KeychainKind, Zeroizing<String>)>> {
+        let plaintext = Zeroizing::new(Self::cipher(passphrase, &self.salt)
+            .decrypt(&self.nonce.into(), &*self.ciphertext)
+            .map_err(|_| WalletErrorKind::IncorrectPassphrase)?);
+        let plaintext = str::from_utf8(&plaintext).expect("encrypted descriptors should be UTF-8");
+        Ok(self.keychains.iter().zip(plaintext.split('\n'))
+            .map(|(&keychain, descriptor)| (keychain, Zeroizing::new(descriptor.to_owned())))
+            .collect())
+    }
+
+    fn cipher(passphrase: &str, salt: &[u8]) -> Aes256Gcm {
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+            .expect("default Argon2 params should accept a 16-byte salt");
+        Aes256Gcm::new(&(*key).into())
+    }
+}
+
+/// A write lock on a wallet, which persists the changes staged under it to the wallet's database
+/// (if it has one) when released.
+struct WalletWriteGuard<'a> {
+    wallet: RwLockWriteGuard<'a, Wallet>,
+    db: Option<&'a Mutex<Connection>>,
+}
+
+impl Deref for WalletWriteGuard<'_> {
+    type Target = Wallet;
+
+    fn deref(&self) -> &Wallet { &self.wallet }
+}
+
+impl DerefMut for WalletWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Wallet { &mut self.wallet }
+}
+
+impl Drop for WalletWriteGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(db) = self.db {
+            if let Err(e) = persist_staged(&mut self.wallet, &mut db.lock().unwrap()) {
+                error!("Could not persist wallet changes: {e}");
+            }
+        }
+    }
+}
+
+/// Write the wallet's staged changes to the database, unstaging them only once written, so that
+/// they are retried on the next write should this one fail.
+fn persist_staged(wallet: &mut Wallet, db: &mut Connection) -> Result<()> {
+    if let Some(changeset) = wallet.staged() {
+        WalletPersister::persist(db, changeset)?;
+        wallet.take_staged();
+    }
+    Ok(())
+}
+
+/// Create the table that the last index reserved from each key range is persisted to, next to the
+/// wallet, and return the indices found there. The wallet itself only records its last revealed
+/// index, which tells nothing of the ranges below the highest one reserved from.
+fn load_reserved_key_indices(db: &Connection) -> Result<Vec<u32>> {
+    db.execute("CREATE TABLE IF NOT EXISTS bmp_reserved_key_indices \
+        (purpose TEXT PRIMARY KEY NOT NULL, last_index INTEGER NOT NULL) STRICT", [])?;
+    let mut statement = db.prepare("SELECT last_index FROM bmp_reserved_key_indices")?;
+    let indices = statement.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+    Ok(indices)
+}
+
+fn persist_reserved_key_index(db: &Connection, purpose: KeychainPurpose, index: u32) -> Result<()> {
+    db.execute("INSERT INTO bmp_reserved_key_indices (purpose, last_index) VALUES (?1, ?2) \
+        ON CONFLICT (purpose) DO UPDATE SET last_index = max(last_index, excluded.last_index)",
+        (format!("{purpose:?}"), index))?;
+    Ok(())
+}
+
+enum Broadcaster {
+    BitcoindRpc(Arc<BitcoindRpcPool>),
+    CompactBlockFilters(Requester),
+    Electrum(Arc<BdkElectrumClient<electrum_client::Client>>),
+}
+
+struct BroadcastTx {
+    tx: Arc<Transaction>,
+    height: u32,
+    time: u64,
+    failed: bool,
 }
 
 impl Default for WalletServiceImpl {
@@ -71,23 +510,196 @@ impl WalletServiceImpl {
             .network(Network::Regtest)
             .create_wallet_no_persist()
             .expect("hardcoded descriptors should be valid");
+        Self::from_wallet(wallet)
+    }
+
+    /// Create a service for a wallet with the given single descriptor on the given network, used for
+    /// both receiving and change addresses, rather than the hardcoded pair of descriptors. Given a
+    /// database path, the wallet's changes are persisted there, and any wallet already stored there
+    /// is loaded. (Only the public descriptor is stored, so the private one must be given again to
+    /// load the wallet.)
+    ///
+    /// # Errors
+    /// Will return `Err` if the descriptor is invalid, or the database can't be opened or holds a
+    /// wallet with another descriptor or network
+    pub fn from_descriptor(descriptor: &str, network: Network, db_path: Option<&Path>) -> Result<Self> {
+        let create_wallet = || Wallet::create_single(descriptor.to_owned())
+            .network(network)
+            .create_wallet_no_persist();
+        let Some(db_path) = db_path else {
+            return Ok(Self::from_wallet(create_wallet()?));
+        };
+        let mut db = Connection::open(db_path)?;
+        let changeset = WalletPersister::initialize(&mut db)?;
+        let loaded_wallet = Wallet::load()
+            .descriptor(KeychainKind::External, Some(descriptor.to_owned()))
+            .extract_keys()
+            .check_network(network)
+            .load_wallet_no_persist(changeset)?;
+        let mut wallet = match loaded_wallet {
+            Some(wallet) => wallet,
+            None => create_wallet()?,
+        };
+        persist_staged(&mut wallet, &mut db)?;
+        let reserved_indices = load_reserved_key_indices(&db)?;
+        let tx_cache = ElectrumTxCache::open(db_path)?;
+        let wallet_service = Self {
+            db: Some(Mutex::new(db)),
+            tx_cache: Some(Mutex::new(tx_cache)),
+            ..Self::from_wallet(wallet)
+        };
+        wallet_service.key_ranges.lock().unwrap().resume_after(reserved_indices);
+        Ok(wallet_service)
+    }
+
+    /// Create a service for a watch-only wallet with the given public descriptor, on the given
+    /// network. It may be synced and queried as usual, but cannot sign or reveal new addresses.
+    ///
+    /// # Errors
+    /// Will return `Err` if the descriptor is invalid or contains any private keys
+    pub fn watch_only(descriptor: &str, network: Network) -> Result<Self> {
+        let wallet = Wallet::create_single(descriptor.to_owned())
+            .network(network)
+            .create_wallet_no_persist()?;
+        if !wallet.get_signers(KeychainKind::External).signers().is_empty() {
+            return Err(WalletErrorKind::PrivateKeyInWatchOnlyDescriptor);
+        }
+        Ok(Self { mode: WalletMode::WatchOnly, ..Self::from_wallet(wallet) })
+    }
 
+    fn from_wallet(wallet: Wallet) -> Self {
+        let signing_key = master_key(&wallet.get_signers(KeychainKind::External).as_key_map(wallet.secp_ctx()));
         let mut tx_confidence_map = ObservableHashMap::new();
         tx_confidence_map.sync(tx_confidence_entries(&wallet));
+        // Only the last revealed index is known to have been handed out, as revealing it would
+        // have revealed all the indices below it as well. (The last index reserved from each of the
+        // other ranges is persisted apart from the wallet, if it has a database.)
+        let mut key_ranges = KeyRangeRegistry::default();
+        key_ranges.resume_after(wallet.derivation_index(KeychainKind::External));
 
         Self {
             wallet: RwLock::new(wallet),
+            key_ranges: Mutex::new(key_ranges),
             tx_confidence_map: Mutex::new(tx_confidence_map),
+            broadcast_txs: Mutex::new(HashMap::new()),
+            broadcaster: RwLock::new(None),
+            watched_wallets: RwLock::new(Vec::new()),
+            signing_key: Arc::new(Mutex::new(signing_key)),
+            encrypted_descriptors: None,
+            mode: WalletMode::Full,
+            db: None,
+            tx_cache: None,
             poll_period: BITCOIND_POLLING_PERIOD,
+            broadcast_grace_period: BROADCAST_GRACE_PERIOD_BLOCKS,
+            stuck_tx_timeout: STUCK_TX_TIMEOUT,
+            tor_proxy: None,
+            max_retries: None,
+            fee_estimator: None,
         }
     }
 
     #[must_use]
     pub fn with_poll_period(self, poll_period: Duration) -> Self { Self { poll_period, ..self } }
 
+    /// Set the number of blocks after broadcast that a tx may be missing from the wallet's tx graph
+    /// (i.e. neither in the mempool nor a block) before it is reported as having failed to propagate.
+    /// Only the Bitcoin Core backends sync the mempool; with compact block filters, a tx is instead
+    /// reported as failed once a peer rejects it.
+    #[must_use]
+    pub fn with_broadcast_grace_period(self, broadcast_grace_period: u32) -> Self {
+        Self { broadcast_grace_period, ..self }
+    }
+
+    /// Set how long a tx may go unconfirmed before [`WalletService::auto_accelerate`] will fee-bump it.
+    #[must_use]
+    pub fn with_stuck_tx_timeout(self, stuck_tx_timeout: Duration) -> Self { Self { stuck_tx_timeout, ..self } }
+
+    /// Route connections to P2P peers and Electrum servers through the Tor SOCKS5 proxy at the given
+    /// address. Bitcoin Core RPC & ZMQ connections (meant for the user's own node) can't be proxied,
+    /// so [`WalletService::connect`] then refuses any backend but [`WalletBackend::CompactBlockFilters`]
+    /// or [`WalletBackend::Electrum`], rather than leak traffic outside Tor. Neither is a fee
+    /// estimator set with [`Self::with_fee_estimator`] proxied.
+    #[must_use]
+    pub fn with_tor_proxy(self, tor_proxy: Option<SocketAddr>) -> Self { Self { tor_proxy, ..self } }
+
+    /// Limit the number of times that [`WalletService::connect`] retries after a connection failure,
+    /// rather than retrying indefinitely (the default).
+    #[must_use]
+    pub fn with_max_retries(self, max_retries: Option<u32>) -> Self { Self { max_retries, ..self } }
+
+    /// Fall back to the given fee estimator whenever the backend gives no fee rate estimate.
+    #[must_use]
+    pub fn with_fee_estimator(self, fee_estimator: impl FeeEstimator + Send + Sync + 'static) -> Self {
+        Self { fee_estimator: Some(Arc::new(fee_estimator)), ..self }
+    }
+
+    /// Set the passphrase needed to unlock the wallet, once locked. Without one, the wallet cannot
+    /// be locked.
+    #[must_use]
+    pub fn with_passphrase(self, passphrase: Option<&str>) -> Self {
+        let encrypted_descriptors = passphrase.map(|passphrase| {
+            let wallet = self.wallet.read().unwrap();
+            let private_descriptors: Vec<_> = wallet.keychains().map(|(keychain, descriptor)| {
+                let keymap = wallet.get_signers(keychain).as_key_map(wallet.secp_ctx());
+                (keychain, Zeroizing::new(descriptor.to_string_with_secret(&keymap)))
+            }).collect();
+            EncryptedDescriptors::encrypt(&private_descriptors, passphrase)
+        });
+        Self { encrypted_descriptors, ..self }
+    }
+
+    pub const fn mode(&self) -> WalletMode { self.mode }
+
+    /// Take the write lock on the wallet, persisting any changes made under it on release.
+    fn wallet_mut(&self) -> WalletWriteGuard<'_> {
+        WalletWriteGuard { wallet: self.wallet.write().unwrap(), db: self.db.as_ref() }
+    }
+
+    const fn check_can_sign(&self) -> Result<()> {
+        match self.mode {
+            WalletMode::Full => Ok(()),
+            WalletMode::WatchOnly => Err(WalletErrorKind::WatchOnly),
+        }
+    }
+
+    /// Use the master private key the wallet signs with, under its lock, so that no copy of it is
+    /// left behind once the wallet is locked. Returns `None` if the wallet is locked or watch-only.
+    fn with_xpriv<T>(&self, f: impl FnOnce(&Xpriv) -> T) -> Option<T> {
+        self.signing_key.lock().unwrap().as_ref().map(f)
+    }
+
     fn sync_tx_confidence_map(&self) {
         let wallet = self.wallet.read().unwrap();
-        self.tx_confidence_map.lock().unwrap().sync(tx_confidence_entries(&wallet));
+        let broadcast_txs = self.broadcast_txs.lock().unwrap();
+        let failed_entries = broadcast_txs.iter()
+            .filter(|(_, broadcast_tx)| broadcast_tx.failed)
+            .map(|(&txid, broadcast_tx)| (txid, broadcast_tx.failed_confidence(txid)));
+        self.tx_confidence_map.lock().unwrap().sync(tx_confidence_entries(&wallet).chain(failed_entries));
+    }
+
+    /// Stop tracking the broadcast txs that have confirmed. If the wallet's tx graph follows the
+    /// mempool, also flag those missing from it for longer than the grace period as failed. (Without
+    /// a mempool sync, a broadcast tx stays in the graph as unconfirmed regardless of its fate.)
+    fn check_broadcast_txs(&self, mempool_synced: bool) {
+        let wallet = self.wallet.read().unwrap();
+        let tip_height = wallet.latest_checkpoint().height();
+        self.broadcast_txs.lock().unwrap().retain(|&txid, broadcast_tx| {
+            if let Some(wallet_tx) = wallet.get_tx(txid) {
+                if wallet_tx.chain_position.is_confirmed() {
+                    return false;
+                }
+                if mempool_synced {
+                    broadcast_tx.failed = false;
+                }
+                return true;
+            }
+            if !broadcast_tx.failed && tip_height >= broadcast_tx.height + self.broadcast_grace_period {
+                warn!(%txid, broadcast_height = broadcast_tx.height, tip_height,
+                    "Broadcast tx is missing from the mempool and chain; it may have been rejected.");
+                broadcast_tx.failed = true;
+            }
+            true
+        });
     }
 
     fn sync_from_rpc_emitter(&self, emitter: &mut Emitter<&Client>) -> Result<()> {
@@ -95,26 +707,345 @@ impl WalletServiceImpl {
         while let Some(block) = task::block_in_place(|| emitter.next_block())? {
             let height = block.block_height();
             debug!(hash = %block.block_hash(), height, "New block.");
-            self.wallet.write().unwrap()
-                .apply_block_connected_to(&block.block, height, block.connected_to())?;
+            let mut wallet = self.wallet_mut();
+            let connected_to = block.connected_to();
+            if connected_to != wallet.latest_checkpoint().block_id() {
+                // The emitter has rewound to the fork point, so the block connects below our tip.
+                // Applying it invalidates the stale blocks above the fork point, after which any
+                // txs they confirmed are unconfirmed again, as the confidence map sync below picks up.
+                warn!(fork_height = connected_to.height, stale_tip_height = wallet.latest_checkpoint().height(),
+                    "reorg detected at height {}", connected_to.height);
+            }
+            wallet.apply_block_connected_to(&block.block, height, connected_to)?;
         }
 
         trace!("Syncing mempool...");
         {
             let mempool_emissions = task::block_in_place(|| emitter.mempool())?;
-            let mut wallet = self.wallet.write().unwrap();
+            let mut wallet = self.wallet_mut();
             wallet.apply_evicted_txs(mempool_emissions.evicted);
             wallet.apply_unconfirmed_txs(mempool_emissions.update);
         }
 
+        trace!("Checking propagation of broadcast txs.");
+        self.check_broadcast_txs(true);
+
         trace!("Syncing tx confidence map with wallet.");
         // TODO: Skip needless cache/map updates if the wallet hasn't actually changed:
         self.sync_tx_confidence_map();
 
+        trace!("Syncing watched wallets.");
+        self.sync_watched_wallets()
+    }
+
+    fn bitcoind_rpc(&self) -> Option<Arc<BitcoindRpcPool>> {
+        match self.broadcaster.read().unwrap().as_ref()? {
+            Broadcaster::BitcoindRpc(rpc) => Some(rpc.clone()),
+            Broadcaster::CompactBlockFilters(_) | Broadcaster::Electrum(_) => None,
+        }
+    }
+
+    fn sync_watched_wallets(&self) -> Result<()> {
+        let Some(rpc) = self.bitcoind_rpc() else { return Ok(()) };
+        for wallet in self.watched_wallets.write().unwrap().iter_mut() {
+            sync_watched_wallet(&rpc, wallet)?;
+        }
+        Ok(())
+    }
+
+    fn init_bitcoind_rpc(&self, rpc: &Arc<BitcoindRpcPool>) -> Result<()> {
+        let blockchain_info = call_rpc(rpc, Client::get_blockchain_info)?;
+        info!(chain = %blockchain_info.chain, best_block_hash = %blockchain_info.best_block_hash,
+            blocks = blockchain_info.blocks, "Connected to Bitcoin Core RPC.");
+        *self.broadcaster.write().unwrap() = Some(Broadcaster::BitcoindRpc(rpc.clone()));
+        Ok(())
+    }
+
+    async fn connect_once(&self, backend: WalletBackend) -> Result<Never> {
+        match backend {
+            WalletBackend::BitcoindRpc(rpc) => self.connect_bitcoind_rpc(rpc).await,
+            WalletBackend::CompactBlockFilters { url } => self.connect_compact_block_filters(&url).await,
+            WalletBackend::Zmq { rpc, block_hash_endpoint, raw_tx_endpoint } =>
+                self.connect_zmq(rpc, &block_hash_endpoint, &raw_tx_endpoint).await,
+            WalletBackend::Electrum { url, config } => self.connect_electrum(&url, config).await,
+        }
+    }
+
+    async fn connect_bitcoind_rpc(&self, rpc: Arc<BitcoindRpcPool>) -> Result<Never> {
+        self.init_bitcoind_rpc(&rpc)?;
+
+        // Hold one client from the pool for syncing, leaving the rest for other requests.
+        let sync_rpc = task::block_in_place(|| rpc.get())?;
+        let mut emitter = new_emitter(&sync_rpc, &self.wallet.read().unwrap());
+        self.sync_from_rpc_emitter(&mut emitter)?;
+        info!(wallet_balance_total = %self.balance().total(), "Finished initial sync.");
+
+        info!("Polling for further blocks and mempool txs...");
+        let mut interval = time::interval(self.poll_period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.sync_from_rpc_emitter(&mut emitter)?;
+        }
+    }
+
+    async fn connect_zmq(&self, rpc: Arc<BitcoindRpcPool>, block_hash_endpoint: &str, raw_tx_endpoint: &str) -> Result<Never> {
+        self.init_bitcoind_rpc(&rpc)?;
+
+        // Subscribe before the initial sync, so that no notifications are missed in between.
+        let mut block_hash_sub = zmq_subscribe(block_hash_endpoint, "hashblock").await?;
+        let mut raw_tx_sub = zmq_subscribe(raw_tx_endpoint, "rawtx").await?;
+
+        let sync_rpc = task::block_in_place(|| rpc.get())?;
+        let mut emitter = new_emitter(&sync_rpc, &self.wallet.read().unwrap());
+        self.sync_from_rpc_emitter(&mut emitter)?;
+        info!(wallet_balance_total = %self.balance().total(), "Finished initial sync.");
+
+        info!("Listening for ZMQ block and tx notifications...");
+        loop {
+            tokio::select! {
+                msg = block_hash_sub.recv() => {
+                    trace!(block_hash = ?zmq_payload(&msg?)?, "New block hash notification.");
+                    // Let the emitter fetch the full block(s), so that reorgs are handled as when polling.
+                    self.sync_from_rpc_emitter(&mut emitter)?;
+                }
+                msg = raw_tx_sub.recv() => {
+                    let tx: Transaction = consensus::deserialize(zmq_payload(&msg?)?)?;
+                    trace!(txid = %tx.compute_txid(), "New raw tx notification.");
+                    self.wallet_mut().apply_unconfirmed_txs([(tx, unix_time_now())]);
+                    self.sync_tx_confidence_map();
+                }
+            }
+        }
+    }
+
+    async fn connect_electrum(&self, url: &str, config: ElectrumConfig) -> Result<Never> {
+        let socks5 = self.tor_proxy.map(Socks5Config::new);
+        let client = task::block_in_place(|| {
+            electrum_client::Client::from_config(url, ConfigBuilder::new().socks5(socks5).build())
+        })?;
+        let client = Arc::new(BdkElectrumClient::new(client));
+        // Seed the client's tx cache, so that it doesn't download the txs the wallet already has.
+        client.populate_tx_cache(self.wallet.read().unwrap().tx_graph().full_txs().map(|tx_node| tx_node.tx));
+        if let Some(tx_cache) = &self.tx_cache {
+            match tx_cache.lock().unwrap().load() {
+                Ok(txs) => client.populate_tx_cache(txs),
+                Err(e) => warn!("Could not load the Electrum tx cache: {e}"),
+            }
+        }
+        info!(url, "Connected to Electrum server.");
+        *self.broadcaster.write().unwrap() = Some(Broadcaster::Electrum(client.clone()));
+
+        let request = self.wallet.read().unwrap().start_full_scan();
+        let update = task::block_in_place(|| client.full_scan(request, config.stop_gap, config.batch_size, false))?;
+        self.cache_electrum_txs(&update.tx_update.txs);
+        self.wallet_mut().apply_update(update)?;
+        self.check_broadcast_txs(true);
+        self.sync_tx_confidence_map();
+        info!(wallet_balance_total = %self.balance().total(), "Finished initial sync.");
+
+        info!("Polling for changes to the wallet's script pubkeys...");
+        let mut interval = time::interval(self.poll_period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let request = self.wallet.read().unwrap().start_sync_with_revealed_spks();
+            let update = task::block_in_place(|| client.sync(request, config.batch_size, false))?;
+            self.cache_electrum_txs(&update.tx_update.txs);
+            self.wallet_mut().apply_update(update)?;
+            self.check_broadcast_txs(true);
+            self.sync_tx_confidence_map();
+        }
+    }
+
+    /// Store the txs downloaded from Electrum in the tx cache, if the wallet has one. (A failure to
+    /// do so only costs their download again after a restart, so it doesn't fail the sync.)
+    fn cache_electrum_txs(&self, txs: &[Arc<Transaction>]) {
+        if let Some(tx_cache) = &self.tx_cache {
+            if let Err(e) = tx_cache.lock().unwrap().persist(txs.iter().map(Arc::as_ref)) {
+                warn!("Could not persist downloaded txs to the Electrum tx cache: {e}");
+            }
+        }
+    }
+
+    async fn connect_compact_block_filters(&self, url: &str) -> Result<Never> {
+        let peer: SocketAddr = url.parse()?;
+        let client = {
+            let wallet = self.wallet.read().unwrap();
+            let mut builder = Builder::new(wallet.network()).add_peer(peer);
+            if let Some(tor_proxy) = self.tor_proxy {
+                builder = builder.socks5_proxy(tor_proxy);
+            }
+            builder.build_with_wallet(&wallet, ScanType::Sync)?
+        };
+        let (client, LoggingSubscribers { info_subscriber, warning_subscriber }, mut update_subscriber) =
+            client.subscribe();
+        task::spawn(log_compact_block_filter_info(info_subscriber));
+        let requester = client.start().requester();
+        info!(%peer, "Started compact block filter node.");
+        *self.broadcaster.write().unwrap() = Some(Broadcaster::CompactBlockFilters(requester));
+
+        let apply_updates = async {
+            loop {
+                let update = update_subscriber.update().await?;
+                trace!("Applying compact block filter update.");
+                self.wallet_mut().apply_update(update)?;
+                self.check_broadcast_txs(false);
+                self.sync_tx_confidence_map();
+            }
+        };
+        // Fetching an update isn't cancel safe, so it mustn't be restarted in a select loop. Instead,
+        // the warnings are handled by a concurrent future that never completes.
+        tokio::select! {
+            result = apply_updates => result,
+            never = self.handle_compact_block_filter_warnings(warning_subscriber) => match never {},
+        }
+    }
+
+    /// Log the warnings of the compact block filter node, flagging each broadcast tx that a peer
+    /// rejects (or that couldn't be sent to one) as failed.
+    async fn handle_compact_block_filter_warnings(&self, mut warning_subscriber: UnboundedReceiver<Warning>) -> Never {
+        while let Some(warning) = warning_subscriber.recv().await {
+            warn!(%warning, "Compact block filter node warning.");
+            if let Warning::TransactionRejected { payload } = warning {
+                self.reject_broadcast_tx(payload);
+            }
+        }
+        // The node has stopped, which the update subscriber will report.
+        future::pending().await
+    }
+
+    fn reject_broadcast_tx(&self, RejectPayload { reason, wtxid }: RejectPayload) {
+        {
+            let mut broadcast_txs = self.broadcast_txs.lock().unwrap();
+            let Some((txid, broadcast_tx)) = broadcast_txs.iter_mut()
+                .find(|(_, broadcast_tx)| broadcast_tx.tx.compute_wtxid() == wtxid) else { return };
+            warn!(%txid, ?reason, "Broadcast tx was rejected by a peer.");
+            broadcast_tx.failed = true;
+        }
+        self.sync_tx_confidence_map();
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<()> {
+        check_no_double_spend(tx, &self.wallet.read().unwrap())?;
+        let txid = match self.broadcaster.read().unwrap().as_ref().ok_or(WalletErrorKind::NotConnected)? {
+            Broadcaster::BitcoindRpc(rpc) => call_rpc(rpc, |rpc| rpc.send_raw_transaction(tx))?,
+            Broadcaster::CompactBlockFilters(requester) => {
+                task::block_in_place(|| Handle::current().block_on(requester.submit_package(tx.clone())))?;
+                tx.compute_txid()
+            }
+            Broadcaster::Electrum(client) => task::block_in_place(|| client.transaction_broadcast(tx))?,
+        };
+        info!(%txid, "Broadcast tx.");
+        let time = unix_time_now();
+        {
+            let mut wallet = self.wallet_mut();
+            wallet.apply_unconfirmed_txs([(tx.clone(), time)]);
+            let height = wallet.latest_checkpoint().height();
+            let tx = Arc::new(tx.clone());
+            self.broadcast_txs.lock().unwrap().insert(txid, BroadcastTx { tx, height, time, failed: false });
+        }
+        self.sync_tx_confidence_map();
+        Ok(())
+    }
+}
+
+/// The fee a child tx of the given weight must pay for the package of it and its parent to have the
+/// target fee rate.
+fn cpfp_child_fee(parent_fee: Amount, parent_weight: Weight, child_weight: Weight, target_fee_rate: FeeRate) -> Amount {
+    (target_fee_rate * (parent_weight + child_weight)).checked_sub(parent_fee).unwrap_or_default()
+}
+
+/// Sync a watched wallet from Bitcoin Core, from its latest checkpoint up to the chain tip, then with
+/// the mempool. A fresh emitter is used each time, as the wallet may have only just been imported.
+fn sync_watched_wallet(rpc: &BitcoindRpcPool, wallet: &mut Wallet) -> Result<()> {
+    task::block_in_place(|| {
+        let client = rpc.get()?;
+        let mut emitter = Emitter::new(&*client, wallet.latest_checkpoint(), 0, unconfirmed_txs(wallet));
+        while let Some(block) = emitter.next_block()? {
+            wallet.apply_block_connected_to(&block.block, block.block_height(), block.connected_to())?;
+        }
+        let mempool_emissions = emitter.mempool()?;
+        wallet.apply_evicted_txs(mempool_emissions.evicted);
+        wallet.apply_unconfirmed_txs(mempool_emissions.update);
         Ok(())
+    })
+}
+
+/// Make a blocking call with a client checked out of the pool, waiting for one to become free if
+/// all are in use.
+fn call_rpc<T>(rpc: &BitcoindRpcPool, f: impl FnOnce(&Client) -> bdk_bitcoind_rpc::bitcoincore_rpc::Result<T>) -> Result<T> {
+    task::block_in_place(|| {
+        let client = rpc.get()?;
+        Ok(f(&client)?)
+    })
+}
+
+/// Create a block emitter which resumes from the wallet's latest checkpoint. The full checkpoint
+/// chain is passed, so that the emitter can find the most recent block in agreement with bitcoind
+/// and only request blocks above that. The start height is left at zero, since forcing it to the
+/// wallet tip height would skip the blocks between any fork point and the old tip, had the chain
+/// reorganised while the wallet was offline.
+fn new_emitter<'a>(rpc: &'a Client, wallet: &Wallet) -> Emitter<&'a Client> {
+    let wallet_tip: CheckPoint = wallet.latest_checkpoint();
+    info!(tip_hash = %wallet_tip.hash(), tip_height = wallet_tip.height(), "Fetched latest wallet checkpoint.");
+    Emitter::new(rpc, wallet_tip, 0, unconfirmed_txs(wallet))
+}
+
+impl BroadcastTx {
+    fn failed_confidence(&self, txid: Txid) -> TxConfidence {
+        let chain_position = ChainPosition::Unconfirmed { first_seen: Some(self.time), last_seen: Some(self.time) };
+        let wallet_tx = WalletTx::new(txid, self.tx.clone(), chain_position);
+        TxConfidence { wallet_tx, num_confirmations: 0, broadcast_failed: true }
+    }
+}
+
+fn master_key(keymap: &KeyMap) -> Option<Xpriv> {
+    keymap.values().find_map(|key| match key {
+        DescriptorSecretKey::XPrv(xkey) => Some(xkey.xkey),
+        _ => None,
+    })
+}
+
+async fn zmq_subscribe(endpoint: &str, topic: &str) -> Result<SubSocket> {
+    let mut socket = SubSocket::new();
+    socket.connect(endpoint).await?;
+    socket.subscribe(topic).await?;
+    info!(endpoint, topic, "Subscribed to ZMQ notifications.");
+    Ok(socket)
+}
+
+/// Get the body of a Bitcoin Core ZMQ notification, which is its second frame, after the topic.
+fn zmq_payload(msg: &ZmqMessage) -> Result<&[u8]> {
+    msg.get(1).map(AsRef::as_ref).ok_or(WalletErrorKind::MalformedZmqMessage)
+}
+
+async fn log_compact_block_filter_info(mut info_subscriber: Receiver<Info>) {
+    while let Some(info) = info_subscriber.recv().await {
+        debug!(%info, "Compact block filter node info.");
     }
 }
 
+/// Lexicographic ordering of tx inputs & outputs as specified by BIP69, so that the wallet's txs
+/// cannot be fingerprinted by the order in which it places them.
+pub fn bip69_ordering() -> TxOrdering {
+    TxOrdering::Custom { input_sort: Arc::new(bip69_input_cmp), output_sort: Arc::new(bip69_output_cmp) }
+}
+
+fn bip69_input_cmp(a: &TxIn, b: &TxIn) -> std::cmp::Ordering {
+    // BIP69 compares the prevout txids in reversed byte order, i.e. as displayed in hex.
+    let txid_bytes = |input: &TxIn| input.previous_output.txid.to_byte_array().into_iter().rev();
+    txid_bytes(a).cmp(txid_bytes(b))
+        .then(a.previous_output.vout.cmp(&b.previous_output.vout))
+}
+
+fn bip69_output_cmp(a: &TxOut, b: &TxOut) -> std::cmp::Ordering {
+    a.value.cmp(&b.value)
+        .then_with(|| a.script_pubkey.as_bytes().cmp(b.script_pubkey.as_bytes()))
+}
+
 fn unconfirmed_txs(wallet: &Wallet) -> impl Iterator<Item = Arc<Transaction>> + '_ {
     tx_confidence_entries(wallet)
         .filter_map(|(_, conf)| (conf.num_confirmations == 0).then_some(conf.wallet_tx.tx))
@@ -130,59 +1061,535 @@ fn tx_confidence_entries(wallet: &Wallet) -> impl Iterator<Item = (Txid, TxConfi
             let conf_height = wallet_tx.chain_position.confirmation_height_upper_bound().unwrap_or(next_height);
             let num_confirmations = next_height - conf_height;
             trace!(%num_confirmations, %wallet_tx.txid, "New transaction confirmations.");
-            (wallet_tx.txid, TxConfidence { wallet_tx, num_confirmations })
+            (wallet_tx.txid, TxConfidence { wallet_tx, num_confirmations, broadcast_failed: false })
         })
 }
 
 #[tonic::async_trait]
 impl WalletService for WalletServiceImpl {
-    async fn connect(&self, rpc: Arc<Client>) -> Result<Never> {
-        let blockchain_info = task::block_in_place(|| rpc.get_blockchain_info())?;
-        info!(chain = %blockchain_info.chain, best_block_hash = %blockchain_info.best_block_hash,
-            blocks = blockchain_info.blocks, "Connected to Bitcoin Core RPC.");
-
-        let wallet_tip: CheckPoint = self.wallet.read().unwrap().latest_checkpoint();
-        let start_height = wallet_tip.height();
-        info!(start_hash = %wallet_tip.hash(), start_height, "Fetched latest wallet checkpoint.");
-
-        let mut emitter = Emitter::new(rpc.as_ref(), wallet_tip, start_height,
-            unconfirmed_txs(&self.wallet.read().unwrap()));
-        self.sync_from_rpc_emitter(&mut emitter)?;
-        info!(wallet_balance_total = %self.balance().total(), "Finished initial sync.");
-
-        info!("Polling for further blocks and mempool txs...");
-        let mut interval = time::interval(self.poll_period);
-        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
-        interval.tick().await;
+    async fn connect(&self, backend: WalletBackend, shutdown: CancellationToken) -> Result<()> {
+        if self.tor_proxy.is_some()
+            && !matches!(backend, WalletBackend::CompactBlockFilters { .. } | WalletBackend::Electrum { .. }) {
+            return Err(WalletErrorKind::TorProxyUnsupported);
+        }
+        // Retry with exponential backoff & jitter. Shutdown is checked at every await point, both
+        // while connected and while waiting to retry. (Wallet updates are made between await points,
+        // so they are never left half-applied.)
+        let mut delay = INITIAL_RETRY_DELAY;
+        let mut attempt = 0_u32;
         loop {
-            interval.tick().await;
-            self.sync_from_rpc_emitter(&mut emitter)?;
+            let e = tokio::select! {
+                () = shutdown.cancelled() => break,
+                Err(e) = self.connect_once(backend.clone()) => e,
+            };
+            attempt = attempt.saturating_add(1);
+            if self.max_retries.is_some_and(|max_retries| attempt > max_retries) {
+                return Err(e);
+            }
+            let jittered_delay = delay.mul_f64(rand::random_range(0.5..1.0));
+            warn!(attempt, retry_delay = ?jittered_delay, "Wallet connection failed: {e}");
+            tokio::select! {
+                () = shutdown.cancelled() => break,
+                () = time::sleep(jittered_delay) => {}
+            }
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
         }
+        info!("Wallet connection shut down.");
+        Ok(())
     }
 
     fn balance(&self) -> Balance {
         self.wallet.read().unwrap().balance()
     }
 
-    fn reveal_next_address(&self) -> AddressInfo {
-        self.wallet.write().unwrap().reveal_next_address(KeychainKind::External)
+    fn block_height(&self) -> u32 {
+        self.wallet.read().unwrap().latest_checkpoint().height()
+    }
+
+    fn reveal_next_address(&self, purpose: KeychainPurpose) -> Result<AddressInfo> {
+        self.check_can_sign()?;
+        let mut wallet = self.wallet_mut();
+        let mut key_ranges = self.key_ranges.lock().unwrap();
+        // Addresses may have received funds since last time, even ones never handed out here.
+        key_ranges.resume_after(wallet.spk_index().keychain_outpoints(KeychainKind::External).map(|(index, _)| index));
+        let index = key_ranges.reserve_next_index(purpose)?;
+        if let Some(db) = &self.db {
+            persist_reserved_key_index(&db.lock().unwrap(), purpose, index)?;
+        }
+        // Reveal all indices up to the reserved one, so that the wallet watches its address when
+        // syncing. (The bdk wallet only tracks a contiguous range of revealed indices.)
+        let _ = wallet.reveal_addresses_to(KeychainKind::External, index);
+        Ok(wallet.peek_address(KeychainKind::External, index))
     }
 
     fn list_unspent(&self) -> Vec<LocalOutput> {
         self.wallet.read().unwrap().list_unspent().collect()
     }
 
+    fn get_utxo(&self, outpoint: OutPoint) -> Option<LocalOutput> {
+        self.wallet.read().unwrap().get_utxo(outpoint)
+    }
+
+    fn contains_tx(&self, txid: Txid) -> bool {
+        self.wallet.read().unwrap().get_tx(txid).is_some()
+    }
+
+    fn get_tx_fee(&self, txid: Txid) -> Option<Amount> {
+        let wallet = self.wallet.read().unwrap();
+        let tx = wallet.get_tx(txid)?.tx_node.tx;
+        wallet.calculate_fee(&tx).ok()
+    }
+
     fn get_tx_confidence_stream(&self, txid: Txid) -> BoxStream<'static, Option<TxConfidence>> {
         self.tx_confidence_map.lock().unwrap().observe(txid)
             .on_drop(move || debug!(%txid, "Confidence stream has been dropped."))
             .boxed()
     }
+
+    async fn wait_for_confirmations(&self, txid: Txid, required: u32, timeout: Duration) -> Result<u32> {
+        let mut stream = self.get_tx_confidence_stream(txid);
+        let num_confirmations = async {
+            while let Some(conf) = stream.next().await {
+                match conf {
+                    Some(conf) if conf.num_confirmations >= required => return conf.num_confirmations,
+                    _ => {}
+                }
+            }
+            // The stream shouldn't end, but if it does, just wait out the timeout.
+            future::pending().await
+        };
+        time::timeout(timeout, num_confirmations).await.map_err(|_| WalletErrorKind::Timeout)
+    }
+
+    fn export_transactions_csv(&self, heights: RangeInclusive<u32>) -> Result<Vec<u8>> {
+        let wallet = self.wallet.read().unwrap();
+        let mut txs: Vec<_> = wallet.transactions()
+            .filter_map(|wallet_tx| match wallet_tx.chain_position {
+                ChainPosition::Confirmed { anchor, .. } if heights.contains(&anchor.block_id.height) =>
+                    Some((anchor, wallet_tx.tx_node.txid, wallet_tx.tx_node.tx)),
+                ChainPosition::Confirmed { .. } | ChainPosition::Unconfirmed { .. } => None
+            })
+            .collect();
+        txs.sort_by_key(|&(anchor, txid, _)| (anchor.block_id.height, txid));
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["txid", "block_height", "confirmation_date", "amount_sats", "fee_sats", "type"])?;
+        for (anchor, txid, tx) in txs {
+            let (sent, received) = wallet.sent_and_received(&tx);
+            let tx_type = if sent == Amount::ZERO {
+                "receive"
+            } else if tx.output.iter().all(|o| wallet.is_mine(o.script_pubkey.clone())) {
+                "internal"
+            } else {
+                "send"
+            };
+            let fee = (tx_type != "receive").then(|| wallet.calculate_fee(&tx).ok()).flatten();
+            let confirmation_date = i64::try_from(anchor.confirmation_time).ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true));
+            writer.write_record([
+                txid.to_string(),
+                anchor.block_id.height.to_string(),
+                confirmation_date.unwrap_or_default(),
+                (i128::from(received.to_sat()) - i128::from(sent.to_sat())).to_string(),
+                fee.map(|fee| fee.to_sat().to_string()).unwrap_or_default(),
+                tx_type.to_owned(),
+            ])?;
+        }
+        Ok(writer.into_inner().map_err(|e| csv::Error::from(e.into_error()))?)
+    }
+
+    fn send(&self, address: Address<NetworkUnchecked>, amount: Amount, options: SendOptions) -> Result<Transaction> {
+        self.check_can_sign()?;
+        let tx = {
+            let mut wallet = self.wallet_mut();
+            if self.signing_key.lock().unwrap().is_none() {
+                return Err(WalletErrorKind::WalletLocked);
+            }
+            let address = address.require_network(wallet.network())?;
+            let tip_height = wallet.latest_checkpoint().height();
+            options.check_lock_time(tip_height)?;
+
+            let mut psbt = if options.coin_age_priority() {
+                let mut builder = wallet.build_tx().coin_selection(CoinAgePrioritySelector { tip_height });
+                options.apply_to(&mut builder, address.script_pubkey(), amount);
+                builder.finish()?
+            } else {
+                let mut builder = wallet.build_tx();
+                options.apply_to(&mut builder, address.script_pubkey(), amount);
+                builder.finish()?
+            };
+            validate_tx_size(&psbt.unsigned_tx)?;
+            check_no_double_spend(&psbt.unsigned_tx, &wallet)?;
+            wallet.sign(&mut psbt, SignOptions::default_for_musig())?;
+            let tx = psbt.extract_tx()?;
+
+            if !options.is_final_at(tip_height) {
+                info!(txid = %tx.compute_txid(), lock_time = %tx.lock_time, "Signed time-locked tx; not broadcasting.");
+                return Ok(tx);
+            }
+            tx
+        };
+        self.broadcast(&tx)?;
+        Ok(tx)
+    }
+
+    fn broadcast_tx(&self, tx: Transaction) -> Result<Txid> {
+        self.broadcast(&tx)?;
+        Ok(tx.compute_txid())
+    }
+
+    fn sign_psbt(&self, psbt: &mut Psbt) -> Result<bool> {
+        self.check_can_sign()?;
+        let wallet = self.wallet.read().unwrap();
+        if self.signing_key.lock().unwrap().is_none() {
+            return Err(WalletErrorKind::WalletLocked);
+        }
+        Ok(wallet.sign(psbt, SignOptions::default_for_musig())?)
+    }
+
+    fn insert_prevouts(&self, psbt: &Psbt) {
+        let mut wallet = self.wallet_mut();
+        for (tx_in, input) in psbt.unsigned_tx.input.iter().zip(&psbt.inputs) {
+            let prevout = input.witness_utxo.clone().or_else(|| input.non_witness_utxo.as_ref()
+                .and_then(|tx| tx.output.get(tx_in.previous_output.vout as usize).cloned()));
+            if let Some(prevout) = prevout {
+                wallet.insert_txout(tx_in.previous_output, prevout);
+            }
+        }
+    }
+
+    async fn auto_accelerate(&self, txid: Txid) -> Result<Option<Txid>> {
+        self.check_can_sign()?;
+        let (tx, first_seen) = {
+            let wallet = self.wallet.read().unwrap();
+            let Some(wallet_tx) = wallet.get_tx(txid) else { return Ok(None) };
+            let ChainPosition::Unconfirmed { first_seen, .. } = wallet_tx.chain_position else { return Ok(None) };
+            let first_seen = first_seen
+                .or_else(|| self.broadcast_txs.lock().unwrap().get(&txid).map(|broadcast_tx| broadcast_tx.time));
+            (wallet_tx.tx_node.tx, first_seen)
+        };
+        if first_seen.is_none_or(|time| unix_time_now().saturating_sub(time) < self.stuck_tx_timeout.as_secs()) {
+            return Ok(None);
+        }
+        let Some(target_fee_rate) = self.estimate_fee_rate(1).await? else { return Ok(None) };
+
+        let child_tx = {
+            let mut wallet = self.wallet_mut();
+            if self.signing_key.lock().unwrap().is_none() {
+                return Err(WalletErrorKind::WalletLocked);
+            }
+            let parent_fee = wallet.calculate_fee(&tx)?;
+            if parent_fee / tx.weight() >= target_fee_rate {
+                return Ok(None);
+            }
+            let Some(output) = wallet.list_unspent().find(|output| output.outpoint.txid == txid) else {
+                debug!(%txid, "Stuck tx has no wallet output to spend; cannot fee-bump it.");
+                return Ok(None);
+            };
+            let drain_script = wallet.next_unused_address(KeychainKind::Internal).script_pubkey();
+            let mut build_and_sign = |fee: Amount| -> Result<Transaction> {
+                let mut builder = wallet.build_tx();
+                builder.add_utxo(output.outpoint)?
+                    .manually_selected_only()
+                    .drain_to(drain_script.clone())
+                    .fee_absolute(fee);
+                let mut psbt = builder.finish()?;
+                wallet.sign(&mut psbt, SignOptions::default_for_musig())?;
+                Ok(psbt.extract_tx()?)
+            };
+            // Sign a child tx once to find its weight, then again with the fee which brings the
+            // pair of txs up to the target fee rate.
+            let child_weight = build_and_sign(Amount::ZERO)?.weight();
+            build_and_sign(cpfp_child_fee(parent_fee, tx.weight(), child_weight, target_fee_rate))?
+        };
+        self.broadcast(&child_tx)?;
+        Ok(Some(child_tx.compute_txid()))
+    }
+
+    fn verify_address(&self, address_str: &str) -> Result<Address> {
+        let address: Address<NetworkUnchecked> = address_str.parse().map_err(WalletErrorKind::InvalidAddress)?;
+        let network = self.network();
+        if !address.is_valid_for_network(network) {
+            return Err(WalletErrorKind::AddressNetworkMismatch(network));
+        }
+        Ok(address.assume_checked())
+    }
+
+    async fn sign_message(&self, address: &Address, message: &str) -> Result<String> {
+        self.check_can_sign()?;
+        if !address.script_pubkey().is_p2tr() {
+            return Err(WalletErrorKind::CannotSignMessage(address.clone()));
+        }
+        let mut psbt = bip322::to_sign_psbt(address, message);
+        let wallet = self.wallet.read().unwrap();
+        if self.signing_key.lock().unwrap().is_none() {
+            return Err(WalletErrorKind::WalletLocked);
+        }
+        let sign_options = SignOptions { trust_witness_utxo: true, ..SignOptions::default_for_musig() };
+        if !wallet.sign(&mut psbt, sign_options)? {
+            return Err(WalletErrorKind::CannotSignMessage(address.clone()));
+        }
+        let witness = psbt.inputs[0].final_script_witness.as_ref().expect("finalized PSBT");
+        Ok(bip322::encode_simple_signature(witness))
+    }
+
+    fn network(&self) -> Network { self.wallet.read().unwrap().network() }
+
+    fn derive_silent_payment_address(&self) -> Result<SilentPaymentAddress> {
+        self.check_can_sign()?;
+        let wallet = self.wallet.read().unwrap();
+        let address = self.with_xpriv(|xpriv| SilentPaymentAddress::from_master_key(wallet.secp_ctx(), xpriv, wallet.network()))
+            .ok_or(WalletErrorKind::WalletLocked)?;
+        Ok(address?)
+    }
+
+    fn import_watch_descriptor(&self, descriptor: &str) -> Result<()> {
+        let mut wallet = Wallet::create_single(descriptor.to_owned())
+            .network(self.network())
+            .create_wallet_no_persist()?;
+        if !wallet.get_signers(KeychainKind::External).signers().is_empty() {
+            return Err(WalletErrorKind::PrivateKeyInWatchOnlyDescriptor);
+        }
+        let public_descriptor = wallet.public_descriptor(KeychainKind::External).clone();
+        let is_watched = |watched_wallets: &[Wallet]| watched_wallets.iter()
+            .any(|w| *w.public_descriptor(KeychainKind::External) == public_descriptor);
+        if is_watched(&self.watched_wallets.read().unwrap()) {
+            return Ok(());
+        }
+
+        if let Some(rpc) = self.bitcoind_rpc() {
+            info!(%public_descriptor, "Scanning for txs of imported watch-only descriptor...");
+            sync_watched_wallet(&rpc, &mut wallet)?;
+        }
+        let mut watched_wallets = self.watched_wallets.write().unwrap();
+        if !is_watched(&watched_wallets) {
+            info!(%public_descriptor, balance_total = %wallet.balance().total(), "Watching imported descriptor.");
+            watched_wallets.push(wallet);
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<()> {
+        let encrypted_descriptors = self.encrypted_descriptors.as_ref().ok_or(WalletErrorKind::NoPassphrase)?;
+        let mut wallet = self.wallet_mut();
+        wallet.set_keymaps(encrypted_descriptors.keychains.iter().map(|&keychain| (keychain, KeyMap::new())));
+        // Erase the key in place, as taking it out of the mutex would leave its bytes behind there.
+        let mut signing_key = self.signing_key.lock().unwrap();
+        if let Some(signing_key) = signing_key.as_mut() {
+            signing_key.private_key.non_secure_erase();
+            signing_key.chain_code = ChainCode::from([0; 32]);
+        }
+        *signing_key = None;
+        info!("Wallet locked.");
+        Ok(())
+    }
+
+    fn unlock(&self, passphrase: &str) -> Result<()> {
+        let encrypted_descriptors = self.encrypted_descriptors.as_ref().ok_or(WalletErrorKind::NoPassphrase)?;
+        let private_descriptors = encrypted_descriptors.decrypt(passphrase)?;
+        let mut wallet = self.wallet_mut();
+        let mut signing_key = self.signing_key.lock().unwrap();
+        for (keychain, descriptor) in &private_descriptors {
+            let (_, keymap) = descriptor.as_str().into_wallet_descriptor(wallet.secp_ctx(), wallet.network().into())?;
+            *signing_key = signing_key.or_else(|| master_key(&keymap));
+            wallet.set_keymap(*keychain, keymap);
+        }
+        info!("Wallet unlocked.");
+        Ok(())
+    }
+
+    async fn estimate_fee_rate(&self, target_blocks: u16) -> Result<Option<FeeRate>> {
+        let estimate = match self.broadcaster.read().unwrap().as_ref().ok_or(WalletErrorKind::NotConnected)? {
+            Broadcaster::BitcoindRpc(rpc) => {
+                let estimate = call_rpc(rpc, |rpc| rpc.estimate_smart_fee(target_blocks, None))?;
+                // Bitcoin Core gives the fee rate per kvB, which is four times per kwu.
+                estimate.fee_rate.map(|fee_per_kvb| FeeRate::from_sat_per_kwu(fee_per_kvb.to_sat() / 4))
+            }
+            Broadcaster::CompactBlockFilters(_) => None,
+            Broadcaster::Electrum(client) => {
+                // The server gives the fee rate in BTC per kvB, or -1 if it has no estimate.
+                let btc_per_kvb = task::block_in_place(|| client.inner.estimate_fee(target_blocks.into()))?;
+                Amount::from_btc(btc_per_kvb).ok().map(|fee_per_kvb| FeeRate::from_sat_per_kwu(fee_per_kvb.to_sat() / 4))
+            }
+        };
+        match (estimate, &self.fee_estimator) {
+            (None, Some(fee_estimator)) => {
+                debug!(target_blocks, "Backend has no fee rate estimate; falling back to external estimator.");
+                fee_estimator.estimate(target_blocks).await.map(Some)
+            }
+            (estimate, _) => Ok(estimate),
+        }
+    }
+
+    fn get_merkle_proof(&self, txid: Txid, block_hash: BlockHash) -> Result<Option<MerkleProof>> {
+        match self.broadcaster.read().unwrap().as_ref().ok_or(WalletErrorKind::NotConnected)? {
+            Broadcaster::BitcoindRpc(rpc) => {
+                // Take only the header & txids from the node, so that the path to the Merkle root
+                // committed to in the header is computed (and then checked) here.
+                let (block_header, block_info) = call_rpc(rpc, |rpc| {
+                    Ok((rpc.get_block_header(&block_hash)?, rpc.get_block_info(&block_hash)?))
+                })?;
+                let tx_index = (0..).zip(&block_info.tx).find_map(|(i, id)| (*id == txid).then_some(i))
+                    .ok_or(WalletErrorKind::TxNotInBlock(txid, block_hash))?;
+                Ok(MerkleProof::from_block_txids(block_header, &block_info.tx, tx_index))
+            }
+            Broadcaster::CompactBlockFilters(_) | Broadcaster::Electrum(_) => Ok(None),
+        }
+    }
+}
+
+/// Holds the wallets created at runtime, keyed by wallet ID, so that concurrent trades may be
+/// funded from different wallets on one server, alongside the daemon's own (default) wallet.
+pub struct WalletManager {
+    default_wallet: Arc<dyn WalletService + Send + Sync>,
+    wallets: RwLock<HashMap<String, Arc<dyn WalletService + Send + Sync>>>,
+    // The directory that created wallets are persisted to, if any, each to '<wallet_id>.sqlite':
+    data_dir: Option<PathBuf>,
+    // The backend that newly created wallets are connected to, if any, along with the shutdown token:
+    backend: Option<(WalletBackend, CancellationToken)>,
+    tor_proxy: Option<SocketAddr>,
+}
+
+impl WalletManager {
+    const MAX_WALLET_ID_LEN: usize = 64;
+
+    /// Manage wallets alongside the given default wallet, creating them on its network.
+    pub fn new(default_wallet: Arc<dyn WalletService + Send + Sync>) -> Self {
+        Self { default_wallet, wallets: RwLock::default(), data_dir: None, backend: None, tor_proxy: None }
+    }
+
+    /// Persist each created wallet to a database in the given directory, named after its wallet ID,
+    /// so that its state survives a restart. Creating the wallet again under the same ID, with the
+    /// same descriptor, then loads it. Without a directory, created wallets are not persisted.
+    #[must_use]
+    pub fn with_data_dir(self, data_dir: Option<PathBuf>) -> Self { Self { data_dir, ..self } }
+
+    /// Connect each newly created wallet to the given backend, continually syncing it until
+    /// `shutdown` is cancelled. Without a backend, created wallets are left unconnected.
+    #[must_use]
+    pub fn with_backend(self, backend: WalletBackend, shutdown: CancellationToken) -> Self {
+        Self { backend: Some((backend, shutdown)), ..self }
+    }
+
+    /// Route the network traffic of each newly created wallet through the given Tor SOCKS5 proxy,
+    /// as with [`WalletServiceImpl::with_tor_proxy`].
+    #[must_use]
+    pub fn with_tor_proxy(self, tor_proxy: Option<SocketAddr>) -> Self { Self { tor_proxy, ..self } }
+
+    pub fn default_wallet(&self) -> &Arc<dyn WalletService + Send + Sync> { &self.default_wallet }
+
+    /// Create a wallet with the given descriptor, registered under `wallet_id`, or load it from the
+    /// data directory if it was persisted there before.
+    ///
+    /// # Errors
+    /// Will return `Err` if the wallet ID or descriptor is invalid, a wallet with the given ID
+    /// already exists, or the wallet can't be persisted
+    pub fn create_wallet(&self, wallet_id: &str, descriptor: &str) -> Result<()> {
+        Self::validate_wallet_id(wallet_id)?;
+        let mut wallets = self.wallets.write().unwrap();
+        if wallets.contains_key(wallet_id) {
+            return Err(WalletErrorKind::WalletExists(wallet_id.to_owned()));
+        }
+        let db_path = match &self.data_dir {
+            Some(data_dir) => {
+                std::fs::create_dir_all(data_dir)?;
+                Some(data_dir.join(format!("{wallet_id}.sqlite")))
+            }
+            None => None,
+        };
+        let network = self.default_wallet.network();
+        let wallet_service = WalletServiceImpl::from_descriptor(descriptor, network, db_path.as_deref())?;
+        let wallet_service = Arc::new(wallet_service.with_tor_proxy(self.tor_proxy));
+        if let Some((backend, shutdown)) = &self.backend {
+            wallet_service.clone().spawn_connection(backend.clone(), shutdown.clone());
+        }
+        wallets.insert(wallet_id.to_owned(), wallet_service);
+        Ok(())
+    }
+
+    /// The wallet with the given ID, or the default wallet if the ID is empty.
+    pub fn get_wallet(&self, wallet_id: &str) -> Option<Arc<dyn WalletService + Send + Sync>> {
+        if wallet_id.is_empty() {
+            return Some(self.default_wallet.clone());
+        }
+        self.wallets.read().unwrap().get(wallet_id).cloned()
+    }
+
+    /// Check that the wallet ID is non-empty & not too long, and safe to use as a file name.
+    fn validate_wallet_id(wallet_id: &str) -> Result<()> {
+        let is_valid = !wallet_id.is_empty() && wallet_id.len() <= Self::MAX_WALLET_ID_LEN
+            && wallet_id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if is_valid { Ok(()) } else { Err(WalletErrorKind::InvalidWalletId(wallet_id.to_owned())) }
+    }
+}
+
+impl SendOptions {
+    const fn sequence(self) -> Sequence {
+        // Both sequence numbers leave any absolute lock time enabled.
+        if self.rbf() { Sequence::ENABLE_RBF_NO_LOCKTIME } else { Sequence::ENABLE_LOCKTIME_NO_RBF }
+    }
+
+    fn apply_to<Cs>(self, builder: &mut TxBuilder<'_, Cs>, script_pubkey: ScriptBuf, amount: Amount) {
+        builder.add_recipient(script_pubkey, amount)
+            .set_exact_sequence(self.sequence())
+            .ordering(bip69_ordering());
+        if let Some(lock_time) = self.lock_time() {
+            builder.nlocktime(lock_time);
+        }
+    }
+
+    /// Check that any block-height-based lock time is above the given chain tip. (Any time-based
+    /// lock time is necessarily in the correct epoch range, by construction.)
+    pub fn check_lock_time(self, tip_height: u32) -> Result<()> {
+        if let Some(absolute::LockTime::Blocks(height)) = self.lock_time() {
+            if height.to_consensus_u32() <= tip_height {
+                return Err(WalletErrorKind::LockTimeNotInFuture { lock_time: height.into(), tip_height });
+            }
+        }
+        Ok(())
+    }
+
+    fn is_final_at(self, tip_height: u32) -> bool {
+        match self.lock_time() {
+            None => true,
+            Some(absolute::LockTime::Blocks(height)) => height.to_consensus_u32() <= tip_height,
+            Some(absolute::LockTime::Seconds(time)) => u64::from(time.to_consensus_u32()) <= unix_time_now(),
+        }
+    }
+}
+
+/// Check that no input of the given tx spends an outpoint already spent by a confirmed tx in the
+/// wallet's tx graph, so that we don't sign or broadcast a tx that can never confirm. Conflicts
+/// with unconfirmed txs are left for the mempool to resolve, as the tx may be a replacement.
+pub fn check_no_double_spend(tx: &Transaction, wallet: &Wallet) -> Result<(), DoubleSpendError> {
+    let txid = tx.compute_txid();
+    for tx_in in &tx.input {
+        let outpoint = tx_in.previous_output;
+        for &conflicting_txid in wallet.tx_graph().outspends(outpoint) {
+            if conflicting_txid != txid
+                && wallet.get_tx(conflicting_txid).is_some_and(|wallet_tx| wallet_tx.chain_position.is_confirmed()) {
+                return Err(DoubleSpendError { outpoint, conflicting_txid });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Error)]
+#[error("input {outpoint} is already spent by confirmed tx {conflicting_txid}")]
+pub struct DoubleSpendError {
+    pub outpoint: OutPoint,
+    pub conflicting_txid: Txid,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TxConfidence {
     pub wallet_tx: WalletTx,
     pub num_confirmations: u32,
+    /// Set if we broadcast the tx but it went missing from the mempool & chain for longer than the
+    /// grace period, or a peer rejected it, say for too low a fee or as a double-spend.
+    pub broadcast_failed: bool,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -190,11 +1597,21 @@ pub struct WalletTx {
     pub txid: Txid,
     pub tx: Arc<Transaction>,
     pub chain_position: ChainPosition<ConfirmationBlockTime>,
+    /// Whether any input of the tx has a sequence number below `0xFFFFFFFE`, signalling
+    /// replaceability (BIP 125), so that it may be fee-bumped while unconfirmed.
+    pub signals_rbf: bool,
+}
+
+impl WalletTx {
+    pub fn new(txid: Txid, tx: Arc<Transaction>, chain_position: ChainPosition<ConfirmationBlockTime>) -> Self {
+        let signals_rbf = tx.is_explicitly_rbf();
+        Self { txid, tx, chain_position, signals_rbf }
+    }
 }
 
 impl From<bdk_wallet::WalletTx<'_>> for WalletTx {
     fn from(value: bdk_wallet::WalletTx) -> Self {
-        Self { txid: value.tx_node.txid, tx: value.tx_node.tx, chain_position: value.chain_position }
+        Self::new(value.tx_node.txid, value.tx_node.tx, value.chain_position)
     }
 }
 
@@ -204,6 +1621,663 @@ pub type Result<T, E = WalletErrorKind> = std::result::Result<T, E>;
 #[error(transparent)]
 #[non_exhaustive]
 pub enum WalletErrorKind {
+    #[error("not connected to the Bitcoin network")]
+    NotConnected,
+    #[error("timed out waiting for tx confirmations")]
+    Timeout,
+    #[error("lock time {lock_time} is not in the future (chain tip at height {tip_height})")]
+    LockTimeNotInFuture {
+        lock_time: absolute::LockTime,
+        tip_height: u32,
+    },
     BitcoindRpc(#[from] bdk_bitcoind_rpc::bitcoincore_rpc::Error),
+    BitcoindRpcPool(#[from] r2d2::Error),
     ApplyHeader(#[from] bdk_wallet::chain::local_chain::ApplyHeaderError),
+    CannotConnect(#[from] bdk_wallet::chain::local_chain::CannotConnectError),
+    PeerAddressParse(#[from] std::net::AddrParseError),
+    CompactBlockFilterBuilder(#[from] bdk_kyoto::builder::BuilderError),
+    CompactBlockFilterClient(#[from] bdk_kyoto::ClientError),
+    CompactBlockFilterUpdate(#[from] bdk_kyoto::UpdateError),
+    Electrum(#[from] electrum_client::Error),
+    #[error("only compact block filter & Electrum connections can be routed through the Tor proxy")]
+    TorProxyUnsupported,
+    #[error("malformed ZMQ notification")]
+    MalformedZmqMessage,
+    Zmq(#[from] zeromq::ZmqError),
+    Decode(#[from] consensus::encode::Error),
+    AddressParse(#[from] bdk_wallet::bitcoin::address::ParseError),
+    #[error("could not parse address: {0}")]
+    InvalidAddress(bdk_wallet::bitcoin::address::ParseError),
+    #[error("address is not valid on the wallet's network ({0})")]
+    AddressNetworkMismatch(Network),
+    #[error("cannot sign a message with the key of address {0}, not a taproot address of the wallet")]
+    CannotSignMessage(Address),
+    Descriptor(#[from] bdk_wallet::descriptor::DescriptorError),
+    #[error("wallet already exists: {0}")]
+    WalletExists(String),
+    #[error("invalid wallet id (must be 1-64 alphanumeric, '-' or '_' chars): {0:?}")]
+    InvalidWalletId(String),
+    LoadWallet(#[from] LoadError),
+    WalletDatabase(#[from] rusqlite::Error),
+    Io(#[from] io::Error),
+    #[error("wallet is locked")]
+    WalletLocked,
+    #[error("no wallet passphrase set")]
+    NoPassphrase,
+    #[error("incorrect wallet passphrase")]
+    IncorrectPassphrase,
+    #[error("watch-only wallet cannot sign")]
+    WatchOnly,
+    FeeEstimatorRequest(#[from] minreq::Error),
+    #[error("invalid fee estimate: {0}")]
+    InvalidFeeEstimate(String),
+    #[error("tx {0} is not in block {1}")]
+    TxNotInBlock(Txid, BlockHash),
+    #[error("no more addresses available for {0:?}")]
+    KeyRangeExhausted(KeychainPurpose),
+    #[error("watch-only descriptor must not contain private keys")]
+    PrivateKeyInWatchOnlyDescriptor,
+    CreateTx(#[from] bdk_wallet::error::CreateTxError),
+    Signer(#[from] SignerError),
+    ExtractTx(#[from] Box<ExtractTxError>),
+    Bip32(#[from] bdk_wallet::bitcoin::bip32::Error),
+    TxSize(#[from] TxSizeError),
+    DoubleSpend(#[from] DoubleSpendError),
+    Csv(#[from] csv::Error),
+    AddUtxo(#[from] bdk_wallet::tx_builder::AddUtxoError),
+    CalculateFee(#[from] bdk_wallet::chain::tx_graph::CalculateFeeError),
+}
+
+impl From<ExtractTxError> for WalletErrorKind {
+    fn from(error: ExtractTxError) -> Self { Box::new(error).into() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use bdk_kyoto::{RejectReason, Wtxid};
+    use bdk_wallet::bitcoin::{ScriptBuf, transaction};
+    use bdk_wallet::chain::{BlockId, TxUpdate};
+    use bdk_wallet::Update;
+    use testenv::TestEnv;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_emitter_resumes_from_wallet_tip() -> anyhow::Result<()> {
+        let mut testenv = TestEnv::new()?;
+        let rpc = testenv.bitcoin_core_rpc_client()?;
+        let wallet_service = WalletServiceImpl::new();
+
+        // Sync the wallet up to block 100, as if from an earlier run of the service.
+        let block_count = u32::try_from(testenv.block_count()?)?;
+        if block_count < 100 {
+            testenv.mine_blocks((100 - block_count) as usize)?;
+        }
+        let mut emitter = new_emitter(&rpc, &wallet_service.wallet.read().unwrap());
+        wallet_service.sync_from_rpc_emitter(&mut emitter)?;
+        let tip_height = wallet_service.wallet.read().unwrap().latest_checkpoint().height();
+        assert!(tip_height >= 100);
+
+        // Restart with a fresh emitter from the wallet checkpoint, after 10 more blocks are mined.
+        testenv.mine_blocks(10)?;
+        let mut emitter = new_emitter(&rpc, &wallet_service.wallet.read().unwrap());
+        let mut emitted_heights = vec![];
+        while let Some(block) = emitter.next_block()? {
+            emitted_heights.push(block.block_height());
+        }
+        assert_eq!(emitted_heights, (tip_height + 1..=tip_height + 10).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_electrum_scans_up_to_stop_gap() -> anyhow::Result<()> {
+        let mut testenv = TestEnv::new()?;
+        let wallet_service = Arc::new(WalletServiceImpl::new());
+        let peek_address = |index| wallet_service.wallet.read().unwrap().peek_address(KeychainKind::External, index).address;
+        let txids = [
+            testenv.fund_address(&peek_address(5), Amount::from_sat(100_000))?,
+            testenv.fund_address(&peek_address(20), Amount::from_sat(200_000))?,
+        ];
+        for txid in txids {
+            testenv.wait_for_tx(txid)?;
+        }
+
+        let config = ElectrumConfig { stop_gap: 10, batch_size: 5 };
+        let backend = WalletBackend::Electrum { url: testenv.electrum_url(), config };
+        let shutdown = CancellationToken::new();
+        let connection = wallet_service.clone().spawn_connection(backend, shutdown.clone());
+        time::timeout(Duration::from_secs(10), async {
+            while wallet_service.balance().total() == Amount::ZERO {
+                time::sleep(Duration::from_millis(100)).await;
+            }
+        }).await?;
+        // The address more than the stop gap past the last used one is never scanned.
+        assert_eq!(wallet_service.balance().total(), Amount::from_sat(100_000));
+        shutdown.cancel();
+        connection.await??;
+        Ok(())
+    }
+
+    //noinspection SpellCheckingInspection
+    #[test]
+    fn test_lock_and_unlock() {
+        let address: Address<NetworkUnchecked> = "bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz"
+            .parse().unwrap();
+        let send = |wallet_service: &WalletServiceImpl| {
+            wallet_service.send(address.clone(), Amount::from_sat(10_000), SendOptions::new()).unwrap_err()
+        };
+        assert!(matches!(WalletServiceImpl::new().lock(), Err(WalletErrorKind::NoPassphrase)));
+
+        let wallet_service = WalletServiceImpl::new().with_passphrase(Some("correct horse"));
+        let signing_key = *wallet_service.signing_key.lock().unwrap();
+        assert!(signing_key.is_some());
+        let encrypted_descriptors = wallet_service.encrypted_descriptors.as_ref().unwrap();
+        assert_eq!(encrypted_descriptors.keychains, [KeychainKind::External, KeychainKind::Internal]);
+        assert!(!encrypted_descriptors.ciphertext.windows(4).any(|window| window == b"tprv"));
+
+        wallet_service.lock().unwrap();
+        assert!(wallet_service.signing_key.lock().unwrap().is_none());
+        assert!(matches!(send(&wallet_service), WalletErrorKind::WalletLocked));
+        assert!(matches!(wallet_service.unlock("wrong horse"), Err(WalletErrorKind::IncorrectPassphrase)));
+        assert!(matches!(send(&wallet_service), WalletErrorKind::WalletLocked));
+
+        wallet_service.unlock("correct horse").unwrap();
+        assert_eq!(*wallet_service.signing_key.lock().unwrap(), signing_key);
+        // The unfunded wallet can now get as far as building the tx.
+        assert!(matches!(send(&wallet_service), WalletErrorKind::CreateTx(_)));
+        assert!(!wallet_service.wallet.read().unwrap().get_signers(KeychainKind::Internal).signers().is_empty());
+    }
+
+    //noinspection SpellCheckingInspection
+    #[test]
+    fn test_watch_only() {
+        let full_wallet_service = WalletServiceImpl::new();
+        let public_descriptor = full_wallet_service.wallet.read().unwrap()
+            .public_descriptor(KeychainKind::External).to_string();
+        let wallet_service = WalletServiceImpl::watch_only(&public_descriptor, Network::Regtest).unwrap();
+        assert_eq!(wallet_service.mode(), WalletMode::WatchOnly);
+        assert_eq!(full_wallet_service.mode(), WalletMode::Full);
+
+        assert_eq!(wallet_service.balance(), Balance::default());
+        assert!(wallet_service.list_unspent().is_empty());
+        assert!(matches!(wallet_service.reveal_next_address(KeychainPurpose::External), Err(WalletErrorKind::WatchOnly)));
+        let address = "bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz".parse().unwrap();
+        let result = wallet_service.send(address, Amount::from_sat(10_000), SendOptions::new());
+        assert!(matches!(result, Err(WalletErrorKind::WatchOnly)));
+
+        let result = WalletServiceImpl::watch_only(EXTERNAL_DESCRIPTOR, Network::Regtest);
+        assert!(matches!(result, Err(WalletErrorKind::PrivateKeyInWatchOnlyDescriptor)));
+    }
+
+    #[test]
+    fn test_import_watch_descriptor() {
+        let wallet_service = WalletServiceImpl::new();
+        // Watch the wallet's own change keychain, as though it were some other wallet's.
+        let other_wallet_service = WalletServiceImpl::from_descriptor(INTERNAL_DESCRIPTOR, Network::Regtest, None).unwrap();
+        let public_descriptor = other_wallet_service.wallet.read().unwrap()
+            .public_descriptor(KeychainKind::External).to_string();
+
+        wallet_service.import_watch_descriptor(&public_descriptor).unwrap();
+        wallet_service.import_watch_descriptor(&public_descriptor).unwrap();
+        assert_eq!(wallet_service.watched_wallets.read().unwrap().len(), 1);
+        assert!(matches!(wallet_service.import_watch_descriptor("tr(invalid)"), Err(WalletErrorKind::Descriptor(_))));
+        assert!(matches!(wallet_service.import_watch_descriptor(EXTERNAL_DESCRIPTOR),
+            Err(WalletErrorKind::PrivateKeyInWatchOnlyDescriptor)));
+        assert_eq!(wallet_service.watched_wallets.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_wallet_manager_persistence() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let wallet_manager = || WalletManager::new(Arc::new(WalletServiceImpl::new()))
+            .with_data_dir(Some(data_dir.path().to_owned()));
+
+        let manager = wallet_manager();
+        manager.create_wallet("trader-1", INTERNAL_DESCRIPTOR).unwrap();
+        let wallet = manager.get_wallet("trader-1").unwrap();
+        let address = wallet.reveal_next_address(KeychainPurpose::External).unwrap();
+        let fee_bump_address = wallet.reveal_next_address(KeychainPurpose::BuyerFeeBump).unwrap();
+        wallet.reveal_next_address(KeychainPurpose::SellerFeeBump).unwrap();
+        drop((manager, wallet));
+
+        // After a restart, creating the wallet again loads it, with its keys & revealed addresses,
+        // and each key range resumes after the last index reserved from it, below the highest too.
+        let manager = wallet_manager();
+        manager.create_wallet("trader-1", INTERNAL_DESCRIPTOR).unwrap();
+        let wallet = manager.get_wallet("trader-1").unwrap();
+        assert_eq!(wallet.reveal_next_address(KeychainPurpose::External).unwrap().index, address.index + 1);
+        assert_eq!(wallet.reveal_next_address(KeychainPurpose::BuyerFeeBump).unwrap().index, fee_bump_address.index + 1);
+        assert!(wallet.derive_silent_payment_address().is_ok());
+
+        // The persisted wallet can't be loaded with another descriptor or network, nor can wallet
+        // IDs which aren't safe file names be used.
+        let db_path = data_dir.path().join("trader-1.sqlite");
+        assert!(matches!(WalletServiceImpl::from_descriptor(EXTERNAL_DESCRIPTOR, Network::Regtest, Some(&db_path)),
+            Err(WalletErrorKind::LoadWallet(_))));
+        assert!(matches!(WalletServiceImpl::from_descriptor(INTERNAL_DESCRIPTOR, Network::Testnet, Some(&db_path)),
+            Err(WalletErrorKind::LoadWallet(_))));
+        for wallet_id in ["", "../trader-1", "trader 1", &"x".repeat(65)] {
+            assert!(matches!(manager.create_wallet(wallet_id, INTERNAL_DESCRIPTOR), Err(WalletErrorKind::InvalidWalletId(_))));
+        }
+    }
+
+    #[test]
+    fn test_electrum_tx_cache_kept_in_wallet_db() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let db_path = data_dir.path().join("trader-1.sqlite");
+        let load_wallet = || WalletServiceImpl::from_descriptor(INTERNAL_DESCRIPTOR, Network::Regtest, Some(&db_path)).unwrap();
+        let tx = Arc::new(test_tx(vec![spend(OutPoint::new(Txid::all_zeros(), 0))],
+            vec![pay(100_000, ScriptBuf::new_p2a())]));
+
+        load_wallet().cache_electrum_txs(std::slice::from_ref(&tx));
+        let cached_txs = load_wallet().tx_cache.unwrap().into_inner().unwrap().load().unwrap();
+        assert_eq!(cached_txs, [tx]);
+        assert!(WalletServiceImpl::new().tx_cache.is_none());
+    }
+
+    #[test]
+    fn test_derive_silent_payment_address() {
+        let wallet_service = WalletServiceImpl::new();
+        let address = wallet_service.derive_silent_payment_address().unwrap();
+        assert!(address.to_string().starts_with("sprt1q"));
+        assert_eq!(wallet_service.derive_silent_payment_address().unwrap(), address);
+        assert_ne!(address.scan_pub_key, address.spend_pub_key);
+
+        let public_descriptor = wallet_service.wallet.read().unwrap()
+            .public_descriptor(KeychainKind::External).to_string();
+        let watch_only_service = WalletServiceImpl::watch_only(&public_descriptor, Network::Regtest).unwrap();
+        assert!(watch_only_service.with_xpriv(|_| ()).is_none());
+        assert!(matches!(watch_only_service.derive_silent_payment_address(), Err(WalletErrorKind::WatchOnly)));
+    }
+
+    #[test]
+    fn test_reveal_next_address_by_purpose() {
+        let wallet_service = WalletServiceImpl::new();
+        let reveal = |purpose| wallet_service.reveal_next_address(purpose).unwrap().index;
+
+        assert_eq!(reveal(KeychainPurpose::External), 0);
+        assert_eq!(reveal(KeychainPurpose::SellerFeeBump), 2000);
+        assert_eq!(reveal(KeychainPurpose::BuyerFeeBump), 1000);
+        assert_eq!(reveal(KeychainPurpose::External), 1);
+        assert_eq!(reveal(KeychainPurpose::BuyerFeeBump), 1001);
+        // The addresses below the highest reserved index are watched, though not handed out.
+        assert_eq!(wallet_service.wallet.read().unwrap().derivation_index(KeychainKind::External), Some(2000));
+
+        // Funds received on a watched address not handed out here move its range on past it.
+        let script_pubkey = wallet_service.wallet.read().unwrap().peek_address(KeychainKind::External, 5).script_pubkey();
+        let funding_tx = test_tx(vec![spend(OutPoint::new(Txid::all_zeros(), 0))], vec![pay(100_000, script_pubkey)]);
+        wallet_service.wallet.write().unwrap().apply_unconfirmed_txs([(funding_tx, unix_time_now())]);
+        assert_eq!(reveal(KeychainPurpose::External), 6);
+    }
+
+    #[test]
+    fn test_key_range_registry() {
+        let mut registry = KeyRangeRegistry::new(vec![
+            (KeychainPurpose::External, 0..2),
+            (KeychainPurpose::BuyerFeeBump, 2..3),
+        ]);
+        assert_eq!(registry.range(KeychainPurpose::External), Some(&(0..2)));
+        assert_eq!(registry.reserve_next_index(KeychainPurpose::BuyerFeeBump).unwrap(), 2);
+        assert!(matches!(registry.reserve_next_index(KeychainPurpose::BuyerFeeBump),
+            Err(WalletErrorKind::KeyRangeExhausted(KeychainPurpose::BuyerFeeBump))));
+        assert!(matches!(registry.reserve_next_index(KeychainPurpose::SellerFeeBump),
+            Err(WalletErrorKind::KeyRangeExhausted(KeychainPurpose::SellerFeeBump))));
+
+        // Indices outside every range are ignored, and a range never moves back.
+        registry.resume_after([0, 5]);
+        assert_eq!(registry.reserve_next_index(KeychainPurpose::External).unwrap(), 1);
+        registry.resume_after([0]);
+        assert!(matches!(registry.reserve_next_index(KeychainPurpose::External),
+            Err(WalletErrorKind::KeyRangeExhausted(KeychainPurpose::External))));
+    }
+
+    #[test]
+    #[should_panic(expected = "key ranges for External and SellerFeeBump overlap")]
+    fn test_key_range_registry_overlap() {
+        KeyRangeRegistry::new(vec![
+            (KeychainPurpose::External, 0..1000),
+            (KeychainPurpose::BuyerFeeBump, 1000..2000),
+            (KeychainPurpose::SellerFeeBump, 999..1000),
+        ]);
+    }
+
+    #[test]
+    fn test_sign_psbt() {
+        let wallet_service = WalletServiceImpl::new().with_passphrase(Some("correct horse"));
+        let public_descriptor = wallet_service.wallet.read().unwrap()
+            .public_descriptor(KeychainKind::External).to_string();
+        let watch_only_service = WalletServiceImpl::watch_only(&public_descriptor, Network::Regtest).unwrap();
+
+        // Fund both wallets with the same unconfirmed coin, then have the watch-only wallet build a
+        // PSBT spending it, as an external (say air-gapped) setup would.
+        let address = wallet_service.reveal_next_address(KeychainPurpose::External).unwrap().address;
+        let funding_tx = test_tx(vec![spend(OutPoint::new(Txid::all_zeros(), 0))],
+            vec![pay(100_000, address.script_pubkey())]);
+        for service in [&wallet_service, &watch_only_service] {
+            service.wallet.write().unwrap().apply_unconfirmed_txs([(funding_tx.clone(), unix_time_now())]);
+        }
+        let mut psbt = {
+            let mut wallet = watch_only_service.wallet.write().unwrap();
+            let mut builder = wallet.build_tx();
+            builder.add_recipient(address.script_pubkey(), Amount::from_sat(50_000));
+            builder.finish().unwrap()
+        };
+        assert!(matches!(watch_only_service.sign_psbt(&mut psbt.clone()), Err(WalletErrorKind::WatchOnly)));
+
+        wallet_service.lock().unwrap();
+        assert!(matches!(wallet_service.sign_psbt(&mut psbt.clone()), Err(WalletErrorKind::WalletLocked)));
+        wallet_service.unlock("correct horse").unwrap();
+
+        assert!(wallet_service.sign_psbt(&mut psbt).unwrap());
+        let tx = psbt.extract_tx().unwrap();
+        assert_eq!(tx.input[0].previous_output, OutPoint::new(funding_tx.compute_txid(), 0));
+        assert_eq!(tx.input[0].witness.len(), 1);
+    }
+
+    //noinspection SpellCheckingInspection
+    #[test]
+    fn test_bip69_ordering() {
+        // Txids in display (reversed) byte order. Their internal byte order sorts the other way.
+        let txid_lo = Txid::from_str("00000000000000000000000000000000000000000000000000000000000000ff").unwrap();
+        let txid_hi = Txid::from_str("ff00000000000000000000000000000000000000000000000000000000000000").unwrap();
+        let input = |txid, vout| spend(OutPoint { txid, vout });
+        let output = |sats, script: &[u8]| pay(sats, ScriptBuf::from_bytes(script.to_vec()));
+        let mut tx = test_tx(vec![input(txid_hi, 0), input(txid_lo, 1), input(txid_lo, 0)],
+            vec![output(2_000, &[0x00]), output(1_000, &[0x51, 0x01]), output(1_000, &[0x51, 0x00])]);
+
+        bip69_ordering().sort_tx(&mut tx);
+
+        assert_eq!(tx.input, [input(txid_lo, 0), input(txid_lo, 1), input(txid_hi, 0)]);
+        assert_eq!(tx.output, [output(1_000, &[0x51, 0x00]), output(1_000, &[0x51, 0x01]), output(2_000, &[0x00])]);
+    }
+
+    #[test]
+    fn test_validate_tx_size() {
+        let tx = |num_outputs: usize| test_tx(vec![TxIn::default()],
+            vec![pay(1_000, ScriptBuf::new_op_return([0; 32])); num_outputs]);
+        // Each 32-byte OP_RETURN output takes 8 + 1 + 34 = 43 vbytes.
+        assert_eq!(validate_tx_size(&tx(1)), Ok(()));
+        let vsize = tx(2_324).vsize();
+        assert!(vsize <= MAX_STANDARD_TX_VSIZE && vsize + 43 > MAX_STANDARD_TX_VSIZE);
+        assert_eq!(validate_tx_size(&tx(2_324)), Ok(()));
+        assert_eq!(validate_tx_size(&tx(2_325)), Err(TxSizeError::ExceedsMaxStandardSize { vsize: vsize + 43 }));
+    }
+
+    #[test]
+    fn test_export_transactions_csv() {
+        let wallet_service = WalletServiceImpl::new();
+        let [receive_txid, send_txid, internal_txid] = {
+            let mut wallet = wallet_service.wallet.write().unwrap();
+            let external_spk = ScriptBuf::new_p2a();
+            let my_spk = |keychain| wallet.peek_address(keychain, 0).script_pubkey();
+            let (receive_spk, change_spk) = (my_spk(KeychainKind::External), my_spk(KeychainKind::Internal));
+            let tx = |previous_output, output: Vec<(u64, &ScriptBuf)>| Arc::new(test_tx(vec![spend(previous_output)],
+                output.into_iter().map(|(sats, spk)| pay(sats, spk.clone())).collect()));
+            let receive_tx = tx(OutPoint::new(Txid::from_byte_array([1; 32]), 0), vec![(100_000, &receive_spk)]);
+            let send_tx = tx(OutPoint::new(receive_tx.compute_txid(), 0), vec![(60_000, &external_spk), (39_000, &change_spk)]);
+            let internal_tx = tx(OutPoint::new(send_tx.compute_txid(), 1), vec![(38_500, &receive_spk)]);
+            let txs = [receive_tx, send_tx, internal_tx];
+
+            // Confirm the txs in blocks 1, 2 & 3, ten minutes apart.
+            confirm_txs(&mut wallet, 3, (1..).zip(txs.clone()));
+            txs.map(|tx| tx.compute_txid())
+        };
+
+        let csv = wallet_service.export_transactions_csv(1..=2).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), format!("\
+            txid,block_height,confirmation_date,amount_sats,fee_sats,type\n\
+            {receive_txid},1,2023-11-14T22:23:20Z,100000,,receive\n\
+            {send_txid},2,2023-11-14T22:33:20Z,-61000,1000,send\n"));
+
+        let csv = wallet_service.export_transactions_csv(3..=10).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), format!("\
+            txid,block_height,confirmation_date,amount_sats,fee_sats,type\n\
+            {internal_txid},3,2023-11-14T22:43:20Z,-500,500,internal\n"));
+
+        let csv = wallet_service.export_transactions_csv(4..=10).unwrap();
+        assert_eq!(csv, b"txid,block_height,confirmation_date,amount_sats,fee_sats,type\n");
+    }
+
+    #[test]
+    fn test_get_tx_fee() {
+        let wallet_service = WalletServiceImpl::new();
+        let [receive_txid, send_txid] = {
+            let mut wallet = wallet_service.wallet.write().unwrap();
+            let receive_spk = wallet.peek_address(KeychainKind::External, 0).script_pubkey();
+            let tx = |previous_output, sats| Arc::new(test_tx(vec![spend(previous_output)],
+                vec![pay(sats, receive_spk.clone())]));
+            let receive_tx = tx(OutPoint::new(Txid::from_byte_array([1; 32]), 0), 100_000);
+            let send_tx = tx(OutPoint::new(receive_tx.compute_txid(), 0), 99_250);
+            let txs = [receive_tx, send_tx];
+
+            confirm_txs(&mut wallet, 1, txs.clone().map(|tx| (1, tx)));
+            txs.map(|tx| tx.compute_txid())
+        };
+
+        assert_eq!(wallet_service.get_tx_fee(send_txid), Some(Amount::from_sat(750)));
+        // The value of the receive tx's input, from outside the wallet, is unknown.
+        assert_eq!(wallet_service.get_tx_fee(receive_txid), None);
+        assert_eq!(wallet_service.get_tx_fee(Txid::from_byte_array([2; 32])), None);
+    }
+
+    #[test]
+    fn test_bitcoind_rpc_pool_connects_lazily() {
+        let manager = BitcoindRpcConnectionManager::new("http://127.0.0.1:1".into(),
+            Auth::UserPass("user".into(), "secret".into()));
+        assert!(!format!("{manager:?}").contains("secret"));
+        let pool = manager.into_pool(3);
+        assert_eq!((pool.max_size(), pool.state().connections), (3, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "RPC pool size 1 is below the minimum of 2")]
+    fn test_bitcoind_rpc_pool_too_small() {
+        BitcoindRpcConnectionManager::new("http://127.0.0.1:1".into(), Auth::None).into_pool(1);
+    }
+
+    #[tokio::test]
+    async fn test_tor_proxy_refuses_bitcoind_rpc() {
+        let wallet_service = WalletServiceImpl::new().with_tor_proxy(Some(([127, 0, 0, 1], 9050).into()));
+        let pool = BitcoindRpcConnectionManager::new("http://127.0.0.1:1".into(), Auth::None).into_pool(2);
+        let result = wallet_service.connect(WalletBackend::BitcoindRpc(Arc::new(pool)), CancellationToken::new()).await;
+        assert!(matches!(result, Err(WalletErrorKind::TorProxyUnsupported)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_tor_proxy_routes_electrum() {
+        let (port, listener) = TestEnv::get_bound_port().await.unwrap();
+        let wallet_service = WalletServiceImpl::new()
+            .with_tor_proxy(Some(([127, 0, 0, 1], port).into()))
+            .with_max_retries(Some(0));
+        let backend = WalletBackend::Electrum { url: "tcp://127.0.0.1:1".into(), config: ElectrumConfig::default() };
+        let connection = task::spawn(async move { wallet_service.connect(backend, CancellationToken::new()).await });
+
+        // The server is reached through the proxy, which fails the connection by hanging up.
+        drop(listener.accept().await.unwrap());
+        assert!(matches!(connection.await.unwrap(), Err(WalletErrorKind::Electrum(_))));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_confirmations() {
+        let wallet_service = WalletServiceImpl::new();
+        let txid = {
+            let mut wallet = wallet_service.wallet.write().unwrap();
+            let tx = Arc::new(test_tx(vec![spend(OutPoint::new(Txid::from_byte_array([1; 32]), 0))],
+                vec![pay(100_000, wallet.peek_address(KeychainKind::External, 0).script_pubkey())]));
+
+            // Confirm the tx in block 1, with block 2 on top, so that it has two confirmations.
+            confirm_txs(&mut wallet, 2, [(1, tx.clone())]);
+            tx.compute_txid()
+        };
+        wallet_service.sync_tx_confidence_map();
+
+        let timeout = Duration::from_millis(50);
+        assert_eq!(wallet_service.wait_for_confirmations(txid, 1, timeout).await.unwrap(), 2);
+        assert_eq!(wallet_service.wait_for_confirmations(txid, 2, timeout).await.unwrap(), 2);
+        let result = wallet_service.wait_for_confirmations(txid, 3, timeout).await;
+        assert!(matches!(result, Err(WalletErrorKind::Timeout)));
+        let unknown_txid = Txid::from_byte_array([2; 32]);
+        let result = wallet_service.wait_for_confirmations(unknown_txid, 0, timeout).await;
+        assert!(matches!(result, Err(WalletErrorKind::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn test_reject_broadcast_tx() {
+        let wallet_service = WalletServiceImpl::new().with_broadcast_grace_period(0);
+        let script_pubkey = wallet_service.wallet.read().unwrap().peek_address(KeychainKind::External, 0).script_pubkey();
+        let tx = Arc::new(test_tx(vec![spend(OutPoint::new(Txid::from_byte_array([1; 32]), 0))],
+            vec![pay(100_000, script_pubkey)]));
+        let txid = tx.compute_txid();
+        let time = unix_time_now();
+        // As when broadcast over compact block filters, which add the tx to the graph as unconfirmed.
+        wallet_service.wallet_mut().apply_unconfirmed_txs([((*tx).clone(), time)]);
+        wallet_service.broadcast_txs.lock().unwrap()
+            .insert(txid, BroadcastTx { tx: tx.clone(), height: 0, time, failed: false });
+
+        // A rejection of some other tx is ignored.
+        wallet_service.reject_broadcast_tx(RejectPayload { reason: None, wtxid: Wtxid::from_byte_array([2; 32]) });
+        wallet_service.sync_tx_confidence_map();
+        let mut confidence_stream = wallet_service.get_tx_confidence_stream(txid);
+        assert!(!confidence_stream.next().await.unwrap().unwrap().broadcast_failed);
+
+        wallet_service.reject_broadcast_tx(RejectPayload { reason: Some(RejectReason::Fee), wtxid: tx.compute_wtxid() });
+        assert!(confidence_stream.next().await.unwrap().unwrap().broadcast_failed);
+
+        // The rejected tx stays in the graph, which mustn't clear the failure without a mempool sync.
+        wallet_service.check_broadcast_txs(false);
+        assert!(wallet_service.broadcast_txs.lock().unwrap()[&txid].failed);
+    }
+
+    #[test]
+    fn test_get_utxo() {
+        let wallet_service = WalletServiceImpl::new();
+        let tx = {
+            let mut wallet = wallet_service.wallet.write().unwrap();
+            let spk = wallet.peek_address(KeychainKind::External, 0).script_pubkey();
+            let tx = |previous_output| test_tx(vec![spend(previous_output)], vec![pay(100_000, spk.clone())]);
+            let receive_tx = tx(OutPoint::new(Txid::from_byte_array([1; 32]), 0));
+            let spend_tx = tx(OutPoint::new(receive_tx.compute_txid(), 0));
+            wallet.apply_unconfirmed_txs([(receive_tx, 1_700_000_000), (spend_tx.clone(), 1_700_000_001)]);
+            spend_tx
+        };
+
+        let outpoint = OutPoint::new(tx.compute_txid(), 0);
+        let utxo = wallet_service.get_utxo(outpoint).unwrap();
+        assert_eq!((utxo.outpoint, utxo.txout.value), (outpoint, Amount::from_sat(100_000)));
+        // Neither a spent output nor a nonexistent one is found.
+        assert_eq!(wallet_service.get_utxo(tx.input[0].previous_output), None);
+        assert_eq!(wallet_service.get_utxo(OutPoint::new(tx.compute_txid(), 1)), None);
+    }
+
+    #[tokio::test]
+    async fn test_sign_message() {
+        let wallet_service = WalletServiceImpl::new();
+        let (address, unrevealed_address) = {
+            let mut wallet = wallet_service.wallet.write().unwrap();
+            (wallet.reveal_next_address(KeychainKind::External).address,
+                wallet.peek_address(KeychainKind::External, 100).address)
+        };
+
+        let signature = wallet_service.sign_message(&address, "Hello World").await.unwrap();
+        assert!(bip322::verify_simple_signature(&address, "Hello World", &signature));
+        assert!(!bip322::verify_simple_signature(&address, "Hello World!", &signature));
+        assert!(!bip322::verify_simple_signature(&unrevealed_address, "Hello World", &signature));
+
+        // Only the wallet's own revealed addresses may be signed for.
+        let result = wallet_service.sign_message(&unrevealed_address, "Hello World").await;
+        assert!(matches!(result, Err(WalletErrorKind::CannotSignMessage(_))));
+    }
+
+    #[test]
+    fn test_check_no_double_spend() {
+        let wallet_service = WalletServiceImpl::new();
+        let mut wallet = wallet_service.wallet.write().unwrap();
+        let spk = wallet.peek_address(KeychainKind::External, 0).script_pubkey();
+        let tx = |previous_output, sats| test_tx(vec![spend(previous_output)], vec![pay(sats, spk.clone())]);
+        let outpoint = |b| OutPoint::new(Txid::from_byte_array([b; 32]), 0);
+        let confirmed_tx = tx(outpoint(1), 100_000);
+        let unconfirmed_tx = tx(outpoint(2), 100_000);
+
+        // Confirm the first tx in block 1, and leave the second in the mempool.
+        confirm_txs(&mut wallet, 1, [(1, Arc::new(confirmed_tx.clone()))]);
+        wallet.apply_unconfirmed_txs([(unconfirmed_tx, 1_700_000_000)]);
+
+        assert_eq!(check_no_double_spend(&confirmed_tx, &wallet), Ok(()));
+        assert_eq!(check_no_double_spend(&tx(outpoint(2), 99_000), &wallet), Ok(()));
+        assert_eq!(check_no_double_spend(&tx(outpoint(3), 99_000), &wallet), Ok(()));
+        assert_eq!(check_no_double_spend(&tx(outpoint(1), 99_000), &wallet),
+            Err(DoubleSpendError { outpoint: outpoint(1), conflicting_txid: confirmed_tx.compute_txid() }));
+    }
+
+    #[test]
+    fn test_cpfp_child_fee() {
+        let (sats, vbytes) = (Amount::from_sat, Weight::from_vb_unchecked);
+        let target_fee_rate = FeeRate::from_sat_per_vb_u32(10);
+        // A 200 vB parent paying 1 sat/vB and a 100 vB child need 3000 sats between them.
+        assert_eq!(cpfp_child_fee(sats(200), vbytes(200), vbytes(100), target_fee_rate), sats(2_800));
+        assert_eq!(cpfp_child_fee(sats(3_000), vbytes(200), vbytes(100), target_fee_rate), Amount::ZERO);
+        assert_eq!(cpfp_child_fee(sats(5_000), vbytes(200), vbytes(100), target_fee_rate), Amount::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_auto_accelerate_only_stuck_txs() {
+        let wallet_service = WalletServiceImpl::new().with_stuck_tx_timeout(Duration::from_hours(1));
+        let [recent_txid, stuck_txid, confirmed_txid] = {
+            let mut wallet = wallet_service.wallet.write().unwrap();
+            let receive_spk = wallet.peek_address(KeychainKind::External, 0).script_pubkey();
+            let [recent_tx, stuck_tx, confirmed_tx] = [1, 2, 3].map(|i| test_tx(
+                vec![spend(OutPoint::new(Txid::from_byte_array([i; 32]), 0))],
+                vec![pay(100_000, receive_spk.clone())]));
+            let txids = [&recent_tx, &stuck_tx, &confirmed_tx].map(Transaction::compute_txid);
+
+            let now = unix_time_now();
+            wallet.apply_unconfirmed_txs([(recent_tx, now - 600), (stuck_tx, now - 7200)]);
+            confirm_txs(&mut wallet, 1, [(1, Arc::new(confirmed_tx))]);
+            txids
+        };
+
+        assert!(wallet_service.auto_accelerate(Txid::from_byte_array([0; 32])).await.unwrap().is_none());
+        assert!(wallet_service.auto_accelerate(recent_txid).await.unwrap().is_none());
+        assert!(wallet_service.auto_accelerate(confirmed_txid).await.unwrap().is_none());
+        // Only for the stuck tx is there any need for a fee estimate, so a connection.
+        assert!(matches!(wallet_service.auto_accelerate(stuck_txid).await, Err(WalletErrorKind::NotConnected)));
+    }
+
+    #[test]
+    fn test_wallet_tx_signals_rbf() {
+        let wallet_tx = |sequences: &[Sequence]| {
+            let tx = test_tx(sequences.iter().map(|&sequence| TxIn { sequence, ..TxIn::default() }).collect(), vec![]);
+            WalletTx::new(tx.compute_txid(), Arc::new(tx), ChainPosition::Unconfirmed { first_seen: None, last_seen: None })
+        };
+        assert!(!wallet_tx(&[Sequence::MAX, Sequence::ENABLE_LOCKTIME_NO_RBF]).signals_rbf);
+        assert!(wallet_tx(&[Sequence::MAX, Sequence::ENABLE_RBF_NO_LOCKTIME]).signals_rbf);
+        assert!(wallet_tx(&[Sequence::ZERO]).signals_rbf);
+    }
+
+    fn test_tx(input: Vec<TxIn>, output: Vec<TxOut>) -> Transaction {
+        Transaction { version: transaction::Version::TWO, lock_time: absolute::LockTime::ZERO, input, output }
+    }
+
+    fn spend(previous_output: OutPoint) -> TxIn { TxIn { previous_output, ..TxIn::default() } }
+
+    fn pay(sats: u64, script_pubkey: ScriptBuf) -> TxOut { TxOut { value: Amount::from_sat(sats), script_pubkey } }
+
+    /// Extend the wallet's chain with blocks 1 to `tip_height`, ten minutes apart, confirming each of
+    /// the given txs in the block of the given height.
+    fn confirm_txs(wallet: &mut Wallet, tip_height: u32, txs: impl IntoIterator<Item = (u32, Arc<Transaction>)>) {
+        let block_id = |height: u32| BlockId { height, hash: BlockHash::from_byte_array([height.try_into().unwrap(); 32]) };
+        let chain = (1..=tip_height).fold(wallet.latest_checkpoint(), |chain, height| chain.insert(block_id(height)));
+        let mut tx_update = TxUpdate::default();
+        for (height, tx) in txs {
+            let anchor = ConfirmationBlockTime {
+                block_id: block_id(height),
+                confirmation_time: 1_700_000_000 + 600 * u64::from(height),
+            };
+            tx_update.anchors.insert((anchor, tx.compute_txid()));
+            tx_update.txs.push(tx);
+        }
+        wallet.apply_update(Update { tx_update, chain: Some(chain), ..Default::default() }).unwrap();
+    }
 }
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{self, StatusCode};
+use axum::routing::get;
+use rpc::pb::walletrpc::ListUnspentRequest;
+use rpc::pb::walletrpc::wallet_client::WalletClient;
+use rpc::server::{API_KEY_HEADER, Config, WalletImpl, WalletServer};
+use rpc::wallet::WalletServiceMock;
+use testenv::TestEnv;
+use tokio::task;
+use tonic::service::InterceptorLayer;
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request};
+use tower::ServiceExt as _;
+use unimock::{MockFn as _, Unimock, matching};
+
+fn list_unspent_request(api_key: Option<&str>) -> Request<ListUnspentRequest> {
+    let mut request = Request::new(ListUnspentRequest::default());
+    if let Some(api_key) = api_key {
+        request.metadata_mut().insert(API_KEY_HEADER, api_key.parse().unwrap());
+    }
+    request
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_api_key_authentication() {
+    let clause = WalletServiceMock::list_unspent.some_call(matching!()).returns(vec![]);
+    let wallet = WalletImpl::new(Arc::new(Unimock::new(clause).no_verify_in_drop()));
+    let config = Config { api_key: Some("correct-key".to_owned()), ..Config::default() };
+
+    let (port, listener) = TestEnv::get_bound_port().await.expect("listener");
+    task::spawn(Server::builder()
+        .layer(InterceptorLayer::new(config.api_key_interceptor()))
+        .add_service(WalletServer::new(wallet))
+        .serve_with_incoming(TcpIncoming::from(listener)));
+    let mut client = WalletClient::connect(format!("http://127.0.0.1:{port}")).await.unwrap();
+
+    for api_key in [None, Some("wrong-key"), Some("correct-ke")] {
+        let status = client.list_unspent(list_unspent_request(api_key)).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated, "for API key: {api_key:?}");
+    }
+    let response = client.list_unspent(list_unspent_request(Some("correct-key"))).await.unwrap();
+    assert!(response.into_inner().utxos.is_empty());
+}
+
+#[tokio::test]
+async fn test_http_api_key_authentication() {
+    let config = Config { api_key: Some("correct-key".to_owned()), ..Config::default() };
+    let router = config.with_api_key_check(Router::new().route("/", get(|| async { "ok" })));
+
+    for api_key in [None, Some("wrong-key"), Some("correct-key")] {
+        let mut request = http::Request::get("/");
+        if let Some(api_key) = api_key {
+            request = request.header(API_KEY_HEADER, api_key);
+        }
+        let response = router.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let expected_status = if api_key == Some("correct-key") { StatusCode::OK } else { StatusCode::UNAUTHORIZED };
+        assert_eq!(response.status(), expected_status, "for API key: {api_key:?}");
+    }
+}
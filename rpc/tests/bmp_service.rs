@@ -30,9 +30,10 @@ use rpc::pb::bmp_protocol::{self, InitializeRequest, InitializeResponse, Role};
 use rpc::pb::bmp_wallet::wallet_server::WalletServer as BmpWalletServer;
 use rpc::pb::convert::TryProtoInto as _;
 use rpc::server::{MusigImpl, MusigServer, WalletImpl, WalletServer};
-use rpc::wallet::WalletServiceImpl;
+use rpc::wallet::{BitcoindRpcConnectionManager, BitcoindRpcPool, WalletServiceImpl};
 use tokio::net::TcpListener;
 use tokio::task::{self, JoinHandle};
+use tokio_util::sync::CancellationToken;
 use tonic::transport::Server;
 use tonic::transport::server::TcpIncoming;
 use tonic::{Request, Response, Result, Status, transport};
@@ -261,17 +262,17 @@ impl BmpProtocolService for BmpServiceImpl {
 fn spawn_musigd(
     listener: TcpListener,
     client: Arc<BitcoinCoreClient>,
+    rpc_pool: Arc<BitcoindRpcPool>,
     electrum_url: String,
 ) -> JoinHandle<Result<(), transport::Error>> {
-    let musig = MusigImpl::default();
-    let wallet = WalletImpl {
-        wallet_service: Arc::new(WalletServiceImpl::new()),
-    };
+    let wallet_service = Arc::new(WalletServiceImpl::new());
+    let musig = MusigImpl::new(wallet_service.clone());
+    let wallet = WalletImpl::new(wallet_service);
 
     wallet
         .wallet_service
         .clone()
-        .spawn_connection(client.clone());
+        .spawn_connection(rpc_pool.into(), CancellationToken::new());
 
     let bmp_protocol_impl = BmpServiceImpl::new(client, electrum_url);
     let bmp_wallet_service = BmpWalletServiceImpl::default();
@@ -309,8 +310,8 @@ async fn run_musigd_server() -> Result<()> {
     let rpc_pass = Some("bitcoin");
     let rpc_user = Some("bitcoin");
 
-    // Create RPC client
-    let rpc_client = rpc_url
+    // Create RPC client, and a pool of them for the wallet service
+    let (rpc_client, rpc_pool) = rpc_url
         .as_ref()
         .map(|rpc_url| {
             info!(rpc_url, "Connecting to external Bitcoin Core RPC");
@@ -324,8 +325,11 @@ async fn run_musigd_server() -> Result<()> {
                 Auth::CookieFile(home.join(".bitcoin").join(".cookie"))
             };
 
-            BitcoinCoreClient::new(rpc_url, auth)
-                .expect("Failed to construct Bitcoin Core RPC client")
+            let rpc_pool = BitcoindRpcConnectionManager::new(rpc_url.clone(), auth.clone())
+                .into_pool(BitcoindRpcConnectionManager::DEFAULT_POOL_SIZE);
+            let rpc_client = BitcoinCoreClient::new(rpc_url, auth)
+                .expect("Failed to construct Bitcoin Core RPC client");
+            (rpc_client, rpc_pool)
         })
         .expect("RPC_URL must be set");
 
@@ -335,6 +339,7 @@ async fn run_musigd_server() -> Result<()> {
     let _ = spawn_musigd(
         listener,
         Arc::new(rpc_client),
+        Arc::new(rpc_pool),
         electrum_url.unwrap(),
     ).await;
 
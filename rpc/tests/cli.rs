@@ -4,7 +4,8 @@ use std::time::Duration;
 use assert_cmd::assert::Assert;
 use assert_cmd::cargo::cargo_bin_cmd;
 use bdk_wallet::bitcoin::hex::test_hex_unwrap as hex;
-use bdk_wallet::bitcoin::{OutPoint, Transaction, consensus};
+use bdk_wallet::bitcoin::absolute::LockTime;
+use bdk_wallet::bitcoin::{Amount, OutPoint, Transaction, consensus};
 use bdk_wallet::chain::{ChainPosition, ConfirmationBlockTime};
 use bdk_wallet::{KeychainKind, LocalOutput};
 use const_format::str_replace;
@@ -58,17 +59,24 @@ const EXPECTED_LIST_UNSPENT_RESPONSE: &str = r#"{
   ]
 }
 "#;
+const EXPECTED_SEND_RESPONSE: &str = str_replace!(r#"{
+  "txId": "37b560334094515cfdaa0146bfd4ce19e940064c505082031858b0aba3218990",
+  "tx": "$MOCK_TX"
+}
+"#, "$MOCK_TX", MOCK_TX);
 const EXPECTED_NOTIFY_CONFIDENCE_RESPONSE: &str = str_replace!(r#"{
   "rawTx": null,
   "confidenceType": "MISSING",
   "numConfirmations": 0,
-  "confirmationBlockTime": null
+  "confirmationBlockTime": null,
+  "signalsRbf": false
 }
 {
   "rawTx": "$MOCK_TX",
   "confidenceType": "UNCONFIRMED",
   "numConfirmations": 0,
-  "confirmationBlockTime": null
+  "confirmationBlockTime": null,
+  "signalsRbf": true
 }
 {
   "rawTx": "$MOCK_TX",
@@ -78,7 +86,8 @@ const EXPECTED_NOTIFY_CONFIDENCE_RESPONSE: &str = str_replace!(r#"{
     "blockHash": "01b623501ea6b83b14035d8b965eaa8c78eeeaf773f60b35228ae4929e7dad56",
     "blockHeight": 104,
     "confirmationTime": 1743580321
-  }
+  },
+  "signalsRbf": true
 }
 "#, "$MOCK_TX", MOCK_TX);
 
@@ -150,6 +159,28 @@ async fn test_cli_list_unspent() {
         .stderr(str::is_empty());
 }
 
+//noinspection SpellCheckingInspection
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_cli_send() {
+    let clause = WalletServiceMock::send
+        .some_call(matching!((_, amount, options)
+            if *amount == Amount::from_sat(1_000) && options.lock_time() == Some(LockTime::from_consensus(500)) && !options.rbf()
+                && options.coin_age_priority()))
+        .answers(&|_, _, _, _| Ok(mock_tx()));
+    let mock_wallet_service = Unimock::new(clause).no_verify_in_drop();
+
+    let (port, listener) = TestEnv::get_bound_port().await.expect("listener");
+    spawn_wallet_grpc_service(listener, mock_wallet_service);
+
+    task::spawn_blocking(move || assert_cli_with_port(port, ["send",
+        "bcrt1pkar3gerekw8f9gef9vn9xz0qypytgacp9wa5saelpksdgct33qdqan7c89", "1000", "--lock-time", "500", "--no-rbf",
+        "--coin-age-priority"]))
+        .await.unwrap()
+        .success()
+        .stdout(EXPECTED_SEND_RESPONSE)
+        .stderr(str::is_empty());
+}
+
 //noinspection SpellCheckingInspection
 #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_cli_notify_confidence() {
@@ -200,20 +231,15 @@ fn mock_confidence_stream() -> BoxStream<'static, Option<TxConfidence>> {
     let txid = tx.compute_txid();
     let event1 = None;
     let event2 = Some(TxConfidence {
-        wallet_tx: WalletTx {
-            txid,
-            tx: tx.clone(),
-            chain_position: ChainPosition::Unconfirmed { first_seen: Some(0), last_seen: Some(0) },
-        },
+        wallet_tx: WalletTx::new(
+            txid, tx.clone(), ChainPosition::Unconfirmed { first_seen: Some(0), last_seen: Some(0) }),
         num_confirmations: 0,
+        broadcast_failed: false,
     });
     let event3 = Some(TxConfidence {
-        wallet_tx: WalletTx {
-            txid,
-            tx,
-            chain_position: mock_chain_position(),
-        },
+        wallet_tx: WalletTx::new(txid, tx, mock_chain_position()),
         num_confirmations: 1,
+        broadcast_failed: false,
     });
     stream::iter([event1, event2, event3]).chain(stream::pending()).boxed()
 }
@@ -238,7 +264,7 @@ fn spawn_wallet_grpc_service(
     listener: TcpListener,
     wallet_service: impl WalletService + Send + Sync + 'static,
 ) -> JoinHandle<Result<(), transport::Error>> {
-    let wallet = WalletImpl { wallet_service: Arc::new(wallet_service) };
+    let wallet = WalletImpl::new(Arc::new(wallet_service));
     let incoming = TcpIncoming::from(listener);
 
     task::spawn(async move {
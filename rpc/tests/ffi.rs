@@ -0,0 +1,17 @@
+#![cfg(feature = "ffi")]
+
+use std::ffi::c_int;
+
+// Pull in the crate, for the C test to link against its exported functions:
+use rpc as _;
+
+#[link(name = "ffi_test", kind = "static")]
+unsafe extern "C" {
+    fn bisq_ffi_test_init_free() -> c_int;
+}
+
+#[test]
+fn test_ffi_init_free() {
+    // The C test returns the line number of the first failed check, if any.
+    assert_eq!(unsafe { bisq_ffi_test_init_free() }, 0);
+}
@@ -0,0 +1,56 @@
+/*
+ * Exercises the C bindings of the trade model, through the generated header. This is compiled by
+ * the build script (with the 'ffi' feature) and run from the 'ffi' integration test, which links it
+ * against the crate.
+ */
+#include <stddef.h>
+#include <string.h>
+
+#include "bisq_musig.h"
+
+#define CHECK(cond) do { if (!(cond)) return __LINE__; } while (0)
+
+/* Returns zero on success, else the line number of the first failed check. */
+int bisq_ffi_test_init_free(void) {
+    uint8_t seller_keys[2][BISQ_PUB_KEY_LEN], seller_script_key[BISQ_X_ONLY_PUB_KEY_LEN];
+    uint8_t buyer_keys[2][BISQ_PUB_KEY_LEN], buyer_script_key[BISQ_X_ONLY_PUB_KEY_LEN];
+    TradeModel *seller, *buyer;
+    char *trade_id;
+
+    /* Bad ids & roles are rejected, and null arguments are handled gracefully. */
+    CHECK(bisq_trade_model_new(NULL, 0) == NULL);
+    CHECK(bisq_trade_model_new("ffi-trade-bad-role", 4) == NULL);
+    CHECK(bisq_trade_init_key_shares(NULL) == BISQ_ERR_NULL_ARGUMENT);
+    bisq_trade_model_free(NULL);
+    bisq_string_free(NULL);
+
+    seller = bisq_trade_model_new("ffi-trade-seller", 0); /* SELLER_AS_MAKER */
+    buyer = bisq_trade_model_new("ffi-trade-buyer", 3);   /* BUYER_AS_TAKER */
+    CHECK(seller != NULL && buyer != NULL);
+    trade_id = bisq_trade_model_trade_id(seller);
+    CHECK(trade_id != NULL && strcmp(trade_id, "ffi-trade-seller") == 0);
+    bisq_string_free(trade_id);
+
+    /* There are no key shares to get until they have been generated. */
+    CHECK(bisq_trade_get_my_key_shares(seller, seller_keys[0], seller_keys[1], seller_script_key) == BISQ_ERR_PROTOCOL);
+    CHECK(bisq_trade_init_key_shares(seller) == BISQ_OK);
+    CHECK(bisq_trade_init_key_shares(buyer) == BISQ_OK);
+    CHECK(bisq_trade_get_my_key_shares(seller, seller_keys[0], seller_keys[1], seller_script_key) == BISQ_OK);
+    CHECK(bisq_trade_get_my_key_shares(buyer, buyer_keys[0], buyer_keys[1], buyer_script_key) == BISQ_OK);
+    CHECK(seller_keys[0][0] == 2 || seller_keys[0][0] == 3);
+
+    /* A malformed peer key share is rejected. */
+    memset(buyer_script_key, 0xff, sizeof buyer_script_key);
+    CHECK(bisq_trade_set_peer_key_shares(seller, buyer_keys[0], buyer_keys[1], buyer_script_key)
+          == BISQ_ERR_INVALID_ARGUMENT);
+    CHECK(bisq_trade_get_my_key_shares(buyer, buyer_keys[0], buyer_keys[1], buyer_script_key) == BISQ_OK);
+
+    CHECK(bisq_trade_set_peer_key_shares(seller, buyer_keys[0], buyer_keys[1], buyer_script_key) == BISQ_OK);
+    CHECK(bisq_trade_set_peer_key_shares(buyer, seller_keys[0], seller_keys[1], seller_script_key) == BISQ_OK);
+    CHECK(bisq_trade_aggregate_key_shares(seller) == BISQ_OK);
+    CHECK(bisq_trade_aggregate_key_shares(buyer) == BISQ_OK);
+
+    bisq_trade_model_free(seller);
+    bisq_trade_model_free(buyer);
+    return 0;
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::{self, Body};
+use axum::http::{Request, header};
+use bdk_wallet::serde_json::{self, Value, json};
+use rpc::jsonrpc::JsonRpcImpl;
+use rpc::server::{MusigImpl, WalletImpl};
+use rpc::wallet::WalletServiceImpl;
+use tower::ServiceExt as _;
+
+fn router() -> Router {
+    let wallet_service = Arc::new(WalletServiceImpl::new());
+    JsonRpcImpl {
+        musig: Arc::new(MusigImpl::new(wallet_service.clone())),
+        wallet: Arc::new(WalletImpl::new(wallet_service)),
+    }.into_router()
+}
+
+async fn post(router: Router, body: impl Into<String>) -> Value {
+    let request = Request::post("/")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.into()))
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert!(response.status().is_success());
+    let bytes = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+async fn call(router: Router, method: &str, params: Value) -> Value {
+    post(router, json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1}).to_string()).await
+}
+
+#[tokio::test]
+async fn test_json_rpc_wallet_balance() {
+    let response = call(router(), "wallet_walletBalance", json!({})).await;
+    assert_eq!(response, json!({
+        "jsonrpc": "2.0",
+        "result": {"immature": 0, "trustedPending": 0, "untrustedPending": 0, "confirmed": 0},
+        "id": 1
+    }));
+}
+
+#[tokio::test]
+async fn test_json_rpc_init_and_get_trade() {
+    let router = router();
+    let params = json!({"tradeId": "json-rpc-trade", "myRole": "BUYER_AS_TAKER", "sequenceNumber": 0, "protocolVersion": 1});
+    let response = call(router.clone(), "musig_initTrade", params).await;
+    assert!(response["result"]["multisigScriptKey"].is_string(), "{response}");
+
+    let response = call(router, "musig_getTrade", json!({"tradeId": "json-rpc-trade"})).await;
+    let summary = &response["result"]["trade"]["summary"];
+    assert_eq!(summary["role"], "BUYER_AS_TAKER");
+    assert_eq!(summary["state"], "INITIALIZED");
+}
+
+#[tokio::test]
+async fn test_json_rpc_errors() {
+    let response = post(router(), "{").await;
+    assert_eq!(response["error"]["code"], -32_700);
+
+    let response = post(router(), r#"{"jsonrpc": "2.0", "id": 2}"#).await;
+    assert_eq!(response["error"]["code"], -32_600);
+
+    let response = call(router(), "musig_noSuchMethod", Value::Null).await;
+    assert_eq!(response["error"]["code"], -32_601);
+    assert_eq!(response["id"], 1);
+
+    let response = call(router(), "musig_getTrade", json!({"tradeId": 42})).await;
+    assert_eq!(response["error"]["code"], -32_602);
+
+    // gRPC status 'NOT_FOUND' (5) is mapped to server error code -32005:
+    let response = call(router(), "musig_getTrade", json!({"tradeId": "unknown-trade"})).await;
+    assert_eq!(response["error"]["code"], -32_005);
+    assert_eq!(response["error"]["message"], "missing trade with id: unknown-trade");
+}
+
+#[tokio::test]
+async fn test_json_rpc_get_unknown_utxo() {
+    let txid = "0101010101010101010101010101010101010101010101010101010101010102";
+    let response = call(router(), "wallet_getUtxo", json!({"txId": txid, "vout": 3})).await;
+    assert_eq!(response["error"]["code"], -32_005);
+    assert_eq!(response["error"]["message"], format!("no wallet utxo with outpoint: {txid}:3"));
+}
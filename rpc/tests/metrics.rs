@@ -0,0 +1,18 @@
+use axum::body::{self, Body};
+use axum::http::{Request, StatusCode};
+use rpc::stats::metrics;
+use tower::ServiceExt as _;
+
+#[tokio::test]
+async fn test_metrics_trade_stats_gauges() {
+    let request = Request::get("/metrics").body(Body::empty()).unwrap();
+    let response = metrics::metrics_router().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let bytes = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(bytes.into()).unwrap();
+    for gauge in ["musig_trades_total", "musig_trade_volume_sats", "musig_trades_completed",
+        "musig_trades_failed", "musig_trade_avg_duration_seconds"] {
+        assert!(text.contains(&format!("# TYPE {gauge} gauge\n{gauge} ")), "missing gauge: {gauge}");
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::{self, Body};
+use axum::http::{Request, StatusCode, header};
+use bdk_wallet::serde_json::{self, Value, json};
+use futures_util::stream::{self, StreamExt as _};
+use rpc::rest;
+use rpc::server::WalletImpl;
+use rpc::wallet::{WalletManager, WalletService, WalletServiceImpl, WalletServiceMock};
+use testenv::TestEnv;
+use tokio::task;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tower::ServiceExt as _;
+use unimock::{MockFn as _, Unimock, matching};
+
+//noinspection SpellCheckingInspection
+const TRADER_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdrjwWCyXqqJ4YqcyG4DmKtjjsRt29v1PtD3r3PuFJAjWytzcvSTKnZAGAkPSmnrdnu\
+    HWxCAwy3i1iPhrtKAfXRH7dVCNGp6/86'/1'/1'/0/*)";
+//noinspection SpellCheckingInspection
+const TXID: &str = "37b560334094515cfdaa0146bfd4ce19e940064c505082031858b0aba3218990";
+
+fn router(wallet_service: impl WalletService + Send + Sync + 'static) -> Router {
+    rest::wallet_router(Arc::new(WalletImpl::new(Arc::new(wallet_service))))
+}
+
+async fn send_request(router: Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = router.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn post_json(uri: &str, body: &Value) -> Request<Body> {
+    Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_rest_wallet_balance() {
+    let request = Request::get("/wallet/balance").body(Body::empty()).unwrap();
+    let (status, body) = send_request(router(WalletServiceImpl::new()), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"immature": 0, "trustedPending": 0, "untrustedPending": 0, "confirmed": 0}));
+}
+
+#[tokio::test]
+async fn test_rest_wallet_id() {
+    let wallet_manager = Arc::new(WalletManager::new(Arc::new(WalletServiceImpl::new())));
+    let router = rest::wallet_router(Arc::new(WalletImpl::from_wallet_manager(wallet_manager.clone())));
+    let new_address = |wallet_id: &str| Request::post(format!("/wallet/address?walletId={wallet_id}"))
+        .body(Body::empty()).unwrap();
+
+    let (status, body) = send_request(router.clone(), new_address("trader-1")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, json!({"error": "unknown wallet id: trader-1"}));
+
+    wallet_manager.create_wallet("trader-1", TRADER_DESCRIPTOR).unwrap();
+    let (status, body) = send_request(router.clone(), new_address("trader-1")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, default_body) = send_request(router, new_address("")).await;
+    assert_ne!(body["address"], default_body["address"]);
+}
+
+#[tokio::test]
+async fn test_rest_new_address() {
+    let request = Request::post("/wallet/address").body(Body::empty()).unwrap();
+    let (status, body) = send_request(router(WalletServiceImpl::new()), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({
+        "address": "bcrt1pkar3gerekw8f9gef9vn9xz0qypytgacp9wa5saelpksdgct33qdqan7c89",
+        "derivationPath": "m/86'/1'/0'/0/0"
+    }));
+}
+
+#[tokio::test]
+async fn test_rest_utxos() {
+    let clause = WalletServiceMock::list_unspent.some_call(matching!()).returns(vec![]);
+    let request = Request::get("/wallet/utxos").body(Body::empty()).unwrap();
+    let (status, body) = send_request(router(Unimock::new(clause)), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"utxos": []}));
+}
+
+#[tokio::test]
+async fn test_rest_send_bad_address() {
+    let request = post_json("/wallet/send", &json!({"address": "not-an-address", "amount": 1000}));
+    let (status, body) = send_request(router(Unimock::new(())), request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().starts_with("could not parse address"), "{body}");
+}
+
+#[tokio::test]
+async fn test_rest_ws_tx_confirmations() {
+    let clause = WalletServiceMock::get_tx_confidence_stream
+        .some_call(matching!((txid) if txid.to_string() == TXID))
+        .answers(&|_, _| stream::iter([None, None]).boxed());
+    let port = serve(router(Unimock::new(clause))).await;
+
+    let url = format!("ws://127.0.0.1:{port}/ws/tx/{TXID}/confirmations");
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let missing = json!({
+        "rawTx": null, "confidenceType": "MISSING", "numConfirmations": 0, "confirmationBlockTime": null,
+        "signalsRbf": false,
+    });
+    for _ in 0..2 {
+        let Some(Ok(Message::Text(text))) = socket.next().await else { panic!("expected a text message") };
+        assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), missing);
+    }
+    // The socket is closed by the server once the confidence stream ends.
+    assert!(matches!(socket.next().await, Some(Ok(Message::Close(_)))));
+}
+
+#[tokio::test]
+async fn test_rest_ws_tx_confirmations_bad_txid() {
+    let port = serve(router(Unimock::new(()))).await;
+
+    let url = format!("ws://127.0.0.1:{port}/ws/tx/not-a-txid/confirmations");
+    let Err(tungstenite::Error::Http(response)) = tokio_tungstenite::connect_async(url).await else {
+        panic!("expected the WebSocket handshake to be refused");
+    };
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+async fn serve(router: Router) -> u16 {
+    let (port, listener) = TestEnv::get_bound_port().await.expect("listener");
+    task::spawn(async move { axum::serve(listener, router).await });
+    port
+}
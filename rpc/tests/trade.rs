@@ -0,0 +1,905 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use bdk_wallet::bitcoin::hashes::Hash as _;
+use bdk_wallet::bitcoin::hex::FromHex as _;
+use bdk_wallet::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bdk_wallet::bitcoin::block::{Header, Version};
+use bdk_wallet::bitcoin::{
+    Amount, BlockHash, CompactTarget, Psbt, Transaction, TxMerkleNode, Txid, absolute, consensus, transaction,
+};
+use bdk_wallet::chain::{ChainPosition, ConfirmationBlockTime};
+use bdk_wallet::serde_json;
+use futures_util::stream::{self, BoxStream, StreamExt as _, TryStreamExt as _};
+use musig2::secp::Scalar;
+use rpc::audit::{AuditEventType, AuditLog};
+use rpc::dead_mans_switch::DeadMansSwitch;
+use rpc::pb::musigrpc::musig_server::Musig as _;
+use rpc::pb::musigrpc::{
+    CloseTradeRequest, DepositPsbt, DepositTxSignatureRequest, GetDisputeEvidenceRequest, GetRecoveryTxRequest, GetTradeReceiptRequest, GetTradeRequest,
+    GetTradeStatsRequest, NonceSharesMessage, NonceSharesRequest, PartialSignaturesMessage, PartialSignaturesRequest, PubKeySharesRequest,
+    PubKeySharesResponse, PublishDepositTxRequest, ReceiverAddressAndAmount, ResetTradeRequest, Role,
+    SubscribeTxConfirmationStatusRequest, SwapTxSignatureRequest, TradeState,
+};
+use rpc::protocol::{IntegrityWarning, MAX_SUPPORTED_VERSION, ProtocolErrorKind, TRADE_MODELS, TradeModel, TradeModelStore as _};
+use rpc::explorer::BlockExplorerConfig;
+use rpc::peer::{accept_peer, connect_peer};
+use rpc::server::{Config, MusigImpl};
+use rpc::spv::MerkleProof;
+use rpc::receipt::TradeReceipt;
+use rpc::verification::{verify_dispute_evidence, verify_proof_of_payment, verify_trade_receipt};
+use rpc::wallet::{TxConfidence, WalletErrorKind, WalletManager, WalletService, WalletServiceMock, WalletTx};
+use tokio::net::TcpListener;
+use tonic::{Code, Request};
+use unimock::{MockFn as _, Unimock, matching};
+
+const TRADE_AMOUNT: u64 = 200_000;
+const SECURITY_DEPOSIT: u64 = 30_000;
+const DEPOSIT_TX_FEE_RATE: u64 = 12_500;
+const PREPARED_TX_FEE_RATE: u64 = 2_500;
+//noinspection SpellCheckingInspection
+const REDIRECTION_ADDRESS: &str = "bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz";
+/// The weight of a P2TR output, such as that paying `REDIRECTION_ADDRESS`.
+const P2TR_OUTPUT_WEIGHT: u64 = 172;
+
+/// One side of a trade, driven through its `MusigImpl` with successive sequence numbers. (Each
+/// trader is given a different trade ID, as the trade models of both share the same process.)
+struct Trader {
+    musig: MusigImpl,
+    trade_id: String,
+    sequence_number: u64,
+    adaptor_point: Option<Vec<u8>>,
+    adaptor_secret: Option<Vec<u8>>,
+}
+
+impl Trader {
+    fn new(trade_id: impl Into<String>, wallet_service: impl WalletService + Send + Sync + 'static) -> Self {
+        Self {
+            musig: MusigImpl::new(Arc::new(wallet_service)),
+            trade_id: trade_id.into(),
+            sequence_number: 0,
+            adaptor_point: None,
+            adaptor_secret: None,
+        }
+    }
+
+    const fn next_sequence_number(&mut self) -> u64 {
+        self.sequence_number += 1;
+        self.sequence_number
+    }
+
+    async fn init_trade(&self, role: Role) -> PubKeySharesResponse {
+        let request = PubKeySharesRequest {
+            trade_id: self.trade_id.clone(),
+            my_role: role.into(),
+            sequence_number: self.sequence_number,
+            protocol_version: MAX_SUPPORTED_VERSION,
+            wallet_id: String::new(),
+        };
+        self.musig.init_trade(Request::new(request)).await.unwrap().into_inner()
+    }
+
+    async fn get_nonce_shares(&mut self, peers_pub_key_shares: &PubKeySharesResponse) -> NonceSharesMessage {
+        let request = self.nonce_shares_request(peers_pub_key_shares);
+        self.musig.get_nonce_shares(Request::new(request)).await.unwrap().into_inner()
+    }
+
+    fn nonce_shares_request(&mut self, peers_pub_key_shares: &PubKeySharesResponse) -> NonceSharesRequest {
+        NonceSharesRequest {
+            trade_id: self.trade_id.clone(),
+            buyer_output_peers_pub_key_share: peers_pub_key_shares.buyer_output_pub_key_share.clone(),
+            seller_output_peers_pub_key_share: peers_pub_key_shares.seller_output_pub_key_share.clone(),
+            peers_multisig_script_key: peers_pub_key_shares.multisig_script_key.clone(),
+            deposit_tx_fee_rate: DEPOSIT_TX_FEE_RATE,
+            prepared_tx_fee_rate: PREPARED_TX_FEE_RATE,
+            trade_amount: TRADE_AMOUNT,
+            buyers_security_deposit: SECURITY_DEPOSIT,
+            sellers_security_deposit: SECURITY_DEPOSIT,
+            trade_fee_receiver: None,
+            sequence_number: self.next_sequence_number(),
+            adaptor_point: self.adaptor_point.clone(),
+            adaptor_secret: self.adaptor_secret.clone(),
+        }
+    }
+
+    async fn get_partial_signatures(&mut self, peers_nonce_shares: Option<NonceSharesMessage>) -> PartialSignaturesMessage {
+        let buyer_ready_to_release = peers_nonce_shares.is_none();
+        let redirection_receivers = peers_nonce_shares.as_ref()
+            .map(|nonce_shares| vec![redirection_receiver(nonce_shares.redirection_amount_msat)])
+            .unwrap_or_default();
+        let request = PartialSignaturesRequest {
+            trade_id: self.trade_id.clone(),
+            peers_nonce_shares,
+            redirection_receivers,
+            buyer_ready_to_release,
+            sequence_number: self.next_sequence_number(),
+        };
+        self.musig.get_partial_signatures(Request::new(request)).await.unwrap().into_inner()
+    }
+
+    async fn sign_deposit_tx(&mut self, peers_partial_signatures: PartialSignaturesMessage) -> DepositPsbt {
+        let request = DepositTxSignatureRequest {
+            trade_id: self.trade_id.clone(),
+            peers_partial_signatures: Some(peers_partial_signatures),
+            sequence_number: self.next_sequence_number(),
+        };
+        self.musig.sign_deposit_tx(Request::new(request)).await.unwrap().into_inner()
+    }
+
+    async fn reset_trade(&mut self) -> tonic::Result<()> {
+        let request = ResetTradeRequest { trade_id: self.trade_id.clone(), sequence_number: self.next_sequence_number() };
+        self.musig.reset_trade(Request::new(request)).await?;
+        Ok(())
+    }
+
+    async fn trade_state_and_swap_txid(&self) -> (TradeState, Option<String>) {
+        let request = GetTradeRequest { trade_id: self.trade_id.clone() };
+        let trade = self.musig.get_trade(Request::new(request)).await.unwrap().into_inner().trade.unwrap();
+        (trade.summary.unwrap().state(), trade.swap_tx_id)
+    }
+}
+
+/// A stream of confidence updates for the given txid, with the given numbers of confirmations, or
+/// `None` where the tx is missing. (Only the txid is known to the mock, so the wallet tx holds a
+/// placeholder in place of the real tx.)
+fn mock_confidence_stream(txid: Txid, num_confirmations: &[Option<u32>]) -> BoxStream<'static, Option<TxConfidence>> {
+    let tx = Arc::new(Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![],
+        output: vec![],
+    });
+    let confidence = move |num_confirmations| Some(TxConfidence {
+        wallet_tx: WalletTx {
+            txid,
+            tx: tx.clone(),
+            chain_position: if num_confirmations == 0 {
+                ChainPosition::Unconfirmed { first_seen: Some(0), last_seen: Some(0) }
+            } else {
+                ChainPosition::Confirmed {
+                    anchor: ConfirmationBlockTime {
+                        block_id: (900_001, BlockHash::all_zeros()).into(),
+                        confirmation_time: 0,
+                    },
+                    transitively: None,
+                }
+            },
+            signals_rbf: false,
+        },
+        num_confirmations,
+        broadcast_failed: false,
+    });
+    let updates: Vec<_> = num_confirmations.iter().map(|n| n.and_then(&confidence)).collect();
+    stream::iter(updates).chain(stream::pending()).boxed()
+}
+
+/// A proof of inclusion of the given txid as the sole tx of a block, or (if `valid` is false) a
+/// proof against a block with some other Merkle root.
+fn mock_merkle_proof(txid: Txid, valid: bool) -> MerkleProof {
+    let merkle_root = if valid { txid.to_byte_array() } else { [0xff; 32] };
+    let block_header = Header {
+        version: Version::TWO,
+        prev_blockhash: BlockHash::all_zeros(),
+        merkle_root: TxMerkleNode::from_byte_array(merkle_root),
+        time: 0,
+        bits: CompactTarget::from_consensus(0),
+        nonce: 0,
+    };
+    MerkleProof { block_header, merkle_path: vec![], tx_index: 0 }
+}
+
+/// A single receiver taking the whole of the available redirection amount, less its output cost.
+fn redirection_receiver(available_msat: u64) -> ReceiverAddressAndAmount {
+    ReceiverAddressAndAmount {
+        address: REDIRECTION_ADDRESS.to_owned(),
+        amount: (available_msat - PREPARED_TX_FEE_RATE * P2TR_OUTPUT_WEIGHT) / 1000,
+    }
+}
+
+/// Run the trade protocol between a seller (as maker) and buyer (as taker), up to the point where
+/// both have signed their half of the deposit tx. The traders pass each other their protocol
+/// messages over a direct peer connection.
+async fn sign_deposit_txs(seller: &mut Trader, buyer: &mut Trader) -> [DepositPsbt; 2] {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let (seller_conn, buyer_conn) = tokio::join!(accept_peer(&listener), connect_peer(listener.local_addr().unwrap()));
+    let (mut seller_conn, mut buyer_conn) = (seller_conn.unwrap(), buyer_conn.unwrap());
+
+    seller_conn.send_key_shares(&seller.init_trade(Role::SellerAsMaker).await).await.unwrap();
+    buyer_conn.send_key_shares(&buyer.init_trade(Role::BuyerAsTaker).await).await.unwrap();
+
+    let seller_nonce_shares = seller.get_nonce_shares(&seller_conn.recv_key_shares().await.unwrap()).await;
+    seller_conn.send_nonce_shares(&seller_nonce_shares).await.unwrap();
+    let buyer_nonce_shares = buyer.get_nonce_shares(&buyer_conn.recv_key_shares().await.unwrap()).await;
+    buyer_conn.send_nonce_shares(&buyer_nonce_shares).await.unwrap();
+
+    let peers_nonce_shares = seller_conn.recv_nonce_shares().await.unwrap();
+    seller_conn.send_partial_signatures(&seller.get_partial_signatures(Some(peers_nonce_shares)).await).await.unwrap();
+    let peers_nonce_shares = buyer_conn.recv_nonce_shares().await.unwrap();
+    buyer_conn.send_partial_signatures(&buyer.get_partial_signatures(Some(peers_nonce_shares)).await).await.unwrap();
+
+    let seller_deposit_psbt = seller.sign_deposit_tx(seller_conn.recv_partial_signatures().await.unwrap()).await;
+    let buyer_deposit_psbt = buyer.sign_deposit_tx(buyer_conn.recv_partial_signatures().await.unwrap()).await;
+    [seller_deposit_psbt, buyer_deposit_psbt]
+}
+
+#[tokio::test]
+async fn test_trade_model_integrity() {
+    let mut seller = Trader::new("integrity-seller", Unimock::new(()));
+    let mut buyer = Trader::new("integrity-buyer", Unimock::new(()));
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    for trader in [&seller, &buyer] {
+        let trade_model = TRADE_MODELS.get_trade_model(&trader.trade_id).unwrap();
+        assert_eq!(trade_model.lock().await.check_integrity().unwrap(), []);
+    }
+
+    let mut trade_model = TradeModel::new("integrity-zero-amount".to_owned(), rpc::protocol::Role::BuyerAsTaker);
+    trade_model.set_buyers_security_deposit(Amount::ZERO);
+    assert_eq!(trade_model.check_integrity().unwrap(), [IntegrityWarning::ZeroAmount("buyer's security deposit")]);
+}
+
+#[tokio::test]
+async fn test_get_recovery_tx() {
+    // There are no recovery txs before the partial signatures on them are exchanged.
+    let early_seller = Trader::new("recovery-tx-early-seller", Unimock::new(()));
+    early_seller.init_trade(Role::SellerAsMaker).await;
+    let request = GetRecoveryTxRequest { trade_id: early_seller.trade_id.clone() };
+    assert!(early_seller.musig.get_recovery_tx(Request::new(request)).await.is_err());
+
+    let mut seller = Trader::new("recovery-tx-seller", Unimock::new(()));
+    let mut buyer = Trader::new("recovery-tx-buyer", Unimock::new(()));
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    let request = GetRecoveryTxRequest { trade_id: seller.trade_id.clone() };
+    let response = seller.musig.get_recovery_tx(Request::new(request)).await.unwrap().into_inner();
+    let warning_tx: Transaction = consensus::deserialize(&response.warning_tx).unwrap();
+    let claim_tx: Transaction = consensus::deserialize(&response.claim_tx).unwrap();
+
+    // The claim tx spends the warning tx, which spends the deposit tx, each after its lock time.
+    let deposit_txid = TRADE_MODELS.get_trade_model(&seller.trade_id).unwrap().lock().await.get_deposit_txid().unwrap();
+    assert!(warning_tx.input.iter().all(|input| input.previous_output.txid == deposit_txid));
+    assert_eq!(claim_tx.input[0].previous_output.txid, warning_tx.compute_txid());
+    assert!(response.warning_lock_time > 0 && response.claim_lock_time > 0);
+    assert_eq!(claim_tx.input[0].sequence.to_relative_lock_time().unwrap().to_consensus_u32(), response.claim_lock_time);
+}
+
+#[tokio::test]
+async fn test_get_dispute_evidence() {
+    let seller = Trader::new("dispute-evidence-seller", Unimock::new(()));
+    seller.init_trade(Role::SellerAsMaker).await;
+    let arbitrator_sec_key = SecretKey::from_slice(&[7; 32]).unwrap();
+    let arbitrator_pub_key = arbitrator_sec_key.public_key(&Secp256k1::new());
+
+    let request = GetDisputeEvidenceRequest { trade_id: seller.trade_id.clone(), arbitrator_pub_key: vec![2; 5] };
+    let status = seller.musig.get_dispute_evidence(Request::new(request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let request = GetDisputeEvidenceRequest {
+        trade_id: seller.trade_id.clone(),
+        arbitrator_pub_key: arbitrator_pub_key.serialize().to_vec(),
+    };
+    let response = seller.musig.get_dispute_evidence(Request::new(request)).await.unwrap().into_inner();
+
+    // Only the arbitrator can decrypt the evidence, which is signed with the seller's payout key share.
+    let evidence_json = ecies::decrypt(&arbitrator_sec_key.secret_bytes(), &response.evidence_json).unwrap();
+    let evidence: serde_json::Value = serde_json::from_slice(&evidence_json).unwrap();
+    assert_eq!(evidence["summary"]["tradeId"].as_str(), Some(seller.trade_id.as_str()));
+
+    let seller_model = TRADE_MODELS.get_trade_model(&seller.trade_id).unwrap();
+    let pub_key = seller_model.lock().await.get_my_key_shares().unwrap().seller_payout.serialize();
+    let pub_key = PublicKey::from_slice(&pub_key).unwrap();
+    assert!(verify_dispute_evidence(&pub_key, &evidence_json, &response.signature));
+    assert!(!verify_dispute_evidence(&pub_key, &response.evidence_json, &response.signature));
+}
+
+#[tokio::test]
+async fn test_seller_force_close() {
+    let clause = WalletServiceMock::broadcast_tx
+        .next_call(matching!((tx) if tx.input.len() == 1 && tx.input[0].witness.len() == 1))
+        .answers(&|_, tx| Ok(tx.compute_txid()))
+        .once();
+    let mut seller = Trader::new("force-close-seller", Unimock::new(clause));
+    let mut buyer = Trader::new("force-close-buyer", Unimock::new(()));
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    // Buyer signals readiness to release (on receipt of the fiat payment), sending the seller its
+    // partial signature on the swap tx, which the seller uses to sign the swap tx but not release.
+    let buyer_partial_signatures = buyer.get_partial_signatures(None).await;
+    let request = SwapTxSignatureRequest {
+        trade_id: seller.trade_id.clone(),
+        swap_tx_input_peers_partial_signature: buyer_partial_signatures.swap_tx_input_partial_signature.unwrap(),
+        seller_ready_to_release: false,
+        sequence_number: seller.next_sequence_number(),
+        adaptor_secret: None,
+    };
+    seller.musig.sign_swap_tx(Request::new(request)).await.unwrap();
+
+    // No receipt is given before the trade is closed.
+    let request = GetTradeReceiptRequest { trade_id: seller.trade_id.clone() };
+    let status = seller.musig.get_trade_receipt(Request::new(request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    // The buyer goes silent, so the seller force-closes the trade by broadcasting the swap tx.
+    let request = CloseTradeRequest {
+        trade_id: seller.trade_id.clone(),
+        my_output_peers_prv_key_share: None,
+        swap_tx: None,
+        sequence_number: seller.next_sequence_number(),
+    };
+    let response = seller.musig.close_trade(Request::new(request)).await.unwrap().into_inner();
+    let swap_txid = Txid::from_slice(&response.swap_tx_id).unwrap();
+    assert_eq!(response.peer_output_prv_key_share.len(), 32);
+
+    let (state, expected_swap_txid) = seller.trade_state_and_swap_txid().await;
+    assert_eq!(state, TradeState::ForceClosing);
+    assert_eq!(Some(swap_txid.to_string()), expected_swap_txid);
+
+    let trade_stats = seller.musig.get_trade_stats(Request::new(GetTradeStatsRequest {}))
+        .await.unwrap().into_inner().stats.unwrap();
+    assert_eq!((trade_stats.total_trade_count, trade_stats.completed_count), (1, 1));
+    assert_eq!(trade_stats.total_volume_sats, TRADE_AMOUNT);
+
+    let request = GetTradeReceiptRequest { trade_id: seller.trade_id.clone() };
+    let response = seller.musig.get_trade_receipt(Request::new(request)).await.unwrap().into_inner();
+    let receipt: TradeReceipt = serde_json::from_str(&response.receipt_json).unwrap();
+    assert_eq!((receipt.trade_id.as_str(), receipt.role), (seller.trade_id.as_str(), Role::SellerAsMaker));
+    assert_eq!((receipt.trade_amount_sats, receipt.payout_txid), (TRADE_AMOUNT, Some(swap_txid)));
+    assert!(receipt.total_fees_sats > 0);
+
+    let seller_model = TRADE_MODELS.get_trade_model(&seller.trade_id).unwrap();
+    let seller_pub_key_share = seller_model.lock().await.get_my_key_shares().unwrap().seller_payout.serialize();
+    let signature = Vec::from_hex(&response.signature_hex).unwrap();
+    let pub_key = PublicKey::from_slice(&seller_pub_key_share).unwrap();
+    assert!(verify_trade_receipt(&pub_key, &response.receipt_json, &signature));
+    assert!(!verify_trade_receipt(&pub_key, &response.receipt_json.replace("200000", "200001"), &signature));
+    // The receipt signature can't be passed off as one over dispute evidence with the same JSON.
+    assert!(!verify_dispute_evidence(&pub_key, response.receipt_json.as_bytes(), &signature));
+}
+
+#[tokio::test]
+async fn test_buyer_dead_mans_switch() {
+    let mut seller = Trader::new("dead-mans-switch-seller", Unimock::new(()));
+    let mut buyer = Trader::new("dead-mans-switch-buyer", Unimock::new(()));
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+    let trade_model = TRADE_MODELS.get_trade_model(&buyer.trade_id).unwrap();
+    let warning_txid = trade_model.lock().await.get_signed_recovery_txs().unwrap()[0].0.compute_txid();
+    let clause = WalletServiceMock::broadcast_tx
+        .next_call(matching!(_))
+        .answers_arc(Arc::new(move |_, tx| {
+            assert_eq!(tx.compute_txid(), warning_txid);
+            Ok(warning_txid)
+        }))
+        .once();
+    let wallet_manager = WalletManager::new(Arc::new(Unimock::new(clause)));
+    let dead_mans_switch = DeadMansSwitch::new(Arc::new(wallet_manager), Duration::ZERO);
+
+    // Nothing is broadcast before the payment is sent, nor before the deposit tx is published. (The
+    // buyer signals readiness to release once it has sent the payment.)
+    dead_mans_switch.check_trade(&trade_model).await;
+    buyer.get_partial_signatures(None).await;
+    dead_mans_switch.check_trade(&trade_model).await;
+
+    // The seller goes silent once the deposit tx is published, so the warning tx is broadcast, just
+    // once, as the trade moves on to force-closing. That is, once the trade's wallet can be found.
+    trade_model.lock().await.advance_state(rpc::protocol::TradeState::DepositTxPublished);
+    trade_model.lock().await.set_wallet_id("unknown-wallet".to_owned());
+    dead_mans_switch.check_trade(&trade_model).await;
+    assert_eq!(buyer.trade_state_and_swap_txid().await.0, TradeState::DepositTxPublished);
+    trade_model.lock().await.set_wallet_id(String::new());
+    dead_mans_switch.check_trade(&trade_model).await;
+    dead_mans_switch.check_trade(&trade_model).await;
+    assert_eq!(buyer.trade_state_and_swap_txid().await.0, TradeState::ForceClosing);
+}
+
+#[tokio::test]
+async fn test_deposit_input_sighash() {
+    let unbuilt_trade = TradeModel::new("unbuilt-deposit-tx".to_owned(), rpc::protocol::Role::SellerAsMaker);
+    assert!(matches!(unbuilt_trade.compute_deposit_input_sighash(), Err(ProtocolErrorKind::MissingDepositTx)));
+
+    let mut seller = Trader::new("deposit-input-sighash-seller", Unimock::new(()));
+    let mut buyer = Trader::new("deposit-input-sighash-buyer", Unimock::new(()));
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    // Both traders' cached sighashes match the one the seller passed to the buyer to partially sign.
+    let seller_partial_signatures = seller.get_partial_signatures(None).await;
+    for trader in [&seller, &buyer] {
+        let trade_model = TRADE_MODELS.get_trade_model(&trader.trade_id).unwrap();
+        let sighash = trade_model.lock().await.compute_deposit_input_sighash().unwrap();
+        assert_eq!(seller_partial_signatures.swap_tx_input_sighash.as_deref(), Some(&sighash[..]));
+    }
+}
+
+#[tokio::test]
+async fn test_half_deposit_psbt() {
+    let mut seller = Trader::new("half-deposit-psbt-seller", Unimock::new(()));
+    let mut buyer = Trader::new("half-deposit-psbt-buyer", Unimock::new(()));
+    let seller_pub_key_shares = seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+
+    // Each trader sends the peer its own funded, but as yet unsigned, half of the deposit tx.
+    let seller_nonce_shares = seller.get_nonce_shares(&buyer_pub_key_shares).await;
+    let buyer_nonce_shares = buyer.get_nonce_shares(&seller_pub_key_shares).await;
+    for (nonce_shares, deposit_amount) in [
+        (&seller_nonce_shares, TRADE_AMOUNT + SECURITY_DEPOSIT),
+        (&buyer_nonce_shares, SECURITY_DEPOSIT),
+    ] {
+        let psbt = Psbt::deserialize(&nonce_shares.half_deposit_psbt).unwrap();
+        assert!(!psbt.inputs.is_empty());
+        assert!(psbt.inputs.iter().all(|input| input.final_script_witness.is_none()));
+        assert_eq!(psbt.unsigned_tx.output[0].value, Amount::from_sat(deposit_amount));
+    }
+}
+
+#[tokio::test]
+async fn test_net_amounts() {
+    let mut seller = Trader::new("net-amounts-seller", Unimock::new(()));
+    let mut buyer = Trader::new("net-amounts-buyer", Unimock::new(()));
+    let seller_pub_key_shares = seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+    let seller_nonce_shares = seller.get_nonce_shares(&buyer_pub_key_shares).await;
+    let buyer_nonce_shares = buyer.get_nonce_shares(&seller_pub_key_shares).await;
+
+    // Each payout is less the fee of a 444 wu sweep tx at the prepared tx fee rate. (No trade fee.)
+    let sweep_fee = PREPARED_TX_FEE_RATE * 444 / 1000;
+    for nonce_shares in [&seller_nonce_shares, &buyer_nonce_shares] {
+        assert_eq!(nonce_shares.buyers_net_amount_sats, TRADE_AMOUNT + SECURITY_DEPOSIT - sweep_fee);
+        assert_eq!(nonce_shares.sellers_net_amount_sats, SECURITY_DEPOSIT - sweep_fee);
+    }
+}
+
+#[tokio::test]
+async fn test_proof_of_payment() {
+    let seller = Trader::new("proof-of-payment-seller", Unimock::new(()));
+    let buyer = Trader::new("proof-of-payment-buyer", Unimock::new(()));
+    let seller_pub_key_shares = seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+
+    let seller_model = TRADE_MODELS.get_trade_model(&seller.trade_id).unwrap();
+    assert!(matches!(seller_model.lock().await.generate_proof_of_payment("SEPA-123"),
+        Err(ProtocolErrorKind::BuyerOnly)));
+
+    let buyer_model = TRADE_MODELS.get_trade_model(&buyer.trade_id).unwrap();
+    let proof = buyer_model.lock().await.generate_proof_of_payment("SEPA-123").unwrap();
+    let [buyer_pub_key, seller_pub_key] = [&buyer_pub_key_shares, &seller_pub_key_shares]
+        .map(|keys| PublicKey::from_slice(&keys.buyer_output_pub_key_share).unwrap());
+    assert!(verify_proof_of_payment(&buyer_pub_key, &proof, &buyer.trade_id, "SEPA-123"));
+    assert!(!verify_proof_of_payment(&buyer_pub_key, &proof, &buyer.trade_id, "SEPA-124"));
+    assert!(!verify_proof_of_payment(&buyer_pub_key, &proof, &seller.trade_id, "SEPA-123"));
+    assert!(!verify_proof_of_payment(&seller_pub_key, &proof, &buyer.trade_id, "SEPA-123"));
+}
+
+#[tokio::test]
+async fn test_swap_tx_with_adaptor_point() {
+    let adaptor_secret = Scalar::try_from(&[7u8; 32][..]).unwrap();
+    let adaptor_point = adaptor_secret.base_point_mul().serialize().to_vec();
+    let mut seller = Trader::new("adaptor-seller", Unimock::new(()));
+    let mut buyer = Trader::new("adaptor-buyer", Unimock::new(()));
+    // The adaptor point locks the buyer's side of the swap on the other chain, so only the buyer
+    // starts out with its secret.
+    seller.adaptor_point = Some(adaptor_point.clone());
+    buyer.adaptor_point = Some(adaptor_point);
+    buyer.adaptor_secret = Some(adaptor_secret.serialize().to_vec());
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    // The seller cannot complete its swap tx signature until it learns the adaptor secret (from the
+    // buyer claiming its side of the swap on the other chain).
+    let buyer_partial_signatures = buyer.get_partial_signatures(None).await;
+    let mut request = SwapTxSignatureRequest {
+        trade_id: seller.trade_id.clone(),
+        swap_tx_input_peers_partial_signature: buyer_partial_signatures.swap_tx_input_partial_signature.unwrap(),
+        seller_ready_to_release: true,
+        sequence_number: seller.sequence_number + 1,
+        adaptor_secret: None,
+    };
+    let status = seller.musig.sign_swap_tx(Request::new(request.clone())).await.unwrap_err();
+    assert_eq!(status.message(), "missing adaptor secret");
+
+    request.adaptor_secret = Some(adaptor_secret.serialize().to_vec());
+    request.sequence_number = seller.next_sequence_number();
+    let swap_tx = seller.musig.sign_swap_tx(Request::new(request)).await.unwrap().into_inner().swap_tx;
+
+    // With no cooperative message from the seller, the buyer recovers the seller's key share for its
+    // payout output from the published swap tx alone, by subtracting the adaptor secret it already
+    // has from the revealed scalar.
+    let request = CloseTradeRequest {
+        trade_id: buyer.trade_id.clone(),
+        my_output_peers_prv_key_share: None,
+        swap_tx: Some(swap_tx),
+        sequence_number: buyer.next_sequence_number(),
+    };
+    buyer.musig.close_trade(Request::new(request)).await.unwrap();
+    assert_eq!(buyer.trade_state_and_swap_txid().await.0, TradeState::Closed);
+}
+
+#[tokio::test]
+async fn test_buyer_needs_adaptor_secret() {
+    let adaptor_secret = Scalar::try_from(&[7u8; 32][..]).unwrap();
+    let adaptor_point = adaptor_secret.base_point_mul().serialize().to_vec();
+    let mut seller = Trader::new("adaptor-secret-seller", Unimock::new(()));
+    let mut buyer = Trader::new("adaptor-secret-buyer", Unimock::new(()));
+    seller.adaptor_point = Some(adaptor_point.clone());
+    buyer.adaptor_point = Some(adaptor_point);
+
+    let seller_pub_key_shares = seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+
+    // The seller may not start out with the adaptor secret, but must learn it from the other chain.
+    seller.adaptor_secret = Some(adaptor_secret.serialize().to_vec());
+    let request = seller.nonce_shares_request(&buyer_pub_key_shares);
+    let status = seller.musig.get_nonce_shares(Request::new(request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    seller.adaptor_secret = None;
+    seller.sequence_number -= 1;
+
+    let seller_nonce_shares = seller.get_nonce_shares(&buyer_pub_key_shares).await;
+    let buyer_nonce_shares = buyer.get_nonce_shares(&seller_pub_key_shares).await;
+    let seller_partial_signatures = seller.get_partial_signatures(Some(buyer_nonce_shares)).await;
+    buyer.get_partial_signatures(Some(seller_nonce_shares)).await;
+
+    // Without the adaptor secret, the buyer could not recover the seller's key share for its payout
+    // output from a force-published swap tx, so it refuses to sign the swap tx.
+    let request = DepositTxSignatureRequest {
+        trade_id: buyer.trade_id.clone(),
+        peers_partial_signatures: Some(seller_partial_signatures),
+        sequence_number: buyer.next_sequence_number(),
+    };
+    let status = buyer.musig.sign_deposit_tx(Request::new(request)).await.unwrap_err();
+    assert_eq!(status.message(), "missing adaptor secret");
+}
+
+#[tokio::test]
+async fn test_mismatched_adaptor_point() {
+    let mut seller = Trader::new("mismatched-adaptor-seller", Unimock::new(()));
+    let mut buyer = Trader::new("mismatched-adaptor-buyer", Unimock::new(()));
+    buyer.adaptor_point = Some(Scalar::one().base_point_mul().serialize().to_vec());
+
+    let seller_pub_key_shares = seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+    seller.get_nonce_shares(&buyer_pub_key_shares).await;
+    let buyer_nonce_shares = buyer.get_nonce_shares(&seller_pub_key_shares).await;
+
+    let request = PartialSignaturesRequest {
+        trade_id: seller.trade_id.clone(),
+        redirection_receivers: vec![redirection_receiver(buyer_nonce_shares.redirection_amount_msat)],
+        peers_nonce_shares: Some(buyer_nonce_shares),
+        buyer_ready_to_release: false,
+        sequence_number: seller.next_sequence_number(),
+    };
+    let status = seller.musig.get_partial_signatures(Request::new(request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_fee_rate_mismatch() {
+    let mut seller = Trader::new("fee-rate-mismatch-seller", Unimock::new(()));
+    let mut buyer = Trader::new("fee-rate-mismatch-buyer", Unimock::new(()));
+
+    let seller_pub_key_shares = seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+    seller.get_nonce_shares(&buyer_pub_key_shares).await;
+    let mut buyer_nonce_shares = buyer.get_nonce_shares(&seller_pub_key_shares).await;
+    assert_eq!(buyer_nonce_shares.proposed_deposit_tx_fee_rate, Some(DEPOSIT_TX_FEE_RATE));
+    assert_eq!(buyer_nonce_shares.proposed_prepared_tx_fee_rate, Some(PREPARED_TX_FEE_RATE));
+    buyer_nonce_shares.proposed_deposit_tx_fee_rate = Some(DEPOSIT_TX_FEE_RATE * 2);
+
+    let request = PartialSignaturesRequest {
+        trade_id: seller.trade_id.clone(),
+        redirection_receivers: vec![redirection_receiver(buyer_nonce_shares.redirection_amount_msat)],
+        peers_nonce_shares: Some(buyer_nonce_shares),
+        buyer_ready_to_release: false,
+        sequence_number: seller.next_sequence_number(),
+    };
+    let status = seller.musig.get_partial_signatures(Request::new(request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(status.message(), "fee rate mismatch");
+}
+
+#[tokio::test]
+async fn test_missing_proposed_fee_rate() {
+    let mut seller = Trader::new("missing-fee-rate-seller", Unimock::new(()));
+    let mut buyer = Trader::new("missing-fee-rate-buyer", Unimock::new(()));
+
+    let seller_pub_key_shares = seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+    seller.get_nonce_shares(&buyer_pub_key_shares).await;
+    let mut buyer_nonce_shares = buyer.get_nonce_shares(&seller_pub_key_shares).await;
+    buyer_nonce_shares.proposed_prepared_tx_fee_rate = None;
+
+    let request = PartialSignaturesRequest {
+        trade_id: seller.trade_id.clone(),
+        redirection_receivers: vec![redirection_receiver(buyer_nonce_shares.redirection_amount_msat)],
+        peers_nonce_shares: Some(buyer_nonce_shares),
+        buyer_ready_to_release: false,
+        sequence_number: seller.next_sequence_number(),
+    };
+    let status = seller.musig.get_partial_signatures(Request::new(request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_retry_rejected_security_deposits() {
+    let mut seller = Trader::new("retry-rejected-deposits-seller", Unimock::new(()));
+    let buyer = Trader::new("retry-rejected-deposits-buyer", Unimock::new(()));
+
+    seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+    let request = seller.nonce_shares_request(&buyer_pub_key_shares);
+    let bad_request = NonceSharesRequest { buyers_security_deposit: 1, ..request.clone() };
+    let status = seller.musig.get_nonce_shares(Request::new(bad_request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // The rejected deposit must not have been kept, so that the corrected request may replace it.
+    seller.musig.get_nonce_shares(Request::new(request)).await.unwrap();
+    let trade_model = TRADE_MODELS.get_trade_model(&seller.trade_id).unwrap();
+    assert_eq!(trade_model.lock().await.buyers_security_deposit(), Some(Amount::from_sat(SECURITY_DEPOSIT)));
+}
+
+#[tokio::test]
+async fn test_reset_trade() {
+    let mut seller = Trader::new("reset-trade-seller", Unimock::new(()));
+    let mut buyer = Trader::new("reset-trade-buyer", Unimock::new(()));
+    let stale_buyer = Trader::new("reset-trade-stale-buyer", Unimock::new(()));
+
+    let seller_pub_key_shares = seller.init_trade(Role::SellerAsMaker).await;
+    let buyer_pub_key_shares = buyer.init_trade(Role::BuyerAsTaker).await;
+    let stale_buyer_pub_key_shares = stale_buyer.init_trade(Role::BuyerAsTaker).await;
+    // The seller is first given the wrong key shares, so must reset the trade to take the right ones.
+    seller.get_nonce_shares(&stale_buyer_pub_key_shares).await;
+    seller.reset_trade().await.unwrap();
+    assert_eq!(seller.trade_state_and_swap_txid().await, (TradeState::Initialized, None));
+
+    let seller_nonce_shares = seller.get_nonce_shares(&buyer_pub_key_shares).await;
+    let buyer_nonce_shares = buyer.get_nonce_shares(&seller_pub_key_shares).await;
+    let seller_partial_signatures = seller.get_partial_signatures(Some(buyer_nonce_shares)).await;
+    let buyer_partial_signatures = buyer.get_partial_signatures(Some(seller_nonce_shares)).await;
+    seller.sign_deposit_tx(buyer_partial_signatures).await;
+    buyer.sign_deposit_tx(seller_partial_signatures).await;
+
+    // Once the seller has handed out its deposit tx signature, the buyer may publish the deposit tx,
+    // so the seller must keep the txs it needs to recover the funds.
+    let status = seller.reset_trade().await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+}
+
+const CONFIRMING_DEPOSIT_TX: &[Option<u32>] = &[None, Some(0), Some(1), Some(2), Some(3)];
+
+/// Publish the deposit tx, as the seller, returning the number of confirmations of each status
+/// received until the stream ends (along with the final trade state). The wallet gives the statuses
+/// with the given numbers of confirmations, then none, without ending its stream.
+async fn publish_deposit_tx(trade_id: &str, required_confirmations: u32, num_confirmations: &'static [Option<u32>])
+                            -> (Vec<u32>, TradeState) {
+    let clause = (
+        WalletServiceMock::broadcast_tx
+            .next_call(matching!(_))
+            .answers(&|_, tx| Ok(tx.compute_txid()))
+            .once(),
+        WalletServiceMock::get_tx_confidence_stream
+            .next_call(matching!(_))
+            .answers_arc(Arc::new(move |_, txid| mock_confidence_stream(txid, num_confirmations)))
+            .once(),
+        WalletServiceMock::get_merkle_proof
+            .next_call(matching!(_, _))
+            .answers(&|_, txid, _| Ok(Some(mock_merkle_proof(txid, true))))
+            .once(),
+        WalletServiceMock::block_height
+            .each_call(matching!())
+            .returns(900_001_u32),
+    );
+    let mut seller = Trader::new(trade_id, Unimock::new(clause));
+    let mut buyer = Trader::new(format!("{trade_id}-buyer"), Unimock::new(()));
+    let [_, buyer_deposit_psbt] = sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    let request = PublishDepositTxRequest {
+        trade_id: seller.trade_id.clone(),
+        peers_deposit_psbt: Some(buyer_deposit_psbt),
+        sequence_number: seller.next_sequence_number(),
+        required_confirmations,
+    };
+    let statuses = seller.musig.publish_deposit_tx(Request::new(request)).await.unwrap().into_inner().try_collect();
+    let statuses: Vec<_> = tokio::time::timeout(Duration::from_secs(5), statuses).await
+        .expect("stream should end with the first status with the required confirmations").unwrap();
+    assert!(statuses.iter().all(|status| status.current_block_height == 900_001 && !status.broadcast_failed));
+
+    let (state, _) = seller.trade_state_and_swap_txid().await;
+    (statuses.iter().map(|status| status.num_confirmations).collect(), state)
+}
+
+#[tokio::test]
+async fn test_publish_deposit_tx() {
+    // By default, the stream ends once the deposit tx has a confirmation.
+    let (num_confirmations, state) = publish_deposit_tx("publish-deposit-tx", 0, CONFIRMING_DEPOSIT_TX).await;
+    assert_eq!(num_confirmations, [0, 1]);
+    assert_eq!(state, TradeState::DepositTxPublished);
+}
+
+#[tokio::test]
+async fn test_publish_deposit_tx_required_confirmations() {
+    let (num_confirmations, _) = publish_deposit_tx("publish-deposit-tx-2-conf", 2, CONFIRMING_DEPOSIT_TX).await;
+    assert_eq!(num_confirmations, [0, 1, 2]);
+}
+
+#[tokio::test]
+async fn test_publish_deposit_tx_ends_without_further_status() {
+    // The wallet gives no status after the confirming one, so the stream must end on that alone.
+    let (num_confirmations, _) = publish_deposit_tx("publish-deposit-tx-no-more", 1, &[None, Some(0), Some(1)]).await;
+    assert_eq!(num_confirmations, [0, 1]);
+}
+
+#[tokio::test]
+async fn test_publish_deposit_tx_too_many_required_confirmations() {
+    // Nothing should be broadcast, so the wallet service has no mocked calls.
+    let mut seller = Trader::new("publish-deposit-tx-101-conf", Unimock::new(()));
+    let mut buyer = Trader::new("publish-deposit-tx-101-conf-buyer", Unimock::new(()));
+    let [_, buyer_deposit_psbt] = sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    let request = PublishDepositTxRequest {
+        trade_id: seller.trade_id.clone(),
+        peers_deposit_psbt: Some(buyer_deposit_psbt),
+        sequence_number: seller.next_sequence_number(),
+        required_confirmations: 101,
+    };
+    let Err(status) = seller.musig.publish_deposit_tx(Request::new(request)).await else {
+        panic!("expected too many required confirmations to be rejected");
+    };
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let (state, _) = seller.trade_state_and_swap_txid().await;
+    assert_eq!(state, TradeState::DepositTxSigned);
+}
+
+#[tokio::test]
+async fn test_subscribe_tx_confirmation_status_until_dropped() {
+    let clause = (
+        WalletServiceMock::get_tx_confidence_stream
+            .next_call(matching!(_))
+            .answers(&|_, txid| mock_confidence_stream(txid, &[None, Some(0), Some(1), None, Some(0)]))
+            .once(),
+        WalletServiceMock::get_merkle_proof
+            .next_call(matching!(_, _))
+            .answers(&|_, txid, _| Ok(Some(mock_merkle_proof(txid, true))))
+            .once(),
+        WalletServiceMock::block_height
+            .each_call(matching!())
+            .returns(900_001_u32),
+    );
+    let mut seller = Trader::new("subscribe-tx-status-seller", Unimock::new(clause));
+    let block_explorer = Some(BlockExplorerConfig::new("https://mempool.space/testnet4"));
+    seller.musig = seller.musig.with_config(Config { block_explorer, ..Config::default() });
+    let mut buyer = Trader::new("subscribe-tx-status-buyer", Unimock::new(()));
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    let request = SubscribeTxConfirmationStatusRequest {
+        trade_id: seller.trade_id.clone(),
+        sequence_number: seller.next_sequence_number(),
+        resume_deposit_monitoring: false,
+    };
+    let statuses: Vec<_> = seller.musig.subscribe_tx_confirmation_status(Request::new(request)).await.unwrap()
+        .into_inner().try_collect().await.unwrap();
+
+    // The stream ends with a zero-confirmation status as soon as the (reorged-out) tx goes missing.
+    let num_confirmations: Vec<_> = statuses.iter().map(|status| status.num_confirmations).collect();
+    assert_eq!(num_confirmations, [0, 1, 0]);
+    let trade_model = TRADE_MODELS.get_trade_model(&seller.trade_id).unwrap();
+    let txid = trade_model.lock().await.get_deposit_txid().unwrap();
+    for status in statuses {
+        assert_eq!(status.explorer_url, Some(format!("https://mempool.space/testnet4/tx/{txid}")));
+    }
+}
+
+#[tokio::test]
+async fn test_subscribe_tx_confirmation_status_invalid_merkle_proof() {
+    let clause = (
+        WalletServiceMock::get_tx_confidence_stream
+            .next_call(matching!(_))
+            .answers(&|_, txid| mock_confidence_stream(txid, &[Some(0), Some(1), Some(2)]))
+            .once(),
+        WalletServiceMock::get_merkle_proof
+            .next_call(matching!(_, _))
+            .answers(&|_, txid, _| Ok(Some(mock_merkle_proof(txid, false))))
+            .once(),
+        WalletServiceMock::block_height
+            .each_call(matching!())
+            .returns(900_001_u32),
+    );
+    let mut seller = Trader::new("invalid-merkle-proof-seller", Unimock::new(clause));
+    let mut buyer = Trader::new("invalid-merkle-proof-buyer", Unimock::new(()));
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    let request = SubscribeTxConfirmationStatusRequest {
+        trade_id: seller.trade_id.clone(),
+        sequence_number: seller.next_sequence_number(),
+        resume_deposit_monitoring: false,
+    };
+    let statuses: Vec<_> = seller.musig.subscribe_tx_confirmation_status(Request::new(request)).await.unwrap()
+        .into_inner().try_collect().await.unwrap();
+
+    // The stream halts at the first confirmation, as the proof of it is invalid.
+    let num_confirmations: Vec<_> = statuses.iter().map(|status| status.num_confirmations).collect();
+    assert_eq!(num_confirmations, [0]);
+}
+
+/// Attempt to publish the deposit tx, as the seller, with the broadcast failing as though the
+/// connection dropped midway, then resume monitoring it with the wallet having the tx already or
+/// not, returning the final trade state.
+async fn resume_deposit_monitoring(trade_id: &str, wallet_has_tx: bool) -> TradeState {
+    let clause = (
+        WalletServiceMock::broadcast_tx
+            .next_call(matching!(_))
+            .answers(&|_, _| Err(WalletErrorKind::NotConnected))
+            .once(),
+        WalletServiceMock::contains_tx
+            .next_call(matching!(_))
+            .returns(wallet_has_tx)
+            .once(),
+        WalletServiceMock::broadcast_tx
+            .next_call(matching!(_))
+            .answers(&|_, tx| Ok(tx.compute_txid()))
+            .n_times(usize::from(!wallet_has_tx)),
+        WalletServiceMock::get_tx_confidence_stream
+            .next_call(matching!(_))
+            .answers(&|_, txid| mock_confidence_stream(txid, &[Some(0), None]))
+            .once(),
+        WalletServiceMock::block_height
+            .each_call(matching!())
+            .returns(900_001_u32),
+    );
+    let mut seller = Trader::new(trade_id, Unimock::new(clause));
+    let mut buyer = Trader::new(format!("{trade_id}-buyer"), Unimock::new(()));
+    let [_, buyer_deposit_psbt] = sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    let request = PublishDepositTxRequest {
+        trade_id: seller.trade_id.clone(),
+        peers_deposit_psbt: Some(buyer_deposit_psbt),
+        sequence_number: seller.next_sequence_number(),
+        required_confirmations: 0,
+    };
+    assert!(seller.musig.publish_deposit_tx(Request::new(request)).await.is_err());
+
+    // The failed request didn't use up its sequence number, so the client reuses it on reconnecting.
+    let request = SubscribeTxConfirmationStatusRequest {
+        trade_id: seller.trade_id.clone(),
+        sequence_number: seller.sequence_number,
+        resume_deposit_monitoring: true,
+    };
+    let statuses: Vec<_> = seller.musig.subscribe_tx_confirmation_status(Request::new(request)).await.unwrap()
+        .into_inner().try_collect().await.unwrap();
+    assert!(statuses.iter().all(|status| status.num_confirmations == 0));
+
+    let (state, _) = seller.trade_state_and_swap_txid().await;
+    state
+}
+
+#[tokio::test]
+async fn test_resume_deposit_monitoring_skips_broadcast() {
+    // The tx reached the wallet (and so the network) before the connection dropped.
+    let state = resume_deposit_monitoring("resume-deposit-monitoring", true).await;
+    assert_eq!(state, TradeState::DepositTxPublished);
+}
+
+#[tokio::test]
+async fn test_resume_deposit_monitoring_rebroadcasts() {
+    let state = resume_deposit_monitoring("resume-deposit-monitoring-rebroadcast", false).await;
+    assert_eq!(state, TradeState::DepositTxPublished);
+}
+
+#[tokio::test]
+async fn test_audit_log_records_state_transitions() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit-log.jsonl");
+    let mut seller = Trader::new("audit-log-seller", Unimock::new(()));
+    seller.musig = seller.musig.with_audit_log(AuditLog::open(&path).unwrap());
+    let mut buyer = Trader::new("audit-log-buyer", Unimock::new(()));
+    sign_deposit_txs(&mut seller, &mut buyer).await;
+
+    let entries: Vec<_> = AuditLog::replay(&path).unwrap().collect();
+    let event_types: Vec<_> = entries.iter().map(|e| e.event_type).collect();
+    assert_eq!(event_types, [
+        AuditEventType::TradeInitiated,
+        AuditEventType::NonceSharesExchanged,
+        AuditEventType::PartialSignaturesExchanged,
+        AuditEventType::DepositTxSigned,
+    ]);
+    assert!(entries.iter().all(|e| e.trade_id == "audit-log-seller"));
+    assert_eq!(entries[0].trade_amount, None);
+    assert_eq!(entries[1].trade_amount, Some(Amount::from_sat(TRADE_AMOUNT)));
+    assert!(entries[2].deposit_txid.is_some());
+}
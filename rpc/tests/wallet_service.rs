@@ -1,13 +1,15 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use bdk_bitcoind_rpc::bitcoincore_rpc;
 use bdk_wallet::Balance;
 use bdk_wallet::bitcoin::Amount;
 use futures_util::StreamExt as _;
-use rpc::wallet::{TxConfidence, WalletService, WalletServiceImpl};
+use rpc::wallet::{
+    BitcoindRpcConnectionManager, BitcoindRpcPool, KeychainPurpose, TxConfidence, WalletService, WalletServiceImpl,
+};
 use testenv::TestEnv;
 use tokio::time::{self, Duration};
+use tokio_util::sync::CancellationToken;
 
 // TODO fix this test, I guess we need to rewrite it, may be the whole streaming of transaction events.
 #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
@@ -15,13 +17,14 @@ use tokio::time::{self, Duration};
 async fn test_wallet_service_mine_single_tx() -> Result<()> {
     let mut testenv = TestEnv::new()?;
 
-    let rpc_client = testenv.bitcoin_core_rpc_client()?;
+    let rpc_pool = BitcoindRpcConnectionManager::new(testenv.bitcoin_core_rpc_url(), testenv.bitcoin_core_rpc_auth())
+        .into_pool(BitcoindRpcConnectionManager::DEFAULT_POOL_SIZE);
 
-    let wallet_service = start_wallet_service(rpc_client).await;
+    let wallet_service = start_wallet_service(rpc_pool).await;
     let balance1 = wallet_service.balance();
 
     // Send 0.01 BTC from bitcoind to a fresh wallet address and wait for wallet to sync.
-    let addr = wallet_service.reveal_next_address();
+    let addr = wallet_service.reveal_next_address(KeychainPurpose::External)?;
     let amount = Amount::from_sat(1_000_000);
 
     let txid = testenv.fund_address(&addr.address, amount)?;
@@ -63,14 +66,14 @@ async fn test_wallet_service_mine_single_tx() -> Result<()> {
     Ok(())
 }
 
-async fn start_wallet_service(rpc_client: bitcoincore_rpc::Client) -> Arc<impl WalletService> {
+async fn start_wallet_service(rpc_pool: BitcoindRpcPool) -> Arc<impl WalletService> {
     let wallet_service = Arc::new(WalletServiceImpl::new()
         .with_poll_period(Duration::from_millis(100)));
     assert_eq!(wallet_service.balance(), Balance::default());
 
     wallet_service
         .clone()
-        .spawn_connection(Arc::new(rpc_client));
+        .spawn_connection(Arc::new(rpc_pool).into(), CancellationToken::new());
     // Wait for RPC sync...
     // FIXME: A bit hacky -- should add logic to the service to notify when the wallet is synced.
     time::sleep(Duration::from_secs(1)).await;
@@ -1,5 +1,7 @@
 //! Bitcoin regtest environment using electrsd with automatic executable downloads
 
+pub mod nigiri;
+
 use std::net::SocketAddrV4;
 use std::path::Path;
 use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
@@ -452,9 +454,15 @@ impl TestEnv {
     }
 
     pub fn bitcoin_core_rpc_client(&self) -> bitcoincore_rpc::Result<bitcoincore_rpc::Client> {
-        let url = &self.bitcoind.rpc_url();
-        let auth: Auth = Auth::CookieFile(self.bitcoind.params.cookie_file.clone());
-        bitcoincore_rpc::Client::new(url, auth)
+        bitcoincore_rpc::Client::new(&self.bitcoin_core_rpc_url(), self.bitcoin_core_rpc_auth())
+    }
+
+    pub fn bitcoin_core_rpc_url(&self) -> String {
+        self.bitcoind.rpc_url()
+    }
+
+    pub fn bitcoin_core_rpc_auth(&self) -> Auth {
+        Auth::CookieFile(self.bitcoind.params.cookie_file.clone())
     }
 
     /// Get the electrum URL
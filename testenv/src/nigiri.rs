@@ -0,0 +1,39 @@
+//! Helpers for a regtest chain run by [Nigiri](https://github.com/vulpemventures/nigiri), as used
+//! for manual testing against its local Esplora (see `rpc::explorer::BlockExplorerConfig`). They
+//! drive Nigiri's bitcoind through `nigiri rpc`, so Nigiri must be installed and started first.
+
+use std::process::Command;
+
+use anyhow::{Context as _, Result, ensure};
+use bdk_wallet::bitcoin::address::NetworkChecked;
+use bdk_wallet::bitcoin::{Address, Amount, BlockHash, Denomination, Txid};
+use bdk_wallet::serde_json;
+
+/// Run `nigiri rpc` with the given `bitcoin-cli` arguments, returning its trimmed output.
+pub fn rpc(args: &[&str]) -> Result<String> {
+    let output = Command::new("nigiri").arg("rpc").args(args).output()
+        .context("Failed to run nigiri; is it installed?")?;
+    ensure!(output.status.success(), "nigiri rpc {} failed: {}",
+        args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+}
+
+/// Mine `n` blocks to a throwaway address of Nigiri's bitcoind wallet, returning their hashes.
+pub fn mine_blocks(n: u32) -> Result<Vec<BlockHash>> {
+    let address = rpc(&["getnewaddress"])?;
+    let block_hashes = rpc(&["generatetoaddress", &n.to_string(), &address])?;
+    Ok(serde_json::from_str(&block_hashes)?)
+}
+
+/// Send `amount` to `address` from Nigiri's bitcoind wallet, then mine a block to confirm it,
+/// returning the funding txid. Should the wallet be short of mature coins, 101 blocks are mined
+/// first to make a coinbase spendable.
+pub fn fund_address(address: &Address<NetworkChecked>, amount: Amount) -> Result<Txid> {
+    let balance = Amount::from_str_in(&rpc(&["getbalance"])?, Denomination::Bitcoin)?;
+    if balance < amount {
+        mine_blocks(101)?;
+    }
+    let txid = rpc(&["sendtoaddress", &address.to_string(), &amount.to_string_in(Denomination::Bitcoin)])?;
+    mine_blocks(1)?;
+    Ok(txid.parse()?)
+}
@@ -0,0 +1,38 @@
+//! Integration test for the [`testenv::nigiri`] helpers, against a locally running Nigiri. Start
+//! Nigiri first, then run it with:
+//!
+//!   ```sh
+//!   nigiri start
+//!   cargo test -p testenv --test nigiri -- --ignored
+//!   ```
+use bdk_wallet::bitcoin::{Address, Amount, Denomination};
+use bdk_wallet::serde_json::{self, Value};
+use testenv::nigiri;
+
+fn block_count() -> u64 { nigiri::rpc(&["getblockcount"]).unwrap().parse().unwrap() }
+
+#[test]
+#[ignore = "needs a running Nigiri"]
+fn test_mine_blocks() {
+    let start_height = block_count();
+    let block_hashes = nigiri::mine_blocks(3).unwrap();
+    assert_eq!(block_hashes.len(), 3);
+    assert_eq!(block_count(), start_height + 3);
+    assert_eq!(nigiri::rpc(&["getbestblockhash"]).unwrap(), block_hashes[2].to_string());
+}
+
+#[test]
+#[ignore = "needs a running Nigiri"]
+fn test_fund_address() {
+    let address: Address = nigiri::rpc(&["getnewaddress"]).unwrap().parse::<Address<_>>().unwrap()
+        .assume_checked();
+    let amount = Amount::from_sat(123_456);
+    let txid = nigiri::fund_address(&address, amount).unwrap();
+
+    // The funding tx is confirmed at once, so the address holds exactly the amount sent, with a
+    // single confirmation.
+    let received = nigiri::rpc(&["getreceivedbyaddress", &address.to_string(), "1"]).unwrap();
+    assert_eq!(received, amount.to_string_in(Denomination::Bitcoin));
+    let tx: Value = serde_json::from_str(&nigiri::rpc(&["gettransaction", &txid.to_string()]).unwrap()).unwrap();
+    assert_eq!(tx["confirmations"], 1);
+}
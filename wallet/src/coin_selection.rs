@@ -1,14 +1,44 @@
-use bdk_wallet::WeightedUtxo;
-use bdk_wallet::bitcoin::{Amount, FeeRate, Script, key};
+use bdk_wallet::bitcoin::{Amount, FeeRate, Script, TxIn, Weight, key};
+use bdk_wallet::chain::ChainPosition;
 use bdk_wallet::coin_selection::{
     CoinSelectionAlgorithm, CoinSelectionResult, DefaultCoinSelectionAlgorithm, InsufficientFunds,
+    decide_change,
 };
+use bdk_wallet::{Utxo, WeightedUtxo};
 
 #[derive(Debug)]
 pub struct AlwaysSpendImportedFirst(pub Vec<WeightedUtxo>);
 #[derive(Debug)]
 pub struct SpendImportedOnly(pub Vec<WeightedUtxo>);
 
+/// Picks the optional UTXOs with the greatest coin age first, that is the product of the value and
+/// the number of confirmations at the given chain tip, until the target amount is reached. Spending
+/// the oldest and largest coins first keeps the age of the remaining UTXO set down, which suits
+/// consolidation. Unconfirmed and foreign UTXOs have no coin age and are picked last.
+///
+/// Note that the sort key is confirmations × value, not confirmation height × value, which would
+/// instead put the youngest coins first.
+#[derive(Debug, Clone, Copy)]
+pub struct CoinAgePrioritySelector {
+    pub tip_height: u32,
+}
+
+impl CoinAgePrioritySelector {
+    /// The coin age of the given UTXO in satoshi-blocks, counting the block it confirmed in.
+    pub fn coin_age(&self, weighted_utxo: &WeightedUtxo) -> u128 {
+        let confirmations = match &weighted_utxo.utxo {
+            Utxo::Local(local) => match local.chain_position {
+                ChainPosition::Confirmed { anchor, .. } => {
+                    (self.tip_height + 1).saturating_sub(anchor.block_id.height)
+                }
+                ChainPosition::Unconfirmed { .. } => 0,
+            },
+            Utxo::Foreign { .. } => 0,
+        };
+        u128::from(weighted_utxo.utxo.txout().value.to_sat()) * u128::from(confirmations)
+    }
+}
+
 impl CoinSelectionAlgorithm for AlwaysSpendImportedFirst {
     fn coin_select<R: key::rand::RngCore>(
         &self,
@@ -77,13 +107,62 @@ impl CoinSelectionAlgorithm for SpendImportedOnly {
         )
     }
 }
+
+impl CoinSelectionAlgorithm for CoinAgePrioritySelector {
+    fn coin_select<R: key::rand::RngCore>(
+        &self,
+        required_utxos: Vec<WeightedUtxo>,
+        mut optional_utxos: Vec<WeightedUtxo>,
+        fee_rate: FeeRate,
+        target_amount: Amount,
+        drain_script: &Script,
+        _: &mut R,
+    ) -> Result<CoinSelectionResult, InsufficientFunds> {
+        // The required UTXOs always go in, followed greedily by the optional UTXOs of greatest
+        // coin age for as long as the selection doesn't yet cover the target amount plus fees.
+        optional_utxos.sort_by_key(|wu| std::cmp::Reverse(self.coin_age(wu)));
+        let mut selected_amount = Amount::ZERO;
+        let mut total_weight = Weight::ZERO;
+        let mut selected = Vec::new();
+        let utxos = (required_utxos.into_iter().map(|wu| (true, wu)))
+            .chain(optional_utxos.into_iter().map(|wu| (false, wu)));
+        for (must_use, weighted_utxo) in utxos {
+            if !must_use && selected_amount >= target_amount + fee_rate * total_weight {
+                break;
+            }
+            total_weight += TxIn::default().segwit_weight() + weighted_utxo.satisfaction_weight;
+            selected_amount += weighted_utxo.utxo.txout().value;
+            selected.push(weighted_utxo.utxo);
+        }
+
+        let fee_amount = fee_rate * total_weight;
+        let amount_needed_with_fees = target_amount + fee_amount;
+        if selected_amount < amount_needed_with_fees {
+            return Err(InsufficientFunds {
+                needed: amount_needed_with_fees,
+                available: selected_amount,
+            });
+        }
+        let excess = decide_change(
+            selected_amount - amount_needed_with_fees,
+            fee_rate,
+            drain_script,
+        );
+        Ok(CoinSelectionResult {
+            selected,
+            fee_amount,
+            excess,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use bdk_wallet::bitcoin::key::rand::thread_rng;
     use bdk_wallet::bitcoin::{Amount, FeeRate, ScriptBuf};
     use bdk_wallet::coin_selection::{CoinSelectionAlgorithm as _, Excess};
 
-    use crate::coin_selection::AlwaysSpendImportedFirst;
+    use crate::coin_selection::{AlwaysSpendImportedFirst, CoinAgePrioritySelector};
     use crate::test_utils::{confirmed_utxo, foreign_utxo};
 
     #[test]
@@ -155,4 +234,80 @@ mod tests {
         assert_eq!(res.selected.len(), 1);
         assert!(matches!(res.excess, Excess::Change { amount: _, fee: _ }));
     }
+
+    #[test]
+    fn test_coin_age_priority() {
+        let selection_strategy = CoinAgePrioritySelector { tip_height: 100 };
+        // Coin ages at height 100: 0.5 * 100, 2 * 11, 1 * 51 & 3 * 1 BTC-blocks respectively.
+        let local_utxos = vec![
+            confirmed_utxo(Amount::from_btc(0.5).unwrap(), 0, 1, 1_231_006_505),
+            confirmed_utxo(Amount::from_int_btc(2), 1, 90, 1_231_006_505),
+            confirmed_utxo(Amount::from_int_btc(1), 2, 50, 1_231_006_505),
+            confirmed_utxo(Amount::from_int_btc(3), 3, 100, 1_231_006_505),
+        ];
+        let coin_ages = local_utxos
+            .iter()
+            .map(|wu| selection_strategy.coin_age(wu))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            coin_ages,
+            [5_000_000_000, 2_200_000_000, 5_100_000_000, 300_000_000]
+        );
+        let drain_script = ScriptBuf::default();
+
+        // The oldest-and-largest coins of 1 & 0.5 BTC together cover 1.2 BTC, with change.
+        let res = selection_strategy
+            .coin_select(
+                vec![],
+                local_utxos.clone(),
+                FeeRate::from_sat_per_kwu(50_000),
+                Amount::from_btc(1.2).unwrap(),
+                &drain_script,
+                &mut thread_rng(),
+            )
+            .unwrap();
+        let selected_coin_age: u128 = (res.selected.iter())
+            .map(|utxo| {
+                (local_utxos
+                    .iter()
+                    .find(|wu| wu.utxo.outpoint() == utxo.outpoint()))
+                .unwrap()
+            })
+            .map(|wu| selection_strategy.coin_age(wu))
+            .sum();
+        assert_eq!(res.selected.len(), 2);
+        assert_eq!(res.selected[0].outpoint(), local_utxos[2].utxo.outpoint());
+        assert_eq!(res.selected[1].outpoint(), local_utxos[0].utxo.outpoint());
+        assert_eq!(selected_coin_age, 10_100_000_000);
+        assert!(matches!(res.excess, Excess::Change { amount: _, fee: _ }));
+
+        // A 3 BTC target needs the 2 BTC coin too, but not the youngest 3 BTC coin.
+        let res = selection_strategy
+            .coin_select(
+                vec![],
+                local_utxos.clone(),
+                FeeRate::from_sat_per_kwu(50_000),
+                Amount::from_int_btc(3),
+                &drain_script,
+                &mut thread_rng(),
+            )
+            .unwrap();
+        assert_eq!(res.selected.len(), 3);
+        assert!(
+            res.selected
+                .iter()
+                .all(|utxo| utxo.outpoint() != local_utxos[3].utxo.outpoint())
+        );
+
+        // Not even all the coins together cover 7 BTC.
+        let res = selection_strategy.coin_select(
+            vec![],
+            local_utxos,
+            FeeRate::from_sat_per_kwu(50_000),
+            Amount::from_int_btc(7),
+            &drain_script,
+            &mut thread_rng(),
+        );
+        assert!(res.is_err());
+    }
 }
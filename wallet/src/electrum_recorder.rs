@@ -0,0 +1,373 @@
+//! Recording and replay of Electrum server responses, so that tests syncing a wallet can be run
+//! offline and reproducibly, once the responses have been recorded from a live server.
+
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use bdk_electrum::electrum_client::{
+    Batch, ElectrumApi, Error, GetBalanceRes, GetHeadersRes, GetHistoryRes, GetMerkleRes,
+    ListUnspentRes, Param, RawHeaderNotification, ScriptStatus, ServerFeaturesRes, TxidFromPosRes,
+};
+use bdk_wallet::bitcoin::hex::{DisplayHex, FromHex as _};
+use bdk_wallet::bitcoin::{Script, Txid};
+use bdk_wallet::serde_json::{self, Value, json};
+
+/// An Electrum client proxy, which either forwards each call made by a wallet sync to a live
+/// client and records the response, or replays a previously saved recording with no network
+/// access. Responses are keyed by method and arguments, so a replayed sync must make the same
+/// calls as the recorded one, e.g. by using a wallet with the same seed.
+///
+/// Only the calls made by [`bdk_electrum::BdkElectrumClient`] to scan and sync a wallet are
+/// recorded. Any other calls are forwarded unrecorded, failing when replaying.
+pub struct RecordingClient<E> {
+    /// The live client, if recording, or `None` if replaying.
+    inner: Option<E>,
+    responses: Mutex<BTreeMap<String, Value>>,
+}
+
+impl<E: ElectrumApi> RecordingClient<E> {
+    pub fn recording(inner: E) -> Self {
+        Self {
+            inner: Some(inner),
+            responses: Mutex::default(),
+        }
+    }
+
+    pub fn replaying(record_path: &Path) -> anyhow::Result<Self> {
+        let responses = serde_json::from_slice(&fs::read(record_path)?)?;
+        Ok(Self {
+            inner: None,
+            responses: Mutex::new(responses),
+        })
+    }
+
+    pub const fn is_recording(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Save the responses recorded so far to the given path, as (pretty printed) JSON.
+    pub fn save(&self, record_path: &Path) -> anyhow::Result<()> {
+        let responses = self
+            .responses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        fs::write(record_path, serde_json::to_string_pretty(&*responses)?)?;
+        Ok(())
+    }
+
+    fn live(&self) -> Result<&E, Error> {
+        self.inner.as_ref().ok_or_else(|| {
+            Error::Message("call not supported when replaying a recording".to_owned())
+        })
+    }
+
+    /// Make the given call on the live client and record its (JSON) response under the given key,
+    /// or look up the response recorded under that key if replaying.
+    fn call(
+        &self,
+        key: String,
+        call: impl FnOnce(&E) -> Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        let Some(inner) = &self.inner else {
+            let responses = self
+                .responses
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            return responses
+                .get(&key)
+                .cloned()
+                .ok_or_else(|| Error::Message(format!("no recorded response for: {key}")));
+        };
+        let response = call(inner)?;
+        self.responses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, response.clone());
+        Ok(response)
+    }
+
+    fn call_raw(
+        &self,
+        key: String,
+        call: impl FnOnce(&E) -> Result<Vec<u8>, Error>,
+    ) -> Result<Vec<u8>, Error> {
+        let response = self.call(key, |inner| {
+            Ok(Value::String(call(inner)?.to_lower_hex_string()))
+        })?;
+        Ok(Vec::from_hex(&serde_json::from_value::<String>(response)?)?)
+    }
+}
+
+// The recorded responses have the same JSON form as those of the Electrum server itself, so that
+// they can be deserialized into the (deserialize-only) response types of the client library.
+
+fn history_json(history: &[GetHistoryRes]) -> Value {
+    history
+        .iter()
+        .map(|res| json!({"height": res.height, "tx_hash": res.tx_hash, "fee": res.fee}))
+        .collect()
+}
+
+fn merkle_json(res: &GetMerkleRes) -> Value {
+    let merkle: Vec<_> = res
+        .merkle
+        .iter()
+        .map(DisplayHex::to_lower_hex_string)
+        .collect();
+    json!({"block_height": res.block_height, "pos": res.pos, "merkle": merkle})
+}
+
+impl<E: ElectrumApi> ElectrumApi for RecordingClient<E> {
+    fn raw_call(
+        &self,
+        method_name: &str,
+        params: impl IntoIterator<Item = Param>,
+    ) -> Result<Value, Error> {
+        self.live()?.raw_call(method_name, params)
+    }
+
+    fn batch_call(&self, batch: &Batch) -> Result<Vec<Value>, Error> {
+        self.live()?.batch_call(batch)
+    }
+
+    fn block_headers_subscribe_raw(&self) -> Result<RawHeaderNotification, Error> {
+        let response = self.call("blockchain.headers.subscribe".to_owned(), |inner| {
+            let notification = inner.block_headers_subscribe_raw()?;
+            Ok(json!({"height": notification.height, "hex": notification.header.to_lower_hex_string()}))
+        })?;
+        Ok(serde_json::from_value(response)?)
+    }
+
+    fn block_headers_pop_raw(&self) -> Result<Option<RawHeaderNotification>, Error> {
+        self.live()?.block_headers_pop_raw()
+    }
+
+    fn block_header_raw(&self, height: usize) -> Result<Vec<u8>, Error> {
+        self.call_raw(format!("blockchain.block.header {height}"), |inner| {
+            inner.block_header_raw(height)
+        })
+    }
+
+    fn block_headers(&self, start_height: usize, count: usize) -> Result<GetHeadersRes, Error> {
+        self.live()?.block_headers(start_height, count)
+    }
+
+    fn estimate_fee(&self, number: usize) -> Result<f64, Error> {
+        self.live()?.estimate_fee(number)
+    }
+
+    fn relay_fee(&self) -> Result<f64, Error> {
+        self.live()?.relay_fee()
+    }
+
+    fn script_subscribe(&self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        self.live()?.script_subscribe(script)
+    }
+
+    fn batch_script_subscribe<'s, I>(&self, scripts: I) -> Result<Vec<Option<ScriptStatus>>, Error>
+    where
+        I: IntoIterator + Clone,
+        I::Item: Borrow<&'s Script>,
+    {
+        self.live()?.batch_script_subscribe(scripts)
+    }
+
+    fn script_unsubscribe(&self, script: &Script) -> Result<bool, Error> {
+        self.live()?.script_unsubscribe(script)
+    }
+
+    fn script_pop(&self, script: &Script) -> Result<Option<ScriptStatus>, Error> {
+        self.live()?.script_pop(script)
+    }
+
+    fn script_get_balance(&self, script: &Script) -> Result<GetBalanceRes, Error> {
+        self.live()?.script_get_balance(script)
+    }
+
+    fn batch_script_get_balance<'s, I>(&self, scripts: I) -> Result<Vec<GetBalanceRes>, Error>
+    where
+        I: IntoIterator + Clone,
+        I::Item: Borrow<&'s Script>,
+    {
+        self.live()?.batch_script_get_balance(scripts)
+    }
+
+    fn script_get_history(&self, script: &Script) -> Result<Vec<GetHistoryRes>, Error> {
+        let key = format!(
+            "blockchain.scripthash.get_history {}",
+            script.to_hex_string()
+        );
+        let response = self.call(key, |inner| {
+            Ok(history_json(&inner.script_get_history(script)?))
+        })?;
+        Ok(serde_json::from_value(response)?)
+    }
+
+    fn batch_script_get_history<'s, I>(&self, scripts: I) -> Result<Vec<Vec<GetHistoryRes>>, Error>
+    where
+        I: IntoIterator + Clone,
+        I::Item: Borrow<&'s Script>,
+    {
+        // Record each script's history separately, so that a replay needn't batch them the same way.
+        scripts
+            .into_iter()
+            .map(|script| self.script_get_history(script.borrow()))
+            .collect()
+    }
+
+    fn script_list_unspent(&self, script: &Script) -> Result<Vec<ListUnspentRes>, Error> {
+        self.live()?.script_list_unspent(script)
+    }
+
+    fn batch_script_list_unspent<'s, I>(
+        &self,
+        scripts: I,
+    ) -> Result<Vec<Vec<ListUnspentRes>>, Error>
+    where
+        I: IntoIterator + Clone,
+        I::Item: Borrow<&'s Script>,
+    {
+        self.live()?.batch_script_list_unspent(scripts)
+    }
+
+    fn transaction_get_raw(&self, txid: &Txid) -> Result<Vec<u8>, Error> {
+        self.call_raw(format!("blockchain.transaction.get {txid}"), |inner| {
+            inner.transaction_get_raw(txid)
+        })
+    }
+
+    fn batch_transaction_get_raw<'t, I>(&self, txids: I) -> Result<Vec<Vec<u8>>, Error>
+    where
+        I: IntoIterator + Clone,
+        I::Item: Borrow<&'t Txid>,
+    {
+        txids
+            .into_iter()
+            .map(|txid| self.transaction_get_raw(txid.borrow()))
+            .collect()
+    }
+
+    fn batch_block_header_raw<I>(&self, heights: I) -> Result<Vec<Vec<u8>>, Error>
+    where
+        I: IntoIterator + Clone,
+        I::Item: Borrow<u32>,
+    {
+        heights
+            .into_iter()
+            .map(|height| self.block_header_raw(*height.borrow() as usize))
+            .collect()
+    }
+
+    fn batch_estimate_fee<I>(&self, numbers: I) -> Result<Vec<f64>, Error>
+    where
+        I: IntoIterator + Clone,
+        I::Item: Borrow<usize>,
+    {
+        self.live()?.batch_estimate_fee(numbers)
+    }
+
+    fn transaction_broadcast_raw(&self, raw_tx: &[u8]) -> Result<Txid, Error> {
+        self.live()?.transaction_broadcast_raw(raw_tx)
+    }
+
+    fn transaction_get_merkle(&self, txid: &Txid, height: usize) -> Result<GetMerkleRes, Error> {
+        let key = format!("blockchain.transaction.get_merkle {txid} {height}");
+        let response = self.call(key, |inner| {
+            Ok(merkle_json(&inner.transaction_get_merkle(txid, height)?))
+        })?;
+        Ok(serde_json::from_value(response)?)
+    }
+
+    fn batch_transaction_get_merkle<I>(
+        &self,
+        txids_and_heights: I,
+    ) -> Result<Vec<GetMerkleRes>, Error>
+    where
+        I: IntoIterator + Clone,
+        I::Item: Borrow<(Txid, usize)>,
+    {
+        txids_and_heights
+            .into_iter()
+            .map(|txid_and_height| {
+                let (txid, height) = txid_and_height.borrow();
+                self.transaction_get_merkle(txid, *height)
+            })
+            .collect()
+    }
+
+    fn txid_from_pos(&self, height: usize, tx_pos: usize) -> Result<Txid, Error> {
+        self.live()?.txid_from_pos(height, tx_pos)
+    }
+
+    fn txid_from_pos_with_merkle(
+        &self,
+        height: usize,
+        tx_pos: usize,
+    ) -> Result<TxidFromPosRes, Error> {
+        self.live()?.txid_from_pos_with_merkle(height, tx_pos)
+    }
+
+    fn server_features(&self) -> Result<ServerFeaturesRes, Error> {
+        self.live()?.server_features()
+    }
+
+    fn ping(&self) -> Result<(), Error> {
+        self.live()?.ping()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk_electrum::electrum_client::Client;
+    use bdk_wallet::bitcoin::hashes::Hash as _;
+    use bdk_wallet::bitcoin::{CompactTarget, ScriptBuf, TxMerkleNode, block, consensus};
+
+    use super::*;
+
+    #[test]
+    fn test_replay_recording() -> anyhow::Result<()> {
+        let header = block::Header {
+            version: block::Version::TWO,
+            prev_blockhash: block::BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_700_000_000,
+            bits: CompactTarget::from_consensus(0x207f_ffff),
+            nonce: 2,
+        };
+        let header_hex = consensus::serialize(&header).to_lower_hex_string();
+        let script = ScriptBuf::from_bytes(vec![0x51, 0x20, 0xab]);
+        let txid = Txid::from_byte_array([1; 32]);
+        let merkle_hash = [2u8; 32].to_lower_hex_string();
+        let recording = json!({
+            "blockchain.headers.subscribe": {"height": 101, "hex": header_hex},
+            "blockchain.block.header 101": header_hex,
+            format!("blockchain.scripthash.get_history {}", script.to_hex_string()):
+                [{"height": 101, "tx_hash": txid, "fee": null}],
+            format!("blockchain.transaction.get_merkle {txid} 101"):
+                {"block_height": 101, "pos": 1, "merkle": [merkle_hash]},
+        });
+        let dir = tempfile::tempdir()?;
+        let record_path = dir.path().join("recording.json");
+        fs::write(&record_path, recording.to_string())?;
+
+        let client = RecordingClient::<Client>::replaying(&record_path)?;
+        assert!(!client.is_recording());
+        let notification = client.block_headers_subscribe()?;
+        assert_eq!((notification.height, notification.header), (101, header));
+        assert_eq!(client.batch_block_header([101])?, [header]);
+        let history = client.batch_script_get_history([script.as_script()])?;
+        assert_eq!((history[0][0].height, history[0][0].tx_hash), (101, txid));
+        let proof = client.transaction_get_merkle(&txid, 101)?;
+        assert_eq!((proof.pos, proof.merkle), (1, vec![[2; 32]]));
+
+        assert!(matches!(
+            client.transaction_get_raw(&txid),
+            Err(Error::Message(_))
+        ));
+        assert!(matches!(client.ping(), Err(Error::Message(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! A persistent cache of the full txs downloaded from Electrum, so that a restarted wallet can
+//! pre-fill the tx cache of each new Electrum client, instead of fetching every tx again on sync.
+
+use std::borrow::Borrow;
+use std::path::Path;
+use std::sync::Arc;
+
+use bdk_wallet::bitcoin::hashes::Hash as _;
+use bdk_wallet::bitcoin::{Transaction, consensus};
+use bdk_wallet::rusqlite::{self, Connection, named_params};
+
+pub struct ElectrumTxCache {
+    db: Connection,
+}
+
+impl ElectrumTxCache {
+    const TABLE_NAME: &str = "electrum_tx_cache";
+
+    /// Open the cache in the database at the given path, creating it if need be.
+    pub fn open(db_path: &Path) -> Result<Self, rusqlite::Error> {
+        let db = Connection::open(db_path)?;
+        db.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} ( \
+                    txid BLOB PRIMARY KEY NOT NULL, \
+                    tx BLOB NOT NULL
+                ) STRICT",
+            Self::TABLE_NAME,
+        ))?;
+        Ok(Self { db })
+    }
+
+    /// Load all the cached txs, to populate the cache of a newly created Electrum client.
+    pub fn load(&self) -> anyhow::Result<Vec<Arc<Transaction>>> {
+        let mut statement = self
+            .db
+            .prepare(&format!("SELECT tx FROM {}", Self::TABLE_NAME))?;
+        let row_iter = statement.query_map([], |row| row.get::<_, Vec<u8>>("tx"))?;
+
+        let mut txs = vec![];
+        for row in row_iter {
+            txs.push(Arc::new(consensus::deserialize(&row?)?));
+        }
+        Ok(txs)
+    }
+
+    /// Store the given txs, skipping any already in the cache.
+    pub fn persist(
+        &mut self,
+        txs: impl IntoIterator<Item = impl Borrow<Transaction>>,
+    ) -> Result<(), rusqlite::Error> {
+        let trx = self.db.transaction()?;
+        {
+            let mut stmt = trx.prepare(&format!(
+                "INSERT OR IGNORE INTO {}(txid, tx) VALUES(:txid, :tx)",
+                Self::TABLE_NAME,
+            ))?;
+            for tx in txs {
+                let tx = tx.borrow();
+                stmt.execute(named_params! {
+                    ":txid": tx.compute_txid().as_byte_array(),
+                    ":tx": consensus::serialize(tx),
+                })?;
+            }
+        }
+        trx.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bdk_wallet::bitcoin::transaction::Version;
+    use bdk_wallet::bitcoin::{Amount, OutPoint, ScriptBuf, TxIn, TxOut, Txid, absolute};
+
+    use super::*;
+
+    fn tx(b: u8) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([b; 32]), 0),
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new_p2a(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_tx_cache_survives_reopening() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db_path = dir.path().join("tx_cache.sqlite");
+
+        let mut cache = ElectrumTxCache::open(&db_path)?;
+        assert!(cache.load()?.is_empty());
+        cache.persist([tx(1), tx(2)])?;
+        // Persisting an already cached tx is a no-op.
+        cache.persist([tx(2), tx(3)])?;
+        drop(cache);
+
+        let cache = ElectrumTxCache::open(&db_path)?;
+        let mut txids: Vec<_> = cache.load()?.iter().map(|tx| tx.compute_txid()).collect();
+        txids.sort_unstable();
+        let mut expected_txids = [tx(1), tx(2), tx(3)].map(|tx| tx.compute_txid());
+        expected_txids.sort_unstable();
+        assert_eq!(txids, expected_txids);
+        Ok(())
+    }
+}
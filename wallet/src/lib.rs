@@ -1,8 +1,17 @@
-mod coin_selection;
+#[cfg(feature = "native")]
 mod utils;
 
+#[cfg(feature = "native")]
 pub mod bmp_wallet;
+#[cfg(feature = "native")]
 pub mod chain_data_source;
+pub mod coin_selection;
+#[cfg(feature = "native")]
+pub mod electrum_recorder;
+#[cfg(feature = "native")]
+pub mod electrum_tx_cache;
+#[cfg(feature = "native")]
+mod mem_wallet;
 pub mod protocol_wallet_api;
 #[cfg(test)]
 pub mod test_utils;
@@ -0,0 +1,316 @@
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bdk_electrum::BdkElectrumClient;
+use bdk_electrum::bdk_core::bitcoin::bip32::Xpriv;
+use bdk_electrum::electrum_client::{Client, ElectrumApi};
+use bdk_wallet::bitcoin::constants::ChainHash;
+use bdk_wallet::bitcoin::hashes::Hash as _;
+use bdk_wallet::bitcoin::{
+    Address, Amount, BlockHash, FeeRate, Network, OutPoint, Psbt, ScriptBuf, XOnlyPublicKey,
+};
+use bdk_wallet::descriptor::ExtendedDescriptor;
+use bdk_wallet::template::{Bip86, DescriptorTemplate as _};
+use bdk_wallet::{AddressInfo, KeychainKind, Wallet};
+use rand::RngCore as _;
+use secp::Scalar;
+
+use crate::electrum_recorder::RecordingClient;
+use crate::electrum_tx_cache::ElectrumTxCache;
+use crate::protocol_wallet_api::{ElectrumConfig, ProtocolWalletApi, Result, WalletExt};
+
+pub struct MemWallet {
+    wallet: Wallet,
+    /// The Electrum client to sync with, or `None` for an offline wallet, which may only be synced
+    /// by replaying a recording.
+    client: Option<BdkElectrumClient<Client>>,
+    /// Whether [`Self::sync_with_recording`] should (re-)record the live Electrum responses, even
+    /// if a recording already exists.
+    recording: bool,
+    electrum_config: ElectrumConfig,
+    /// Where to keep the txs downloaded on sync, so that they needn't be fetched again after a
+    /// restart, or `None` to only cache them in the memory of each Electrum client.
+    tx_cache: Option<ElectrumTxCache>,
+}
+
+/// Check that the Electrum server is on the chain of the given network, by its genesis block hash,
+/// so that a misconfigured server fails the sync outright instead of yielding a bogus scan.
+pub fn check_electrum_health<E: ElectrumApi>(
+    client: &BdkElectrumClient<E>,
+    network: Network,
+) -> anyhow::Result<()> {
+    let features = client
+        .inner
+        .server_features()
+        .context("could not get Electrum server features")?;
+    // The server gives the genesis hash in display (i.e. reversed) byte order.
+    let mut genesis_hash = features.genesis_hash;
+    genesis_hash.reverse();
+    let genesis_hash = BlockHash::from_byte_array(genesis_hash);
+    let expected_genesis_hash =
+        BlockHash::from_byte_array(ChainHash::using_genesis_block_const(network).to_bytes());
+    if genesis_hash != expected_genesis_hash {
+        anyhow::bail!(
+            "Electrum server is on a different chain than {network}: genesis block hash \
+            {genesis_hash} instead of {expected_genesis_hash}"
+        );
+    }
+    Ok(())
+}
+
+impl MemWallet {
+    pub fn public_descriptor(&self, chain: KeychainKind) -> &ExtendedDescriptor {
+        self.wallet.public_descriptor(chain)
+    }
+
+    pub fn new(client: BdkElectrumClient<Client>) -> anyhow::Result<Self> {
+        let mut seed: [u8; 32] = [0u8; 32];
+        rand::rng().fill_bytes(&mut seed);
+        Self::from_seed(Some(client), &seed)
+    }
+
+    /// Create a wallet with keys derived from the given seed, so that its syncs can be recorded and
+    /// replayed with [`Self::sync_with_recording`]. The client may be omitted if only replaying.
+    pub fn from_seed(
+        client: Option<BdkElectrumClient<Client>>,
+        seed: &[u8; 32],
+    ) -> anyhow::Result<Self> {
+        let network: Network = Network::Regtest;
+        let xprv: Xpriv = Xpriv::new_master(network, seed)?;
+        tracing::info!(
+            "Generated Master Private Key:\n{xprv}\nWarning: be very careful with private \
+            keys when using MainNet! We are logging these values for convenience only because this \
+            is an example on RegTest.\n"
+        );
+
+        let (descriptor, external_map, _) = Bip86(xprv, KeychainKind::External)
+            .build(network.into())
+            .expect("Failed to build external descriptor");
+
+        let (change_descriptor, internal_map, _) = Bip86(xprv, KeychainKind::Internal)
+            .build(network.into())
+            .expect("Failed to build internal descriptor");
+
+        let wallet = Wallet::create(descriptor, change_descriptor)
+            .network(network)
+            .keymap(KeychainKind::External, external_map)
+            .keymap(KeychainKind::Internal, internal_map)
+            .create_wallet_no_persist()?;
+
+        Ok(Self {
+            wallet,
+            client,
+            recording: false,
+            electrum_config: ElectrumConfig::default(),
+            tx_cache: None,
+        })
+    }
+
+    #[must_use]
+    pub fn with_recording(self, recording: bool) -> Self {
+        Self { recording, ..self }
+    }
+
+    #[must_use]
+    pub fn with_electrum_config(self, electrum_config: ElectrumConfig) -> Self {
+        Self {
+            electrum_config,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn with_tx_cache(self, tx_cache: ElectrumTxCache) -> Self {
+        Self {
+            tx_cache: Some(tx_cache),
+            ..self
+        }
+    }
+
+    pub fn sync(&mut self) -> anyhow::Result<()> {
+        let client = self
+            .client
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("offline wallet has no Electrum client to sync with"))?;
+        check_electrum_health(client, self.wallet.network())?;
+        Self::full_scan(
+            &mut self.wallet,
+            client,
+            self.electrum_config,
+            self.tx_cache.as_mut(),
+        )
+    }
+
+    /// Sync the wallet with the first of the given Electrum servers to succeed, trying each in turn,
+    /// so that a single unavailable server doesn't fail the sync. The client of the server synced
+    /// with is kept for later calls to [`Self::sync`].
+    pub fn sync_with_fallback(&mut self, urls: &[&str]) -> anyhow::Result<()> {
+        let mut errors = Vec::new();
+        for &url in urls {
+            let result = Client::new(url)
+                .map_err(anyhow::Error::from)
+                .and_then(|client| {
+                    let client = BdkElectrumClient::new(client);
+                    check_electrum_health(&client, self.wallet.network())?;
+                    Self::full_scan(
+                        &mut self.wallet,
+                        &client,
+                        self.electrum_config,
+                        self.tx_cache.as_mut(),
+                    )?;
+                    Ok(client)
+                });
+            match result {
+                Ok(client) => {
+                    self.client = Some(client);
+                    return Ok(());
+                }
+                Err(e) => {
+                    tracing::warn!(url, "Electrum sync failed: {e:#}");
+                    errors.push(format!("{url}: {e:#}"));
+                }
+            }
+        }
+        Err(anyhow::anyhow!(errors.join("; ")))
+            .with_context(|| format!("sync failed with all {} Electrum servers", urls.len()))
+    }
+
+    /// Sync the wallet by replaying the Electrum responses recorded at the given path, without
+    /// network access, or if there is no recording yet (or [`Self::with_recording`] was set),
+    /// by syncing with the live Electrum server and saving its responses there.
+    pub fn sync_with_recording(&mut self, record_path: &Path) -> anyhow::Result<()> {
+        let recorder = if self.recording || !record_path.exists() {
+            let client = self.client.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("offline wallet has no Electrum client to record from")
+            })?;
+            RecordingClient::recording(&client.inner)
+        } else {
+            RecordingClient::replaying(record_path)?
+        };
+        let client = BdkElectrumClient::new(recorder);
+        Self::full_scan(
+            &mut self.wallet,
+            &client,
+            self.electrum_config,
+            self.tx_cache.as_mut(),
+        )?;
+        if client.inner.is_recording() {
+            client.inner.save(record_path)?;
+        }
+        Ok(())
+    }
+
+    fn full_scan<E: ElectrumApi>(
+        wallet: &mut Wallet,
+        client: &BdkElectrumClient<E>,
+        config: ElectrumConfig,
+        tx_cache: Option<&mut ElectrumTxCache>,
+    ) -> anyhow::Result<()> {
+        // Populate the electrum client's transaction cache so it doesn't re-download transaction we
+        // already have.
+        client.populate_tx_cache(wallet.tx_graph().full_txs().map(|tx_node| tx_node.tx));
+        if let Some(tx_cache) = &tx_cache {
+            client.populate_tx_cache(tx_cache.load()?);
+        }
+
+        let request = wallet.start_full_scan().inspect({
+            let mut stdout = std::io::stdout();
+            // let mut once = HashSet::<KeychainKind>::new();
+            move |_k, _spk_i, _| {
+                stdout.flush().expect("must flush");
+            }
+        });
+        tracing::info!("requesting update...");
+        let update = client.full_scan(request, config.stop_gap, config.batch_size, false)?;
+        if let Some(tx_cache) = tx_cache {
+            tx_cache.persist(update.tx_update.txs.iter().map(Arc::as_ref))?;
+        }
+        wallet.apply_update(update)?;
+        Ok(())
+    }
+
+    pub fn balance(&self) -> Amount {
+        self.wallet.balance().trusted_spendable()
+    }
+
+    pub fn reveal_next_address(&mut self) -> AddressInfo {
+        self.wallet.reveal_next_address(KeychainKind::External)
+    }
+
+    pub fn next_unused_address(&mut self) -> AddressInfo {
+        self.wallet.next_unused_address(KeychainKind::External)
+    }
+
+    pub fn funded_wallet_from_rpc<R>(
+        rpc: &R,
+        client: BdkElectrumClient<Client>,
+    ) -> anyhow::Result<Self>
+    where
+        R: chain::ChainFunding,
+    {
+        const MAX_RETRIES: u32 = 20;
+        const RETRY_DELAY_MS: u64 = 500;
+
+        let mut wallet = Self::new(client)?;
+        let address = wallet.reveal_next_address();
+        rpc.send_to_address(&address, Amount::from_btc(10f64).unwrap())?;
+        rpc.generate_to_address(1, &address)?;
+
+        for attempt in 0..MAX_RETRIES {
+            wallet.sync()?;
+            if wallet.balance() > Amount::from_sat(0) {
+                tracing::info!("Wallet funded after {} retries", attempt);
+                return Ok(wallet);
+            }
+            if attempt < MAX_RETRIES - 1 {
+                std::thread::sleep(Duration::from_millis(RETRY_DELAY_MS));
+            }
+        }
+        anyhow::bail!("Wallet failed to sync funded balance after {MAX_RETRIES} attempts")
+    }
+}
+
+impl WalletExt for MemWallet {
+    fn update_psbt_with_derivation_paths(&self, psbt: &mut Psbt) {
+        self.wallet.update_psbt_with_derivation_paths(psbt);
+    }
+}
+
+impl ProtocolWalletApi for MemWallet {
+    fn network(&self) -> Network {
+        self.wallet.network()
+    }
+
+    fn new_address(&mut self) -> Result<Address> {
+        self.wallet.new_address()
+    }
+
+    fn new_internal_key(&mut self) -> Result<XOnlyPublicKey> {
+        self.wallet.new_internal_key()
+    }
+
+    fn create_psbt(
+        &mut self,
+        recipients: Vec<(ScriptBuf, Amount)>,
+        fee_rate: FeeRate,
+    ) -> Result<Psbt> {
+        self.wallet.create_psbt(recipients, fee_rate)
+    }
+
+    fn sign_selected_inputs(
+        &mut self,
+        psbt: &mut Psbt,
+        is_selected: &dyn Fn(&OutPoint) -> bool,
+    ) -> Result<()> {
+        self.wallet.sign_selected_inputs(psbt, is_selected)
+    }
+
+    fn import_private_key(&mut self, _pk: Scalar) {
+        // `MemWallet` is an in-memory wallet that doesn't currently support imported keys.
+        // If/when this is needed, mirror the `BMPWallet` implementation.
+        todo!("MemWallet does not yet support importing private keys")
+    }
+}
@@ -1,25 +1,21 @@
-use std::io::Write as _;
 use std::mem;
 use std::sync::LazyLock;
-use std::time::Duration;
 
-use bdk_electrum::BdkElectrumClient;
-use bdk_electrum::bdk_core::bitcoin::bip32::Xpriv;
-use bdk_electrum::electrum_client::Client;
 use bdk_wallet::bitcoin::{
     Address, Amount, FeeRate, Network, OutPoint, Psbt, ScriptBuf, XOnlyPublicKey, absolute,
     secp256k1,
 };
 use bdk_wallet::coin_selection::CoinSelectionAlgorithm;
-use bdk_wallet::descriptor::{Descriptor, ExtendedDescriptor};
+use bdk_wallet::descriptor::Descriptor;
 use bdk_wallet::miniscript::ToPublicKey as _;
 use bdk_wallet::miniscript::psbt::PsbtExt as _;
-use bdk_wallet::template::{Bip86, DescriptorTemplate as _};
-use bdk_wallet::{AddressInfo, KeychainKind, SignOptions, TxBuilder, TxOrdering, Wallet};
-use rand::RngCore as _;
+use bdk_wallet::{KeychainKind, SignOptions, TxBuilder, TxOrdering, Wallet};
 use secp::Scalar;
 use thiserror::Error;
 
+#[cfg(feature = "native")]
+pub use crate::mem_wallet::{MemWallet, check_electrum_health};
+
 /// The Protocol Wallet API is used by the protocol to create and sign transactions.
 /// It's the part of functionality being exposed only to the protocol.
 /// The protocol will see `protocol_wallet_api` and the GUI will see `WalletApi`, both are
@@ -58,114 +54,28 @@ pub trait ProtocolWalletApi {
     fn import_private_key(&mut self, pk: Scalar);
 }
 
-pub struct MemWallet {
-    wallet: Wallet,
-    client: BdkElectrumClient<Client>,
+/// How a [`MemWallet`] scans its script pubkeys with Electrum on sync, trading scan speed against
+/// thoroughness.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ElectrumConfig {
+    /// The number of consecutive unused script pubkeys after which to stop scanning a keychain.
+    pub stop_gap: usize,
+    /// The number of script pubkeys to look up per request to the Electrum server.
+    pub batch_size: usize,
 }
 
-// TODO think about stop_gap and batch_size
-const STOP_GAP: usize = 50;
-const BATCH_SIZE: usize = 5;
-
-pub(crate) static LIBSECP256K1_CTX: LazyLock<secp256k1::Secp256k1<secp256k1::All>> =
-    LazyLock::new(secp256k1::Secp256k1::new);
-
-impl MemWallet {
-    pub fn public_descriptor(&self, chain: KeychainKind) -> &ExtendedDescriptor {
-        self.wallet.public_descriptor(chain)
-    }
-
-    pub fn new(client: BdkElectrumClient<Client>) -> anyhow::Result<Self> {
-        let mut seed: [u8; 32] = [0u8; 32];
-        rand::rng().fill_bytes(&mut seed);
-
-        let network: Network = Network::Regtest;
-        let xprv: Xpriv = Xpriv::new_master(network, &seed)?;
-        tracing::info!(
-            "Generated Master Private Key:\n{xprv}\nWarning: be very careful with private \
-            keys when using MainNet! We are logging these values for convenience only because this \
-            is an example on RegTest.\n"
-        );
-
-        let (descriptor, external_map, _) = Bip86(xprv, KeychainKind::External)
-            .build(network.into())
-            .expect("Failed to build external descriptor");
-
-        let (change_descriptor, internal_map, _) = Bip86(xprv, KeychainKind::Internal)
-            .build(network.into())
-            .expect("Failed to build internal descriptor");
-
-        let wallet = Wallet::create(descriptor, change_descriptor)
-            .network(network)
-            .keymap(KeychainKind::External, external_map)
-            .keymap(KeychainKind::Internal, internal_map)
-            .create_wallet_no_persist()?;
-
-        Ok(Self { wallet, client })
-    }
-
-    pub fn sync(&mut self) -> anyhow::Result<()> {
-        // Populate the electrum client's transaction cache so it doesn't re-download transaction we
-        // already have.
-        self.client
-            .populate_tx_cache(self.wallet.tx_graph().full_txs().map(|tx_node| tx_node.tx));
-
-        let request = self.wallet.start_full_scan().inspect({
-            let mut stdout = std::io::stdout();
-            // let mut once = HashSet::<KeychainKind>::new();
-            move |_k, _spk_i, _| {
-                stdout.flush().expect("must flush");
-            }
-        });
-        tracing::info!("requesting update...");
-        let update = self
-            .client
-            .full_scan(request, STOP_GAP, BATCH_SIZE, false)?;
-        self.wallet.apply_update(update)?;
-        Ok(())
-    }
-
-    pub fn balance(&self) -> Amount {
-        self.wallet.balance().trusted_spendable()
-    }
-
-    pub fn reveal_next_address(&mut self) -> AddressInfo {
-        self.wallet.reveal_next_address(KeychainKind::External)
-    }
-
-    pub fn next_unused_address(&mut self) -> AddressInfo {
-        self.wallet.next_unused_address(KeychainKind::External)
-    }
-
-    pub fn funded_wallet_from_rpc<R>(
-        rpc: &R,
-        client: BdkElectrumClient<Client>,
-    ) -> anyhow::Result<Self>
-    where
-        R: chain::ChainFunding,
-    {
-        const MAX_RETRIES: u32 = 20;
-        const RETRY_DELAY_MS: u64 = 500;
-
-        let mut wallet = Self::new(client)?;
-        let address = wallet.reveal_next_address();
-        rpc.send_to_address(&address, Amount::from_btc(10f64).unwrap())?;
-        rpc.generate_to_address(1, &address)?;
-
-        for attempt in 0..MAX_RETRIES {
-            wallet.sync()?;
-            if wallet.balance() > Amount::from_sat(0) {
-                tracing::info!("Wallet funded after {} retries", attempt);
-                return Ok(wallet);
-            }
-            if attempt < MAX_RETRIES - 1 {
-                std::thread::sleep(Duration::from_millis(RETRY_DELAY_MS));
-            }
+impl Default for ElectrumConfig {
+    fn default() -> Self {
+        Self {
+            stop_gap: 50,
+            batch_size: 5,
         }
-        anyhow::bail!("Wallet failed to sync funded balance after {MAX_RETRIES} attempts")
     }
 }
 
+pub(crate) static LIBSECP256K1_CTX: LazyLock<secp256k1::Secp256k1<secp256k1::All>> =
+    LazyLock::new(secp256k1::Secp256k1::new);
+
 pub(crate) trait WalletExt {
     fn update_psbt_with_derivation_paths(&self, psbt: &mut Psbt);
 }
@@ -195,48 +105,6 @@ impl WalletExt for Wallet {
     }
 }
 
-impl WalletExt for MemWallet {
-    fn update_psbt_with_derivation_paths(&self, psbt: &mut Psbt) {
-        self.wallet.update_psbt_with_derivation_paths(psbt);
-    }
-}
-
-impl ProtocolWalletApi for MemWallet {
-    fn network(&self) -> Network {
-        self.wallet.network()
-    }
-
-    fn new_address(&mut self) -> Result<Address> {
-        self.wallet.new_address()
-    }
-
-    fn new_internal_key(&mut self) -> Result<XOnlyPublicKey> {
-        self.wallet.new_internal_key()
-    }
-
-    fn create_psbt(
-        &mut self,
-        recipients: Vec<(ScriptBuf, Amount)>,
-        fee_rate: FeeRate,
-    ) -> Result<Psbt> {
-        self.wallet.create_psbt(recipients, fee_rate)
-    }
-
-    fn sign_selected_inputs(
-        &mut self,
-        psbt: &mut Psbt,
-        is_selected: &dyn Fn(&OutPoint) -> bool,
-    ) -> Result<()> {
-        self.wallet.sign_selected_inputs(psbt, is_selected)
-    }
-
-    fn import_private_key(&mut self, _pk: Scalar) {
-        // `MemWallet` is an in-memory wallet that doesn't currently support imported keys.
-        // If/when this is needed, mirror the `BMPWallet` implementation.
-        todo!("MemWallet does not yet support importing private keys")
-    }
-}
-
 impl ProtocolWalletApi for Wallet {
     fn network(&self) -> Network {
         self.network()
@@ -281,6 +149,26 @@ impl ProtocolWalletApi for Wallet {
     }
 }
 
+/// Extends BDK's [`SignOptions`] with the settings used for all trade-related signing.
+pub trait SignOptionsExt {
+    fn default_for_musig() -> Self;
+}
+
+impl SignOptionsExt for SignOptions {
+    /// Sign with the Taproot internal (key-path) key and finalize the inputs where possible, with
+    /// low-r grinding of any remaining ECDSA (non-Taproot) signatures. These happen to match BDK's
+    /// defaults, but are spelled out so that trade signing doesn't silently change with them. The
+    /// Schnorr signatures used throughout the protocol need no grinding, being always 64 bytes.
+    fn default_for_musig() -> Self {
+        Self {
+            sign_with_tap_internal_key: true,
+            try_finalize: true,
+            allow_grinding: true,
+            ..Self::default()
+        }
+    }
+}
+
 /// Sign the wallet-owned inputs of `psbt` using the supplied `sign` closure, then transfer the
 /// resulting signatures and witness data for any input matched by `is_selected` back into the
 /// caller's PSBT — finalizing any selected input that BDK left only partially signed (e.g. a
@@ -313,7 +201,7 @@ where
         &mut psbt_copy,
         SignOptions {
             trust_witness_utxo: true,
-            ..SignOptions::default()
+            ..SignOptions::default_for_musig()
         },
     )?;
     for i in 0..psbt.inputs.len() {
@@ -381,7 +269,7 @@ fn is_well_formed_psbt(psbt: &Psbt) -> bool {
             .all(|i| i.script_sig.is_empty() && i.witness.is_empty())
 }
 
-type Result<T, E = WalletErrorKind> = std::result::Result<T, E>;
+pub(crate) type Result<T, E = WalletErrorKind> = std::result::Result<T, E>;
 
 #[derive(Error, Debug)]
 #[error(transparent)]
@@ -10,6 +10,7 @@ use rand::RngCore as _;
 use secp::Scalar;
 use testenv::TestEnv;
 use wallet::bmp_wallet::*;
+use wallet::protocol_wallet_api::{MemWallet, check_electrum_health};
 
 fn new_private_key() -> Scalar {
     let mut seed: [u8; 32] = [0u8; 32];
@@ -421,3 +422,67 @@ async fn test_drain_wallet_no_balance() {
         .drain_imported_balance(FeeRate::from_sat_per_vb(10).unwrap())
         .unwrap();
 }
+
+#[test]
+fn test_mem_wallet_sync_with_recording() -> anyhow::Result<()> {
+    let mut env = TestEnv::new()?;
+    let seed = [7u8; 32];
+    let record_path = env.new_temp_path().join("electrum_recording.json");
+
+    let mut wallet = MemWallet::from_seed(Some(env.new_client()?), &seed)?;
+    let receive_amount = Amount::from_sat(100_000);
+    let receiving_addr = wallet.reveal_next_address();
+
+    env.fund_address(&receiving_addr, receive_amount)?;
+    env.mine_block()?;
+
+    // The first sync records the Electrum responses...
+    wallet.sync_with_recording(&record_path)?;
+    assert_eq!(wallet.balance(), receive_amount);
+    assert!(record_path.exists());
+
+    // ...which an offline wallet with the same seed then replays, without any network access.
+    let mut offline_wallet = MemWallet::from_seed(None, &seed)?;
+    assert!(offline_wallet.sync().is_err());
+    offline_wallet.sync_with_recording(&record_path)?;
+    assert_eq!(offline_wallet.balance(), receive_amount);
+
+    Ok(())
+}
+
+#[test]
+fn test_mem_wallet_sync_with_fallback() -> anyhow::Result<()> {
+    let mut env = TestEnv::new()?;
+    let mut wallet = MemWallet::from_seed(None, &[8u8; 32])?;
+    let receive_amount = Amount::from_sat(100_000);
+    let receiving_addr = wallet.reveal_next_address();
+
+    env.fund_address(&receiving_addr, receive_amount)?;
+    env.mine_block()?;
+
+    // Nothing listens on port 1, so the sync falls back to the live server...
+    let electrum_url = env.electrum_url();
+    let dead_url = "tcp://127.0.0.1:1";
+    wallet.sync_with_fallback(&[dead_url, &electrum_url])?;
+    assert_eq!(wallet.balance(), receive_amount);
+    // ...whose client is then kept for plain syncs.
+    wallet.sync()?;
+
+    let err = wallet
+        .sync_with_fallback(&[dead_url, dead_url])
+        .unwrap_err();
+    assert!(format!("{err:#}").starts_with("sync failed with all 2 Electrum servers"));
+
+    Ok(())
+}
+
+#[test]
+fn test_check_electrum_health() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let client = env.new_client()?;
+    check_electrum_health(&client, Network::Regtest)?;
+
+    let err = check_electrum_health(&client, Network::Bitcoin).unwrap_err();
+    assert!(err.to_string().contains("different chain"));
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! WebAssembly bindings for the trade protocol, so that browser-based Bisq frontends can run the
+//! `MuSig2` key aggregation & signing steps without a native binary (with the `wasm` feature).
+//!
+//! A JS `TradeModel` drives one side of a trade through the same steps as the daemon's gRPC
+//! handlers, by calling the very same step functions (see `rpc::server::steps`). Each step takes
+//! the JSON of the corresponding gRPC request and returns the JSON of its response, so that my
+//! private key share for the peer's payout output is only ever released as the daemon releases it:
+//! in the response to `signSwapTx` (by a seller ready to release) or `closeTrade`. Build the npm
+//! package with `wasm-pack`, as described in the README.
+#![cfg(feature = "wasm")]
+
+use bdk_wallet::serde::Serialize;
+use bdk_wallet::serde::de::DeserializeOwned;
+use bdk_wallet::serde_json;
+use rpc::pb::musigrpc;
+use rpc::protocol::TradeModel;
+use rpc::server::{Config, steps};
+use tonic::Status;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(js_name = TradeModel)]
+pub struct WasmTradeModel {
+    config: Config,
+    trade_model: TradeModel,
+}
+
+#[wasm_bindgen(js_class = TradeModel)]
+impl WasmTradeModel {
+    /// Start a new trade, given the JSON of a `PubKeySharesRequest`, with the trade limits of a
+    /// daemon of the default config.
+    #[wasm_bindgen(constructor)]
+    pub fn new(request: &str) -> Result<Self, JsError> {
+        let request = serde_json::from_str(request)?;
+        let trade_model = steps::init_trade(request).map_err(|e| js_error(&e))?;
+        Ok(Self { config: Config::default(), trade_model })
+    }
+
+    #[wasm_bindgen(getter, js_name = tradeId)]
+    pub fn trade_id(&self) -> String { self.trade_model.trade_id().to_owned() }
+
+    /// The stage the trade has reached, e.g. `NONCE_SHARES_EXCHANGED`.
+    #[wasm_bindgen(getter)]
+    pub fn state(&self) -> String {
+        musigrpc::TradeState::from(self.trade_model.state()).as_str_name().to_owned()
+    }
+
+    /// Returns the JSON `PubKeySharesResponse`, with my public key shares.
+    #[wasm_bindgen(js_name = pubKeyShares)]
+    pub fn pub_key_shares(&self) -> Result<String, JsError> {
+        to_json(&steps::pub_key_shares_response(&self.config, &self.trade_model).map_err(|e| js_error(&e))?)
+    }
+
+    /// Takes a JSON `NonceSharesRequest`, returning the JSON `NonceSharesMessage` for the peer.
+    #[wasm_bindgen(js_name = getNonceShares)]
+    pub fn get_nonce_shares(&mut self, request: &str) -> Result<String, JsError> {
+        run_step(request, |request| steps::get_nonce_shares(&self.config, request, &mut self.trade_model))
+    }
+
+    /// Takes a JSON `PartialSignaturesRequest`, returning the JSON `PartialSignaturesMessage`.
+    #[wasm_bindgen(js_name = getPartialSignatures)]
+    pub fn get_partial_signatures(&mut self, request: &str) -> Result<String, JsError> {
+        run_step(request, |request| steps::get_partial_signatures(&self.config, request, &mut self.trade_model))
+    }
+
+    /// Takes a JSON `DepositTxSignatureRequest`, returning the JSON `DepositPsbt`.
+    #[wasm_bindgen(js_name = signDepositTx)]
+    pub fn sign_deposit_tx(&mut self, request: &str) -> Result<String, JsError> {
+        run_step(request, |request| steps::sign_deposit_tx(request, &mut self.trade_model))
+    }
+
+    /// Takes a JSON `SwapTxSignatureRequest`, returning the JSON `SwapTxSignatureResponse`.
+    #[wasm_bindgen(js_name = signSwapTx)]
+    pub fn sign_swap_tx(&mut self, request: &str) -> Result<String, JsError> {
+        run_step(request, |request| steps::sign_swap_tx(request, &mut self.trade_model))
+    }
+
+    /// Takes a JSON `CloseTradeRequest`, with the peer's private key share for my payout output or
+    /// (as buyer) the signed swap tx, returning the JSON `CloseTradeResponse`. There is no wallet
+    /// to broadcast the swap tx with, so a seller can't force-close the trade here.
+    #[wasm_bindgen(js_name = closeTrade)]
+    pub fn close_trade(&mut self, request: &str) -> Result<String, JsError> {
+        run_step(request, |request| steps::close_trade(request, &mut self.trade_model, None))
+    }
+}
+
+fn run_step<Req, Res, F>(request: &str, step: F) -> Result<String, JsError>
+    where Req: DeserializeOwned,
+          Res: Serialize,
+          F: FnOnce(Req) -> tonic::Result<Res> {
+    let request = serde_json::from_str(request)?;
+    to_json(&step(request).map_err(|e| js_error(&e))?)
+}
+
+fn to_json(response: &impl Serialize) -> Result<String, JsError> {
+    Ok(serde_json::to_string(response)?)
+}
+
+/// Map a failed step to a JS `Error` with the status message (the JS side has no use for the code).
+fn js_error(status: &Status) -> JsError { JsError::new(status.message()) }
@@ -0,0 +1,101 @@
+// Runs the full two-party trade protocol between a seller (as maker) and buyer (as taker), through
+// the wasm-pack built package. Run with `npm test` from the parent directory.
+
+const { TradeModel } = require("../pkg/bisq_musig_wasm.js");
+
+const TRADE_AMOUNT = 200_000;
+const SECURITY_DEPOSIT = 30_000;
+const DEPOSIT_TX_FEE_RATE = 12_500;
+const PREPARED_TX_FEE_RATE = 2_500;
+
+const REDIRECTION_ADDRESS = "bcrt1p80xu5f0nqjarfnechsmlt488jf3tykx8cva9zeeczlsu4c7x557qr499gz";
+const P2TR_OUTPUT_WEIGHT = 172;
+
+const initTrade = (tradeId, myRole) => new TradeModel(JSON.stringify({ tradeId, myRole, protocolVersion: 1 }));
+
+function nonceSharesRequest(peersPubKeyShares) {
+    peersPubKeyShares = JSON.parse(peersPubKeyShares);
+    return {
+        buyerOutputPeersPubKeyShare: peersPubKeyShares.buyerOutputPubKeyShare,
+        sellerOutputPeersPubKeyShare: peersPubKeyShares.sellerOutputPubKeyShare,
+        peersMultisigScriptKey: peersPubKeyShares.multisigScriptKey,
+        depositTxFeeRate: DEPOSIT_TX_FEE_RATE,
+        preparedTxFeeRate: PREPARED_TX_FEE_RATE,
+        tradeAmount: TRADE_AMOUNT,
+        buyersSecurityDeposit: SECURITY_DEPOSIT,
+        sellersSecurityDeposit: SECURITY_DEPOSIT,
+    };
+}
+
+// A single redirection receiver taking the whole of the available amount, less its output cost.
+function partialSignaturesRequest(peersNonceShares) {
+    peersNonceShares = JSON.parse(peersNonceShares);
+    const amount = Math.floor(
+        (peersNonceShares.redirectionAmountMsat - PREPARED_TX_FEE_RATE * P2TR_OUTPUT_WEIGHT) / 1000);
+    return JSON.stringify({
+        peersNonceShares,
+        redirectionReceivers: [{ address: REDIRECTION_ADDRESS, amount }],
+    });
+}
+
+const base64Bytes = (base64) => Buffer.from(base64, "base64");
+
+test("unknown role", () => {
+    expect(() => initTrade("wasm-trade-bad-role", "ARBITRATOR")).toThrow();
+});
+
+test("bad peer key share", () => {
+    const trade = initTrade("wasm-trade-bad-key", "SELLER_AS_MAKER");
+    const request = nonceSharesRequest(trade.pubKeyShares());
+    request.buyerOutputPeersPubKeyShare = Buffer.from([2]).toString("base64");
+    expect(() => trade.getNonceShares(JSON.stringify(request))).toThrow();
+});
+
+test("full protocol", () => {
+    const seller = initTrade("wasm-trade-seller", "SELLER_AS_MAKER");
+    const buyer = initTrade("wasm-trade-buyer", "BUYER_AS_TAKER");
+    expect(seller.tradeId).toBe("wasm-trade-seller");
+    expect(seller.state).toBe("INITIALIZED");
+
+    // Round 1: exchange public key shares.
+    const sellerKeyShares = seller.pubKeyShares();
+    const buyerKeyShares = buyer.pubKeyShares();
+    expect(base64Bytes(JSON.parse(sellerKeyShares).multisigScriptKey)).toHaveLength(32); // x-only
+
+    // Round 2: exchange nonce shares (with addresses & half deposit PSBTs).
+    const sellerNonceShares = seller.getNonceShares(JSON.stringify(nonceSharesRequest(buyerKeyShares)));
+    const buyerNonceShares = buyer.getNonceShares(JSON.stringify(nonceSharesRequest(sellerKeyShares)));
+    expect(seller.state).toBe("NONCE_SHARES_EXCHANGED");
+
+    // Round 3: exchange partial signatures, then sign the deposit tx.
+    const sellerPartialSignatures = seller.getPartialSignatures(partialSignaturesRequest(buyerNonceShares));
+    const buyerPartialSignatures = buyer.getPartialSignatures(partialSignaturesRequest(sellerNonceShares));
+    expect(JSON.parse(sellerPartialSignatures)).toHaveProperty("swapTxInputSighash");
+    const depositPsbts = [
+        seller.signDepositTx(JSON.stringify({ peersPartialSignatures: JSON.parse(buyerPartialSignatures) })),
+        buyer.signDepositTx(JSON.stringify({ peersPartialSignatures: JSON.parse(sellerPartialSignatures) })),
+    ];
+    for (const psbt of depositPsbts) {
+        expect(base64Bytes(JSON.parse(psbt).depositPsbt).subarray(0, 5).toString("latin1")).toBe("psbt\xff");
+    }
+    expect(buyer.state).toBe("DEPOSIT_TX_SIGNED");
+
+    // Once payment has been sent, the buyer releases its partial signature on the swap tx, which
+    // the seller signs, releasing its private key share for the buyer's payout output only once
+    // ready to (on receipt of the payment).
+    const buyersSwapTxSignature = JSON.parse(buyer.getPartialSignatures(JSON.stringify({ buyerReadyToRelease: true })))
+        .swapTxInputPartialSignature;
+    const swapTxSignatureRequest = { swapTxInputPeersPartialSignature: buyersSwapTxSignature };
+    expect(JSON.parse(seller.signSwapTx(JSON.stringify(swapTxSignatureRequest))).peerOutputPrvKeyShare).toBe("");
+    swapTxSignatureRequest.sellerReadyToRelease = true;
+    const sellersPrvKeyShare = JSON.parse(seller.signSwapTx(JSON.stringify(swapTxSignatureRequest)))
+        .peerOutputPrvKeyShare;
+
+    // Close cooperatively: the buyer releases its private key share for the seller's payout output
+    // in return for the seller's.
+    const buyersPrvKeyShare = JSON.parse(buyer.closeTrade(JSON.stringify({ myOutputPeersPrvKeyShare: sellersPrvKeyShare })))
+        .peerOutputPrvKeyShare;
+    expect(JSON.parse(seller.closeTrade(JSON.stringify({ myOutputPeersPrvKeyShare: buyersPrvKeyShare })))
+        .peerOutputPrvKeyShare).toBe(sellersPrvKeyShare);
+    expect([seller.state, buyer.state]).toEqual(["CLOSED", "CLOSED"]);
+});